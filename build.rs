@@ -0,0 +1,37 @@
+//! Embeds the build information (git commit and enabled features) in the binary, see
+//! `src/build_info.rs`. Nothing is reported anywhere: the information is only printed by the
+//! `version` command and written next to the outputs.
+
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map_or(false, |output| !output.stdout.is_empty());
+
+    let mut features = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase()))
+        .collect::<Vec<_>>();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=TIMETRAVEL_GIT_COMMIT={}{}",
+        commit,
+        if dirty { "-dirty" } else { "" }
+    );
+    println!("cargo:rustc-env=TIMETRAVEL_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
@@ -0,0 +1,155 @@
+//! Budget of the runs over ranges of blocks.
+//!
+//! The `--max-duration`, `--max-blocks` and `--max-bytes-downloaded` limits bound the resources
+//! spent by a run, e.g. to stay within the quota of a paid RPC provider. The budget is checked
+//! before each block of the range is extracted (or loaded), and once it is exhausted no further
+//! block is started: the blocks in flight complete and are stored, the operation runs over the
+//! blocks processed so far and its outputs are flushed as in a complete run. The first block
+//! skipped is logged (and raised as a `budget_exhausted` warning) to resume the run from it.
+//!
+//! The bytes downloaded are the response bodies received by the shared RPC client of the run, as
+//! measured by their JSON encoding, less the envelope of the responses. The `remote-externalities`
+//! engine downloads through its own client, so the size of the state it scrapes is counted
+//! instead.
+
+use crate::prelude::*;
+
+use sp_core::H256;
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// The limits of a run, unbounded if `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Limits {
+    pub max_duration: Option<Duration>,
+    pub max_blocks: Option<u32>,
+    pub max_bytes_downloaded: Option<u64>,
+}
+
+impl Limits {
+    /// Returns the limit reached after `elapsed` time, `blocks` blocks and `bytes` downloaded, if
+    /// any.
+    pub fn reached(&self, elapsed: Duration, blocks: u32, bytes: u64) -> Option<String> {
+        if let Some(max) = self.max_blocks.filter(|max| blocks >= *max) {
+            return Some(format!("{} blocks processed, max {}", blocks, max));
+        }
+        if let Some(max) = self.max_bytes_downloaded.filter(|max| bytes >= *max) {
+            return Some(format!("{} bytes downloaded, max {}", bytes, max));
+        }
+        if let Some(max) = self.max_duration.filter(|max| elapsed >= *max) {
+            return Some(format!(
+                "{}s elapsed, max {}s",
+                elapsed.as_secs(),
+                max.as_secs()
+            ));
+        }
+        None
+    }
+}
+
+/// The resources spent by the run.
+#[derive(Debug, Default)]
+struct Spent {
+    blocks: u32,
+    bytes: u64,
+}
+
+struct Budget {
+    limits: Limits,
+    started: Instant,
+    spent: Mutex<Spent>,
+}
+
+static BUDGET: OnceLock<Budget> = OnceLock::new();
+
+/// Starts the budget of the run with the given `limits`.
+pub(crate) fn start(limits: Limits) {
+    let budget = Budget {
+        limits,
+        started: Instant::now(),
+        spent: Mutex::new(Spent::default()),
+    };
+    if BUDGET.set(budget).is_err() {
+        log::warn!(target: LOG_TARGET, "The budget of the run is started only once.");
+    }
+}
+
+/// Records a processed block.
+pub(crate) fn spend() {
+    if let Some(mut spent) = BUDGET.get().and_then(|b| b.spent.lock().ok()) {
+        spent.blocks += 1;
+    }
+}
+
+/// Records `bytes` downloaded from the remote node.
+pub(crate) fn download(bytes: u64) {
+    if let Some(mut spent) = BUDGET.get().and_then(|b| b.spent.lock().ok()) {
+        spent.bytes += bytes;
+    }
+}
+
+/// Whether the budget limits the bytes downloaded, which are otherwise not measured.
+pub(crate) fn tracks_bytes() -> bool {
+    BUDGET
+        .get()
+        .map_or(false, |b| b.limits.max_bytes_downloaded.is_some())
+}
+
+/// Returns the limit reached by the run, if the budget is exhausted.
+pub(crate) fn exhausted() -> Option<String> {
+    let budget = BUDGET.get()?;
+    let spent = budget.spent.lock().ok()?;
+    budget
+        .limits
+        .reached(budget.started.elapsed(), spent.blocks, spent.bytes)
+}
+
+/// Reports whether the run stopped at the budget after processing `processed` out of `blocks`,
+/// i.e. whether it is to be resumed from `blocks[processed]`.
+pub(crate) fn report_stop(processed: usize, blocks: &[H256]) {
+    if let (Some(next), Some(reason)) = (blocks.get(processed), exhausted()) {
+        crate::warnings::warn_run(
+            "budget_exhausted",
+            format!(
+                "Budget exhausted ({}): stopped after {} of {} blocks, resume from block {:?}.",
+                reason,
+                processed,
+                blocks.len(),
+                next,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_reached_works() {
+        let elapsed = Duration::from_secs(60);
+
+        assert_eq!(Limits::default().reached(elapsed, 1_000, u64::MAX), None);
+
+        let limits = Limits {
+            max_duration: Some(Duration::from_secs(120)),
+            max_blocks: Some(10),
+            max_bytes_downloaded: Some(1_000),
+        };
+        assert_eq!(limits.reached(elapsed, 9, 999), None);
+        assert_eq!(
+            limits.reached(elapsed, 10, 0),
+            Some("10 blocks processed, max 10".to_string())
+        );
+        assert_eq!(
+            limits.reached(elapsed, 0, 1_500),
+            Some("1500 bytes downloaded, max 1000".to_string())
+        );
+        assert_eq!(
+            limits.reached(Duration::from_secs(120), 0, 0),
+            Some("120s elapsed, max 120s".to_string())
+        );
+    }
+}
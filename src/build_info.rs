@@ -0,0 +1,156 @@
+//! Build information of the CLI.
+//!
+//! The results of an analysis depend on the version of the CLI and on the runtimes compiled into
+//! it, so the build information is printed by the `version` command and, with
+//! `--output-manifest`, recorded next to the outputs of each run. The information is embedded at
+//! build time and never reported anywhere else.
+
+use serde::Serialize;
+
+/// The version, git commit, enabled features and compiled runtimes of the CLI.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct BuildInfo {
+    pub version: &'static str,
+    /// The git commit the CLI was built from, suffixed with `-dirty` if the tree had changes.
+    pub git_commit: &'static str,
+    pub features: Vec<&'static str>,
+    pub runtimes: Vec<CompiledRuntime>,
+    /// The operations compiled into the CLI.
+    pub operations: Vec<String>,
+}
+
+/// A runtime compiled into the CLI.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct CompiledRuntime {
+    pub spec_name: String,
+    pub spec_version: u32,
+    pub transaction_version: u32,
+}
+
+impl From<sp_version::RuntimeVersion> for CompiledRuntime {
+    fn from(version: sp_version::RuntimeVersion) -> Self {
+        Self {
+            spec_name: version.spec_name.to_string(),
+            spec_version: version.spec_version,
+            transaction_version: version.transaction_version,
+        }
+    }
+}
+
+impl BuildInfo {
+    /// The build information of this binary.
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("TIMETRAVEL_GIT_COMMIT"),
+            features: env!("TIMETRAVEL_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .collect(),
+            runtimes: vec![
+                polkadot_runtime::VERSION.into(),
+                kusama_runtime::VERSION.into(),
+                westend_runtime::VERSION.into(),
+            ],
+            operations: compiled_operations(),
+        }
+    }
+}
+
+/// Returns the names of the operations compiled into the CLI, as passed to the `transform`
+/// command.
+pub(crate) fn compiled_operations() -> Vec<String> {
+    use clap::CommandFactory;
+
+    crate::operations::Operation::command()
+        .get_subcommands()
+        .map(|operation| operation.get_name().to_string())
+        .collect()
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "substrate-timetravel {}", self.version)?;
+        writeln!(f, "git commit:     {}", self.git_commit)?;
+        writeln!(
+            f,
+            "features:       {}",
+            match self.features.is_empty() {
+                true => "none".to_string(),
+                false => self.features.join(", "),
+            }
+        )?;
+        for runtime in self.runtimes.iter() {
+            writeln!(
+                f,
+                "runtime:        {} v{} (transaction version {})",
+                runtime.spec_name, runtime.spec_version, runtime.transaction_version
+            )?;
+        }
+        writeln!(f, "operations:     {}", self.operations.join(", "))?;
+        Ok(())
+    }
+}
+
+/// A run of the CLI, recorded in the output manifest next to its outputs.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RunManifest {
+    pub build: BuildInfo,
+    /// The arguments of the run, as passed to the CLI.
+    pub args: Vec<String>,
+    pub chain: String,
+    /// Unix timestamp of the start of the run, in seconds.
+    pub started_at: u64,
+}
+
+/// Returns the path of the output manifest of `output_path`.
+pub(crate) fn manifest_path(output_path: &str) -> String {
+    format!("{}.manifest.jsonl", output_path)
+}
+
+/// Appends the manifest of a run to the output manifest of `output_path`, one JSON object per
+/// run and line, since the runs may append to the same output.
+pub(crate) fn write_manifest(output_path: &str, chain: &str) -> Result<(), anyhow::Error> {
+    use std::io::Write;
+
+    let manifest = RunManifest {
+        build: BuildInfo::current(),
+        args: std::env::args().collect(),
+        chain: chain.to_string(),
+        started_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+    };
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(manifest_path(output_path))?;
+    writeln!(file, "{}", serde_json::to_string(&manifest)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_works() {
+        let info = BuildInfo::current();
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_commit.is_empty());
+        assert_eq!(
+            info.runtimes
+                .iter()
+                .map(|r| r.spec_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["polkadot", "kusama", "westend"]
+        );
+        assert!(info.to_string().contains(info.git_commit));
+
+        assert!(info.operations.contains(&"stake-flows".to_string()));
+        assert!(info.operations.contains(&"pool-yields".to_string()));
+    }
+}
@@ -6,52 +6,127 @@
 //! and stores the externalities snapshot in disk for posterior use.
 //! * `subtrate-timetravel transform`: computes a given transformation on an externalities and stored the
 //! results in a CSV file.
+//!
+//! Besides those, there are utility commands which rely only on RPC queries to a remote node:
+//! * `substrate-timetravel runtime-upgrades`: outputs the block at which each runtime version
+//! activated in a range of blocks.
+//! * `substrate-timetravel bisect`: finds the first block in a range at which a storage value
+//! satisfies a predicate.
+//! * `substrate-timetravel estimate`: estimates the number of keys, bytes and time required to
+//! extract a set of pallets at a block.
+//! * `substrate-timetravel lookup-keys`: identifies the storage item of raw storage keys and
+//! decodes their map keys.
+//! * `substrate-timetravel storage-layout`: outputs the storage items which changed between the
+//! runtimes of two blocks.
+//!
+//! The `substrate-timetravel generate` command populates a stored snapshot with a synthetic
+//! electorate, to study operations over controlled inputs.
+//!
+//! The `substrate-timetravel snapshot inspect` command prints the contents of a stored snapshot,
+//! offline.
+//!
+//! The `substrate-timetravel load` command closes the loop, writing the state of a stored (and
+//! possibly transformed) snapshot into a raw chain spec, to boot a local node forked from it.
+//!
+//! The extract phase fetches the state through a [`crate::scraper::Scraper`], backed by default by
+//! `frame-remote-externalities` or, with `--engine raw`, by paged RPC queries.
 
-use crate::operations::Operation;
+use crate::configs::{Engine, RuntimeMode, ScoringMode, StoragePredicate};
+use crate::operations::{Operation, Pairing};
 use crate::prelude::*;
-use crate::Error;
+use crate::rpc::SharedRpcClient;
+use crate::scraper::{AnyScraper, RawScraper, RemoteExternalities, ScrapeRequest, Scraper};
+use crate::TimetravelError;
 
-use anyhow::anyhow;
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
 
-use frame_support::storage::generator::StorageMap;
-use remote_externalities::{Builder, Mode, OfflineConfig, OnlineConfig, SnapshotConfig, Transport};
-use sp_core::{hashing::twox_128, H256};
+use sp_core::{hashing::twox_128, Bytes, H256};
 
 macro_rules! extract_for {
 	($runtime:ident) => {
 		paste::paste! {
 			pub(crate) async fn [<extract_cmd_ $runtime>](
-				uri: String,
+				rpc: SharedRpcClient,
                 pallets: Vec<String>,
                 block_hashes: Vec<H256>,
                 snapshot_paths: Vec<String>,
                 live: bool,
+                delta: bool,
+                raw: bool,
 			)  -> Result<Vec<Ext>, anyhow::Error> {
 				use $crate::[<$runtime _runtime_exports>]::*;
 
                 log::info!(target: LOG_TARGET, "Scrapping keys for pallets {:?} for block(s) {:?}", pallets, block_hashes);
 
-                let mut exts: Vec<Ext> = vec![];
+                let ext_config = crate::EXT_CONFIG.get().cloned().unwrap_or_default();
+
+                let scraper = match ext_config.engine {
+                    Engine::RemoteExternalities => AnyScraper::RemoteExternalities(RemoteExternalities::<Block>::default()),
+                    Engine::Raw => AnyScraper::Raw(RawScraper::new(rpc.clone(), &ext_config)),
+                };
+
+                let mut requests = block_hashes.iter().enumerate().map(|(i, block_hash)| ScrapeRequest {
+                    uri: rpc.uri().to_string(),
+                    at: *block_hash,
+                    pallets: pallets.clone(),
+                    hashed_prefixes: crate::gadgets::compose::standalone_prefixes(),
+                    hashed_keys: crate::gadgets::compose::standalone_keys(),
+                    snapshot_path: if live { None } else { Some(snapshot_paths[i].clone()) },
+                    compress: ext_config.compress,
+                    child_trie: !ext_config.skip_child_trie,
+                    state_version: ext_config.state_version,
+                });
+
+                // in the raw mode, the key-values are streamed to the snapshot files by the raw
+                // engine, and no externalities is built.
+                if raw {
+                    let scraper = RawScraper::new(rpc.clone(), &ext_config);
+                    let scraper = &scraper;
+
+                    let stored = futures::stream::iter(requests)
+                        .take_while(|_| futures::future::ready(crate::budget::exhausted().is_none()))
+                        .map(|request| async move {
+                            scraper.scrape_to_file(&request).await
+                                .map(|_| crate::budget::spend())
+                        })
+                        .buffered(ext_config.concurrency.max(1))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    crate::budget::report_stop(stored.len(), &block_hashes);
+
+                    log::info!(target: LOG_TARGET, "Extract done, raw snapshot(s) stored in {:?}", snapshot_paths);
+                    return Ok(vec![]);
+                }
 
-                for (i, block_hash) in block_hashes.iter().enumerate() {
-                    let state_snapshot = if live { None } else { Some(snapshot_paths[i].clone().into()) };
+                // in the delta mode, the first block is the base whose full snapshot is stored,
+                // and only the changes relative to it are stored for the other blocks.
+                let mut exts: Vec<Ext> = vec![];
+                let base = match (delta && !live).then(|| requests.next()).flatten() {
+                    Some(request) => {
+                        let block_hash = request.at;
+                        let mut ext = scrape_block(&rpc, &scraper, request, &pallets, None).await?;
+                        let pairs = crate::gadgets::compose::storage_pairs(&mut ext).into_iter().collect();
+                        exts.push(ext);
+                        Some((block_hash, pairs))
+                    },
+                    None => None,
+                };
 
-                    let ext = Builder::<Block>::new()
-					    .mode(Mode::Online(OnlineConfig {
-						transport: Transport::Uri(uri.clone()),
-						at: Some(*block_hash),
-						pallets: pallets.clone(),
-						hashed_prefixes: vec![<frame_system::BlockHash<Runtime>>::prefix_hash().to_vec()],
-						hashed_keys: vec![[twox_128(b"System"), twox_128(b"Number")].concat()],
-						state_snapshot,
-						..Default::default()
-					}))
-					.build()
-                    .await
-		            .map(|rx| rx.inner_ext)
-                    .map_err(|e| return anyhow!(Error::Externalities{ error: e.to_string()}))?;
+                // the blocks are scraped concurrently, since the extraction is bound by the RPC
+                // round trips rather than by the CPU. No block is started once the budget of the
+                // run is exhausted.
+                let scraped = futures::stream::iter(requests)
+                    .take_while(|_| futures::future::ready(crate::budget::exhausted().is_none()))
+                    .map(|request| scrape_block(&rpc, &scraper, request, &pallets, base.as_ref()))
+                    .buffered(ext_config.concurrency.max(1))
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                exts.extend(scraped);
 
-                    exts.push(ext);
+                // the runs over the extracted blocks report where they stopped themselves.
+                if !live {
+                    crate::budget::report_stop(exts.len(), &block_hashes);
                 }
 
                 log::info!(target: LOG_TARGET, "Extract done, snapshot(s) stored in {:?}", snapshot_paths);
@@ -62,47 +137,276 @@ macro_rules! extract_for {
 	};
 }
 
+/// Options of the `transform` command, besides the operation and the blocks it runs over.
+#[derive(Debug, Default)]
+pub(crate) struct TransformOptions {
+    /// Whether the unbounded elections are computed, see [`Operation::ElectionAnalysis`].
+    pub compute_unbounded: bool,
+    /// Whether the snapshots are extracted from the remote node rather than loaded from disk.
+    pub live: bool,
+    /// Whether the elections are mined with the multi-threaded solvers.
+    pub parallel_solver: bool,
+    pub scoring: ScoringMode,
+    /// Snapshots overlaid on the loaded state, see [`crate::gadgets::compose`].
+    pub patches: Vec<String>,
+    /// Mutation specs applied to the loaded state, see [`crate::gadgets::mutation`].
+    pub mutation_specs: Vec<String>,
+    /// Whether the operation may mutate the loaded state.
+    pub allow_mutation: bool,
+    /// Directory where the snapshots trimmed to the keys read by the operation are written.
+    pub trimmed_path: Option<String>,
+    /// Path where the keys read by the operation are written.
+    pub trace_path: Option<String>,
+}
+
 macro_rules! transform_for {
     ($runtime:ident) => {
         paste::paste! {
             pub(crate) async fn [<transform_cmd_ $runtime>](
-                uri: String,
+                rpc: SharedRpcClient,
                 operation: Operation,
-                block_hashes: Vec<H256>,
+                mut block_hashes: Vec<H256>,
                 output_path: String,
-                snapshot_paths: Vec<String>,
-                compute_unbounded: bool,
-                live: bool,
+                mut snapshot_paths: Vec<String>,
+                options: TransformOptions,
             )  -> Result<(), anyhow::Error> {
                 use $crate::[<$runtime _runtime_exports>]::*;
 
-                let exts = if live {
-                    let default_pallets = vec!["ElectionProviderMultiPhase".to_string(), "Staking".to_string(), "VoterList".to_string()];
-                    extract_cmd(uri, default_pallets, block_hashes, snapshot_paths.clone(), true).await?
+                let TransformOptions {
+                    compute_unbounded,
+                    live,
+                    ref patches,
+                    ref mutation_specs,
+                    allow_mutation,
+                    ref trimmed_path,
+                    ref trace_path,
+                    ..
+                } = options;
+
+                // the numbers reported from a mutated state are not the numbers of the chain, so
+                // the mutations must be explicitly allowed.
+                let mutations = operation.mutations(compute_unbounded, patches, mutation_specs);
+                if !mutations.is_empty() {
+                    if !allow_mutation {
+                        return Err(TimetravelError::Unsupported {
+                            reason: format!(
+                                "operation {} mutates the loaded state ({}); pass `--allow-mutation` to run it.",
+                                operation.name(),
+                                mutations.join(", "),
+                            ),
+                        }.into());
+                    }
+                    log::warn!(target: LOG_TARGET, "Mutations applied to the loaded state by {}:", operation.name());
+                    for mutation in &mutations {
+                        log::warn!(target: LOG_TARGET, "  - {}", mutation);
+                    }
+                }
+
+                // the specs are read before any snapshot is loaded, to fail early.
+                let mutation_specs = mutation_specs
+                    .iter()
+                    .map(|path| crate::gadgets::mutation::MutationSpec::load(path).map(|spec| (path, spec)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // the rows of the operation are written to the output as they complete.
+                let mut out = crate::output::Emitter::new(output_path);
+
+                // operations that only rely on RPC queries do not require externalities.
+                if let Operation::Constants { ref pallets } = operation {
+                    return crate::operations::constants(&rpc, block_hashes, &mut out, pallets).await;
+                }
+                if let Operation::Headers { from, to, step, block_time_ms } = operation {
+                    return crate::operations::headers(&rpc, block_hashes, from, to, step, block_time_ms, &mut out).await;
+                }
+                if let Operation::Track { ref accounts, ref items, from, to, step } = operation {
+                    return crate::operations::track(&rpc, block_hashes, accounts, items, (from, to, step), &mut out).await;
+                }
+
+                // in the metadata mode, the storage is decoded with the on-chain metadata rather
+                // than the compiled runtime, which only a subset of the operations supports.
+                let metadata_mode = matches!(crate::EXT_CONFIG.get().map(|c| c.runtime_mode), Some(RuntimeMode::Metadata));
+                let requirements = match metadata_mode {
+                    true => operation.metadata_requirements().ok_or_else(|| TimetravelError::Unsupported {
+                        reason: format!("operation {} requires the compiled runtime, use `--runtime-mode compiled`.", operation.name()),
+                    })?,
+                    false => operation.requirements(),
+                };
+
+                let compiled_spec_name = <<Runtime as frame_system::Config>::Version as frame_support::traits::Get<sp_version::RuntimeVersion>>::get().spec_name;
+                crate::operations::check_runtime_requirements(
+                    &rpc,
+                    &operation,
+                    &requirements,
+                    &block_hashes,
+                    (!metadata_mode).then(|| &*compiled_spec_name),
+                ).await?;
+
+                let mut exts = if live {
+                    let pallets = operation.pallets_to_extract(&requirements);
+                    log::info!(target: LOG_TARGET, "Extracting pallets {:?} for operation {} live.", pallets, operation.name());
+                    extract_cmd(rpc.clone(), pallets, block_hashes.clone(), snapshot_paths.clone(), true, false, false).await?
                 } else {
                     let mut exts = vec![];
 
+                    let state_version = crate::EXT_CONFIG.get().and_then(|c| c.state_version);
+
                     for snapshot_path in snapshot_paths.clone() {
-                        let ext = Builder::<Block>::new()
-                            .mode(Mode::Offline(OfflineConfig {
-				            state_snapshot: SnapshotConfig::new(snapshot_path)
-                        }))
-                        .build()
-                        .await
-		                .map(|rx| rx.inner_ext)
-                        .map_err(|e| return anyhow!(Error::Externalities{ error: e.to_string()}))?;
+                        if crate::budget::exhausted().is_some() {
+                            break;
+                        }
+                        let ext = crate::scraper::load_snapshot::<Block>(snapshot_path, state_version).await?;
+                        crate::budget::spend();
 
                         exts.push(ext);
                     }
                     exts
                 };
 
+                exts.iter_mut().for_each(crate::scraper::attach_trie_cache);
+
+                // a run stopped at the budget runs the operation over the blocks processed so far,
+                // i.e. over the whole pairs of blocks for the operations over a block and its parent.
+                if operation.pairing() == Some(Pairing::Parent) {
+                    exts.truncate(exts.len() - exts.len() % 2);
+                }
+                crate::budget::report_stop(exts.len(), &block_hashes);
+                block_hashes.truncate(exts.len());
+                snapshot_paths.truncate(exts.len());
+
                 log::info!(target: LOG_TARGET, "Loaded snapshot from {:?}", snapshot_paths);
 
+                let state_version = crate::EXT_CONFIG.get().and_then(|c| c.state_version);
+                for patch_path in patches {
+                    let mut patch = crate::scraper::load_snapshot::<Block>(patch_path.clone(), state_version).await?;
+                    let pairs = crate::gadgets::compose::storage_pairs(&mut patch);
+
+                    for ext in exts.iter_mut() {
+                        let pallets = crate::gadgets::compose::overlay(ext, &pairs);
+                        log::info!(target: LOG_TARGET, "Overlaid {} pallets of patch {}.", pallets, patch_path);
+                    }
+                }
+                for (spec_path, spec) in mutation_specs {
+                    for ext in exts.iter_mut() {
+                        let mutations = crate::gadgets::mutation::mutate(ext, &spec);
+                        log::info!(target: LOG_TARGET, "Mutated {} storage keys as per mutation spec {}.", mutations.len(), spec_path);
+                    }
+                }
+
+                for (ext, snapshot_path) in exts.iter_mut().zip(snapshot_paths.iter()) {
+                    let missing = requirements.pallets.iter().filter(|p| !crate::gadgets::has_pallet_storage(ext, p)).collect::<Vec<_>>();
+                    if !missing.is_empty() {
+                        return Err(TimetravelError::Snapshot {
+                            error: format!(
+                                "operation {} requires the storage of pallets {:?}, which is missing from snapshot {}; extract it with `--pallets`.",
+                                operation.name(),
+                                missing,
+                                snapshot_path,
+                            ),
+                        }.into());
+                    }
+                }
+
+                // the reads of the operation are recorded to trim the snapshots or trace the keys.
+                let traces = match trimmed_path.is_some() || trace_path.is_some() {
+                    true => exts.iter_mut().map(crate::trace::ReadTrace::attach).collect(),
+                    false => vec![],
+                };
+                let (operation_name, traced_blocks, traced_paths) = (operation.name(), block_hashes.clone(), snapshot_paths.clone());
+
+                // the state diff decodes the storage with the on-chain metadata of the blocks in
+                // both runtime modes.
+                if let Operation::StateDiff { pallets, keys } = &operation {
+                    let blocks = crate::gadgets::by_block_number::<Runtime, _>("state_diff", block_hashes.into_iter().zip(exts), 2)?;
+                    crate::operations::state_diff(&rpc, blocks, pallets, *keys, &mut out).await?;
+                } else if metadata_mode {
+                    crate::operations::transform_with_metadata(&rpc, &operation, exts, block_hashes, &mut out).await?;
+                } else {
+                    [<run_operation_ $runtime>](operation, exts, &mut out, snapshot_paths, &options)?;
+                }
+                out.flush()?;
+                log::info!(target: LOG_TARGET, "Transform::{} wrote {} rows to {}.", operation_name, out.rows(), out.output_path());
+
+                if let Some(trimmed_path) = trimmed_path {
+                    std::fs::create_dir_all(trimmed_path)?;
+                    for (trace, snapshot_path) in traces.iter().zip(&traced_paths) {
+                        let file_name = std::path::Path::new(snapshot_path)
+                            .file_name()
+                            .map_or(snapshot_path.clone(), |name| name.to_string_lossy().to_string());
+                        crate::scraper::write_trimmed_snapshot(snapshot_path, &format!("{}/{}", trimmed_path, file_name), trace)?;
+                    }
+                }
+
+                if let Some(trace_path) = trace_path {
+                    for (trace, block_hash) in traces.iter().zip(traced_blocks) {
+                        write_traced_keys(&rpc, &operation_name, block_hash, trace, trace_path).await?;
+                    }
+                }
+
+                Ok(())
+            }
+
+            /// Runs the `operation` over the loaded externalities.
+            fn [<run_operation_ $runtime>](
+                operation: Operation,
+                exts: Vec<Ext>,
+                out: &mut crate::output::Emitter,
+                snapshot_paths: Vec<String>,
+                options: &TransformOptions,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                let TransformOptions { compute_unbounded, parallel_solver, scoring, .. } = *options;
+
+                // the operation runs on this thread, so its warnings are collected for its rows.
+                let _warnings = out.scope();
+
                 match operation {
-                    Operation::MinActiveStake => crate::operations::[<min_active_stake_ $runtime>]::<Runtime>(exts, output_path),
-                    Operation::ElectionAnalysis => crate::operations::[<election_analysis_ $runtime>]::<Runtime>(exts, output_path, compute_unbounded),
-                    Operation::StakingLedgerChecks => crate::operations::[<staking_ledger_checks_ $runtime>]::<Runtime>(exts),
+                    Operation::MinActiveStake => crate::operations::[<min_active_stake_ $runtime>]::<Runtime>(exts, out),
+                    Operation::ElectionAnalysis { solver, iterations, desired_targets } => crate::operations::[<election_analysis_ $runtime>]::<Runtime>(exts, out, compute_unbounded, solver.solver(iterations, parallel_solver), scoring, desired_targets),
+                    Operation::StakingLedgerChecks => crate::operations::[<staking_ledger_checks_ $runtime>]::<Runtime>(exts, out),
+                    Operation::ControllerDeprecation => crate::operations::[<controller_deprecation_ $runtime>]::<Runtime>(exts, out),
+                    Operation::ExposureComparison => crate::operations::[<exposure_comparison_ $runtime>]::<Runtime>(exts, out, parallel_solver),
+                    // these operations query the RPC node or the on-chain metadata, which are not
+                    // available over the loaded externalities.
+                    Operation::Constants { .. } | Operation::Headers { .. } | Operation::Track { .. } | Operation::StateDiff { .. } => Err(TimetravelError::Unsupported {
+                        reason: format!("operation {} does not run over loaded snapshots.", operation.name()),
+                    }.into()),
+                    Operation::StressTest { factors, seed, desired_targets } => crate::operations::[<stress_test_ $runtime>]::<Runtime>(exts, out, parallel_solver, factors, seed, desired_targets),
+                    Operation::DesiredTargetsWhatIf { desired_targets } => crate::operations::[<desired_targets_what_if_ $runtime>]::<Runtime>(exts, out, parallel_solver, desired_targets),
+                    Operation::TargetsWhatIf { exclude_targets, include_targets } => crate::operations::[<targets_what_if_ $runtime>]::<Runtime>(exts, out, parallel_solver, exclude_targets, include_targets),
+                    Operation::MinBondWhatIf { min_bonds } => crate::operations::[<min_bond_what_if_ $runtime>]::<Runtime>(exts, out, parallel_solver, min_bonds),
+                    Operation::PayeeDistribution => crate::operations::[<payee_distribution_ $runtime>]::<Runtime>(exts, out),
+                    Operation::NominationsDistribution => crate::operations::[<nominations_distribution_ $runtime>]::<Runtime>(exts, out),
+                    Operation::VoterDistribution => crate::operations::[<voter_distribution_ $runtime>]::<Runtime>(exts, out),
+                    Operation::CommissionAudit { min_commissions } => crate::operations::[<commission_audit_ $runtime>]::<Runtime>(exts, out, min_commissions),
+                    Operation::WaitingValidators { top } => crate::operations::[<waiting_validators_ $runtime>]::<Runtime>(exts, out, top),
+                    Operation::StakingSummary => crate::operations::[<staking_summary_ $runtime>]::<Runtime>(exts, out),
+                    Operation::Decentralization { entities } => crate::operations::[<decentralization_ $runtime>]::<Runtime>(exts, out, entities),
+                    Operation::ElectionHistory => crate::operations::[<election_history_ $runtime>]::<Runtime>(exts, out),
+                    Operation::SubmissionAnalysis => crate::operations::[<submission_analysis_ $runtime>]::<Runtime>(exts, out, parallel_solver),
+                    Operation::EmergencyResult => crate::operations::[<emergency_result_ $runtime>]::<Runtime>(exts, out, parallel_solver),
+                    Operation::FeasibilityCheck { solution } => crate::operations::[<feasibility_check_ $runtime>]::<Runtime>(exts, out, solution),
+                    Operation::PagedElection { pages, max_winners_per_page, max_backers_per_winner, desired_targets } => crate::operations::[<paged_election_ $runtime>]::<Runtime>(exts, out, pages, crate::gadgets::paged::PageBounds { max_winners_per_page, max_backers_per_winner }, parallel_solver, desired_targets),
+                    Operation::Authorship => crate::operations::[<authorship_ $runtime>]::<Runtime>(exts, out),
+                    Operation::MemoryReport => crate::operations::[<memory_report_ $runtime>]::<Runtime>(exts, out),
+                    Operation::NominatorApy { from_era, to_era, accounts, eras_per_year } => crate::operations::[<nominator_apy_ $runtime>]::<Runtime>(exts, out, from_era, to_era, accounts, eras_per_year),
+                    Operation::ValidatorScore { from_era, to_era, points_weight, commission_weight, slash_weight, uptime_weight } => {
+                        let weights = crate::gadgets::staking_stats::ScoreWeights {
+                            points: points_weight,
+                            commission: commission_weight,
+                            slash: slash_weight,
+                            uptime: uptime_weight,
+                        };
+                        crate::operations::[<validator_score_ $runtime>]::<Runtime>(exts, out, from_era, to_era, weights)
+                    },
+                    Operation::PoolYields { from_era, to_era, eras_per_year } => crate::operations::[<pool_yields_ $runtime>]::<Runtime>(exts, out, from_era, to_era, eras_per_year),
+                    Operation::RewardAnalysis { from_era, to_era, eras_per_year } => crate::operations::[<reward_analysis_ $runtime>]::<Runtime>(exts, out, from_era, to_era, eras_per_year),
+                    Operation::EraPayout { era_duration_ms } => crate::operations::[<era_payout_ $runtime>]::<Runtime>(exts, out, era_duration_ms),
+                    Operation::Inflation { era_duration_ms } => crate::operations::[<inflation_ $runtime>]::<Runtime>(exts, out, era_duration_ms),
+                    Operation::StakeFlows => crate::operations::[<stake_flows_ $runtime>]::<Runtime>(exts, out),
+                    Operation::NominatorChurn { nominators } => crate::operations::[<nominator_churn_ $runtime>]::<Runtime>(exts, out, nominators),
+                    Operation::ValidatorSetDiff => crate::operations::[<validator_set_diff_ $runtime>]::<Runtime>(exts, out),
+                    Operation::Anonymize { seed } => crate::operations::[<anonymize_ $runtime>]::<Runtime>(exts, snapshot_paths, out, seed),
                     Operation::Playground => crate::operations::[<playground_ $runtime>]::<Runtime>(exts),
                 }
             }
@@ -110,6 +414,447 @@ macro_rules! transform_for {
     };
 }
 
+/// The CSV representation of the keys traced by `transform --trace-keys`, one entry per key.
+#[derive(Debug, Serialize)]
+pub(crate) struct TracedKeyCsv {
+    operation: String,
+    block_hash: String,
+    key: String,
+    pallet: Option<String>,
+    item: Option<String>,
+    /// Whether the key was read, written or both (`read`, `write` or `read_write`).
+    access: String,
+}
+
+/// Writes the storage keys accessed by `operation` at block `block_hash`, as recorded by `trace`,
+/// to `trace_path`, along with their pallet and storage item as per the metadata of the block.
+async fn write_traced_keys(
+    rpc: &SharedRpcClient,
+    operation: &str,
+    block_hash: H256,
+    trace: &crate::trace::ReadTrace,
+    trace_path: &str,
+) -> Result<(), anyhow::Error> {
+    let metadata = crate::gadgets::metadata::fetch_metadata(rpc, block_hash).await?;
+    let index = crate::gadgets::metadata::StorageKeyIndex::new(&metadata);
+
+    let keys = trace.accesses();
+    log::info!(target: LOG_TARGET, "Traced {} keys accessed by {} at {:?}.", keys.len(), operation, block_hash);
+
+    for (key, access) in keys {
+        let info = index.lookup(&key);
+        crate::output::write(
+            TracedKeyCsv {
+                operation: operation.to_string(),
+                block_hash: format!("{:?}", block_hash),
+                key: sp_core::bytes::to_hex(&key, false),
+                pallet: info.as_ref().map(|i| i.pallet.clone()),
+                item: info.map(|i| i.item),
+                access: access.to_string(),
+            },
+            trace_path,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `generate` command result.
+#[derive(Debug, Serialize)]
+pub(crate) struct GenerateCsv {
+    template: String,
+    block_hash: String,
+    validators: u32,
+    nominators: u32,
+    snapshot_voters: u32,
+    snapshot_targets: u32,
+    snapshot_size: usize,
+}
+
+/// Populates the template snapshot with a synthetic electorate and stores it under `snapshot_dir`,
+/// named after a hash of the template and parameters so that it can be loaded by
+/// `transform --bn=<hash>`.
+macro_rules! generate_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) async fn [<generate_cmd_ $runtime>](
+                template: H256,
+                snapshot_dir: String,
+                params: crate::gadgets::synthetic::ElectorateParams,
+                output_path: String,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                let template_path = format!("{}/{}.data", snapshot_dir, template);
+                let snapshot = crate::scraper::read_snapshot(&template_path)?;
+
+                let state_version = crate::EXT_CONFIG.get().and_then(|c| c.state_version);
+                let mut ext = crate::scraper::load_snapshot::<Block>(template_path.clone(), state_version).await?;
+
+                let (metadata, snapshot_size) =
+                    crate::gadgets::synthetic::populate_electorate::<Runtime>(&mut ext, &params)?;
+
+                ext.commit_all().map_err(|error| TimetravelError::Snapshot { error })?;
+                let (raw_storage, storage_root) = ext.into_raw_snapshot();
+                let generated = crate::utils::replace_snapshot_storage(&snapshot, raw_storage, storage_root)?;
+
+                let block_hash = H256(sp_core::hashing::blake2_256(format!("{:?}{:?}", template, params).as_bytes()));
+                let snapshot_path = format!("{}/{}.data", snapshot_dir, block_hash);
+                let compress = crate::EXT_CONFIG.get().map_or(false, |c| c.compress);
+                crate::scraper::write_snapshot(&snapshot_path, &generated, compress)?;
+
+                log::info!(
+                    target: LOG_TARGET,
+                    "Generated electorate with {} voters and {} targets, snapshot stored in {} (--bn={:?}).",
+                    metadata.voters,
+                    metadata.targets,
+                    snapshot_path,
+                    block_hash,
+                );
+
+                crate::output::write(
+                    GenerateCsv {
+                        template: format!("{:?}", template),
+                        block_hash: format!("{:?}", block_hash),
+                        validators: params.validators,
+                        nominators: params.nominators,
+                        snapshot_voters: metadata.voters,
+                        snapshot_targets: metadata.targets,
+                        snapshot_size,
+                    },
+                    &output_path,
+                )
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `load` command result.
+#[derive(Debug, Serialize)]
+pub(crate) struct LoadCsv {
+    block_hash: String,
+    keys: usize,
+    has_code: bool,
+    chain_spec_path: String,
+}
+
+/// Loads the state of the stored snapshot of `block_hash` (overlaid with the `patches`) into the
+/// genesis storage of a raw chain spec, stored under `chain_spec_path`.
+macro_rules! load_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) async fn [<load_cmd_ $runtime>](
+                block_hash: H256,
+                snapshot_dir: String,
+                base_spec: Option<String>,
+                chain_spec_path: Option<String>,
+                patches: Vec<String>,
+                output_path: String,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                let state_version = crate::EXT_CONFIG.get().and_then(|c| c.state_version);
+                let snapshot_path = format!("{}/{}.data", snapshot_dir, block_hash);
+                let mut ext = crate::scraper::load_snapshot::<Block>(snapshot_path, state_version).await?;
+
+                for patch_path in patches {
+                    let mut patch = crate::scraper::load_snapshot::<Block>(patch_path.clone(), state_version).await?;
+                    let pairs = crate::gadgets::compose::storage_pairs(&mut patch);
+                    let pallets = crate::gadgets::compose::overlay(&mut ext, &pairs);
+                    log::info!(target: LOG_TARGET, "Overlaid {} pallets of patch {}.", pallets, patch_path);
+                }
+
+                let pairs = crate::gadgets::compose::storage_pairs(&mut ext);
+                let has_code = pairs.iter().any(|(key, _)| key.as_slice() == sp_core::storage::well_known_keys::CODE);
+                if !has_code {
+                    crate::warnings::warn_run("missing_code", format!(
+                        "Load: snapshot of {:?} has no `:code`, the chain spec will not boot.",
+                        block_hash,
+                    ));
+                }
+
+                let base = match base_spec {
+                    Some(path) => Some(serde_json::from_slice(&std::fs::read(&path)?)?),
+                    None => None,
+                };
+                let spec = crate::gadgets::chain_spec::raw_chain_spec(base, &format!("timetravel-{:?}", block_hash), &pairs)?;
+
+                let chain_spec_path = chain_spec_path.unwrap_or_else(|| format!("{}/{}.json", snapshot_dir, block_hash));
+                std::fs::write(&chain_spec_path, serde_json::to_vec_pretty(&spec)?)?;
+
+                log::info!(
+                    target: LOG_TARGET,
+                    "Loaded {} keys of {:?} into the chain spec {}.",
+                    pairs.len(),
+                    block_hash,
+                    chain_spec_path,
+                );
+
+                crate::output::write(
+                    LoadCsv {
+                        block_hash: format!("{:?}", block_hash),
+                        keys: pairs.len(),
+                        has_code,
+                        chain_spec_path,
+                    },
+                    &output_path,
+                )
+            }
+        }
+    };
+}
+
+/// Prints the contents of the snapshot stored under `snapshot_path`: its format and size, block
+/// number, runtime version and the keys and bytes under each prefix.
+///
+/// The runtime version is the one of `System::LastRuntimeUpgrade`, if stored in the snapshot.
+macro_rules! inspect_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) async fn [<inspect_cmd_ $runtime>](snapshot_path: String) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use codec::{Compact, Decode};
+                use frame_support::traits::PalletsInfoAccess;
+
+                let file_size = std::fs::metadata(&snapshot_path).map_err(|e| TimetravelError::Snapshot {
+                    error: format!("{}: {:?}", snapshot_path, e),
+                })?.len() as usize;
+                let (header, compressed) = crate::scraper::read_snapshot_header(&snapshot_path)?;
+                let format = crate::scraper::detect_snapshot_format(&header);
+
+                let state_version = crate::EXT_CONFIG.get().and_then(|c| c.state_version);
+                let mut ext = crate::scraper::load_snapshot::<Block>(snapshot_path.clone(), state_version).await?;
+
+                let (block_number, runtime_version) = ext.execute_with(|| {
+                    let block_number = sp_io::storage::get(&[twox_128(b"System"), twox_128(b"Number")].concat())
+                        .and_then(|v| BlockNumber::decode(&mut &v[..]).ok());
+                    let runtime_version = sp_io::storage::get(&[twox_128(b"System"), twox_128(b"LastRuntimeUpgrade")].concat())
+                        .and_then(|v| <(Compact<u32>, String)>::decode(&mut &v[..]).ok())
+                        .map(|(Compact(spec_version), spec_name)| format!("{} v{}", spec_name, spec_version));
+                    (block_number, runtime_version)
+                });
+
+                let pallets = AllPalletsWithSystem::infos().into_iter().map(|p| p.name).collect::<Vec<_>>();
+                let usage = crate::gadgets::memory::memory_usage(&mut ext, &pallets);
+
+                println!("snapshot:       {}", snapshot_path);
+                println!("format:         {:?}", format);
+                println!("compressed:     {}", compressed);
+                println!("file size:      {} bytes", file_size);
+                println!("block number:   {}", block_number.map_or("unknown".to_string(), |n| format!("#{}", n)));
+                println!("runtime:        {}", runtime_version.unwrap_or_else(|| "unknown".to_string()));
+                println!("keys:           {}", usage.iter().map(|u| u.keys).sum::<u64>());
+                println!("storage size:   {} bytes", usage.iter().map(|u| u.bytes()).sum::<u64>());
+                println!();
+                println!("{:<34} {:<28} {:>10} {:>14}", "prefix", "pallet", "keys", "bytes");
+                for prefix_usage in usage {
+                    println!(
+                        "{:<34} {:<28} {:>10} {:>14}",
+                        sp_core::bytes::to_hex(&prefix_usage.prefix, false),
+                        prefix_usage.name.clone().unwrap_or_else(|| "unknown".to_string()),
+                        prefix_usage.keys,
+                        prefix_usage.bytes(),
+                    );
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Scrapes the block of `request` and verifies its snapshot against the remote node.
+///
+/// In the delta mode, i.e. given the `base` block and its storage pairs, only the changes of the
+/// storage relative to the base are fetched (see [`RawScraper::scrape_delta`]) and stored in the
+/// snapshot path of the request.
+async fn scrape_block<S: Scraper>(
+    rpc: &SharedRpcClient,
+    scraper: &S,
+    request: ScrapeRequest,
+    pallets: &[String],
+    base: Option<&(H256, std::collections::BTreeMap<Vec<u8>, Vec<u8>>)>,
+) -> Result<Ext, anyhow::Error> {
+    let block_hash = request.at;
+
+    let mut ext = match (base, request.snapshot_path.clone()) {
+        (Some(base), Some(snapshot_path)) => {
+            let ext_config = crate::EXT_CONFIG.get().cloned().unwrap_or_default();
+            let delta_scraper = RawScraper::new(rpc.clone(), &ext_config);
+            let (delta_scraper, request) = (&delta_scraper, &request);
+            let (ext, changes) = build_with_retry(block_hash, move || {
+                delta_scraper.scrape_delta(request, base)
+            })
+            .await?;
+
+            crate::scraper::write_snapshot(
+                &snapshot_path,
+                &crate::scraper::encode_delta(base.0, &changes),
+                request.compress,
+            )?;
+            log::info!(
+                target: LOG_TARGET,
+                "Delta snapshot of {} changed keys relative to {:?} stored in {}.",
+                changes.len(),
+                base.0,
+                snapshot_path,
+            );
+
+            ext
+        }
+        _ => {
+            let request = &request;
+            build_with_retry(block_hash, move || scraper.scrape(request)).await?
+        }
+    };
+
+    verify_snapshot(rpc, &mut ext, block_hash, pallets).await?;
+
+    // the responses of the shared client are counted as they are received, while the size of the
+    // state is the estimate of the bytes downloaded by the scrapers with their own client.
+    if crate::budget::tracks_bytes() && base.is_none() && !scraper.downloads_through_rpc() {
+        crate::budget::download(
+            crate::gadgets::compose::storage_pairs(&mut ext)
+                .iter()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum(),
+        );
+    }
+    crate::budget::spend();
+
+    Ok(ext)
+}
+
+/// Number of attempts to build an externalities from a remote node before giving up.
+const EXT_BUILD_ATTEMPTS: u32 = 5;
+
+/// Whether an externalities build error is transient, i.e. caused by the connection to the remote
+/// node rather than by the requested state, and thus worth retrying. The errors of the `jsonrpsee`
+/// client are transient if the connection or the request failed (but not if the node answered
+/// with an error), and so are the [`TimetravelError::Rpc`] errors.
+pub(crate) fn is_transient_error(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<jsonrpsee::core::Error>() {
+        return is_transient_rpc_error(error);
+    }
+
+    matches!(
+        error.downcast_ref::<TimetravelError>(),
+        Some(TimetravelError::Rpc { .. })
+    )
+}
+
+/// Whether an error of the `jsonrpsee` client is transient, i.e. the connection or the request
+/// failed rather than the node answering with an error.
+pub(crate) fn is_transient_rpc_error(error: &jsonrpsee::core::Error) -> bool {
+    use jsonrpsee::core::Error as RpcError;
+
+    matches!(
+        error,
+        RpcError::Transport(_)
+            | RpcError::RestartNeeded(_)
+            | RpcError::RequestTimeout
+            | RpcError::MaxSlotsExceeded
+    )
+}
+
+/// Builds an externalities with `build`, retrying with an exponential backoff while the build
+/// fails with a transient error. Permanent errors (e.g. an invalid snapshot) are returned
+/// immediately.
+pub(crate) async fn build_with_retry<R, F, Fut>(
+    block_hash: H256,
+    mut build: F,
+) -> Result<R, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<R, anyhow::Error>>,
+{
+    let mut backoff = std::time::Duration::from_secs(2);
+    let mut attempt = 1;
+
+    loop {
+        match build().await {
+            Ok(ext) => return Ok(ext),
+            Err(error) if attempt < EXT_BUILD_ATTEMPTS && is_transient_error(&error) => {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "Transient error building externalities at {:?} (attempt {}/{}): {}, retrying in {:?}.",
+                    block_hash,
+                    attempt,
+                    EXT_BUILD_ATTEMPTS,
+                    error,
+                    backoff,
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Number of keys of each pallet sampled from the node to verify the completeness of a snapshot.
+const VERIFY_SAMPLE_SIZE: u32 = 1000;
+
+/// Verifies that the externalities extracted at `at` hold at least (approximately) as many keys
+/// under each pallet as the node, see [`crate::utils::appears_incomplete`], warning about the pallets which appear incomplete, e.g. due to silently
+/// truncated RPC responses.
+pub(crate) async fn verify_snapshot(
+    rpc: &SharedRpcClient,
+    ext: &mut Ext,
+    at: H256,
+    pallets: &[String],
+) -> Result<(), anyhow::Error> {
+    use sp_core::storage::StorageKey;
+
+    for pallet in pallets {
+        let prefix = twox_128(pallet.as_bytes());
+        let sample = rpc
+            .storage_keys_paged(
+                Some(StorageKey(prefix.to_vec())),
+                VERIFY_SAMPLE_SIZE,
+                None,
+                Some(at),
+            )
+            .await?;
+        let estimated = sample.last().map_or(0.0, |last| {
+            crate::utils::estimate_key_count(
+                sample.len() as u32,
+                VERIFY_SAMPLE_SIZE,
+                &last.0[prefix.len()..],
+            )
+        });
+        let snapshot_keys = crate::gadgets::count_keys(ext, &prefix);
+
+        if crate::utils::appears_incomplete(
+            snapshot_keys,
+            sample.len() as u32,
+            VERIFY_SAMPLE_SIZE,
+            estimated,
+        ) {
+            log::warn!(
+                target: LOG_TARGET,
+                "Snapshot at {:?} appears incomplete: {} keys of {} extracted, ~{} keys in the node.",
+                at,
+                snapshot_keys,
+                pallet,
+                estimated.round(),
+            );
+        } else {
+            log::debug!(
+                target: LOG_TARGET,
+                "Snapshot at {:?}: {} keys of {} extracted, ~{} keys in the node.",
+                at,
+                snapshot_keys,
+                pallet,
+                estimated.round(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
 //extract_for!(polkadot);
 //extract_for!(kusama);
 extract_for!(westend);
@@ -117,3 +862,1315 @@ extract_for!(westend);
 //transform_for!(polkadot);
 //transform_for!(kusama);
 transform_for!(westend);
+
+//generate_for!(polkadot);
+//generate_for!(kusama);
+generate_for!(westend);
+
+//load_for!(polkadot);
+//load_for!(kusama);
+load_for!(westend);
+
+//inspect_for!(polkadot);
+//inspect_for!(kusama);
+inspect_for!(westend);
+
+/// The CSV representation of a runtime upgrade, as output by the `runtime-upgrades` command.
+#[derive(Debug, Serialize)]
+pub(crate) struct RuntimeUpgradeCsv {
+    block_number: BlockNumber,
+    block_hash: String,
+    spec_name: String,
+    spec_version: u32,
+}
+
+/// Resolves the hash of the canonical block with number `number`.
+pub(crate) async fn block_hash_of(
+    rpc: &SharedRpcClient,
+    number: BlockNumber,
+) -> Result<Hash, anyhow::Error> {
+    Ok(rpc
+        .block_hash(Some(number))
+        .await?
+        .ok_or(TimetravelError::Rpc {
+            error: format!("Block #{} not found.", number),
+        })?)
+}
+
+/// Returns the number of the latest finalized block.
+pub(crate) async fn finalized_number(rpc: &SharedRpcClient) -> Result<BlockNumber, anyhow::Error> {
+    let finalized = rpc.finalized_head().await?;
+    Ok(rpc
+        .header(Some(finalized))
+        .await?
+        .ok_or(TimetravelError::Rpc {
+            error: format!("Header of finalized block {:?} not found.", finalized),
+        })?
+        .number)
+}
+
+/// Returns the index of the active era at block `at`, if any.
+pub(crate) async fn active_era_at(
+    rpc: &SharedRpcClient,
+    at: Hash,
+) -> Result<Option<sp_staking::EraIndex>, anyhow::Error> {
+    use codec::Decode;
+
+    let key = sp_core::storage::StorageKey([twox_128(b"Staking"), twox_128(b"ActiveEra")].concat());
+
+    // the era index is the first field of the `ActiveEraInfo`.
+    rpc.storage(&key, Some(at))
+        .await?
+        .map(|value| sp_staking::EraIndex::decode(&mut &value.0[..]))
+        .transpose()
+        .map_err(|e| {
+            TimetravelError::Decode {
+                error: format!("active era at {:?}: {:?}", at, e),
+            }
+            .into()
+        })
+}
+
+/// Returns the hashes of the first block of each era in `eras`, found through binary search over
+/// the active era of the blocks up to the latest finalized block. The eras which did not start
+/// yet are skipped.
+pub(crate) async fn era_start_blocks(
+    rpc: &SharedRpcClient,
+    eras: std::ops::RangeInclusive<sp_staking::EraIndex>,
+) -> Result<Vec<H256>, anyhow::Error> {
+    let to = finalized_number(rpc).await?;
+    let mut from = 0;
+    let mut block_hashes = vec![];
+
+    for era in eras {
+        let found = crate::utils::find_first(from, to, |number| {
+            let rpc = rpc.clone();
+            async move {
+                let hash = block_hash_of(&rpc, number).await?;
+                Ok(active_era_at(&rpc, hash)
+                    .await?
+                    .map_or(false, |active| active >= era))
+            }
+        })
+        .await?;
+
+        match found {
+            Some(block_number) => {
+                let block_hash = block_hash_of(rpc, block_number).await?;
+                log::info!(
+                    target: LOG_TARGET,
+                    "Era {} starts at block #{} ({:?}).",
+                    era,
+                    block_number,
+                    block_hash
+                );
+
+                block_hashes.push(block_hash);
+                from = block_number;
+            }
+            None => {
+                log::warn!(target: LOG_TARGET, "Era {} did not start by block #{}, skipping.", era, to);
+                break;
+            }
+        }
+    }
+
+    Ok(block_hashes)
+}
+
+/// Returns the timestamp (in milliseconds) of block `at`, as per `Timestamp::Now`.
+pub(crate) async fn timestamp_at(
+    rpc: &SharedRpcClient,
+    at: Hash,
+) -> Result<Option<u64>, anyhow::Error> {
+    use codec::Decode;
+
+    let key = sp_core::storage::StorageKey([twox_128(b"Timestamp"), twox_128(b"Now")].concat());
+
+    rpc.storage(&key, Some(at))
+        .await?
+        .map(|value| u64::decode(&mut &value.0[..]))
+        .transpose()
+        .map_err(|e| {
+            TimetravelError::Decode {
+                error: format!("timestamp at {:?}: {:?}", at, e),
+            }
+            .into()
+        })
+}
+
+/// Returns the hashes of the first block at or after each of the `timestamps` (in milliseconds),
+/// found through binary search over the timestamps of the blocks up to the latest finalized
+/// block. The timestamps past the latest finalized block are skipped, and so are the timestamps
+/// which resolve to the same block as the previous one.
+pub(crate) async fn calendar_blocks(
+    rpc: &SharedRpcClient,
+    timestamps: Vec<u64>,
+) -> Result<Vec<H256>, anyhow::Error> {
+    let to = finalized_number(rpc).await?;
+    let mut from = 0;
+    let mut block_hashes: Vec<H256> = vec![];
+
+    for timestamp in timestamps {
+        let found = crate::utils::find_first(from, to, |number| {
+            let rpc = rpc.clone();
+            async move {
+                let hash = block_hash_of(&rpc, number).await?;
+                Ok(timestamp_at(&rpc, hash)
+                    .await?
+                    .map_or(false, |now| now >= timestamp))
+            }
+        })
+        .await?;
+
+        match found {
+            Some(block_number) => {
+                let block_hash = block_hash_of(rpc, block_number).await?;
+                log::info!(
+                    target: LOG_TARGET,
+                    "Timestamp {} resolves to block #{} ({:?}).",
+                    timestamp,
+                    block_number,
+                    block_hash
+                );
+
+                if block_hashes.last() != Some(&block_hash) {
+                    block_hashes.push(block_hash);
+                }
+                from = block_number;
+            }
+            None => {
+                log::warn!(target: LOG_TARGET, "Timestamp {} is past block #{}, skipping.", timestamp, to);
+                break;
+            }
+        }
+    }
+
+    Ok(block_hashes)
+}
+
+/// Resolves the blocks selected by block hashes, block numbers, eras or calendar timestamps, in
+/// this order of precedence. Returns `None` if no block is selected.
+pub(crate) async fn select_blocks(
+    rpc: &SharedRpcClient,
+    block_hashes: Option<Vec<H256>>,
+    block_numbers: Option<Vec<BlockNumber>>,
+    eras: Option<std::ops::RangeInclusive<sp_staking::EraIndex>>,
+    timestamps: Option<Vec<u64>>,
+) -> Result<Option<Vec<H256>>, anyhow::Error> {
+    if let Some(block_hashes) = block_hashes {
+        return Ok(Some(block_hashes));
+    }
+
+    if let Some(block_numbers) = block_numbers {
+        let mut block_hashes = vec![];
+        for number in block_numbers {
+            let block_hash = block_hash_of(rpc, number).await?;
+            log::info!(target: LOG_TARGET, "Block #{} is {:?}.", number, block_hash);
+            block_hashes.push(block_hash);
+        }
+        return Ok(Some(block_hashes));
+    }
+
+    if let Some(eras) = eras {
+        return era_start_blocks(rpc, eras).await.map(Some);
+    }
+
+    match timestamps {
+        Some(timestamps) => calendar_blocks(rpc, timestamps).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Completes the selection of an operation over a pair of blocks when only one block is selected,
+/// adding the block it is paired with. The blocks are returned in chronological order.
+pub(crate) async fn pair_blocks(
+    rpc: &SharedRpcClient,
+    block_hashes: Vec<H256>,
+    pairing: Pairing,
+) -> Result<Vec<H256>, anyhow::Error> {
+    let block_hash = match block_hashes.as_slice() {
+        [block_hash] => *block_hash,
+        _ => return Ok(block_hashes),
+    };
+
+    let paired = match pairing {
+        Pairing::Parent => vec![
+            rpc.header(Some(block_hash))
+                .await?
+                .ok_or(TimetravelError::Rpc {
+                    error: format!("Header of block {:?} not found.", block_hash),
+                })?
+                .parent_hash,
+            block_hash,
+        ],
+        Pairing::NextEra => {
+            let era = active_era_at(rpc, block_hash)
+                .await?
+                .ok_or(TimetravelError::missing("Active era"))?;
+            let next = era_start_blocks(rpc, era + 1..=era + 1)
+                .await?
+                .pop()
+                .ok_or(TimetravelError::Rpc {
+                    error: format!("Era {} did not start yet.", era + 1),
+                })?;
+            vec![block_hash, next]
+        }
+    };
+
+    log::info!(target: LOG_TARGET, "Paired block {:?} as {:?}: {:?}.", block_hash, pairing, paired);
+
+    Ok(paired)
+}
+
+/// Scans the blocks in `[from, to]` and stores the block at which each runtime version activated,
+/// i.e. the first block in the range whose state has a new runtime code. The first entry is the
+/// runtime version at `from`.
+///
+/// The runtime versions are fetched through binary search over the range, so the number of RPC
+/// queries is logarithmic in the size of the range.
+pub(crate) async fn runtime_upgrades_cmd(
+    rpc: SharedRpcClient,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    let to = match to {
+        Some(to) => to,
+        None => finalized_number(&rpc).await?,
+    };
+
+    if from > to {
+        return Err(TimetravelError::Unsupported {
+            reason: format!("Invalid range of blocks #{}..#{}.", from, to),
+        }
+        .into());
+    }
+
+    log::info!(target: LOG_TARGET, "Scanning runtime upgrades in blocks #{}..#{}.", from, to);
+
+    // the hashes of the queried blocks, so that the ones of the upgrades are not fetched again.
+    let hashes = std::cell::RefCell::new(std::collections::BTreeMap::new());
+
+    let upgrades = crate::utils::find_value_changes(from, to, |number| {
+        let rpc = rpc.clone();
+        let hashes = &hashes;
+        async move {
+            let hash = block_hash_of(&rpc, number).await?;
+            hashes.borrow_mut().insert(number, hash);
+            let version = rpc.runtime_version(Some(hash)).await?;
+            Ok((version.spec_name.to_string(), version.spec_version))
+        }
+    })
+    .await?;
+    let hashes = hashes.into_inner();
+
+    for (block_number, (spec_name, spec_version)) in upgrades {
+        log::info!(
+            target: LOG_TARGET,
+            "Runtime {} v{} active at block #{}.",
+            spec_name,
+            spec_version,
+            block_number
+        );
+
+        let csv_entry = RuntimeUpgradeCsv {
+            block_number,
+            block_hash: format!("{:?}", hashes[&block_number]),
+            spec_name,
+            spec_version,
+        };
+        crate::output::write(csv_entry, &output_path)?;
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `bisect` command result.
+#[derive(Debug, Serialize)]
+pub(crate) struct BisectCsv {
+    key: String,
+    block_number: BlockNumber,
+    block_hash: String,
+    value: Option<String>,
+}
+
+/// Binary searches the blocks in `[from, to]` for the first block at which the value of the
+/// storage `key` satisfies `predicate`, and stores it.
+///
+/// The predicate is expected to be monotonic over the range, i.e. once it holds at some block, it
+/// holds for all the following blocks.
+pub(crate) async fn bisect_cmd(
+    rpc: SharedRpcClient,
+    key: Bytes,
+    predicate: StoragePredicate,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    let to = match to {
+        Some(to) => to,
+        None => finalized_number(&rpc).await?,
+    };
+
+    if from > to {
+        return Err(TimetravelError::Unsupported {
+            reason: format!("Invalid range of blocks #{}..#{}.", from, to),
+        }
+        .into());
+    }
+
+    let key = sp_core::storage::StorageKey(key.0);
+    log::info!(
+        target: LOG_TARGET,
+        "Bisecting blocks #{}..#{} for key {:?} with predicate {:?}.",
+        from,
+        to,
+        key,
+        predicate
+    );
+
+    let found = crate::utils::find_first(from, to, |number| {
+        let (rpc, key, predicate) = (rpc.clone(), key.clone(), predicate.clone());
+        async move {
+            let hash = block_hash_of(&rpc, number).await?;
+            let value = rpc.storage(&key, Some(hash)).await?;
+            let holds = predicate.holds(value.as_ref().map(|v| &v.0[..]));
+            log::debug!(target: LOG_TARGET, "Predicate at block #{}: {}.", number, holds);
+            Ok(holds)
+        }
+    })
+    .await?;
+
+    match found {
+        Some(block_number) => {
+            let block_hash = block_hash_of(&rpc, block_number).await?;
+            let value = rpc.storage(&key, Some(block_hash)).await?;
+
+            log::info!(target: LOG_TARGET, "Predicate first holds at block #{}.", block_number);
+
+            let csv_entry = BisectCsv {
+                key: sp_core::bytes::to_hex(&key.0, false),
+                block_number,
+                block_hash: format!("{:?}", block_hash),
+                value: value.map(|v| sp_core::bytes::to_hex(&v.0, false)),
+            };
+            crate::output::write(csv_entry, &output_path)?;
+        }
+        None => log::warn!(
+            target: LOG_TARGET,
+            "Predicate does not hold at the end of the range (block #{}).",
+            to
+        ),
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `estimate` command result, one entry per pallet.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct EstimateCsv {
+    block_hash: String,
+    pallet: String,
+    estimated_keys: u64,
+    estimated_bytes: u64,
+    estimated_seconds: f64,
+}
+
+/// Estimates the number of keys, bytes and extraction time of each pallet at block `at`.
+///
+/// For each storage item of a pallet, the first `sample_size` keys are fetched, together with a
+/// subset of their values. The number of keys is extrapolated from the position of the last
+/// sampled key in the (hashed) key space, the number of bytes from the average size of the sampled
+/// entries and the extraction time from the latency of the sampling queries. The estimations are
+/// biased for storage items whose keys are not hashed.
+pub(crate) async fn estimate_cmd(
+    rpc: SharedRpcClient,
+    at: Option<H256>,
+    pallets: Vec<String>,
+    sample_size: u32,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    use sp_core::storage::StorageKey;
+    use std::time::Instant;
+
+    const MAX_VALUES_SAMPLE: usize = 100;
+
+    let at = match at {
+        Some(at) => at,
+        None => rpc.finalized_head().await?,
+    };
+    let metadata = crate::gadgets::metadata::fetch_metadata(&rpc, at).await?;
+
+    let (mut total_keys, mut total_bytes, mut total_seconds) = (0, 0, 0.0);
+
+    for pallet in pallets {
+        let storage = match metadata
+            .pallets
+            .iter()
+            .find(|p| p.name == pallet)
+            .and_then(|p| p.storage.as_ref())
+        {
+            Some(storage) => storage,
+            None => {
+                log::warn!(target: LOG_TARGET, "Pallet {} has no storage at {:?}, skipping.", pallet, at);
+                continue;
+            }
+        };
+
+        let mut entry = EstimateCsv {
+            block_hash: format!("{:?}", at),
+            pallet: pallet.clone(),
+            ..Default::default()
+        };
+
+        for item in storage.entries.iter() {
+            let prefix = [
+                twox_128(storage.prefix.as_bytes()),
+                twox_128(item.name.as_bytes()),
+            ]
+            .concat();
+
+            let now = Instant::now();
+            let keys = rpc
+                .storage_keys_paged(
+                    Some(StorageKey(prefix.clone())),
+                    sample_size,
+                    None,
+                    Some(at),
+                )
+                .await?;
+            let keys_latency = now.elapsed().as_secs_f64();
+
+            if keys.is_empty() {
+                continue;
+            }
+
+            let sampled_keys = keys
+                .iter()
+                .take(MAX_VALUES_SAMPLE)
+                .cloned()
+                .collect::<Vec<_>>();
+            let now = Instant::now();
+            let values = rpc.query_storage_at(sampled_keys.clone(), Some(at)).await?;
+            let values_latency = now.elapsed().as_secs_f64();
+
+            let sampled_bytes = values
+                .iter()
+                .flat_map(|set| set.changes.iter())
+                .map(|(k, v)| k.0.len() + v.as_ref().map_or(0, |v| v.0.len()))
+                .sum::<usize>();
+            let avg_entry_bytes = sampled_bytes as f64 / sampled_keys.len() as f64;
+
+            let last_suffix = &keys.last().expect("keys is not empty; qed.").0[prefix.len()..];
+            let estimated_keys =
+                crate::utils::estimate_key_count(keys.len() as u32, sample_size, last_suffix);
+
+            log::debug!(
+                target: LOG_TARGET,
+                "{}::{}: ~{} keys, ~{} bytes per entry.",
+                pallet,
+                item.name,
+                estimated_keys.round(),
+                avg_entry_bytes.round(),
+            );
+
+            entry.estimated_keys += estimated_keys.round() as u64;
+            entry.estimated_bytes += (estimated_keys * avg_entry_bytes).round() as u64;
+            entry.estimated_seconds += estimated_keys
+                * (keys_latency / keys.len() as f64 + values_latency / sampled_keys.len() as f64);
+        }
+
+        log::info!(
+            target: LOG_TARGET,
+            "Pallet {}: ~{} keys, ~{} bytes, ~{:.0}s to extract.",
+            entry.pallet,
+            entry.estimated_keys,
+            entry.estimated_bytes,
+            entry.estimated_seconds,
+        );
+
+        total_keys += entry.estimated_keys;
+        total_bytes += entry.estimated_bytes;
+        total_seconds += entry.estimated_seconds;
+
+        crate::output::write(entry, &output_path)?;
+    }
+
+    log::info!(
+        target: LOG_TARGET,
+        "Estimate at {:?}: ~{} keys, ~{} bytes, ~{:.0}s to extract.",
+        at,
+        total_keys,
+        total_bytes,
+        total_seconds,
+    );
+
+    Ok(())
+}
+
+/// The CSV representation of the `lookup-keys` command result, one entry per storage key.
+#[derive(Debug, Serialize)]
+pub(crate) struct LookupKeyCsv {
+    key: String,
+    pallet: Option<String>,
+    item: Option<String>,
+    map_keys: String,
+}
+
+/// Identifies the pallet and storage item of each of the storage `keys` using the metadata at
+/// block `at` and decodes their map keys where the hashers permit.
+pub(crate) async fn lookup_keys_cmd(
+    rpc: SharedRpcClient,
+    at: Option<H256>,
+    keys: Vec<Bytes>,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    let at = match at {
+        Some(at) => at,
+        None => rpc.finalized_head().await?,
+    };
+    let metadata = crate::gadgets::metadata::fetch_metadata(&rpc, at).await?;
+    let index = crate::gadgets::metadata::StorageKeyIndex::new(&metadata);
+
+    for key in keys {
+        let info = index.lookup(&key);
+        if info.is_none() {
+            log::warn!(target: LOG_TARGET, "Unknown storage key {:?}.", key);
+        }
+
+        crate::output::write(
+            LookupKeyCsv {
+                key: sp_core::bytes::to_hex(&key, false),
+                pallet: info.as_ref().map(|i| i.pallet.clone()),
+                item: info.as_ref().map(|i| i.item.clone()),
+                map_keys: info.map(|i| i.map_keys.join(";")).unwrap_or_default(),
+            },
+            &output_path,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `prove` command result, one entry per proven storage key.
+#[derive(Debug, Serialize)]
+pub(crate) struct ProveCsv {
+    block_number: u32,
+    block_hash: String,
+    state_root: String,
+    /// The account the storage item is proven for, empty for plain storage items and raw keys.
+    account: Option<String>,
+    pallet: Option<String>,
+    item: Option<String>,
+    key: String,
+    /// The hex encoded value, empty if the key is proven not to be set.
+    value: Option<String>,
+    /// The value decoded with the on-chain metadata, empty for raw keys.
+    decoded: Option<String>,
+}
+
+/// A proven storage value.
+#[derive(Debug, Serialize)]
+pub(crate) struct ProvenValue {
+    key: Bytes,
+    /// `None` if the key is proven not to be set.
+    value: Option<Bytes>,
+}
+
+/// A read proof stored by the `prove` command, along with the values it proves.
+#[derive(Debug, Serialize)]
+pub(crate) struct StoredProof {
+    block_number: BlockNumber,
+    block_hash: Hash,
+    state_root: Hash,
+    values: Vec<ProvenValue>,
+    /// The trie nodes of the proof.
+    proof: Vec<Bytes>,
+}
+
+/// Checks the read `proof` against the `state_root`, and returns the proven value of each of the
+/// `keys`.
+pub(crate) fn verify_proof(
+    state_root: Hash,
+    proof: &[Bytes],
+    keys: &[Vec<u8>],
+) -> Result<Vec<Option<Vec<u8>>>, anyhow::Error> {
+    let proof = sp_trie::StorageProof::new(proof.iter().map(|node| node.0.clone()));
+    let values = sp_state_machine::read_proof_check::<sp_runtime::traits::BlakeTwo256, _>(
+        state_root, proof, keys,
+    )
+    .map_err(|e| TimetravelError::Rpc {
+        error: format!("invalid read proof: {:?}", e),
+    })?;
+
+    Ok(keys
+        .iter()
+        .map(|key| values.get(key).cloned().flatten())
+        .collect())
+}
+
+/// Fetches the read proof of the storage keys of `config` at a block, checks it against the state
+/// root of the block and stores it along with the proven values. The keys are given raw, or as
+/// storage items under the metadata of the block.
+pub(crate) async fn prove_cmd(
+    rpc: SharedRpcClient,
+    config: crate::configs::ProveConfig,
+    snapshot_path: &str,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    use crate::gadgets::{dynamic::DynamicStorage, metadata::fetch_metadata};
+    use crate::operations::{item_keys, parse_accounts, parse_items};
+    use sp_core::storage::StorageKey;
+
+    let accounts = parse_accounts(&config.accounts)?;
+    let items = parse_items(&config.items)?;
+    if config.keys.is_empty() && items.is_empty() {
+        return Err(TimetravelError::Unsupported {
+            reason: "expected a `--key` or an `--item` to prove.".to_string(),
+        }
+        .into());
+    }
+
+    let at = match config.bn {
+        Some(at) => at,
+        None => rpc.finalized_head().await?,
+    };
+    let header = rpc.header(Some(at)).await?.ok_or(TimetravelError::Rpc {
+        error: format!("Header of block {:?} not found.", at),
+    })?;
+
+    // the proven keys, as (account, (pallet, item), key).
+    let mut keys = config
+        .keys
+        .iter()
+        .map(|key| (None, None, StorageKey(key.0.clone())))
+        .collect::<Vec<_>>();
+    let metadata = match items.is_empty() {
+        true => None,
+        false => Some(fetch_metadata(&rpc, at).await?),
+    };
+    if let Some(metadata) = metadata.as_ref() {
+        keys.extend(
+            item_keys(&DynamicStorage::new(metadata), &items, &accounts)?
+                .into_iter()
+                .map(|(account, pallet, item, key)| (account, Some((pallet, item)), key)),
+        );
+    }
+
+    let read_proof = rpc
+        .read_proof(
+            keys.iter().map(|(_, _, key)| key.clone()).collect(),
+            Some(at),
+        )
+        .await?;
+    let values = verify_proof(
+        header.state_root,
+        &read_proof.proof,
+        &keys
+            .iter()
+            .map(|(_, _, key)| key.0.clone())
+            .collect::<Vec<_>>(),
+    )?;
+    log::info!(
+        target: LOG_TARGET,
+        "Proved {} keys at block #{} with {} trie nodes.",
+        keys.len(),
+        header.number,
+        read_proof.proof.len(),
+    );
+
+    let proof_path = config
+        .proof_path
+        .unwrap_or_else(|| format!("{}/{}.proof.json", snapshot_path, at));
+    let stored = StoredProof {
+        block_number: header.number,
+        block_hash: at,
+        state_root: header.state_root,
+        values: keys
+            .iter()
+            .zip(values.iter())
+            .map(|((_, _, key), value)| ProvenValue {
+                key: Bytes(key.0.clone()),
+                value: value.clone().map(Bytes),
+            })
+            .collect(),
+        proof: read_proof.proof,
+    };
+    std::fs::write(&proof_path, serde_json::to_string_pretty(&stored)?)?;
+    log::info!(target: LOG_TARGET, "Proof stored in {}.", proof_path);
+
+    let mut out = crate::output::Emitter::new(output_path);
+    for ((account, item, key), value) in keys.iter().zip(values.into_iter()) {
+        let decoded = match (item, metadata.as_ref()) {
+            (Some((pallet, item)), Some(metadata)) => DynamicStorage::new(metadata)
+                .decode_stored(pallet, item, value.as_deref())?
+                .map(|v| v.to_string()),
+            _ => None,
+        };
+
+        out.emit(ProveCsv {
+            block_number: header.number,
+            block_hash: format!("{:?}", at),
+            state_root: format!("{:?}", header.state_root),
+            account: account.map(|a| a.to_string()),
+            pallet: item.map(|(pallet, _)| pallet.to_string()),
+            item: item.map(|(_, item)| item.to_string()),
+            key: sp_core::bytes::to_hex(&key.0, false),
+            value: value.map(|v| sp_core::bytes::to_hex(&v, false)),
+            decoded,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `storage-layout` command result, one entry per changed storage
+/// item.
+#[derive(Debug, Serialize)]
+pub(crate) struct StorageLayoutCsv {
+    from: String,
+    to: String,
+    pallet: String,
+    item: String,
+    change: String,
+    details: String,
+}
+
+/// Compares the storage layout of the runtimes at blocks `from` and `to` and outputs the storage
+/// items which were added, removed or retyped.
+pub(crate) async fn storage_layout_cmd(
+    rpc: SharedRpcClient,
+    from: H256,
+    to: Option<H256>,
+    output_path: String,
+) -> Result<(), anyhow::Error> {
+    use crate::gadgets::metadata::{fetch_metadata, storage_layout_changes};
+
+    let to = match to {
+        Some(to) => to,
+        None => rpc.finalized_head().await?,
+    };
+
+    let changes = storage_layout_changes(
+        &fetch_metadata(&rpc, from).await?,
+        &fetch_metadata(&rpc, to).await?,
+    );
+
+    log::info!(
+        target: LOG_TARGET,
+        "{} storage items changed between {:?} and {:?}.",
+        changes.len(),
+        from,
+        to
+    );
+
+    for change in changes {
+        crate::output::write(
+            StorageLayoutCsv {
+                from: format!("{:?}", from),
+                to: format!("{:?}", to),
+                pallet: change.pallet,
+                item: change.item,
+                change: format!("{:?}", change.kind).to_lowercase(),
+                details: change.details.join(";"),
+            },
+            &output_path,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// A snapshot stored in the snapshot path.
+#[derive(Debug, Clone)]
+pub(crate) struct StoredSnapshot {
+    pub path: std::path::PathBuf,
+    /// The block of the snapshot, as in its file name.
+    pub block: String,
+    /// Whether the snapshot is an anonymized copy (see the `anonymize` operation).
+    pub anonymized: bool,
+    /// The block of the base snapshot of a delta snapshot, as in its file name.
+    pub base: Option<String>,
+    pub size: u64,
+    pub modified: std::time::SystemTime,
+}
+
+/// Parses the file name of a stored snapshot, `<block_hash>.data` or `<block_hash>.data.anon`,
+/// into its block and whether it is anonymized.
+pub(crate) fn parse_snapshot_name(file_name: &str) -> Option<(String, bool)> {
+    let (stem, anonymized) = match file_name.strip_suffix(".anon") {
+        Some(stem) => (stem, true),
+        None => (file_name, false),
+    };
+
+    stem.strip_suffix(".data")
+        .filter(|block| block.starts_with("0x"))
+        .map(|block| (block.to_string(), anonymized))
+}
+
+/// Returns the snapshots stored in `snapshot_dir`, sorted by age (oldest first).
+pub(crate) fn stored_snapshots(snapshot_dir: &str) -> Result<Vec<StoredSnapshot>, anyhow::Error> {
+    let mut snapshots = vec![];
+    for entry in std::fs::read_dir(snapshot_dir)? {
+        let entry = entry?;
+        let Some((block, anonymized)) = entry.file_name().to_str().and_then(parse_snapshot_name)
+        else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        let base = crate::scraper::delta_base(&entry.path())?.map(|base| format!("{}", base));
+
+        snapshots.push(StoredSnapshot {
+            path: entry.path(),
+            block,
+            anonymized,
+            base,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        });
+    }
+
+    snapshots.sort_by_key(|s| s.modified);
+    Ok(snapshots)
+}
+
+/// Returns the age of a snapshot in days, as per its last modification.
+fn age_in_days(snapshot: &StoredSnapshot) -> f64 {
+    snapshot
+        .modified
+        .elapsed()
+        .unwrap_or_default()
+        .as_secs_f64()
+        / (24.0 * 60.0 * 60.0)
+}
+
+/// Lists the snapshots stored in `snapshot_dir`, with their size and age.
+pub(crate) fn snapshot_list_cmd(snapshot_dir: &str) -> Result<(), anyhow::Error> {
+    let snapshots = stored_snapshots(snapshot_dir)?;
+
+    println!(
+        "{:<40} {:>10} {:>14} {:>10}",
+        "block", "anonymized", "bytes", "age (d)"
+    );
+    for snapshot in snapshots.iter() {
+        println!(
+            "{:<40} {:>10} {:>14} {:>10.1}",
+            snapshot.block,
+            snapshot.anonymized,
+            snapshot.size,
+            age_in_days(snapshot),
+        );
+    }
+    println!(
+        "{} snapshots, {} bytes in {}",
+        snapshots.len(),
+        snapshots.iter().map(|s| s.size).sum::<u64>(),
+        snapshot_dir,
+    );
+
+    Ok(())
+}
+
+/// Returns the delta snapshots which are not `removed` but whose base snapshot is.
+pub(crate) fn dependent_deltas<'a>(
+    snapshots: &'a [StoredSnapshot],
+    removed: &[StoredSnapshot],
+) -> Vec<&'a StoredSnapshot> {
+    let removed_bases = removed
+        .iter()
+        .filter(|s| !s.anonymized)
+        .map(|s| s.block.as_str())
+        .collect::<std::collections::BTreeSet<_>>();
+    let removed_paths = removed
+        .iter()
+        .map(|s| &s.path)
+        .collect::<std::collections::BTreeSet<_>>();
+
+    snapshots
+        .iter()
+        .filter(|s| !removed_paths.contains(&s.path))
+        .filter(|s| {
+            s.base
+                .as_deref()
+                .map_or(false, |base| removed_bases.contains(base))
+        })
+        .collect()
+}
+
+/// Removes the snapshots stored in `snapshot_dir` which were last modified more than
+/// `older_than` ago. With `dry_run`, the snapshots are only listed.
+///
+/// The base snapshots of the delta snapshots which are kept are kept as well, unless `cascade`,
+/// in which case the delta snapshots are removed along with their base.
+pub(crate) fn snapshot_prune_cmd(
+    snapshot_dir: &str,
+    older_than: std::time::Duration,
+    dry_run: bool,
+    cascade: bool,
+) -> Result<(), anyhow::Error> {
+    let snapshots = stored_snapshots(snapshot_dir)?;
+    let mut pruned = snapshots
+        .iter()
+        .filter(|s| s.modified.elapsed().unwrap_or_default() > older_than)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let dependents = dependent_deltas(&snapshots, &pruned);
+    if cascade {
+        pruned.extend(dependents.into_iter().cloned());
+    } else if !dependents.is_empty() {
+        let bases = dependents
+            .iter()
+            .filter_map(|s| s.base.clone())
+            .collect::<std::collections::BTreeSet<_>>();
+        log::warn!(
+            target: LOG_TARGET,
+            "Keeping the snapshots of blocks {:?}, the base of {} delta snapshots; prune with --cascade to remove them along with their deltas.",
+            bases,
+            dependents.len(),
+        );
+        pruned.retain(|s| s.anonymized || !bases.contains(&s.block));
+    }
+
+    for snapshot in pruned.iter() {
+        if !dry_run {
+            std::fs::remove_file(&snapshot.path)?;
+        }
+        println!(
+            "{} {} ({} bytes, {:.1} days old)",
+            if dry_run { "would remove" } else { "removed" },
+            snapshot.path.display(),
+            snapshot.size,
+            age_in_days(snapshot),
+        );
+    }
+
+    log::info!(
+        target: LOG_TARGET,
+        "Pruned {} snapshots, {} bytes{}.",
+        pruned.len(),
+        pruned.iter().map(|s| s.size).sum::<u64>(),
+        if dry_run { " (dry run)" } else { "" },
+    );
+
+    Ok(())
+}
+
+/// Removes the snapshots of `blocks` stored in `snapshot_dir`, along with their anonymized
+/// versions.
+///
+/// Removing the base snapshot of delta snapshots fails, unless `cascade`, in which case the delta
+/// snapshots are removed as well.
+pub(crate) fn snapshot_rm_cmd(
+    snapshot_dir: &str,
+    blocks: &[H256],
+    cascade: bool,
+) -> Result<(), anyhow::Error> {
+    // the snapshots are named after the (`Display`) block hash, as in `extract`.
+    let names = blocks.iter().map(|b| format!("{}", b)).collect::<Vec<_>>();
+
+    let snapshots = stored_snapshots(snapshot_dir)?;
+    let mut removed = snapshots
+        .iter()
+        .filter(|s| names.contains(&s.block))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let dependents = dependent_deltas(&snapshots, &removed);
+    if !dependents.is_empty() && !cascade {
+        return Err(TimetravelError::Snapshot {
+            error: format!(
+                "the snapshots of blocks {:?} are the base of the delta snapshots of blocks {:?}; remove them with --cascade.",
+                names,
+                dependents.iter().map(|s| &s.block).collect::<Vec<_>>(),
+            ),
+        }
+        .into());
+    }
+    removed.extend(dependents.into_iter().cloned());
+    for snapshot in removed.iter() {
+        std::fs::remove_file(&snapshot.path)?;
+        println!(
+            "removed {} ({} bytes)",
+            snapshot.path.display(),
+            snapshot.size
+        );
+    }
+
+    if removed.is_empty() {
+        log::warn!(target: LOG_TARGET, "No snapshots of blocks {:?} in {}.", blocks, snapshot_dir);
+    }
+
+    Ok(())
+}
+
+/// Returns the next nonce of `account` at block `at`, as per its `System::Account` info.
+async fn account_nonce(
+    rpc: &SharedRpcClient,
+    account: &sp_core::crypto::AccountId32,
+    at: H256,
+) -> Result<u32, anyhow::Error> {
+    use codec::{Decode, Encode};
+
+    let encoded = account.encode();
+    let key = sp_core::storage::StorageKey(
+        [
+            &twox_128(b"System")[..],
+            &twox_128(b"Account"),
+            &sp_core::hashing::blake2_128(&encoded),
+            &encoded,
+        ]
+        .concat(),
+    );
+
+    // the nonce is the first field of the `AccountInfo`, and zero for new accounts.
+    rpc.storage(&key, Some(at))
+        .await?
+        .map_or(Ok(0), |value| u32::decode(&mut &value.0[..]))
+        .map_err(|e| {
+            TimetravelError::Decode {
+                error: format!("nonce of {} at {:?}: {:?}", account, at, e),
+            }
+            .into()
+        })
+}
+
+/// Builds the unsigned extrinsic of `call`, signed by `signer`, at block `at` (or the latest
+/// finalized block) and writes it to `payload_path`, for the payload to be signed offline.
+pub(crate) async fn signing_payload_cmd(
+    rpc: SharedRpcClient,
+    config: crate::configs::SigningPayloadConfig,
+) -> Result<(), anyhow::Error> {
+    use crate::signing::{encode_extensions, signing_payload, ExtensionParams, UnsignedExtrinsic};
+    use sp_runtime::generic::Era;
+
+    let call = match sp_core::bytes::from_hex(config.call.trim()) {
+        Ok(call) => call,
+        Err(_) => sp_core::bytes::from_hex(std::fs::read_to_string(&config.call)?.trim()).map_err(
+            |e| TimetravelError::Decode {
+                error: format!("call in {}: {:?}", config.call, e),
+            },
+        )?,
+    };
+
+    let at = match config.bn {
+        Some(at) => at,
+        None => rpc.finalized_head().await?,
+    };
+    let header = rpc.header(Some(at)).await?.ok_or(TimetravelError::Rpc {
+        error: format!("Header of block {:?} not found.", at),
+    })?;
+    let genesis_hash = block_hash_of(&rpc, 0).await?;
+    let runtime_version = rpc.runtime_version(Some(at)).await?;
+    let metadata = crate::gadgets::metadata::fetch_metadata(&rpc, at).await?;
+
+    let nonce = match config.nonce {
+        Some(nonce) => nonce,
+        None => account_nonce(&rpc, &config.signer, at).await?,
+    };
+    let (era, checkpoint) = match config.mortality {
+        0 => (Era::Immortal, genesis_hash),
+        period => (Era::mortal(period, header.number as u64), at),
+    };
+
+    let params = ExtensionParams {
+        spec_version: runtime_version.spec_version,
+        transaction_version: runtime_version.transaction_version,
+        genesis_hash,
+        checkpoint,
+        era,
+        nonce,
+        tip: config.tip,
+    };
+    let (explicit, implicit) = encode_extensions(&metadata.extensions, &params)?;
+    let payload = signing_payload(&call, &explicit, &implicit);
+
+    let unsigned = UnsignedExtrinsic {
+        at,
+        spec_version: runtime_version.spec_version,
+        signer: config.signer,
+        nonce,
+        tip: config.tip,
+        call: sp_core::bytes::to_hex(&call, false),
+        explicit: sp_core::bytes::to_hex(&explicit, false),
+        payload: sp_core::bytes::to_hex(&payload, false),
+    };
+    std::fs::write(&config.payload_path, serde_json::to_vec_pretty(&unsigned)?)?;
+
+    log::info!(
+        target: LOG_TARGET,
+        "Unsigned extrinsic of {} (nonce {}, {:?}) at #{} stored in {}, payload to sign: {}",
+        unsigned.signer,
+        nonce,
+        era,
+        header.number,
+        config.payload_path,
+        unsigned.payload,
+    );
+
+    Ok(())
+}
+
+/// Assembles the unsigned extrinsic stored at `payload_path` with its offline `signature` and
+/// broadcasts it, waiting until it is included in a block (or finalized).
+pub(crate) async fn submit_signed_cmd(
+    rpc: SharedRpcClient,
+    config: crate::configs::SubmitSignedConfig,
+) -> Result<(), anyhow::Error> {
+    use sc_transaction_pool_api::TransactionStatus;
+
+    let unsigned: crate::signing::UnsignedExtrinsic =
+        serde_json::from_slice(&std::fs::read(&config.payload_path)?)?;
+
+    let spec_version = rpc.runtime_version(None).await?.spec_version;
+    if spec_version != unsigned.spec_version {
+        crate::warnings::warn_run(
+            "runtime_upgraded",
+            format!(
+                "the runtime was upgraded from spec version {} to {} since the payload was built, the extrinsic is expected to be invalid.",
+                unsigned.spec_version, spec_version
+            ),
+        );
+    }
+
+    let signature = crate::signing::multi_signature(config.scheme, &config.signature.0)?;
+    let extrinsic = Bytes(unsigned.sign(signature)?);
+
+    log::info!(
+        target: LOG_TARGET,
+        "Submitting extrinsic of {} bytes signed by {}.",
+        extrinsic.0.len(),
+        unsigned.signer
+    );
+
+    let mut subscription = rpc.watch_extrinsic(&extrinsic).await?;
+    while let Some(status) = subscription.next().await {
+        let status = status?;
+        log::info!(target: LOG_TARGET, "Extrinsic status: {:?}", status);
+
+        match status {
+            TransactionStatus::InBlock(..) if !config.finalized => return Ok(()),
+            TransactionStatus::Finalized(..) => return Ok(()),
+            TransactionStatus::Invalid
+            | TransactionStatus::Dropped
+            | TransactionStatus::Usurped(_)
+            | TransactionStatus::FinalityTimeout(_) => {
+                return Err(TimetravelError::Rpc {
+                    error: format!("extrinsic not included: {:?}", status),
+                }
+                .into())
+            }
+            _ => (),
+        }
+    }
+
+    Err(TimetravelError::Rpc {
+        error: "extrinsic subscription closed before its inclusion.".to_string(),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_proof_works() {
+        let mut ext = Ext::default();
+        ext.insert(b"alice".to_vec(), b"stake".to_vec());
+        ext.insert(b"bob".to_vec(), b"other stake".to_vec());
+
+        let backend = ext.as_backend();
+        let state_root = *backend.root();
+        let proof = sp_state_machine::prove_read(backend, [b"alice".as_slice(), b"carol"])
+            .unwrap()
+            .into_iter_nodes()
+            .map(Bytes)
+            .collect::<Vec<_>>();
+
+        let keys = vec![b"alice".to_vec(), b"carol".to_vec()];
+        assert_eq!(
+            verify_proof(state_root, &proof, &keys).unwrap(),
+            vec![Some(b"stake".to_vec()), None]
+        );
+        // the proof does not hold against another state root.
+        assert!(verify_proof(Hash::repeat_byte(1), &proof, &keys).is_err());
+    }
+
+    #[test]
+    fn parse_snapshot_name_works() {
+        assert_eq!(
+            parse_snapshot_name("0x1477…0c74.data"),
+            Some(("0x1477…0c74".to_string(), false))
+        );
+        assert_eq!(
+            parse_snapshot_name("0x1477…0c74.data.anon"),
+            Some(("0x1477…0c74".to_string(), true))
+        );
+        assert_eq!(parse_snapshot_name("0x1477…0c74.json"), None);
+        assert_eq!(parse_snapshot_name("jobs.yaml.state"), None);
+        assert_eq!(parse_snapshot_name("notes.data"), None);
+    }
+
+    #[test]
+    fn dependent_deltas_works() {
+        let snapshot = |block: &str, anonymized: bool, base: Option<&str>| StoredSnapshot {
+            path: format!("{}.data{}", block, if anonymized { ".anon" } else { "" }).into(),
+            block: block.to_string(),
+            anonymized,
+            base: base.map(String::from),
+            size: 0,
+            modified: std::time::SystemTime::UNIX_EPOCH,
+        };
+        let snapshots = vec![
+            snapshot("0x01", false, None),
+            snapshot("0x01", true, None),
+            snapshot("0x02", false, Some("0x01")),
+            snapshot("0x03", false, Some("0x01")),
+            snapshot("0x04", false, None),
+        ];
+        let blocks = |deltas: Vec<&StoredSnapshot>| {
+            deltas.iter().map(|s| s.block.clone()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            blocks(dependent_deltas(&snapshots, &snapshots[..1])),
+            vec!["0x02", "0x03"]
+        );
+        assert_eq!(
+            blocks(dependent_deltas(&snapshots, &snapshots[..3])),
+            vec!["0x03"]
+        );
+        // the anonymized copy is not the base of the deltas.
+        assert!(dependent_deltas(&snapshots, &snapshots[1..2]).is_empty());
+        assert!(dependent_deltas(&snapshots, &snapshots[4..]).is_empty());
+    }
+
+    #[test]
+    fn is_transient_error_works() {
+        use jsonrpsee::core::Error as RpcError;
+
+        assert!(is_transient_error(&RpcError::RequestTimeout.into()));
+        assert!(is_transient_error(
+            &anyhow::Error::new(RpcError::RestartNeeded("closed".into()))
+                .context("state_getKeysPaged request failed")
+        ));
+        assert!(is_transient_error(
+            &TimetravelError::Rpc {
+                error: "rpc get_keys failed".into()
+            }
+            .into()
+        ));
+        assert!(!is_transient_error(
+            &RpcError::Custom("invalid params".into()).into()
+        ));
+        assert!(!is_transient_error(
+            &TimetravelError::Snapshot {
+                error: "Invalid snapshot version".into()
+            }
+            .into()
+        ));
+        assert!(!is_transient_error(&anyhow::anyhow!("request timeout")));
+    }
+}
@@ -3,9 +3,10 @@
 use super::*;
 use crate::operations::Operation;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use sp_core::H256;
+use sp_core::{Bytes, H256};
+use sp_staking::EraIndex;
 
 /// Clap CLI ops.
 #[derive(Debug, Clone, Parser)]
@@ -21,27 +22,259 @@ pub(crate) struct Opt {
     pub connection_timeout: usize,
 
     /// WS request timeout in number of seconds.
+    ///
+    /// The request timeouts bound the requests of the CLI and of the `raw` engine. The default
+    /// engine downloads the state through its own client, with its own timeouts.
     #[arg(long, default_value_t = 60 * 10)]
     pub request_timeout: usize,
 
-    /// Externalities snapshot path to use.
-    #[arg(long, short, default_value = "./", env = "SNAPSHOT_PATH")]
-    pub snapshot_path: String,
+    /// Per-method request timeout overrides in number of seconds, e.g.
+    /// `--rpc-timeout state_getKeysPaged=1800 --rpc-timeout chain_getBlockHash=10`. Like
+    /// `--request-timeout`, they do not apply to the downloads of the default engine.
+    #[arg(long = "rpc-timeout", value_parser = parse_rpc_timeout, global = true)]
+    pub rpc_timeouts: Vec<(String, u64)>,
 
-    /// File path where to store the output of a tranform operation.
+    /// Externalities snapshot path to use.
     #[arg(
         long,
         short,
-        default_value = "./output.csv",
-        env = "OUTPUT_PATH",
+        default_value = "./",
+        env = "SNAPSHOT_PATH",
         global = true
     )]
-    pub output_path: String,
+    pub snapshot_path: String,
+
+    /// File path where to store the output of a tranform operation. If none is provided, the
+    /// path follows the `--output-layout`.
+    #[arg(long, short, env = "OUTPUT_PATH", global = true)]
+    pub output_path: Option<String>,
+
+    /// Layout of the output paths, with the `{chain}`, `{operation}` and `{month}` placeholders.
+    /// Ignored if an `--output-path` is set.
+    #[arg(long, default_value = crate::output::DEFAULT_LAYOUT, env = "OUTPUT_LAYOUT", global = true)]
+    pub output_layout: String,
+
+    /// Appends the build information of the CLI (version, git commit, features and compiled
+    /// runtimes) and the arguments of the run to a `<output_path>.manifest.jsonl` file.
+    #[arg(long, default_value_t = false, global = true)]
+    pub output_manifest: bool,
+
+    /// Overrides the state version of the externalities. Blocks older than the state version 1
+    /// migration of the chain require `v0`. If none is provided, the state version of the runtime
+    /// at the block (or of the stored snapshot) is used.
+    #[arg(long, value_enum, global = true)]
+    pub state_version: Option<StateVersion>,
+
+    /// Skips the child tries when extracting the externalities, which reduces their memory for
+    /// operations that do not rely on them.
+    #[arg(long, default_value_t = false, global = true)]
+    pub skip_child_trie: bool,
+
+    /// Size in bytes of the trie cache shared by the externalities of a run, which speeds up the
+    /// repeated reads of the operations over large snapshots. No cache is used if none is
+    /// provided.
+    #[arg(long, global = true)]
+    pub trie_cache_size: Option<usize>,
+
+    /// Compresses the stored snapshots with zstd. Compressed snapshots are detected and
+    /// decompressed when loaded, regardless of this flag.
+    #[arg(long, default_value_t = false, global = true)]
+    pub compress: bool,
+
+    /// Engine which scrapes the state of the remote node in the extract phase.
+    #[arg(long, value_enum, default_value_t = Engine::RemoteExternalities, global = true)]
+    pub engine: Engine,
+
+    /// How the storage is decoded: with the `compiled` runtime, or with the on-chain `metadata`
+    /// of each block. Chains without a compiled runtime always use the `metadata`.
+    #[arg(long, value_enum, default_value_t = RuntimeMode::Compiled, global = true)]
+    pub runtime_mode: RuntimeMode,
+
+    /// Number of keys fetched per request by the `raw` engine.
+    #[arg(long, default_value_t = DEFAULT_RAW_PAGE_SIZE, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    pub raw_page_size: u32,
+
+    /// Number of times a failed request of the `raw` engine is retried.
+    #[arg(long, default_value_t = DEFAULT_RAW_RETRIES, global = true)]
+    pub raw_retries: u32,
+
+    /// Number of shards in which the key space of each prefix is split by the `raw` engine. The
+    /// shards are paged concurrently.
+    #[arg(long, default_value_t = DEFAULT_RAW_SHARDS, global = true)]
+    pub raw_shards: u32,
+
+    /// Maximum number of concurrent requests of the `raw` engine, over all the blocks and prefixes
+    /// scraped concurrently.
+    #[arg(long, default_value_t = DEFAULT_RAW_MAX_IN_FLIGHT, global = true)]
+    pub raw_max_in_flight: usize,
+
+    /// Number of blocks extracted concurrently, and of pallets whose keys are paged concurrently
+    /// by the `raw` engine.
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY, global = true)]
+    pub concurrency: usize,
+
+    /// Format of the output of the commands.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv, global = true)]
+    pub format: OutputFormat,
+
+    /// Writes the outputs in long (tidy) format, with one row per metric of each result instead
+    /// of one column per metric.
+    #[arg(long, global = true)]
+    pub long_format: bool,
+
+    /// Labels attached as extra columns to every output row, e.g. `--tag experiment=iter-sweep`.
+    #[arg(long = "tag", value_parser = parse_tag, global = true)]
+    pub tags: Vec<(String, String)>,
+
+    /// Stops a run over a range of blocks once it ran for this long, e.g. `--max-duration 2h`.
+    #[arg(long, value_parser = parse_age, global = true)]
+    pub max_duration: Option<u64>,
+
+    /// Stops a run over a range of blocks once this number of blocks is processed.
+    #[arg(long, global = true)]
+    pub max_blocks: Option<u32>,
+
+    /// Stops a run over a range of blocks once this number of bytes of state is downloaded from
+    /// the remote node.
+    #[arg(long, global = true)]
+    pub max_bytes_downloaded: Option<u64>,
+
+    /// CSV file with the `address` and `label` of known accounts, joined to the accounts of the
+    /// outputs in an extra `account_label` column.
+    #[arg(long, global = true)]
+    pub labels: Option<String>,
 
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// Parses a `key=value` tag.
+fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid tag {:?}, expected key=value", tag)),
+    }
+}
+
+/// Parses a `method=seconds` timeout override.
+fn parse_rpc_timeout(timeout: &str) -> Result<(String, u64), String> {
+    match timeout.split_once('=') {
+        Some((method, secs)) if !method.is_empty() => secs
+            .parse()
+            .map(|secs| (method.to_string(), secs))
+            .map_err(|e| format!("invalid timeout {:?}: {}", timeout, e)),
+        _ => Err(format!(
+            "invalid timeout {:?}, expected method=seconds",
+            timeout
+        )),
+    }
+}
+
+/// Parses an age with a `s`, `m`, `h` or `d` unit (e.g. `30d`) into seconds.
+fn parse_age(age: &str) -> Result<u64, String> {
+    let unit = match age.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid age {:?}, expected e.g. 30d, 12h or 90m",
+                age
+            ))
+        }
+    };
+
+    age[..age.len() - 1]
+        .parse::<u64>()
+        .map(|n| n * unit)
+        .map_err(|e| format!("invalid age {:?}: {}", age, e))
+}
+
+/// Parses an inclusive `a..b` range of eras.
+fn parse_era_range(range: &str) -> Result<(EraIndex, EraIndex), String> {
+    let parsed = range
+        .split_once("..")
+        .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)));
+
+    match parsed {
+        Some((a, b)) if a <= b => Ok((a, b)),
+        _ => Err(format!(
+            "invalid era range {:?}, expected a..b with a <= b",
+            range
+        )),
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date into the unix timestamp in seconds of its start (UTC).
+fn parse_date(date: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid date {:?}, expected YYYY-MM-DD", date);
+
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (
+        parts
+            .next()
+            .and_then(|y| y.parse::<i64>().ok())
+            .ok_or_else(invalid)?,
+        parts
+            .next()
+            .and_then(|m| m.parse::<u32>().ok())
+            .ok_or_else(invalid)?,
+        parts
+            .next()
+            .and_then(|d| d.parse::<u32>().ok())
+            .ok_or_else(invalid)?,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    Ok(crate::output::unix_secs_of(year, month, day))
+}
+
+/// Parses an inclusive `YYYY-MM-DD..YYYY-MM-DD` range of dates into the unix timestamps in
+/// seconds of the start of the first day and of the end of the last day.
+fn parse_date_range(range: &str) -> Result<(i64, i64), String> {
+    let (a, b) = range.split_once("..").ok_or_else(|| {
+        format!(
+            "invalid date range {:?}, expected YYYY-MM-DD..YYYY-MM-DD",
+            range
+        )
+    })?;
+    let (from, to) = (parse_date(a)?, parse_date(b)? + 24 * 60 * 60);
+
+    match from < to {
+        true => Ok((from, to)),
+        false => Err(format!("invalid date range {:?}, expected a <= b", range)),
+    }
+}
+
+/// Returns the timestamps in milliseconds selected by `--every` and `--between`, i.e. every
+/// `every` seconds from the start of the range, before its end.
+pub(crate) fn selected_times(every: Option<u64>, between: Option<(i64, i64)>) -> Option<Vec<u64>> {
+    let (every, (from, to)) = (every?.max(1), between?);
+
+    Some(
+        (from.max(0) as u64..to.max(0) as u64)
+            .step_by(every as usize)
+            .map(|secs| secs * 1_000)
+            .collect(),
+    )
+}
+
+/// Returns the eras selected by `--era` or `--era-range`, if any.
+pub(crate) fn selected_eras(
+    era: Option<EraIndex>,
+    era_range: Option<(EraIndex, EraIndex)>,
+) -> Option<std::ops::RangeInclusive<EraIndex>> {
+    match (era, era_range) {
+        (Some(era), _) => Some(era..=era),
+        (None, Some((a, b))) => Some(a..=b),
+        (None, None) => None,
+    }
+}
+
 /// Commands for `substrate-etc` CLI.
 #[derive(Debug, Clone, Parser)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -52,6 +285,71 @@ pub(crate) enum Command {
 
     /// Loads externality snapshot from disk and applies some operation over the storage items.
     Transform(TransformConfig),
+
+    /// Scans a range of blocks and outputs the block at which each runtime version activated.
+    RuntimeUpgrades(RuntimeUpgradesConfig),
+
+    /// Binary searches a range of blocks for the first block at which the value of a storage key
+    /// satisfies a predicate.
+    Bisect(BisectConfig),
+
+    /// Samples the keys of the pallets at a block to estimate the size and duration of their
+    /// extraction.
+    Estimate(EstimateConfig),
+
+    /// Identifies the pallet and storage item of hex encoded storage keys and decodes their map
+    /// keys, where the hashers permit.
+    LookupKeys(LookupKeysConfig),
+
+    /// Fetches the read proof of storage keys at a block, and stores it along with the proven
+    /// values so that they can be verified against the state root of the block.
+    Prove(ProveConfig),
+
+    /// Compares the metadata at two blocks and outputs the storage items added, removed or
+    /// retyped per pallet.
+    StorageLayout(StorageLayoutConfig),
+
+    /// Populates a stored snapshot with a synthetic electorate and stores it as a new snapshot.
+    Generate(GenerateConfig),
+
+    /// Writes the state of a stored snapshot into the genesis storage of a raw chain spec, to boot
+    /// a local node forked from it.
+    Load(LoadConfig),
+
+    /// Inspects stored snapshots offline, without connecting to a remote node.
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+
+    /// Runs the jobs of a manifest in dependency order, resuming from the last run.
+    Run(RunConfig),
+
+    /// Prints the version, git commit, enabled features and compiled runtimes of the CLI.
+    Version,
+
+    /// Builds the payload of an extrinsic to be signed offline (e.g. on an air-gapped machine)
+    /// from a call output by an operation.
+    SigningPayload(SigningPayloadConfig),
+
+    /// Assembles an extrinsic built by `signing-payload` with its offline signature and
+    /// broadcasts it.
+    SubmitSigned(SubmitSignedConfig),
+}
+
+impl Command {
+    /// The name of the command, or of the operation of a `transform`, in kebab-case.
+    pub(crate) fn name(&self) -> String {
+        let name = match self {
+            Command::Transform(config) => config.operation.name(),
+            Command::Snapshot(SnapshotCommand::Trim(config)) => config.operation.name(),
+            _ => format!("{:?}", self)
+                .split(|c: char| !c.is_alphanumeric())
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+
+        crate::output::kebab_case(&name)
+    }
 }
 
 /// Configs for the `extract` operation.
@@ -62,9 +360,45 @@ pub(crate) struct ExtractConfig {
     #[arg(long, env = "BN")]
     pub bn: Option<Vec<H256>>,
 
+    /// The block number(s) at which scraping happens, resolved to the hashes of the canonical
+    /// chain, instead of `--bn`.
+    #[arg(long, conflicts_with_all = ["bn", "era", "era_range"])]
+    pub at_number: Option<Vec<BlockNumber>>,
+
+    /// Selects the first block of a staking era, instead of `--bn`.
+    #[arg(long, conflicts_with_all = ["bn", "era_range"])]
+    pub era: Option<EraIndex>,
+
+    /// Selects the first block of each staking era in an inclusive range `a..b`, instead of
+    /// `--bn`.
+    #[arg(long, value_parser = parse_era_range, conflicts_with = "bn")]
+    pub era_range: Option<(EraIndex, EraIndex)>,
+
+    /// Selects the first block at or after each point in time of a calendar schedule, every
+    /// given period (e.g. `1d`, `12h` or `1w`) within the `--between` dates, instead of `--bn`.
+    #[arg(long, value_parser = parse_age, requires = "between", conflicts_with_all = ["bn", "at_number", "era", "era_range"])]
+    pub every: Option<u64>,
+
+    /// The inclusive range of dates (UTC) of the `--every` schedule, as
+    /// `YYYY-MM-DD..YYYY-MM-DD`.
+    #[arg(long, value_parser = parse_date_range, requires = "every")]
+    pub between: Option<(i64, i64)>,
+
     /// List of pallets to scrap keys from the remote node and store in the snapshot.
     #[arg(long, env = "PALLETS", default_values_t = ["ElectionProviderMultiPhase".to_string(), "Staking".to_string(), "VoterList".to_string()])]
     pub pallets: Vec<String>,
+
+    /// Stores the full snapshot of the first block only, and the changes of the top storage
+    /// relative to it for the other blocks. The delta snapshots are loaded over the snapshot of
+    /// the first block, which must be kept next to them.
+    #[arg(long, default_value_t = false)]
+    pub delta: bool,
+
+    /// Streams the key-values straight to the snapshot files, as key-value snapshots, without
+    /// building the externalities in memory. The state is paged through the RPC as with
+    /// `--engine raw`, and the snapshots are not verified against the remote node.
+    #[arg(long, default_value_t = false, conflicts_with = "delta")]
+    pub raw: bool,
 }
 
 /// Configs for the `transform` operation.
@@ -75,7 +409,32 @@ pub(crate) struct TransformConfig {
     #[arg(long, env = "BN")]
     pub bn: Option<Vec<H256>>,
 
-    /// Compute unbounded election operations or not.
+    /// The block number(s) at which scraping happens, resolved to the hashes of the canonical
+    /// chain, instead of `--bn`.
+    #[arg(long, conflicts_with_all = ["bn", "era", "era_range"])]
+    pub at_number: Option<Vec<BlockNumber>>,
+
+    /// Selects the first block of a staking era, instead of `--bn`.
+    #[arg(long, conflicts_with_all = ["bn", "era_range"])]
+    pub era: Option<EraIndex>,
+
+    /// Selects the first block of each staking era in an inclusive range `a..b`, instead of
+    /// `--bn`.
+    #[arg(long, value_parser = parse_era_range, conflicts_with = "bn")]
+    pub era_range: Option<(EraIndex, EraIndex)>,
+
+    /// Selects the first block at or after each point in time of a calendar schedule, every
+    /// given period (e.g. `1d`, `12h` or `1w`) within the `--between` dates, instead of `--bn`.
+    #[arg(long, value_parser = parse_age, requires = "between", conflicts_with_all = ["bn", "at_number", "era", "era_range"])]
+    pub every: Option<u64>,
+
+    /// The inclusive range of dates (UTC) of the `--every` schedule, as
+    /// `YYYY-MM-DD..YYYY-MM-DD`.
+    #[arg(long, value_parser = parse_date_range, requires = "every")]
+    pub between: Option<(i64, i64)>,
+
+    /// Compute the unbounded elections of `election-analysis`, over a snapshot recomputed with all
+    /// the voters. Without it, only the bounded elections are computed.
     #[arg(long, default_value_t = false)]
     pub compute_unbounded: bool,
 
@@ -83,11 +442,521 @@ pub(crate) struct TransformConfig {
     #[arg(long, default_value_t = false)]
     pub live: bool,
 
+    /// Use the multi-threaded Phragmen solver when mining NPoS elections.
+    #[arg(long, default_value_t = false)]
+    pub parallel_solver: bool,
+
+    /// Election scoring mode. The approximate scoring is considerably faster than the exact one
+    /// and is useful for coarse scans over many blocks.
+    #[arg(long, value_enum, default_value_t = ScoringMode::Exact)]
+    pub scoring: ScoringMode,
+
+    /// Paths of snapshots overlaid on top of the snapshot of each block, in order. The storage of
+    /// each pallet in a patch replaces the storage of the same pallet in the snapshot.
+    #[arg(long = "patch")]
+    pub patches: Vec<String>,
+
+    /// Paths of mutation specs applied to the snapshot of each block after the patches, in order.
+    /// A spec sets or removes hex encoded storage keys, in JSON, or in TOML if it has a `.toml`
+    /// extension.
+    #[arg(long = "mutate")]
+    pub mutation_specs: Vec<String>,
+
+    /// Allow the operations which mutate the loaded state, e.g. through patches or simulations.
+    /// A summary of the mutations applied is logged.
+    #[arg(long, default_value_t = false)]
+    pub allow_mutation: bool,
+
+    /// Path of the output where the storage keys read by the operation are written, with their
+    /// pallet and storage item.
+    #[arg(long)]
+    pub trace_keys: Option<String>,
+
+    /// Path of the output where the score, voters and edges of the solutions mined by the
+    /// operation are written after each balancing iteration, to debug the solvers.
+    #[arg(long)]
+    pub debug_solver: Option<String>,
+
     /// The operation to perform.
     #[command(subcommand)]
     pub operation: Operation,
 }
 
+/// Configs for the `runtime-upgrades` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct RuntimeUpgradesConfig {
+    /// The first block number of the range to scan.
+    #[arg(long, default_value_t = 0)]
+    pub from: BlockNumber,
+
+    /// The last block number of the range to scan. If none is provided, the latest finalized
+    /// block is used.
+    #[arg(long)]
+    pub to: Option<BlockNumber>,
+}
+
+/// Configs for the `bisect` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct BisectConfig {
+    /// The hex encoded storage key to query.
+    #[arg(long)]
+    pub key: Bytes,
+
+    /// The first block number of the range to search.
+    #[arg(long, default_value_t = 0)]
+    pub from: BlockNumber,
+
+    /// The last block number of the range to search. If none is provided, the latest finalized
+    /// block is used.
+    #[arg(long)]
+    pub to: Option<BlockNumber>,
+
+    /// The predicate over the storage value. It is expected to be false up to some block of the
+    /// range and true onwards.
+    #[command(subcommand)]
+    pub predicate: StoragePredicate,
+}
+
+/// Predicates over a (possibly non-existent) storage value.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum StoragePredicate {
+    /// The storage value exists.
+    Exists,
+    /// The storage value equals the given hex encoded bytes.
+    Equals {
+        #[arg(long)]
+        value: Bytes,
+    },
+    /// The storage value decodes into an unsigned integer greater than the given one.
+    GreaterThan {
+        #[arg(long)]
+        value: u128,
+    },
+}
+
+impl StoragePredicate {
+    /// Whether the predicate holds for the storage value.
+    pub fn holds(&self, value: Option<&[u8]>) -> bool {
+        match (self, value) {
+            (StoragePredicate::Exists, value) => value.is_some(),
+            (StoragePredicate::Equals { value: expected }, Some(value)) => value == &expected[..],
+            (StoragePredicate::GreaterThan { value: n }, Some(value)) => {
+                crate::utils::decode_uint(value).map_or(false, |v| v > *n)
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+/// Configs for the `estimate` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct EstimateConfig {
+    /// The block hash at which the estimation happens. If none is provided, the latest finalized
+    /// block is used.
+    #[arg(long, env = "BN")]
+    pub bn: Option<H256>,
+
+    /// List of pallets to estimate.
+    #[arg(long, env = "PALLETS", default_values_t = ["ElectionProviderMultiPhase".to_string(), "Staking".to_string(), "VoterList".to_string()])]
+    pub pallets: Vec<String>,
+
+    /// Number of keys sampled per storage item.
+    #[arg(long, default_value_t = 1000)]
+    pub sample_size: u32,
+}
+
+/// Configs for the `lookup-keys` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct LookupKeysConfig {
+    /// The block hash of the metadata used for the lookup. If none is provided, the latest
+    /// finalized block is used.
+    #[arg(long, env = "BN")]
+    pub bn: Option<H256>,
+
+    /// Hex encoded storage keys to lookup.
+    #[arg(required = true)]
+    pub keys: Vec<Bytes>,
+}
+
+/// Configs for the `prove` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct ProveConfig {
+    /// The block hash of the proof. If none is provided, the latest finalized block is used.
+    #[arg(long, env = "BN")]
+    pub bn: Option<H256>,
+
+    /// Hex encoded storage keys to prove.
+    #[arg(long = "key")]
+    pub keys: Vec<Bytes>,
+
+    /// Storage items to prove, as `Pallet::Item`. The map items are proven under each of the
+    /// `--account`s.
+    #[arg(long = "item")]
+    pub items: Vec<String>,
+
+    /// SS58 encoded accounts under which the map items are proven.
+    #[arg(long = "account")]
+    pub accounts: Vec<String>,
+
+    /// The path of the JSON file which stores the proof. If none is provided, the proof is
+    /// stored as `<snapshot_path>/<block_hash>.proof.json`.
+    #[arg(long)]
+    pub proof_path: Option<String>,
+}
+
+/// Configs for the `storage-layout` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct StorageLayoutConfig {
+    /// The block hash of the metadata to compare from.
+    #[arg(long)]
+    pub from: H256,
+
+    /// The block hash of the metadata to compare to. If none is provided, the latest finalized
+    /// block is used.
+    #[arg(long)]
+    pub to: Option<H256>,
+}
+
+/// Configs for the `generate` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct GenerateConfig {
+    /// The block hash of the stored snapshot used as template. Everything but the electorate is
+    /// kept from the template.
+    #[arg(long)]
+    pub template: H256,
+
+    /// Number of validators.
+    #[arg(long, default_value_t = 300)]
+    pub validators: u32,
+
+    /// Number of nominators.
+    #[arg(long, default_value_t = 10_000)]
+    pub nominators: u32,
+
+    /// Number of targets nominated by each nominator.
+    #[arg(long, default_value_t = 16)]
+    pub nominations: u32,
+
+    /// Distribution of the stake of validators and nominators.
+    #[arg(long, value_enum, default_value_t = StakeDistribution::Pareto)]
+    pub distribution: StakeDistribution,
+
+    /// Minimum stake of a staker.
+    #[arg(long, default_value_t = 1_000_000_000_000)]
+    pub min_stake: u128,
+
+    /// Maximum stake of a staker.
+    #[arg(long, default_value_t = 1_000_000_000_000_000)]
+    pub max_stake: u128,
+
+    /// Number of winners of the election. If none is provided, the template's validator count is
+    /// used.
+    #[arg(long)]
+    pub desired_targets: Option<u32>,
+
+    /// Seed of the generator.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+/// Configs for the `load` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct LoadConfig {
+    /// The block hash of the stored snapshot to load.
+    #[arg(long)]
+    pub bn: H256,
+
+    /// Path of a chain spec whose genesis is replaced by the state of the snapshot, e.g. the spec
+    /// of a local development chain. If none is provided, a minimal local chain spec is written.
+    #[arg(long)]
+    pub base_spec: Option<String>,
+
+    /// Path where the raw chain spec is written. If none is provided, it is written next to the
+    /// snapshot, as `<hash>.json`.
+    #[arg(long)]
+    pub chain_spec_path: Option<String>,
+
+    /// Snapshots overlaid on top of the snapshot before it is loaded (see `transform --patch`).
+    #[arg(long = "patch")]
+    pub patches: Vec<String>,
+}
+
+/// Configs for the `signing-payload` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct SigningPayloadConfig {
+    /// The hex encoded call, or the path of a file with it (e.g. a `.call` file of
+    /// `emergency-result`).
+    #[arg(long)]
+    pub call: String,
+
+    /// The account which signs the extrinsic, as SS58 or hex.
+    #[arg(long)]
+    pub signer: sp_core::crypto::AccountId32,
+
+    /// The nonce of the extrinsic. If none is provided, the next nonce of the signer is used.
+    #[arg(long)]
+    pub nonce: Option<u32>,
+
+    /// The tip of the extrinsic.
+    #[arg(long, default_value_t = 0)]
+    pub tip: u128,
+
+    /// Number of blocks the extrinsic is valid for, from the block it is built at. With `0`, the
+    /// extrinsic is immortal.
+    #[arg(long, default_value_t = 64)]
+    pub mortality: u64,
+
+    /// The block hash the extrinsic is built at. If none is provided, the latest finalized block
+    /// is used.
+    #[arg(long)]
+    pub bn: Option<H256>,
+
+    /// Path where the unsigned extrinsic is written, as JSON.
+    #[arg(long, default_value = "unsigned_extrinsic.json")]
+    pub payload_path: String,
+}
+
+/// Configs for the `submit-signed` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct SubmitSignedConfig {
+    /// Path of the unsigned extrinsic written by `signing-payload`.
+    #[arg(long, default_value = "unsigned_extrinsic.json")]
+    pub payload_path: String,
+
+    /// The hex encoded signature of the payload.
+    #[arg(long)]
+    pub signature: Bytes,
+
+    /// The scheme of the signature.
+    #[arg(long, value_enum, default_value_t = crate::signing::SignatureScheme::Sr25519)]
+    pub scheme: crate::signing::SignatureScheme,
+
+    /// Waits for the extrinsic to be finalized, rather than included in a block.
+    #[arg(long, default_value_t = false)]
+    pub finalized: bool,
+}
+
+/// Subcommands of the `snapshot` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum SnapshotCommand {
+    /// Prints the block number, runtime version, pallet prefixes, key counts and size of a stored
+    /// snapshot.
+    Inspect(InspectConfig),
+    /// Lists the snapshots stored in the snapshot path and their disk usage.
+    List,
+    /// Removes the snapshots stored in the snapshot path which are older than a given age.
+    Prune(PruneConfig),
+    /// Removes the snapshots of the given blocks from the snapshot path.
+    Rm(RmConfig),
+    /// Runs an operation over stored snapshots and writes trimmed copies of them, with only the
+    /// storage read by the operation, e.g. as small fixtures for tests and bug reports.
+    Trim(TrimConfig),
+}
+
+/// Configs for the `snapshot inspect` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct InspectConfig {
+    /// Path of the snapshot, e.g. `<snapshot_path>/<block_hash>.data`.
+    pub path: String,
+}
+
+/// Configs for the `snapshot prune` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct PruneConfig {
+    /// The age, as per the last modification, above which snapshots are removed, e.g. `30d`,
+    /// `12h` or `90m`.
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: u64,
+
+    /// Only lists the snapshots which would be removed.
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Removes the delta snapshots along with their base snapshot. Otherwise, the base snapshots
+    /// of the delta snapshots which are kept are kept as well.
+    #[arg(long, default_value_t = false)]
+    pub cascade: bool,
+}
+
+/// Configs for the `snapshot rm` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct RmConfig {
+    /// The block hash(es) of the snapshots to remove, along with their anonymized versions.
+    #[arg(long = "block", required = true)]
+    pub blocks: Vec<H256>,
+
+    /// Removes the delta snapshots along with their base snapshot. Otherwise, removing the base
+    /// snapshot of delta snapshots fails.
+    #[arg(long, default_value_t = false)]
+    pub cascade: bool,
+}
+
+/// Configs for the `snapshot trim` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct TrimConfig {
+    /// The block hash(es) of the stored snapshots to trim, as expected by the operation.
+    #[arg(long = "block", required = true)]
+    pub blocks: Vec<H256>,
+
+    /// Directory where the trimmed snapshots are written, as `<block_hash>.data`. If none is
+    /// provided, the `trimmed` directory of the snapshot path is used.
+    #[arg(long)]
+    pub trimmed_path: Option<String>,
+
+    /// The operation whose reads are kept.
+    #[command(subcommand)]
+    pub operation: Operation,
+}
+
+/// Configs for the `run` command.
+#[derive(Debug, Clone, Parser)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct RunConfig {
+    /// Path to the YAML manifest of jobs.
+    #[arg(long)]
+    pub manifest: String,
+
+    /// Re-runs all the jobs, including the ones completed in previous runs.
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
+
+/// Formats of the output of the commands.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum OutputFormat {
+    /// Appends one row per result to a CSV file.
+    Csv,
+    /// Appends one object per result to a JSON array in a file.
+    Json,
+    /// Appends one JSON object per line per result to a JSON Lines file.
+    Jsonl,
+    /// Writes one row per result to a Parquet file, which is closed when the command finishes.
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Writes one CSV row per result to the standard output.
+    Stdout,
+}
+
+/// State versions of the externalities trie.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum StateVersion {
+    /// Values are always stored in the trie nodes.
+    V0,
+    /// Values larger than 32 bytes are stored by hash, outside the trie nodes.
+    V1,
+}
+
+impl From<StateVersion> for sp_core::storage::StateVersion {
+    fn from(version: StateVersion) -> Self {
+        match version {
+            StateVersion::V0 => sp_core::storage::StateVersion::V0,
+            StateVersion::V1 => sp_core::storage::StateVersion::V1,
+        }
+    }
+}
+
+/// Default number of keys fetched per request by the `raw` engine.
+pub(crate) const DEFAULT_RAW_PAGE_SIZE: u32 = 1000;
+
+/// Default number of retries of a failed request of the `raw` engine.
+pub(crate) const DEFAULT_RAW_RETRIES: u32 = 5;
+
+/// Default number of shards of each prefix paged by the `raw` engine.
+pub(crate) const DEFAULT_RAW_SHARDS: u32 = 1;
+
+/// Default maximum number of concurrent requests of the `raw` engine.
+pub(crate) const DEFAULT_RAW_MAX_IN_FLIGHT: usize = 4;
+
+/// Default number of blocks (and pallets) extracted concurrently.
+pub(crate) const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Engines which scrape the state of a remote node.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum Engine {
+    /// The `frame-remote-externalities` builder.
+    RemoteExternalities,
+    /// The in-crate scraper, which pages through the keys with `state_getKeysPaged` and fetches
+    /// their values with `state_queryStorageAt`.
+    Raw,
+}
+
+/// How the storage of the externalities is decoded.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum RuntimeMode {
+    /// With the types of the runtime compiled into the CLI.
+    Compiled,
+    /// With the types of the on-chain metadata of each block, which supports any Substrate chain
+    /// but only a subset of the operations.
+    Metadata,
+}
+
+/// Settings of the externalities built from a remote node or a stored snapshot, set once from
+/// the CLI.
+#[derive(Debug, Clone)]
+pub(crate) struct ExtConfig {
+    pub state_version: Option<StateVersion>,
+    pub skip_child_trie: bool,
+    pub trie_cache_size: Option<usize>,
+    pub compress: bool,
+    pub engine: Engine,
+    pub runtime_mode: RuntimeMode,
+    pub raw_page_size: u32,
+    pub raw_retries: u32,
+    pub raw_shards: u32,
+    pub raw_max_in_flight: usize,
+    pub concurrency: usize,
+}
+
+impl Default for ExtConfig {
+    fn default() -> Self {
+        Self {
+            state_version: None,
+            skip_child_trie: false,
+            trie_cache_size: None,
+            compress: false,
+            engine: Engine::RemoteExternalities,
+            runtime_mode: RuntimeMode::Compiled,
+            raw_page_size: DEFAULT_RAW_PAGE_SIZE,
+            raw_retries: DEFAULT_RAW_RETRIES,
+            raw_shards: DEFAULT_RAW_SHARDS,
+            raw_max_in_flight: DEFAULT_RAW_MAX_IN_FLIGHT,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+/// Distributions of the stake of a synthetic electorate.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum StakeDistribution {
+    /// Stakes uniformly distributed between the minimum and maximum stake.
+    Uniform,
+    /// Heavy-tailed stakes, where few stakers hold most of the stake.
+    Pareto,
+    /// Stakes exponentially decaying from the minimum stake.
+    Exponential,
+}
+
 /// Solvers for NPoS elections.
 #[derive(Debug, Clone, Parser)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -100,4 +969,54 @@ pub(crate) enum Solver {
         #[arg(long, default_value_t = 10)]
         iterations: usize,
     },
+    ParallelPhragmen {
+        #[arg(long, default_value_t = 10)]
+        iterations: usize,
+    },
+}
+
+/// Solvers selectable by the operations mining NPoS elections.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum SolverKind {
+    /// Sequential Phragmen, or its multi-threaded version with `--parallel-solver`.
+    SeqPhragmen,
+    /// PhragMMS, which is single-threaded only.
+    Phragmms,
+}
+
+impl SolverKind {
+    /// The solver of this kind with the given number of balancing `iterations`.
+    pub fn solver(&self, iterations: usize, parallel: bool) -> Solver {
+        match self {
+            SolverKind::SeqPhragmen if parallel => Solver::ParallelPhragmen { iterations },
+            SolverKind::SeqPhragmen => Solver::SeqPhragmen { iterations },
+            SolverKind::Phragmms => Solver::PhragMMS { iterations },
+        }
+    }
+}
+
+/// Scoring modes of the NPoS elections.
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) enum ScoringMode {
+    /// Mines a full solution (balanced and trimmed) and reports its score.
+    #[default]
+    Exact,
+    /// Reports the score of the unbalanced supports, without mining a full solution.
+    Approximate,
+    /// Reports both the exact and the approximate scores.
+    Both,
+}
+
+impl ScoringMode {
+    /// Whether the exact score should be computed.
+    pub fn exact(&self) -> bool {
+        matches!(self, ScoringMode::Exact | ScoringMode::Both)
+    }
+
+    /// Whether the approximate score should be computed.
+    pub fn approximate(&self) -> bool {
+        matches!(self, ScoringMode::Approximate | ScoringMode::Both)
+    }
 }
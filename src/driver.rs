@@ -0,0 +1,626 @@
+//! The entry logic of the CLI.
+//!
+//! The [`Driver`] is built from the parsed [`Opt`] and runs a command: it connects to the remote
+//! node (unless a client is injected with [`Driver::with_rpc`]), detects the chain and selects its
+//! runtime, and dispatches the command. The commands which do not need a remote node (e.g. the
+//! management of the stored snapshots) are run offline, without connecting.
+
+use crate::{
+    any_runtime, budget, build_info,
+    commands::{self, TransformOptions},
+    configs::{self, Command, ExtConfig, Opt},
+    output,
+    output::OutputConfig,
+    prelude::*,
+    rpc::SharedRpcClient,
+    AnyRuntime, EXT_CONFIG,
+};
+
+use anyhow::anyhow;
+use sp_core::crypto::Ss58AddressFormatRegistry;
+use std::{collections::BTreeMap, time::Duration};
+
+/// The settings of a chain with a compiled runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct KnownChain {
+    pub runtime: AnyRuntime,
+    pub ss58: Ss58AddressFormatRegistry,
+    pub token: &'static str,
+    pub decimal_points: u128,
+}
+
+/// The settings of the `chain`, as named by `system_chain`, if its runtime is compiled.
+pub(crate) fn known_chain(chain: &str) -> Option<KnownChain> {
+    let (runtime, ss58, token, decimal_points) = match chain.to_lowercase().as_str() {
+        "polkadot" | "development" => (
+            AnyRuntime::Polkadot,
+            Ss58AddressFormatRegistry::PolkadotAccount,
+            "DOT",
+            10_000_000_000,
+        ),
+        "kusama" | "kusama-dev" => (
+            AnyRuntime::Kusama,
+            Ss58AddressFormatRegistry::KusamaAccount,
+            "KSM",
+            1_000_000_000_000,
+        ),
+        "westend" => (
+            AnyRuntime::Westend,
+            Ss58AddressFormatRegistry::PolkadotAccount,
+            "WND",
+            1_000_000_000_000,
+        ),
+        _ => return None,
+    };
+
+    Some(KnownChain {
+        runtime,
+        ss58,
+        token,
+        decimal_points,
+    })
+}
+
+/// The connection settings of the remote node.
+#[derive(Debug, Clone)]
+pub(crate) struct Connection {
+    pub uri: String,
+    pub connection_timeout: Duration,
+    pub request_timeout: Duration,
+    pub method_timeouts: BTreeMap<String, Duration>,
+}
+
+/// Runs a command of the CLI.
+pub(crate) struct Driver {
+    command: Command,
+    connection: Connection,
+    snapshot_path: String,
+    output_path: Option<String>,
+    output_layout: String,
+    output_manifest: bool,
+    output_config: OutputConfig,
+    ext_config: ExtConfig,
+    limits: budget::Limits,
+    rpc: Option<SharedRpcClient>,
+    /// The runtime of the chain, selected once connected to the remote node.
+    runtime: AnyRuntime,
+}
+
+impl Driver {
+    /// Builds the driver of the command of `opt`. Fails if the labels can not be loaded.
+    pub fn new(opt: Opt) -> Result<Self, anyhow::Error> {
+        let Opt {
+            uri,
+            command,
+            connection_timeout,
+            request_timeout,
+            rpc_timeouts,
+            snapshot_path,
+            output_path,
+            output_layout,
+            output_manifest,
+            format,
+            state_version,
+            skip_child_trie,
+            trie_cache_size,
+            compress,
+            engine,
+            runtime_mode,
+            raw_page_size,
+            raw_retries,
+            raw_shards,
+            raw_max_in_flight,
+            concurrency,
+            long_format,
+            tags,
+            max_duration,
+            max_blocks,
+            max_bytes_downloaded,
+            labels,
+        } = opt;
+
+        let labels = labels
+            .as_deref()
+            .map(crate::labels::Labels::load)
+            .transpose()
+            .map_err(|e| e.context("Labels error"))?
+            .unwrap_or_default();
+
+        Ok(Driver {
+            command,
+            connection: Connection {
+                uri,
+                connection_timeout: Duration::from_secs(connection_timeout as u64),
+                request_timeout: Duration::from_secs(request_timeout as u64),
+                method_timeouts: rpc_timeouts
+                    .into_iter()
+                    .map(|(method, secs)| (method, Duration::from_secs(secs)))
+                    .collect(),
+            },
+            snapshot_path,
+            output_path,
+            output_layout,
+            output_manifest,
+            output_config: OutputConfig {
+                format,
+                tags,
+                long_format,
+                labels,
+            },
+            ext_config: ExtConfig {
+                state_version,
+                skip_child_trie,
+                trie_cache_size,
+                compress,
+                engine,
+                runtime_mode,
+                raw_page_size,
+                raw_retries,
+                raw_shards,
+                raw_max_in_flight,
+                concurrency,
+            },
+            limits: budget::Limits {
+                max_duration: max_duration.map(Duration::from_secs),
+                max_blocks,
+                max_bytes_downloaded,
+            },
+            rpc: None,
+            runtime: AnyRuntime::default(),
+        })
+    }
+
+    /// Injects the client of the remote node, which is otherwise connected to when the command
+    /// runs, e.g. a client with canned responses in the tests (see `SharedRpcClient::mock`).
+    pub fn with_rpc(mut self, rpc: SharedRpcClient) -> Self {
+        self.rpc = Some(rpc);
+        self
+    }
+
+    /// Whether the command runs without a remote node. The snapshots are managed offline, with
+    /// the compiled runtime, except for their trimming which runs an operation.
+    pub fn is_offline(&self) -> bool {
+        match &self.command {
+            Command::Version => true,
+            Command::Snapshot(snapshot_command) => {
+                !matches!(snapshot_command, configs::SnapshotCommand::Trim(_))
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the command. The settings of the outputs, the externalities and the budget are set
+    /// globally, so a single command runs per process.
+    pub async fn run(mut self) -> Result<(), anyhow::Error> {
+        if let Command::Version = self.command {
+            print!("{}", build_info::BuildInfo::current());
+            return Ok(());
+        }
+
+        output::CONFIG
+            .set(self.output_config.clone())
+            .map_err(|_| anyhow!("the output config is set only once."))?;
+        budget::start(self.limits);
+
+        if self.is_offline() {
+            return self.run_offline().await;
+        }
+
+        let rpc = match self.rpc.take() {
+            Some(rpc) => rpc,
+            None => self.connect().await,
+        };
+
+        let chain: String = rpc.system_chain().await?;
+        self.select_runtime(&chain);
+        log::info!(target: LOG_TARGET, "connected to chain {:?}", chain);
+        output::set_chain(&chain);
+
+        EXT_CONFIG
+            .set(self.ext_config.clone())
+            .map_err(|_| anyhow!("the externalities config is set only once."))?;
+
+        let output_path = output::resolve_output_path(
+            self.output_path.take(),
+            &self.output_layout,
+            &chain,
+            &self.command.name(),
+            self.output_config.format,
+        )
+        .map_err(|e| e.context("Output error"))?;
+        log::info!(target: LOG_TARGET, "writing output to {}", output_path);
+
+        if self.output_manifest {
+            build_info::write_manifest(&output_path, &chain)
+                .map_err(|e| e.context("Output manifest error"))?;
+        }
+
+        let outcome = self.dispatch(rpc, output_path).await;
+
+        output::finish().map_err(|e| e.context("Output error"))?;
+        let unattached = crate::warnings::take_unattached();
+        if !unattached.is_empty() {
+            log::warn!(
+                target: LOG_TARGET,
+                "warnings of the run not attached to any output row: {}",
+                crate::warnings::summary(&unattached)
+            );
+        }
+        log::info!(
+            target: LOG_TARGET,
+            "round of execution finished. outcome = {:?}",
+            outcome
+        );
+        outcome
+    }
+
+    /// Connects to the remote node, retrying until it is reachable.
+    async fn connect(&self) -> SharedRpcClient {
+        let Connection {
+            uri,
+            connection_timeout,
+            request_timeout,
+            method_timeouts,
+        } = &self.connection;
+
+        loop {
+            match SharedRpcClient::new(
+                uri,
+                *connection_timeout,
+                *request_timeout,
+                method_timeouts.clone(),
+            )
+            .await
+            {
+                Ok(client) => break client,
+                Err(why) => {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "failed to connect to client due to {:?}, retrying soon..",
+                        why
+                    );
+                    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+                }
+            }
+        }
+    }
+
+    /// Selects the runtime of `chain`, and its address format and token. The storage of the
+    /// chains without a compiled runtime is decoded with their on-chain metadata.
+    fn select_runtime(&mut self, chain: &str) {
+        match known_chain(chain) {
+            Some(known) => {
+                sp_core::crypto::set_default_ss58_version(known.ss58.into());
+                sub_tokens::dynamic::set_name(known.token);
+                sub_tokens::dynamic::set_decimal_points(known.decimal_points);
+                self.runtime = known.runtime;
+            }
+            None => {
+                log::info!(
+                    target: LOG_TARGET,
+                    "no compiled runtime for chain {:?}, decoding the storage with the on-chain metadata.",
+                    chain
+                );
+                self.ext_config.runtime_mode = configs::RuntimeMode::Metadata;
+            }
+        }
+    }
+
+    /// Runs the snapshot commands which do not need a remote node.
+    async fn run_offline(self) -> Result<(), anyhow::Error> {
+        EXT_CONFIG
+            .set(self.ext_config)
+            .map_err(|_| anyhow!("the externalities config is set only once."))?;
+
+        let snapshot_path = self.snapshot_path;
+        let Command::Snapshot(snapshot_command) = self.command else {
+            unreachable!("only the snapshot commands run offline; qed.");
+        };
+
+        match snapshot_command {
+            configs::SnapshotCommand::Inspect(config) => {
+                any_runtime! { self.runtime => inspect_cmd(config.path.clone()).await }
+            }
+            configs::SnapshotCommand::List => commands::snapshot_list_cmd(&snapshot_path),
+            configs::SnapshotCommand::Prune(config) => commands::snapshot_prune_cmd(
+                &snapshot_path,
+                Duration::from_secs(config.older_than),
+                config.dry_run,
+                config.cascade,
+            ),
+            configs::SnapshotCommand::Rm(config) => {
+                commands::snapshot_rm_cmd(&snapshot_path, &config.blocks, config.cascade)
+            }
+            configs::SnapshotCommand::Trim(_) => unreachable!("trimming runs online; qed."),
+        }
+        .map_err(|e| e.context("Snapshot error"))
+    }
+
+    /// Dispatches the command to the runtime of the chain, writing its output in `output_path`.
+    async fn dispatch(
+        self,
+        rpc: SharedRpcClient,
+        output_path: String,
+    ) -> Result<(), anyhow::Error> {
+        let snapshot_path = self.snapshot_path;
+
+        any_runtime! {
+            self.runtime =>
+            match self.command {
+                Command::Extract(config) => {
+                    let eras = configs::selected_eras(config.era, config.era_range);
+                    let timestamps = configs::selected_times(config.every, config.between);
+                    let block_hashes = crate::commands::select_blocks(&rpc, config.bn, config.at_number, eras, timestamps)
+                        .await
+                        .map_err(|e| e.context("Block selection error"))?
+                        .ok_or_else(|| anyhow!("Config: expected a block selection (--bn, --at-number, --era, --era-range or --every)."))?;
+                    let file_paths = block_hashes.iter().map(|h| format!("{}/{}.data", snapshot_path, h)).collect::<Vec<_>>();
+
+                    extract_cmd(rpc.clone(), config.pallets, block_hashes, file_paths, false, config.delta, config.raw).await
+                        .map_err(|e| e.context("Extract error"))
+                },
+                Command::Transform(config) => {
+                    if let Some(debug_solver) = &config.debug_solver {
+                        crate::gadgets::set_solver_debug(debug_solver);
+                    }
+
+                    let eras = configs::selected_eras(config.era, config.era_range);
+                    let timestamps = configs::selected_times(config.every, config.between);
+                    let mut block_hashes = match crate::commands::select_blocks(&rpc, config.bn, config.at_number, eras, timestamps)
+                        .await
+                        .map_err(|e| e.context("Block selection error"))?
+                    {
+                        Some(hs) => hs,
+                        // a range of headers (or tracked values) is exported without block hashes.
+                        None if matches!(
+                            config.operation,
+                            crate::operations::Operation::Headers { from: Some(_), .. } | crate::operations::Operation::Track { from: Some(_), .. }
+                        ) => vec![],
+                        None => return Err(anyhow!("Config: expected a block selection (--bn, --at-number, --era, --era-range or --every).")),
+                    };
+
+                    // operations over a pair of blocks complete the selection of a single block,
+                    // and extract the snapshots which are not stored yet unless the run is live.
+                    let pairing = config.operation.pairing();
+                    if let Some(pairing) = pairing {
+                        block_hashes = crate::commands::pair_blocks(&rpc, block_hashes, pairing)
+                            .await
+                            .map_err(|e| e.context("Block pairing error"))?;
+                    }
+
+                    let snapshot_paths = block_hashes.iter().map(|h| format!("{}/{}.data", snapshot_path, h)).collect::<Vec<_>>();
+
+                    if pairing.is_some() && !config.live {
+                        let (missing_hashes, missing_paths): (Vec<_>, Vec<_>) = block_hashes
+                            .iter()
+                            .zip(snapshot_paths.iter())
+                            .filter(|(_, path)| !std::path::Path::new(path).exists())
+                            .map(|(h, path)| (*h, path.clone()))
+                            .unzip();
+
+                        if !missing_hashes.is_empty() {
+                            let requirements = match self.ext_config.runtime_mode {
+                                configs::RuntimeMode::Metadata => config.operation.metadata_requirements().unwrap_or_default(),
+                                configs::RuntimeMode::Compiled => config.operation.requirements(),
+                            };
+                            let pallets = config.operation.pallets_to_extract(&requirements);
+                            log::info!(target: LOG_TARGET, "Extracting the missing snapshot(s) of pallets {:?} for block(s) {:?}", pallets, missing_hashes);
+
+                            extract_cmd(rpc.clone(), pallets, missing_hashes, missing_paths, false, false, false).await
+                                .map_err(|e| e.context("Extract error"))?;
+                        }
+                    }
+
+                    transform_cmd(
+                        rpc.clone(),
+                        config.operation,
+                        block_hashes,
+                        output_path,
+                        snapshot_paths,
+                        TransformOptions {
+                            compute_unbounded: config.compute_unbounded,
+                            live: config.live,
+                            parallel_solver: config.parallel_solver,
+                            scoring: config.scoring,
+                            patches: config.patches,
+                            mutation_specs: config.mutation_specs,
+                            allow_mutation: config.allow_mutation,
+                            trace_path: config.trace_keys,
+                            ..Default::default()
+                        },
+                    ).await
+                    .map_err(|e| e.context("Transform error"))
+                },
+                Command::RuntimeUpgrades(config) => {
+                    crate::commands::runtime_upgrades_cmd(rpc.clone(), config.from, config.to, output_path).await
+                        .map_err(|e| e.context("Runtime upgrades error"))
+                },
+                Command::Bisect(config) => {
+                    crate::commands::bisect_cmd(rpc.clone(), config.key, config.predicate, config.from, config.to, output_path).await
+                        .map_err(|e| e.context("Bisect error"))
+                },
+                Command::Estimate(config) => {
+                    crate::commands::estimate_cmd(rpc.clone(), config.bn, config.pallets, config.sample_size, output_path).await
+                        .map_err(|e| e.context("Estimate error"))
+                },
+                Command::LookupKeys(config) => {
+                    crate::commands::lookup_keys_cmd(rpc.clone(), config.bn, config.keys, output_path).await
+                        .map_err(|e| e.context("Lookup keys error"))
+                },
+                Command::Prove(config) => {
+                    crate::commands::prove_cmd(rpc.clone(), config, &snapshot_path, output_path).await
+                        .map_err(|e| e.context("Prove error"))
+                },
+                Command::StorageLayout(config) => {
+                    crate::commands::storage_layout_cmd(rpc.clone(), config.from, config.to, output_path).await
+                        .map_err(|e| e.context("Storage layout error"))
+                },
+                Command::Generate(config) => {
+                    let params = crate::gadgets::synthetic::ElectorateParams {
+                        validators: config.validators,
+                        nominators: config.nominators,
+                        nominations: config.nominations,
+                        distribution: config.distribution,
+                        min_stake: config.min_stake,
+                        max_stake: config.max_stake,
+                        desired_targets: config.desired_targets,
+                        seed: config.seed,
+                    };
+
+                    generate_cmd(config.template, snapshot_path, params, output_path).await
+                        .map_err(|e| e.context("Generate error"))
+                },
+                Command::Load(config) => {
+                    load_cmd(config.bn, snapshot_path, config.base_spec, config.chain_spec_path, config.patches, output_path).await
+                        .map_err(|e| e.context("Load error"))
+                },
+                Command::Snapshot(configs::SnapshotCommand::Trim(config)) => {
+                    let snapshot_paths = config.blocks.iter().map(|h| format!("{}/{}.data", snapshot_path, h)).collect::<Vec<_>>();
+                    let trimmed_path = config.trimmed_path.unwrap_or_else(|| format!("{}/trimmed", snapshot_path));
+
+                    // the trimmed snapshots replay the operation as is, so mutations are allowed.
+                    transform_cmd(
+                        rpc.clone(),
+                        config.operation,
+                        config.blocks,
+                        output_path,
+                        snapshot_paths,
+                        TransformOptions {
+                            allow_mutation: true,
+                            trimmed_path: Some(trimmed_path),
+                            ..Default::default()
+                        },
+                    ).await
+                    .map_err(|e| e.context("Snapshot trim error"))
+                },
+                Command::Snapshot(_) | Command::Version => unreachable!("run offline; qed."),
+                Command::SigningPayload(config) => {
+                    crate::commands::signing_payload_cmd(rpc.clone(), config).await
+                        .map_err(|e| e.context("Signing payload error"))
+                },
+                Command::SubmitSigned(config) => {
+                    crate::commands::submit_signed_cmd(rpc.clone(), config).await
+                        .map_err(|e| e.context("Submit signed error"))
+                },
+                Command::Run(config) => {
+                    crate::manifest::run_manifest(config.manifest, config.force).await
+                        .map_err(|e| e.context("Run error"))
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn known_chain_works() {
+        assert_eq!(
+            known_chain("Polkadot").unwrap().runtime,
+            AnyRuntime::Polkadot
+        );
+        assert_eq!(known_chain("Development").unwrap().token, "DOT");
+        assert_eq!(
+            known_chain("kusama").unwrap().ss58,
+            Ss58AddressFormatRegistry::KusamaAccount
+        );
+        assert_eq!(known_chain("Westend").unwrap().runtime, AnyRuntime::Westend);
+        assert_eq!(known_chain("Rococo"), None);
+    }
+
+    #[test]
+    fn driver_works() {
+        let opt = Opt::parse_from([
+            "substrate-timetravel",
+            "--rpc-timeout=state_getMetadata=30",
+            "--max-blocks=10",
+            "snapshot",
+            "list",
+        ]);
+        let driver = Driver::new(opt).unwrap();
+
+        assert!(driver.is_offline());
+        assert_eq!(
+            driver.connection.method_timeouts.get("state_getMetadata"),
+            Some(&Duration::from_secs(30))
+        );
+        assert_eq!(driver.limits.max_blocks, Some(10));
+        assert!(driver.rpc.is_none());
+
+        // the labels are loaded when the driver is built.
+        let opt = Opt::parse_from(["substrate-timetravel", "--labels=./missing.csv", "version"]);
+        assert!(Driver::new(opt).is_err());
+    }
+
+    fn mocked_driver(args: &[&str], chain: &str) -> Driver {
+        let opt = Opt::parse_from(["substrate-timetravel"].iter().chain(args));
+        let rpc = SharedRpcClient::mock([
+            ("system_chain", serde_json::json!(chain)),
+            (
+                "chain_getBlockHash",
+                serde_json::json!(format!("{:?}", Hash::repeat_byte(1))),
+            ),
+            (
+                "state_getRuntimeVersion",
+                serde_json::json!({
+                    "specName": "westend",
+                    "implName": "parity-westend",
+                    "authoringVersion": 2,
+                    "specVersion": 9430,
+                    "implVersion": 0,
+                    "apis": [],
+                    "transactionVersion": 22,
+                    "stateVersion": 1,
+                }),
+            ),
+        ]);
+        Driver::new(opt).unwrap().with_rpc(rpc)
+    }
+
+    #[tokio::test]
+    async fn select_runtime_works() {
+        let mut driver = mocked_driver(&["runtime-upgrades"], "Westend");
+        let chain = driver.rpc.as_ref().unwrap().system_chain().await.unwrap();
+        driver.select_runtime(&chain);
+
+        assert_eq!(driver.runtime, AnyRuntime::Westend);
+        assert_eq!(
+            driver.ext_config.runtime_mode,
+            configs::RuntimeMode::Compiled
+        );
+
+        // the chains without a compiled runtime are decoded with their metadata.
+        let mut driver = mocked_driver(&["runtime-upgrades"], "Rococo");
+        let chain = driver.rpc.as_ref().unwrap().system_chain().await.unwrap();
+        driver.select_runtime(&chain);
+
+        assert_eq!(
+            driver.ext_config.runtime_mode,
+            configs::RuntimeMode::Metadata
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch_works() {
+        let output_path = std::env::temp_dir().join("substrate_timetravel_dispatch.csv");
+        let output_path = output_path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&output_path);
+
+        let mut driver = mocked_driver(&["runtime-upgrades", "--from=5", "--to=5"], "Westend");
+        let rpc = driver.rpc.take().unwrap();
+        driver.dispatch(rpc, output_path.clone()).await.unwrap();
+
+        let output = std::fs::read_to_string(&output_path).unwrap();
+        assert!(output.starts_with("block_number,block_hash,spec_name,spec_version"));
+        assert!(output.contains(&format!("5,{:?},westend,9430", Hash::repeat_byte(1))));
+        std::fs::remove_file(&output_path).unwrap();
+
+        // the methods which are not mocked fail the command.
+        let mut driver = mocked_driver(&["lookup-keys", "0x00"], "Westend");
+        let rpc = driver.rpc.take().unwrap();
+        assert!(driver.dispatch(rpc, output_path).await.is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Gadgets to anonymize the accounts of an externalities.
+//!
+//! Accounts are remapped to deterministic pseudonyms derived from a secret seed. The pseudonyms
+//! replace every occurrence of the accounts in the storage keys and values, which preserves the
+//! staking graph (bonds, nominations, payees) and the stake of each account. Storage map keys
+//! hashed with `twox64concat` or `blake2_128concat` are rehashed, whereas opaque hashers of
+//! accounts are left untouched.
+
+use crate::prelude::*;
+
+use codec::Encode;
+use sp_core::hashing::{blake2_128, blake2_256, twox_64};
+use std::collections::{BTreeSet, HashMap};
+
+/// Maps the encoded accounts of the staking pallet to their encoded pseudonyms.
+pub(crate) type Pseudonyms = HashMap<Vec<u8>, Vec<u8>>;
+
+/// Computes the pseudonyms of all the stashes, controllers, nominated targets and payees of the
+/// staking pallet.
+pub(crate) fn pseudonyms<T: Staking::Config>(ext: &mut Ext, seed: &str) -> Pseudonyms {
+    let accounts = ext.execute_with(|| {
+        let mut accounts = BTreeSet::new();
+
+        for (stash, controller) in Staking::Bonded::<T>::iter() {
+            accounts.insert(stash.encode());
+            accounts.insert(controller.encode());
+        }
+        for validator in Staking::Validators::<T>::iter_keys() {
+            accounts.insert(validator.encode());
+        }
+        for (nominator, nominations) in Staking::Nominators::<T>::iter() {
+            accounts.insert(nominator.encode());
+            accounts.extend(nominations.targets.iter().map(|t| t.encode()));
+        }
+        for (_, payee) in Staking::Payee::<T>::iter() {
+            if let Staking::RewardDestination::Account(account) = payee {
+                accounts.insert(account.encode());
+            }
+        }
+
+        accounts
+    });
+
+    accounts
+        .into_iter()
+        .map(|account| {
+            let pseudonym = blake2_256(&(seed, &account).encode())[..account.len()].to_vec();
+            (account, pseudonym)
+        })
+        .collect()
+}
+
+/// Replaces all the accounts in the storage of `ext` by their pseudonyms. Returns the number of
+/// storage entries rewritten.
+pub(crate) fn anonymize(ext: &mut Ext, pseudonyms: &Pseudonyms) -> usize {
+    let account_len = match pseudonyms.keys().next() {
+        Some(account) => account.len(),
+        None => return 0,
+    };
+
+    ext.execute_with(|| {
+        let mut pairs = vec![];
+        let mut key = vec![];
+        while let Some(next) = sp_io::storage::next_key(&key) {
+            let value = sp_io::storage::get(&next)
+                .expect("key exists; qed.")
+                .to_vec();
+            pairs.push((next.clone(), value));
+            key = next;
+        }
+
+        let mut rewritten = 0;
+        for (key, value) in pairs {
+            let new_key = replace_accounts(&key, pseudonyms, account_len, true);
+            let new_value = replace_accounts(&value, pseudonyms, account_len, false);
+
+            if new_key == key && new_value == value {
+                continue;
+            }
+            if new_key != key {
+                sp_io::storage::clear(&key);
+            }
+            sp_io::storage::set(&new_key, &new_value);
+            rewritten += 1;
+        }
+
+        rewritten
+    })
+}
+
+/// Replaces the accounts in `bytes` by their pseudonyms. If `rehash` is set, the
+/// `twox64concat`/`blake2_128concat` hashes preceding an account are replaced by the hashes of
+/// its pseudonym.
+pub(crate) fn replace_accounts(
+    bytes: &[u8],
+    pseudonyms: &Pseudonyms,
+    account_len: usize,
+    rehash: bool,
+) -> Vec<u8> {
+    let mut result = bytes.to_vec();
+    if bytes.len() < account_len {
+        return result;
+    }
+
+    let mut i = 0;
+    while i + account_len <= bytes.len() {
+        let account = &bytes[i..i + account_len];
+        let pseudonym = match pseudonyms.get(account) {
+            Some(pseudonym) => pseudonym,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+
+        result[i..i + account_len].copy_from_slice(pseudonym);
+
+        if rehash {
+            if i >= 8 && bytes[i - 8..i] == twox_64(account) {
+                result[i - 8..i].copy_from_slice(&twox_64(pseudonym));
+            } else if i >= 16 && bytes[i - 16..i] == blake2_128(account) {
+                result[i - 16..i].copy_from_slice(&blake2_128(pseudonym));
+            }
+        }
+
+        i += account_len;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_accounts_works() {
+        let (alice, bob, charlie) = ([1u8; 32].to_vec(), [2u8; 32].to_vec(), [3u8; 32].to_vec());
+        let pseudonyms: Pseudonyms = vec![(alice.clone(), bob.clone())].into_iter().collect();
+        let prefix = [0u8; 32].to_vec();
+
+        // twox64concat keys are rehashed.
+        let key = [prefix.clone(), twox_64(&alice).to_vec(), alice.clone()].concat();
+        assert_eq!(
+            replace_accounts(&key, &pseudonyms, 32, true),
+            [prefix.clone(), twox_64(&bob).to_vec(), bob.clone()].concat()
+        );
+
+        // blake2_128concat keys are rehashed.
+        let key = [prefix.clone(), blake2_128(&alice).to_vec(), alice.clone()].concat();
+        assert_eq!(
+            replace_accounts(&key, &pseudonyms, 32, true),
+            [prefix.clone(), blake2_128(&bob).to_vec(), bob.clone()].concat()
+        );
+
+        // values are replaced without rehashing and unknown accounts are kept.
+        let value = [vec![0u8; 4], alice.clone(), charlie.clone(), alice].concat();
+        assert_eq!(
+            replace_accounts(&value, &pseudonyms, 32, false),
+            [vec![0u8; 4], bob.clone(), charlie, bob].concat()
+        );
+
+        // bytes shorter than an account are kept.
+        assert_eq!(
+            replace_accounts(&[1u8; 4], &pseudonyms, 32, false),
+            vec![1u8; 4]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Gadgets to attribute blocks to their authors.
+//!
+//! The author of a block is derived from the Babe pre-runtime digest of the block, which is kept
+//! in `System::Digest` until the next block is initialized, and the session validators, whose
+//! order matches the order of the Babe authorities. Both are read from the externalities, which
+//! therefore must be extracted with the `System::Digest` and `Session::Validators` keys.
+
+use crate::prelude::*;
+use crate::utils::babe_authority_index;
+
+use sp_core::hashing::twox_128;
+use sp_staking::EraIndex;
+use std::collections::BTreeMap;
+
+/// The author of a block.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BlockAuthor<A> {
+    /// The index of the author in the Babe authorities (and session validators).
+    pub authority_index: u32,
+    pub author: A,
+}
+
+/// Returns the author of the current block, if the externalities has a Babe pre-runtime digest
+/// and the session validators.
+pub(crate) fn block_author<T: frame_system::Config>(
+    ext: &mut Ext,
+) -> Option<BlockAuthor<AccountIdOf<T>>> {
+    ext.execute_with(|| {
+        let authority_index = babe_authority_index(&frame_system::Pallet::<T>::digest())?;
+        let validators: Vec<AccountIdOf<T>> = frame_support::storage::unhashed::get(
+            &[twox_128(b"Session"), twox_128(b"Validators")].concat(),
+        )?;
+
+        validators
+            .get(authority_index as usize)
+            .cloned()
+            .map(|author| BlockAuthor {
+                authority_index,
+                author,
+            })
+    })
+}
+
+/// Returns the reward points of each validator in `era`.
+pub(crate) fn era_reward_points<T: Staking::Config>(
+    ext: &mut Ext,
+    era: EraIndex,
+) -> BTreeMap<AccountIdOf<T>, u32> {
+    ext.execute_with(|| {
+        Staking::ErasRewardPoints::<T>::get(era)
+            .individual
+            .into_iter()
+            .collect()
+    })
+}
+
+/// Returns whether `validator` was slashed in `era`, e.g. as the result of an equivocation.
+pub(crate) fn slashed_in_era<T: Staking::Config>(
+    ext: &mut Ext,
+    era: EraIndex,
+    validator: &AccountIdOf<T>,
+) -> bool {
+    ext.execute_with(|| Staking::ValidatorSlashInEra::<T>::get(era, validator).is_some())
+}
+
+/// The blocks authored by a validator in an era.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct AuthoredBlocks {
+    pub count: u32,
+    pub first: BlockNumber,
+    pub last: BlockNumber,
+}
+
+/// Aggregates the authored blocks, given as `(era, block number, author)`, per era and author.
+pub(crate) fn aggregate_authorship<A: Ord + Clone>(
+    blocks: &[(EraIndex, BlockNumber, A)],
+) -> BTreeMap<(EraIndex, A), AuthoredBlocks> {
+    let mut authored = BTreeMap::<_, AuthoredBlocks>::new();
+
+    for (era, block_number, author) in blocks {
+        authored
+            .entry((*era, author.clone()))
+            .and_modify(|a| {
+                a.count += 1;
+                a.first = a.first.min(*block_number);
+                a.last = a.last.max(*block_number);
+            })
+            .or_insert(AuthoredBlocks {
+                count: 1,
+                first: *block_number,
+                last: *block_number,
+            });
+    }
+
+    authored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_authorship_works() {
+        let blocks = vec![
+            (1, 12, "alice"),
+            (1, 10, "alice"),
+            (1, 11, "bob"),
+            (2, 20, "alice"),
+            (1, 15, "alice"),
+        ];
+
+        let authored = aggregate_authorship(&blocks);
+
+        assert_eq!(authored.len(), 3);
+        assert_eq!(
+            authored[&(1, "alice")],
+            AuthoredBlocks {
+                count: 3,
+                first: 10,
+                last: 15
+            }
+        );
+        assert_eq!(
+            authored[&(1, "bob")],
+            AuthoredBlocks {
+                count: 1,
+                first: 11,
+                last: 11
+            }
+        );
+        assert_eq!(authored[&(2, "alice")].count, 1);
+    }
+}
@@ -0,0 +1,86 @@
+//! Gadgets to load the state of an externalities into a chain spec.
+//!
+//! The top storage of the externalities is written as the raw genesis storage of a chain spec, so
+//! that a local node can be booted from the (possibly transformed) historical state. The state
+//! must include `:code` and the storage of all the pallets the runtime relies on to start.
+
+use crate::TimetravelError;
+
+use serde_json::{json, Map, Value};
+
+/// Returns a raw chain spec with the storage `pairs` as genesis storage.
+///
+/// If a `base` chain spec is provided, its genesis is replaced and everything else (e.g. name,
+/// boot nodes and properties) is kept. Otherwise, a minimal local chain spec named `name` is
+/// returned.
+pub(crate) fn raw_chain_spec(
+    base: Option<Value>,
+    name: &str,
+    pairs: &[(Vec<u8>, Vec<u8>)],
+) -> Result<Value, anyhow::Error> {
+    let top = pairs
+        .iter()
+        .map(|(key, value)| {
+            (
+                sp_core::bytes::to_hex(key, false),
+                Value::String(sp_core::bytes::to_hex(value, false)),
+            )
+        })
+        .collect::<Map<_, _>>();
+    let genesis = json!({ "raw": { "top": top, "childrenDefault": {} } });
+
+    let mut spec = base.unwrap_or_else(|| {
+        json!({
+            "name": name,
+            "id": "timetravel",
+            "chainType": "Local",
+            "bootNodes": [],
+            "telemetryEndpoints": null,
+            "protocolId": null,
+            "properties": null,
+            "codeSubstitutes": {},
+        })
+    });
+
+    match spec.as_object_mut() {
+        Some(spec) => {
+            spec.insert("genesis".to_string(), genesis);
+        }
+        None => {
+            return Err(TimetravelError::Unsupported {
+                reason: "base chain spec is not a JSON object.".to_string(),
+            }
+            .into())
+        }
+    }
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_chain_spec_works() {
+        let pairs = vec![(b":code".to_vec(), vec![1, 2]), (vec![0xab], vec![])];
+
+        let spec = raw_chain_spec(None, "timetravel-fork", &pairs).unwrap();
+        assert_eq!(spec["name"], "timetravel-fork");
+        assert_eq!(spec["genesis"]["raw"]["top"]["0x3a636f6465"], "0x0102");
+        assert_eq!(spec["genesis"]["raw"]["top"]["0xab"], "0x");
+
+        let base = json!({
+            "name": "Development",
+            "bootNodes": ["/ip4/127.0.0.1/tcp/30333"],
+            "genesis": { "runtimeGenesis": {} },
+        });
+        let spec = raw_chain_spec(Some(base), "timetravel-fork", &pairs).unwrap();
+        assert_eq!(spec["name"], "Development");
+        assert_eq!(spec["bootNodes"][0], "/ip4/127.0.0.1/tcp/30333");
+        assert!(spec["genesis"].get("runtimeGenesis").is_none());
+        assert_eq!(spec["genesis"]["raw"]["top"]["0x3a636f6465"], "0x0102");
+
+        assert!(raw_chain_spec(Some(json!([])), "timetravel-fork", &pairs).is_err());
+    }
+}
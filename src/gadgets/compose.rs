@@ -0,0 +1,213 @@
+//! Gadgets to compose an externalities from multiple snapshots.
+//!
+//! A patch snapshot (e.g. with only the `ElectionProviderMultiPhase` pallet at a block) is
+//! overlaid on top of a base snapshot (e.g. with the `Staking` pallet at the same block), which
+//! is useful when the pallets were scraped at different times. The storage of each pallet in the
+//! patch replaces the storage of the same pallet in the base, and well-known keys (e.g. `:code`)
+//! are replaced one by one, as are the standalone keys extracted with every snapshot (e.g.
+//! `Balances::TotalIssuance`), which do not stand for the storage of their pallet.
+
+use crate::prelude::*;
+
+use sp_core::hashing::twox_128;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The storage items extracted with every snapshot besides the storage of its pallets, as
+/// (pallet, item).
+const STANDALONE_ITEMS: [(&str, &str); 6] = [
+    ("System", "Number"),
+    ("Balances", "TotalIssuance"),
+    ("System", "Digest"),
+    ("System", "LastRuntimeUpgrade"),
+    ("Session", "Validators"),
+    ("Timestamp", "Now"),
+];
+
+/// The storage maps extracted with every snapshot besides the storage of its pallets, as
+/// (pallet, item).
+const STANDALONE_MAPS: [(&str, &str); 1] = [("System", "BlockHash")];
+
+fn item_key(pallet: &str, item: &str) -> Vec<u8> {
+    [twox_128(pallet.as_bytes()), twox_128(item.as_bytes())].concat()
+}
+
+/// The keys of the storage items extracted with every snapshot.
+pub(crate) fn standalone_keys() -> Vec<Vec<u8>> {
+    STANDALONE_ITEMS
+        .iter()
+        .map(|(pallet, item)| item_key(pallet, item))
+        .collect()
+}
+
+/// The prefixes of the storage maps extracted with every snapshot.
+pub(crate) fn standalone_prefixes() -> Vec<Vec<u8>> {
+    STANDALONE_MAPS
+        .iter()
+        .map(|(pallet, item)| item_key(pallet, item))
+        .collect()
+}
+
+/// Whether `key` is extracted with every snapshot, regardless of its pallets.
+fn is_standalone(key: &[u8], keys: &[Vec<u8>], prefixes: &[Vec<u8>]) -> bool {
+    keys.iter().any(|k| k == key) || prefixes.iter().any(|prefix| key.starts_with(prefix))
+}
+
+/// Returns all the top storage key-value pairs of the externalities.
+pub(crate) fn storage_pairs(ext: &mut Ext) -> Vec<(Vec<u8>, Vec<u8>)> {
+    ext.execute_with(|| {
+        let mut pairs = vec![];
+        let mut key = vec![];
+        while let Some(next) = sp_io::storage::next_key(&key) {
+            if let Some(value) = sp_io::storage::get(&next) {
+                pairs.push((next.clone(), value.to_vec()));
+            }
+            key = next;
+        }
+        pairs
+    })
+}
+
+/// The change of a storage key between two states.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum KeyChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Returns the keys added, removed and changed from the `before` to the `after` storage pairs, in
+/// key order.
+pub(crate) fn storage_diff<'a>(
+    before: &'a BTreeMap<Vec<u8>, Vec<u8>>,
+    after: &'a BTreeMap<Vec<u8>, Vec<u8>>,
+) -> Vec<(&'a [u8], KeyChange)> {
+    let keys = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+
+    keys.into_iter()
+        .filter_map(|key| match (before.get(key), after.get(key)) {
+            (None, Some(_)) => Some((key.as_slice(), KeyChange::Added)),
+            (Some(_), None) => Some((key.as_slice(), KeyChange::Removed)),
+            (Some(a), Some(b)) if a != b => Some((key.as_slice(), KeyChange::Changed)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the pallet prefixes (i.e. the first 16 bytes) of the keys, excluding the well-known
+/// keys and the standalone keys extracted with every snapshot, i.e. the prefixes of the pallets a
+/// patch was extracted with.
+pub(crate) fn pallet_prefixes<'a>(keys: impl IntoIterator<Item = &'a [u8]>) -> BTreeSet<Vec<u8>> {
+    let (standalone_keys, standalone_prefixes) = (standalone_keys(), standalone_prefixes());
+
+    keys.into_iter()
+        .filter(|key| !key.starts_with(b":") && key.len() >= 16)
+        .filter(|key| !is_standalone(key, &standalone_keys, &standalone_prefixes))
+        .map(|key| key[..16].to_vec())
+        .collect()
+}
+
+/// Overlays the storage `pairs` of a patch on top of the externalities, replacing the storage of
+/// the pallets the patch was extracted with, and setting the other keys of the patch one by one.
+/// Returns the number of pallets replaced.
+pub(crate) fn overlay(ext: &mut Ext, pairs: &[(Vec<u8>, Vec<u8>)]) -> usize {
+    let prefixes = pallet_prefixes(pairs.iter().map(|(key, _)| key.as_slice()));
+
+    ext.execute_with(|| {
+        for prefix in prefixes.iter() {
+            let _ = sp_io::storage::clear_prefix(prefix, None);
+        }
+        for (key, value) in pairs {
+            sp_io::storage::set(key, value);
+        }
+    });
+
+    prefixes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_works() {
+        let staking = [1u8; 16];
+        let epm = [2u8; 16];
+        let key = |prefix: &[u8; 16], suffix: u8| [prefix.as_slice(), &[suffix]].concat();
+
+        let mut base = Ext::default();
+        base.execute_with(|| {
+            sp_io::storage::set(&key(&staking, 1), b"base");
+            sp_io::storage::set(&key(&epm, 1), b"base");
+            sp_io::storage::set(&key(&epm, 2), b"stale");
+            sp_io::storage::set(b":code", b"base");
+        });
+
+        let patch = vec![
+            (key(&epm, 1), b"patch".to_vec()),
+            (b":code".to_vec(), b"patch".to_vec()),
+        ];
+        assert_eq!(overlay(&mut base, &patch), 1);
+
+        let pairs = storage_pairs(&mut base);
+        assert!(pairs.contains(&(key(&staking, 1), b"base".to_vec())));
+        assert!(pairs.contains(&(key(&epm, 1), b"patch".to_vec())));
+        assert!(pairs.contains(&(b":code".to_vec(), b"patch".to_vec())));
+        assert!(!pairs.iter().any(|(k, _)| k == &key(&epm, 2)));
+    }
+
+    #[test]
+    fn overlay_keeps_pallets_of_standalone_keys() {
+        let epm = twox_128(b"ElectionProviderMultiPhase");
+        let account = [
+            twox_128(b"Balances").as_slice(),
+            &twox_128(b"Account"),
+            &[1],
+        ]
+        .concat();
+        let total_issuance = item_key("Balances", "TotalIssuance");
+        let block_hash = [item_key("System", "BlockHash"), vec![1]].concat();
+
+        let mut base = Ext::default();
+        base.execute_with(|| {
+            sp_io::storage::set(&account, b"base");
+            sp_io::storage::set(&total_issuance, b"base");
+        });
+
+        // a patch of the EPM pallet carries the standalone keys of its extraction.
+        let patch = vec![
+            ([epm.as_slice(), &[1]].concat(), b"patch".to_vec()),
+            (total_issuance.clone(), b"patch".to_vec()),
+            (block_hash.clone(), b"patch".to_vec()),
+        ];
+        assert_eq!(overlay(&mut base, &patch), 1);
+
+        let pairs = storage_pairs(&mut base);
+        assert!(pairs.contains(&(account, b"base".to_vec())));
+        assert!(pairs.contains(&(total_issuance, b"patch".to_vec())));
+        assert!(pairs.contains(&(block_hash, b"patch".to_vec())));
+    }
+
+    #[test]
+    fn storage_diff_works() {
+        let pairs = |pairs: &[(u8, u8)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (vec![*k], vec![*v]))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let (before, after) = (
+            pairs(&[(1, 1), (2, 2), (3, 3)]),
+            pairs(&[(2, 2), (3, 4), (5, 5)]),
+        );
+
+        assert_eq!(
+            storage_diff(&before, &after),
+            vec![
+                (&[1u8][..], KeyChange::Removed),
+                (&[3u8][..], KeyChange::Changed),
+                (&[5u8][..], KeyChange::Added),
+            ]
+        );
+        assert!(storage_diff(&before, &before).is_empty());
+    }
+}
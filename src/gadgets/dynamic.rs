@@ -0,0 +1,594 @@
+//! Gadgets to decode the storage of the externalities from the runtime metadata.
+//!
+//! Unlike the rest of the gadgets, which decode the storage with the types of the runtime compiled
+//! into the CLI, these gadgets resolve the storage items and their types from the metadata of the
+//! block (as `subxt` does), so that they work against any Substrate chain. The values are decoded
+//! into a dynamic [`Value`], which is traversed by field name.
+
+use crate::gadgets::metadata::PalletsMetadata;
+use crate::prelude::*;
+use crate::TimetravelError;
+
+use codec::{Compact, Decode, Encode};
+use frame_metadata::v14::{
+    StorageEntryMetadata, StorageEntryModifier, StorageEntryType, StorageHasher,
+};
+use scale_info::{form::PortableForm, PortableRegistry, TypeDef, TypeDefPrimitive};
+use sp_core::hashing::{blake2_128, blake2_256, twox_128, twox_256, twox_64};
+use std::collections::BTreeMap;
+
+/// A SCALE value decoded from its type in the metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Bool(bool),
+    Uint(u128),
+    Int(i128),
+    Str(String),
+    /// Sequences and arrays of bytes, e.g. account ids and hashes.
+    Bytes(Vec<u8>),
+    Composite(Vec<(Option<String>, Value)>),
+    Variant {
+        name: String,
+        fields: Vec<(Option<String>, Value)>,
+    },
+    Sequence(Vec<Value>),
+}
+
+impl Value {
+    /// Returns the field `name` of a composite or variant value.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Composite(fields) | Value::Variant { fields, .. } => fields
+                .iter()
+                .find(|(n, _)| n.as_deref() == Some(name))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an unsigned integer, unwrapping single field composites (e.g.
+    /// `Perbill`).
+    pub fn as_u128(&self) -> Option<u128> {
+        match self {
+            Value::Uint(v) => Some(*v),
+            Value::Composite(fields) if fields.len() == 1 => fields[0].1.as_u128(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as bytes, unwrapping single field composites (e.g. `AccountId32`).
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(v) => Some(v),
+            Value::Composite(fields) if fields.len() == 1 => fields[0].1.as_bytes(),
+            _ => None,
+        }
+    }
+
+    /// Returns the items of a sequence value.
+    pub fn as_sequence(&self) -> Option<&[Value]> {
+        match self {
+            Value::Sequence(items) => Some(items),
+            Value::Composite(fields) if fields.len() == 1 => fields[0].1.as_sequence(),
+            _ => None,
+        }
+    }
+}
+
+/// Renders the value in a compact human readable form, e.g. `{stash: 5F.., total: 10}`. Sequences
+/// of 32 bytes are rendered as SS58 account ids, and the other bytes as hex strings.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn fields(
+            f: &mut std::fmt::Formatter<'_>,
+            fields: &[(Option<String>, Value)],
+        ) -> std::fmt::Result {
+            let named = fields.iter().all(|(name, _)| name.is_some());
+            write!(f, "{}", if named { "{" } else { "(" })?;
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                match name {
+                    Some(name) if named => write!(f, "{}: {}", name, value)?,
+                    _ => write!(f, "{}", value)?,
+                }
+            }
+            write!(f, "{}", if named { "}" } else { ")" })
+        }
+
+        match self {
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Uint(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{:?}", v),
+            Value::Bytes(v) if v.len() == 32 => {
+                let mut raw = [0u8; 32];
+                raw.copy_from_slice(v);
+                write!(f, "{}", sp_core::crypto::AccountId32::from(raw))
+            }
+            Value::Bytes(v) => write!(f, "{}", sp_core::bytes::to_hex(v, false)),
+            // newtypes (e.g. `Perbill`) are rendered as their inner value.
+            Value::Composite(inner) if inner.len() == 1 && inner[0].0.is_none() => {
+                write!(f, "{}", inner[0].1)
+            }
+            Value::Composite(inner) => fields(f, inner),
+            Value::Variant {
+                name,
+                fields: inner,
+            } if inner.is_empty() => write!(f, "{}", name),
+            Value::Variant {
+                name,
+                fields: inner,
+            } => {
+                write!(f, "{}", name)?;
+                fields(f, inner)
+            }
+            Value::Sequence(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Decodes a SCALE encoded value of type `ty` from `input`. Returns `None` if the value could not
+/// be decoded.
+pub(crate) fn decode(types: &PortableRegistry, ty: u32, input: &mut &[u8]) -> Option<Value> {
+    let is_byte = |ty: u32| {
+        matches!(
+            types.resolve(ty).map(|t| &t.type_def),
+            Some(TypeDef::Primitive(TypeDefPrimitive::U8))
+        )
+    };
+    let take_bytes = |input: &mut &[u8], len: usize| {
+        if input.len() < len {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(len);
+        *input = rest;
+        Some(bytes.to_vec())
+    };
+    let fields_of = |fields: &[scale_info::Field<PortableForm>], input: &mut &[u8]| {
+        fields
+            .iter()
+            .map(|f| Some((f.name.clone(), decode(types, f.ty.id, input)?)))
+            .collect::<Option<Vec<_>>>()
+    };
+
+    let value = match &types.resolve(ty)?.type_def {
+        TypeDef::Composite(composite) => Value::Composite(fields_of(&composite.fields, input)?),
+        TypeDef::Variant(variant) => {
+            let index = u8::decode(input).ok()?;
+            let variant = variant.variants.iter().find(|v| v.index == index)?;
+            Value::Variant {
+                name: variant.name.clone(),
+                fields: fields_of(&variant.fields, input)?,
+            }
+        }
+        TypeDef::Sequence(sequence) => {
+            let len = Compact::<u32>::decode(input).ok()?.0;
+            if is_byte(sequence.type_param.id) {
+                Value::Bytes(take_bytes(input, len as usize)?)
+            } else {
+                Value::Sequence(
+                    (0..len)
+                        .map(|_| decode(types, sequence.type_param.id, input))
+                        .collect::<Option<_>>()?,
+                )
+            }
+        }
+        TypeDef::Array(array) => {
+            if is_byte(array.type_param.id) {
+                Value::Bytes(take_bytes(input, array.len as usize)?)
+            } else {
+                Value::Sequence(
+                    (0..array.len)
+                        .map(|_| decode(types, array.type_param.id, input))
+                        .collect::<Option<_>>()?,
+                )
+            }
+        }
+        TypeDef::Tuple(tuple) => Value::Composite(
+            tuple
+                .fields
+                .iter()
+                .map(|f| Some((None, decode(types, f.id, input)?)))
+                .collect::<Option<_>>()?,
+        ),
+        TypeDef::Primitive(primitive) => match primitive {
+            TypeDefPrimitive::Bool => Value::Bool(bool::decode(input).ok()?),
+            TypeDefPrimitive::Char => Value::Str(char::from_u32(u32::decode(input).ok()?)?.into()),
+            TypeDefPrimitive::Str => Value::Str(String::decode(input).ok()?),
+            TypeDefPrimitive::U8 => Value::Uint(u8::decode(input).ok()?.into()),
+            TypeDefPrimitive::U16 => Value::Uint(u16::decode(input).ok()?.into()),
+            TypeDefPrimitive::U32 => Value::Uint(u32::decode(input).ok()?.into()),
+            TypeDefPrimitive::U64 => Value::Uint(u64::decode(input).ok()?.into()),
+            TypeDefPrimitive::U128 => Value::Uint(u128::decode(input).ok()?),
+            TypeDefPrimitive::I8 => Value::Int(i8::decode(input).ok()?.into()),
+            TypeDefPrimitive::I16 => Value::Int(i16::decode(input).ok()?.into()),
+            TypeDefPrimitive::I32 => Value::Int(i32::decode(input).ok()?.into()),
+            TypeDefPrimitive::I64 => Value::Int(i64::decode(input).ok()?.into()),
+            TypeDefPrimitive::I128 => Value::Int(i128::decode(input).ok()?),
+            TypeDefPrimitive::U256 | TypeDefPrimitive::I256 => Value::Bytes(take_bytes(input, 32)?),
+        },
+        TypeDef::Compact(_) => Value::Uint(Compact::<u128>::decode(input).ok()?.0),
+        TypeDef::BitSequence(_) => return None,
+    };
+
+    Some(value)
+}
+
+/// Hashes an encoded map `key` with `hasher`, as in the storage key of a map item.
+pub(crate) fn hash_key(hasher: &StorageHasher, key: &[u8]) -> Vec<u8> {
+    match hasher {
+        StorageHasher::Blake2_128 => blake2_128(key).to_vec(),
+        StorageHasher::Blake2_256 => blake2_256(key).to_vec(),
+        StorageHasher::Blake2_128Concat => [blake2_128(key).as_slice(), key].concat(),
+        StorageHasher::Twox128 => twox_128(key).to_vec(),
+        StorageHasher::Twox256 => twox_256(key).to_vec(),
+        StorageHasher::Twox64Concat => [twox_64(key).as_slice(), key].concat(),
+        StorageHasher::Identity => key.to_vec(),
+    }
+}
+
+/// Read access to the storage items of the externalities, as described by the metadata.
+pub(crate) struct DynamicStorage<'a> {
+    metadata: &'a PalletsMetadata,
+}
+
+impl<'a> DynamicStorage<'a> {
+    pub fn new(metadata: &'a PalletsMetadata) -> Self {
+        Self { metadata }
+    }
+
+    /// Returns the hashed prefix and the metadata of the storage `item` of `pallet`.
+    fn entry(
+        &self,
+        pallet: &str,
+        item: &str,
+    ) -> Result<(Vec<u8>, &'a StorageEntryMetadata<PortableForm>), anyhow::Error> {
+        self.metadata
+            .pallets
+            .iter()
+            .filter(|p| p.name == pallet)
+            .filter_map(|p| p.storage.as_ref())
+            .find_map(|storage| {
+                let entry = storage.entries.iter().find(|e| e.name == item)?;
+                let prefix = [
+                    twox_128(storage.prefix.as_bytes()),
+                    twox_128(entry.name.as_bytes()),
+                ]
+                .concat();
+                Some((prefix, entry))
+            })
+            .ok_or_else(|| {
+                TimetravelError::Unsupported {
+                    reason: format!("storage item {}::{} is not in the metadata.", pallet, item),
+                }
+                .into()
+            })
+    }
+
+    fn decode_entry(
+        &self,
+        pallet: &str,
+        item: &str,
+        ty: u32,
+        value: &[u8],
+    ) -> Result<Value, anyhow::Error> {
+        decode(&self.metadata.types, ty, &mut &value[..]).ok_or_else(|| {
+            TimetravelError::Decode {
+                error: format!("value of {}::{}.", pallet, item),
+            }
+            .into()
+        })
+    }
+
+    /// Returns whether the storage `item` of `pallet` is in the metadata.
+    pub fn has_item(&self, pallet: &str, item: &str) -> bool {
+        self.entry(pallet, item).is_ok()
+    }
+
+    /// Reads the value of the plain storage `item` of `pallet`. Missing values are `None`, unless
+    /// the item has a default value.
+    pub fn value(
+        &self,
+        ext: &mut Ext,
+        pallet: &str,
+        item: &str,
+    ) -> Result<Option<Value>, anyhow::Error> {
+        let key = self.key(pallet, item, None)?;
+        let value = ext.execute_with(|| sp_io::storage::get(&key));
+
+        self.decode_stored(pallet, item, value.as_deref())
+    }
+
+    /// Returns the storage key of the storage `item` of `pallet`, under the (SCALE encoded)
+    /// `map_key` if the item is a map. Only the maps with a single key are supported.
+    pub fn key(
+        &self,
+        pallet: &str,
+        item: &str,
+        map_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let (prefix, entry) = self.entry(pallet, item)?;
+        match (&entry.ty, map_key) {
+            (StorageEntryType::Plain(_), None) => Ok(prefix),
+            (StorageEntryType::Map { hashers, .. }, Some(map_key)) if hashers.len() == 1 => {
+                Ok([prefix, hash_key(&hashers[0], map_key)].concat())
+            }
+            (StorageEntryType::Plain(_), Some(_)) => Err(TimetravelError::Unsupported {
+                reason: format!("storage item {}::{} is not a map.", pallet, item),
+            }
+            .into()),
+            (StorageEntryType::Map { .. }, None) => Err(TimetravelError::Unsupported {
+                reason: format!("storage item {}::{} is a map.", pallet, item),
+            }
+            .into()),
+            (StorageEntryType::Map { .. }, Some(_)) => Err(TimetravelError::Unsupported {
+                reason: format!(
+                    "storage item {}::{} is not a map with a single key.",
+                    pallet, item
+                ),
+            }
+            .into()),
+        }
+    }
+
+    /// Decodes the `value` stored under the storage `item` of `pallet`. Missing values are
+    /// `None`, unless the item has a default value.
+    pub fn decode_stored(
+        &self,
+        pallet: &str,
+        item: &str,
+        value: Option<&[u8]>,
+    ) -> Result<Option<Value>, anyhow::Error> {
+        let (_, entry) = self.entry(pallet, item)?;
+        let ty = match &entry.ty {
+            StorageEntryType::Plain(ty) => ty.id,
+            StorageEntryType::Map { value, .. } => value.id,
+        };
+
+        match value {
+            Some(value) => self.decode_entry(pallet, item, ty, value).map(Some),
+            None if entry.modifier == StorageEntryModifier::Default => self
+                .decode_entry(pallet, item, ty, &entry.default)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads all the values of the map storage `item` of `pallet`. If a `first_key` (SCALE
+    /// encoded) is provided, only the values under that first key are read, e.g. the exposures
+    /// of an era.
+    pub fn iter(
+        &self,
+        ext: &mut Ext,
+        pallet: &str,
+        item: &str,
+        first_key: Option<&[u8]>,
+    ) -> Result<Vec<Value>, anyhow::Error> {
+        let (mut prefix, entry) = self.entry(pallet, item)?;
+        let (hashers, ty) = match &entry.ty {
+            StorageEntryType::Map { hashers, value, .. } => (hashers, value.id),
+            StorageEntryType::Plain(_) => {
+                return Err(TimetravelError::Unsupported {
+                    reason: format!("storage item {}::{} is not a map.", pallet, item),
+                }
+                .into())
+            }
+        };
+        if let (Some(key), Some(hasher)) = (first_key, hashers.first()) {
+            prefix.extend(hash_key(hasher, key));
+        }
+
+        let values = ext.execute_with(|| {
+            let mut values = vec![];
+            let mut key = prefix.clone();
+            while let Some(next) = sp_io::storage::next_key(&key).filter(|k| k.starts_with(&prefix))
+            {
+                if let Some(value) = sp_io::storage::get(&next) {
+                    values.push(value.to_vec());
+                }
+                key = next;
+            }
+            values
+        });
+
+        values
+            .iter()
+            .map(|value| self.decode_entry(pallet, item, ty, value))
+            .collect()
+    }
+}
+
+/// Returns the lowest active stake of the nominators exposed in the active era, i.e. the sum of
+/// the stake of each nominator over the validators it is exposed to, from the legacy or the paged
+/// exposures.
+pub(crate) fn min_exposed_stake(
+    storage: &DynamicStorage,
+    ext: &mut Ext,
+) -> Result<Option<u128>, anyhow::Error> {
+    let era = storage
+        .value(ext, "Staking", "ActiveEra")?
+        .and_then(|active_era| active_era.field("index")?.as_u128())
+        .ok_or(TimetravelError::missing("Active era"))? as u32;
+
+    let mut exposures = vec![];
+    if storage.has_item("Staking", "ErasStakers") {
+        exposures = storage.iter(ext, "Staking", "ErasStakers", Some(&era.encode()))?;
+    }
+    if exposures.is_empty() && storage.has_item("Staking", "ErasStakersPaged") {
+        exposures = storage.iter(ext, "Staking", "ErasStakersPaged", Some(&era.encode()))?;
+    }
+
+    let mut stakes = BTreeMap::<Vec<u8>, u128>::new();
+    for exposure in exposures.iter() {
+        let others = exposure
+            .field("others")
+            .and_then(|others| others.as_sequence())
+            .unwrap_or_default();
+
+        for individual in others {
+            let who = individual.field("who").and_then(|who| who.as_bytes());
+            let value = individual.field("value").and_then(|value| value.as_u128());
+            if let (Some(who), Some(value)) = (who, value) {
+                *stakes.entry(who.to_vec()).or_default() += value;
+            }
+        }
+    }
+
+    Ok(stakes.into_values().min())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::metadata::PalletMetadata;
+    use frame_metadata::v14::PalletStorageMetadata;
+    use pallet_staking::{ActiveEraInfo, Exposure, IndividualExposure};
+    use scale_info::{meta_type, Registry};
+    use sp_core::crypto::AccountId32;
+
+    fn exposure(others: Vec<(u8, u128)>) -> Exposure<AccountId32, u128> {
+        Exposure {
+            total: others.iter().map(|(_, value)| value).sum(),
+            own: 0,
+            others: others
+                .into_iter()
+                .map(|(who, value)| IndividualExposure {
+                    who: AccountId32::from([who; 32]),
+                    value,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn decode_works() {
+        let mut registry = Registry::new();
+        let ty = registry
+            .register_type(&meta_type::<Exposure<AccountId32, u128>>())
+            .id;
+        let types: PortableRegistry = registry.into();
+
+        let value = decode(&types, ty, &mut &exposure(vec![(1, 10)]).encode()[..]).unwrap();
+
+        assert_eq!(value.field("total").and_then(|v| v.as_u128()), Some(10));
+        let others = value.field("others").and_then(|v| v.as_sequence()).unwrap();
+        assert_eq!(others.len(), 1);
+        assert_eq!(
+            others[0].field("who").and_then(|v| v.as_bytes()),
+            Some([1u8; 32].as_slice())
+        );
+        assert_eq!(others[0].field("value").and_then(|v| v.as_u128()), Some(10));
+        assert_eq!(
+            value.to_string(),
+            format!(
+                "{{total: 10, own: 0, others: [{{who: {}, value: 10}}]}}",
+                AccountId32::from([1u8; 32])
+            )
+        );
+
+        assert_eq!(decode(&types, ty, &mut &[0u8; 4][..]), None);
+    }
+
+    #[test]
+    fn min_exposed_stake_works() {
+        let mut registry = Registry::new();
+        let era_ty = registry.register_type(&meta_type::<u32>());
+        let active_era_ty = registry.register_type(&meta_type::<ActiveEraInfo>());
+        let exposure_ty = registry.register_type(&meta_type::<Exposure<AccountId32, u128>>());
+        let types: PortableRegistry = registry.into();
+
+        let metadata = PalletsMetadata {
+            types,
+            extensions: vec![],
+            pallets: vec![PalletMetadata {
+                name: "Staking".to_string(),
+                storage: Some(PalletStorageMetadata {
+                    prefix: "Staking".to_string(),
+                    entries: vec![
+                        StorageEntryMetadata {
+                            name: "ActiveEra".to_string(),
+                            modifier: StorageEntryModifier::Optional,
+                            ty: StorageEntryType::Plain(active_era_ty),
+                            default: vec![],
+                            docs: vec![],
+                        },
+                        StorageEntryMetadata {
+                            name: "ErasStakers".to_string(),
+                            modifier: StorageEntryModifier::Default,
+                            ty: StorageEntryType::Map {
+                                hashers: vec![
+                                    StorageHasher::Twox64Concat,
+                                    StorageHasher::Twox64Concat,
+                                ],
+                                key: era_ty,
+                                value: exposure_ty,
+                            },
+                            default: vec![],
+                            docs: vec![],
+                        },
+                    ],
+                }),
+                constants: vec![],
+            }],
+        };
+
+        let prefix = |item: &str| [twox_128(b"Staking"), twox_128(item.as_bytes())].concat();
+        let exposure_key = |era: u32, validator: u8| {
+            [
+                prefix("ErasStakers"),
+                hash_key(&StorageHasher::Twox64Concat, &era.encode()),
+                hash_key(&StorageHasher::Twox64Concat, &[validator; 32]),
+            ]
+            .concat()
+        };
+
+        let mut ext = Ext::default();
+        ext.execute_with(|| {
+            let active_era = ActiveEraInfo {
+                index: 2,
+                start: None,
+            };
+            sp_io::storage::set(&prefix("ActiveEra"), &active_era.encode());
+            // nominator 10 backs both validators, and is exposed with 5 + 5.
+            sp_io::storage::set(
+                &exposure_key(2, 1),
+                &exposure(vec![(10, 5), (11, 20)]).encode(),
+            );
+            sp_io::storage::set(
+                &exposure_key(2, 2),
+                &exposure(vec![(10, 5), (12, 8)]).encode(),
+            );
+            // exposures of other eras are ignored.
+            sp_io::storage::set(&exposure_key(1, 1), &exposure(vec![(13, 1)]).encode());
+        });
+
+        let storage = DynamicStorage::new(&metadata);
+        assert_eq!(min_exposed_stake(&storage, &mut ext).unwrap(), Some(8));
+        assert!(storage.value(&mut ext, "Staking", "Missing").is_err());
+
+        assert_eq!(
+            storage.key("Staking", "ActiveEra", None).unwrap(),
+            prefix("ActiveEra")
+        );
+        assert!(storage.key("Staking", "ActiveEra", Some(&[1])).is_err());
+        // the exposures are a double map.
+        assert!(storage
+            .key("Staking", "ErasStakers", Some(&2u32.encode()))
+            .is_err());
+        assert_eq!(
+            storage.decode_stored("Staking", "ActiveEra", None).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Gadgets to detect the accounts probably run by the same operator (i.e. known entities).
+//!
+//! The accounts are clustered by behavioral heuristics over the state of a block:
+//! * [`Heuristic::Controller`]: stashes bonded to the same controller.
+//! * [`Heuristic::Payee`]: stashes whose rewards are paid to the same account.
+//! * [`Heuristic::Nominations`]: validators backed by the same (non-empty) set of nominators,
+//!   other than themselves, e.g. the stashes of an operator nominating its own validators.
+//! * [`Heuristic::Targets`]: nominators with the same set of at least [`MIN_SHARED_TARGETS`]
+//!   targets, e.g. the stashes of a custodian nominating on behalf of its customers.
+//! * [`Heuristic::Proxy`]: accounts which delegate to the same proxy account. The proxies are read
+//!   from the raw `Proxy::Proxies` storage, and are therefore only found if the `Proxy` pallet is
+//!   in the externalities (it is extracted if it exists, but not required).
+//!
+//! The heuristics are transitive, e.g. two validators with different controllers but the same
+//! payee end up in the same cluster. They flag *probable* same-operator groups: an account shared
+//! by unrelated operators (e.g. a custodian paying out the rewards of its customers) merges their
+//! clusters.
+
+use crate::prelude::*;
+
+use codec::Decode;
+use sp_core::hashing::twox_128;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A heuristic which links two accounts to the same operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Heuristic {
+    Controller,
+    Payee,
+    Nominations,
+    Targets,
+    Proxy,
+}
+
+/// The minimum number of targets shared by nominators linked by [`Heuristic::Targets`], since
+/// unrelated nominators of a single popular validator often share their only target.
+pub(crate) const MIN_SHARED_TARGETS: usize = 2;
+
+impl std::fmt::Display for Heuristic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Heuristic::Controller => write!(f, "controller"),
+            Heuristic::Payee => write!(f, "payee"),
+            Heuristic::Nominations => write!(f, "nominations"),
+            Heuristic::Targets => write!(f, "targets"),
+            Heuristic::Proxy => write!(f, "proxy"),
+        }
+    }
+}
+
+/// A cluster of accounts, along with the heuristics which linked them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entity<A> {
+    /// The accounts of the cluster, sorted.
+    pub members: Vec<A>,
+    pub heuristics: BTreeSet<Heuristic>,
+}
+
+/// Clusters of linked accounts, as a union-find over the accounts.
+#[derive(Debug, Clone)]
+pub(crate) struct Clusters<A: Ord> {
+    parents: BTreeMap<A, A>,
+    heuristics: BTreeMap<A, BTreeSet<Heuristic>>,
+}
+
+impl<A: Ord + Clone> Default for Clusters<A> {
+    fn default() -> Self {
+        Self {
+            parents: BTreeMap::new(),
+            heuristics: BTreeMap::new(),
+        }
+    }
+}
+
+impl<A: Ord + Clone> Clusters<A> {
+    /// The root of the cluster of `account`, the account itself if it is not linked.
+    fn root(&self, account: &A) -> A {
+        let mut root = account.clone();
+        while let Some(parent) = self.parents.get(&root).filter(|p| **p != root) {
+            root = parent.clone();
+        }
+        root
+    }
+
+    /// Links the clusters of `a` and `b` by `heuristic`. The smallest root becomes the root of
+    /// the merged cluster, so that the clusters do not depend on the order of the links.
+    pub fn link(&mut self, a: &A, b: &A, heuristic: Heuristic) {
+        let (root_a, root_b) = (self.root(a), self.root(b));
+        let (root, other) = match root_a <= root_b {
+            true => (root_a, root_b),
+            false => (root_b, root_a),
+        };
+
+        if root != other {
+            self.parents.insert(other.clone(), root.clone());
+            let merged = self.heuristics.remove(&other).unwrap_or_default();
+            self.heuristics
+                .entry(root.clone())
+                .or_default()
+                .extend(merged);
+        }
+        self.parents
+            .entry(root.clone())
+            .or_insert_with(|| root.clone());
+        self.heuristics.entry(root).or_default().insert(heuristic);
+    }
+
+    /// The entity of each of the `accounts`, i.e. its cluster restricted to the `accounts`, keyed
+    /// by its smallest account. The accounts which are not linked to any other of the `accounts`
+    /// are entities of their own, without heuristics.
+    pub fn entities(&self, accounts: &BTreeSet<A>) -> BTreeMap<A, Entity<A>> {
+        let mut members = BTreeMap::<A, Vec<A>>::new();
+        for account in accounts {
+            members
+                .entry(self.root(account))
+                .or_default()
+                .push(account.clone());
+        }
+
+        members
+            .into_iter()
+            .map(|(root, members)| {
+                let heuristics = match members.len() {
+                    1 => BTreeSet::new(),
+                    _ => self.heuristics.get(&root).cloned().unwrap_or_default(),
+                };
+                (
+                    members[0].clone(),
+                    Entity {
+                        members,
+                        heuristics,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// Links the accounts which share a key (e.g. a controller), by `heuristic`.
+fn link_shared<A: Ord + Clone, K: Ord>(
+    clusters: &mut Clusters<A>,
+    accounts: impl IntoIterator<Item = (K, A)>,
+    heuristic: Heuristic,
+) {
+    let mut first = BTreeMap::<K, A>::new();
+    for (key, account) in accounts {
+        match first.get(&key) {
+            Some(linked) => clusters.link(linked, &account, heuristic),
+            None => {
+                first.insert(key, account);
+            }
+        }
+    }
+}
+
+/// Links the nominators which share the same set of at least [`MIN_SHARED_TARGETS`] targets.
+fn link_targets<A: Ord + Clone>(
+    clusters: &mut Clusters<A>,
+    nominations: impl IntoIterator<Item = (A, BTreeSet<A>)>,
+) {
+    link_shared(
+        clusters,
+        nominations
+            .into_iter()
+            .filter(|(_, targets)| targets.len() >= MIN_SHARED_TARGETS)
+            .map(|(nominator, targets)| (targets, nominator)),
+        Heuristic::Targets,
+    );
+}
+
+/// Clusters the stashes and the `validators` of the staking pallet by the [`Heuristic`]s.
+pub(crate) fn clusters<T: Staking::Config>(
+    ext: &mut Ext,
+    validators: &BTreeSet<AccountIdOf<T>>,
+) -> Clusters<AccountIdOf<T>> {
+    ext.execute_with(|| {
+        let mut clusters = Clusters::default();
+
+        link_shared(
+            &mut clusters,
+            Staking::Bonded::<T>::iter()
+                .filter(|(stash, controller)| stash != controller)
+                .map(|(stash, controller)| (controller, stash)),
+            Heuristic::Controller,
+        );
+
+        link_shared(
+            &mut clusters,
+            Staking::Payee::<T>::iter().filter_map(|(stash, payee)| match payee {
+                Staking::RewardDestination::Account(account) if account != stash => {
+                    Some((account, stash))
+                }
+                _ => None,
+            }),
+            Heuristic::Payee,
+        );
+
+        let mut backers = BTreeMap::<AccountIdOf<T>, BTreeSet<AccountIdOf<T>>>::new();
+        let mut nominations = vec![];
+        for (nominator, targets) in Staking::Nominators::<T>::iter() {
+            let targets = targets.targets.into_iter().collect::<BTreeSet<_>>();
+            for target in targets.iter() {
+                if *target != nominator && validators.contains(target) {
+                    backers
+                        .entry(target.clone())
+                        .or_default()
+                        .insert(nominator.clone());
+                }
+            }
+            nominations.push((nominator, targets));
+        }
+        link_targets(&mut clusters, nominations);
+        link_shared(
+            &mut clusters,
+            backers
+                .into_iter()
+                .map(|(validator, nominators)| (nominators, validator)),
+            Heuristic::Nominations,
+        );
+
+        link_shared(&mut clusters, proxies::<T>(), Heuristic::Proxy);
+
+        clusters
+    })
+}
+
+/// Returns the `(delegate, delegator)` pairs of the proxies in the raw `Proxy::Proxies` storage.
+/// The proxy type and delay are assumed to be encoded as in the relay chains (`u8` and `u32`).
+fn proxies<T: frame_system::Config>() -> Vec<(AccountIdOf<T>, AccountIdOf<T>)> {
+    let prefix = [twox_128(b"Proxy"), twox_128(b"Proxies")].concat();
+
+    let mut proxies = vec![];
+    let mut key = prefix.clone();
+    while let Some(next) = sp_io::storage::next_key(&key).filter(|k| k.starts_with(&prefix)) {
+        // the delegator is hashed with `twox64concat`.
+        let delegator = AccountIdOf::<T>::decode(&mut &next[prefix.len() + 8..]);
+        let definitions = sp_io::storage::get(&next).and_then(|value| {
+            <(Vec<(AccountIdOf<T>, u8, u32)>, u128)>::decode(&mut &value[..]).ok()
+        });
+
+        if let (Ok(delegator), Some((definitions, _))) = (delegator, definitions) {
+            proxies.extend(
+                definitions
+                    .into_iter()
+                    .map(|(delegate, _, _)| (delegate, delegator.clone())),
+            );
+        }
+        key = next;
+    }
+
+    proxies
+}
+
+/// The Nakamoto coefficient of the `backings` of the entities, i.e. the minimum number of
+/// entities which together back more than a third of the total stake.
+pub(crate) fn nakamoto_coefficient(backings: impl IntoIterator<Item = u128>) -> u32 {
+    let mut backings = backings.into_iter().collect::<Vec<_>>();
+    backings.sort_unstable_by(|a, b| b.cmp(a));
+    let total = backings.iter().sum::<u128>();
+
+    let mut backed = 0u128;
+    for (n, backing) in backings.into_iter().enumerate() {
+        backed += backing;
+        if backed > total / 3 {
+            return n as u32 + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nakamoto_coefficient_works() {
+        assert_eq!(nakamoto_coefficient([]), 0);
+        assert_eq!(nakamoto_coefficient([10, 10, 10]), 1);
+        assert_eq!(nakamoto_coefficient([10; 9]), 4);
+        assert_eq!(nakamoto_coefficient([5, 50, 5, 40]), 1);
+    }
+
+    #[test]
+    fn clusters_work() {
+        let mut clusters = Clusters::default();
+        // 1 and 2 share a controller (10), 3 pays out to 2's payee (20), 4 and 5 share nominators.
+        link_shared(
+            &mut clusters,
+            [(10, 1), (10, 2), (11, 3)],
+            Heuristic::Controller,
+        );
+        link_shared(&mut clusters, [(20, 2), (20, 3)], Heuristic::Payee);
+        link_shared(
+            &mut clusters,
+            [(vec![7], 4), (vec![7], 5), (vec![7, 8], 6)],
+            Heuristic::Nominations,
+        );
+
+        let entities = clusters.entities(&BTreeSet::from([1, 2, 3, 4, 5, 6]));
+        assert_eq!(entities.keys().copied().collect::<Vec<_>>(), vec![1, 4, 6]);
+        assert_eq!(
+            entities[&1],
+            Entity {
+                members: vec![1, 2, 3],
+                heuristics: BTreeSet::from([Heuristic::Controller, Heuristic::Payee]),
+            }
+        );
+        assert_eq!(entities[&4].members, vec![4, 5]);
+        assert_eq!(
+            entities[&6],
+            Entity {
+                members: vec![6],
+                heuristics: BTreeSet::new(),
+            }
+        );
+
+        // the clusters are restricted to the accounts of the entities.
+        let entities = clusters.entities(&BTreeSet::from([3, 5]));
+        assert_eq!(entities[&3].members, vec![3]);
+        assert!(entities[&3].heuristics.is_empty());
+    }
+
+    #[test]
+    fn link_targets_works() {
+        let mut clusters = Clusters::default();
+        // 1 and 2 nominate the same targets, 3 and 4 the same single target.
+        link_targets(
+            &mut clusters,
+            [
+                (1, BTreeSet::from([10, 11])),
+                (2, BTreeSet::from([11, 10])),
+                (3, BTreeSet::from([10])),
+                (4, BTreeSet::from([10])),
+                (5, BTreeSet::from([10, 11, 12])),
+            ],
+        );
+
+        let entities = clusters.entities(&BTreeSet::from([1, 2, 3, 4, 5]));
+        assert_eq!(
+            entities.keys().copied().collect::<Vec<_>>(),
+            vec![1, 3, 4, 5]
+        );
+        assert_eq!(
+            entities[&1],
+            Entity {
+                members: vec![1, 2],
+                heuristics: BTreeSet::from([Heuristic::Targets]),
+            }
+        );
+    }
+}
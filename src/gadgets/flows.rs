@@ -0,0 +1,558 @@
+//! Gadgets to compute the flows of stake between two states of the staking ledgers.
+//!
+//! The flows are derived from the diff of each ledger between two blocks (e.g. the first blocks of
+//! two eras), so the operations within the range net out: a stash which unbonds and rebonds the
+//! same amount shows no flow. Slashes are read from the slashes recorded in the eras of the range,
+//! and the growth of the ledgers whose rewards are compounded (i.e. with a `Staked` payee) is
+//! attributed to the rewards rather than to new bonds.
+//!
+//! Similarly, the churn of the nominators (joined, left, rebonded or changed targets) and the
+//! resulting inflow and outflow of approval stake of each validator are derived from the diff of
+//! the nominations between two blocks.
+//!
+//! The changes of the active validator set (i.e. the validators which entered or exited it) are
+//! derived from the diff of the era exposures of the active eras of two blocks.
+
+use crate::prelude::*;
+use crate::TimetravelError;
+
+use sp_staking::EraIndex;
+use std::collections::{BTreeMap, BTreeSet};
+use Staking::RewardDestination;
+
+/// The balances of a staking ledger.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct LedgerState {
+    pub total: u128,
+    pub active: u128,
+    pub unlocking: u128,
+    /// Whether the rewards of the stash are compounded, i.e. its payee is `Staked`.
+    pub compounding: bool,
+}
+
+/// The flows of stake of one or more ledgers between two blocks.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct StakeFlows {
+    /// Stake of the stashes bonded in the range.
+    pub new_bonds: u128,
+    /// Stake added to existing ledgers, other than compounded rewards.
+    pub bond_extra: u128,
+    /// Rewards compounded into the ledgers.
+    pub rewards: u128,
+    /// Active stake retained by the existing ledgers.
+    pub retained: u128,
+    pub unbonds: u128,
+    pub rebonds: u128,
+    /// Unlocked stake withdrawn from the ledgers, including the stake of the ledgers killed in
+    /// the range.
+    pub withdrawals: u128,
+    pub slashes: u128,
+}
+
+impl StakeFlows {
+    /// Computes the flows of a ledger from its state `before` and `after`, given the amount
+    /// `slashed` in between.
+    pub fn of_ledger(
+        before: Option<LedgerState>,
+        after: Option<LedgerState>,
+        slashed: u128,
+    ) -> Self {
+        let mut flows = Self::default();
+
+        match (before, after) {
+            (None, None) => (),
+            (None, Some(after)) => flows.new_bonds = after.total,
+            (Some(before), None) => {
+                flows.slashes = slashed.min(before.total);
+                flows.withdrawals = before.total - flows.slashes;
+            }
+            (Some(before), Some(after)) => {
+                flows.slashes = slashed.min(before.total);
+
+                let remaining = before.total - flows.slashes;
+                if after.total >= remaining {
+                    let added = after.total - remaining;
+                    match after.compounding {
+                        true => flows.rewards = added,
+                        false => flows.bond_extra = added,
+                    }
+                } else {
+                    flows.withdrawals = remaining - after.total;
+                }
+
+                // unlocking after = unlocking before + unbonds - rebonds - withdrawals.
+                let unlocked = after.unlocking + flows.withdrawals;
+                if unlocked >= before.unlocking {
+                    flows.unbonds = unlocked - before.unlocking;
+                } else {
+                    flows.rebonds = before.unlocking - unlocked;
+                }
+
+                flows.retained = before
+                    .active
+                    .saturating_sub(flows.unbonds)
+                    .saturating_sub(flows.slashes);
+            }
+        }
+
+        flows
+    }
+
+    /// Accumulates the flows of `other`.
+    pub fn add(&mut self, other: &Self) {
+        self.new_bonds += other.new_bonds;
+        self.bond_extra += other.bond_extra;
+        self.rewards += other.rewards;
+        self.retained += other.retained;
+        self.unbonds += other.unbonds;
+        self.rebonds += other.rebonds;
+        self.withdrawals += other.withdrawals;
+        self.slashes += other.slashes;
+    }
+
+    /// The flows as `(source, target, value)` links of a Sankey diagram.
+    pub fn links(&self) -> Vec<(&'static str, &'static str, u128)> {
+        vec![
+            ("free", "active", self.new_bonds),
+            ("free", "active", self.bond_extra),
+            ("rewards", "active", self.rewards),
+            ("active", "active", self.retained),
+            ("active", "unlocking", self.unbonds),
+            ("unlocking", "active", self.rebonds),
+            ("unlocking", "free", self.withdrawals),
+            ("active", "slashed", self.slashes),
+        ]
+    }
+}
+
+/// Returns the state of the ledger of each stash.
+pub(crate) fn ledger_states<T: Staking::Config>(
+    ext: &mut Ext,
+) -> BTreeMap<AccountIdOf<T>, LedgerState>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    ext.execute_with(|| {
+        Staking::Ledger::<T>::iter_values()
+            .map(|ledger| {
+                let compounding = matches!(
+                    Staking::Payee::<T>::get(&ledger.stash),
+                    Some(RewardDestination::Staked)
+                );
+                let state = LedgerState {
+                    total: ledger.total.into(),
+                    active: ledger.active.into(),
+                    unlocking: ledger
+                        .unlocking
+                        .iter()
+                        .map(|chunk| chunk.value.into())
+                        .sum(),
+                    compounding,
+                };
+                (ledger.stash, state)
+            })
+            .collect()
+    })
+}
+
+/// Returns the amount slashed from each stash in the `eras`, as a validator or as a nominator.
+pub(crate) fn slashes_in_eras<T: Staking::Config>(
+    ext: &mut Ext,
+    eras: impl IntoIterator<Item = EraIndex>,
+) -> BTreeMap<AccountIdOf<T>, u128>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    ext.execute_with(|| {
+        let mut slashes = BTreeMap::<_, u128>::new();
+        for era in eras {
+            for (stash, (_, amount)) in Staking::ValidatorSlashInEra::<T>::iter_prefix(era) {
+                *slashes.entry(stash).or_default() += amount.into();
+            }
+            for (stash, amount) in Staking::NominatorSlashInEra::<T>::iter_prefix(era) {
+                *slashes.entry(stash).or_default() += amount.into();
+            }
+        }
+        slashes
+    })
+}
+
+/// Aggregates the flows of all the ledgers between the states `before` and `after`.
+pub(crate) fn stake_flows<A: Ord>(
+    before: &BTreeMap<A, LedgerState>,
+    after: &BTreeMap<A, LedgerState>,
+    slashes: &BTreeMap<A, u128>,
+) -> StakeFlows {
+    let stashes = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+
+    let mut flows = StakeFlows::default();
+    for stash in stashes {
+        flows.add(&StakeFlows::of_ledger(
+            before.get(stash).cloned(),
+            after.get(stash).cloned(),
+            slashes.get(stash).cloned().unwrap_or_default(),
+        ));
+    }
+    flows
+}
+
+/// The nominations of a nominator at a block.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Nomination<A: Ord> {
+    /// The active stake of the nominator.
+    pub stake: u128,
+    pub targets: BTreeSet<A>,
+}
+
+/// Returns the nominations of each nominator.
+pub(crate) fn nominations<T: Staking::Config>(
+    ext: &mut Ext,
+) -> BTreeMap<AccountIdOf<T>, Nomination<AccountIdOf<T>>> {
+    ext.execute_with(|| {
+        Staking::Nominators::<T>::iter()
+            .map(|(nominator, nominations)| {
+                let nomination = Nomination {
+                    stake: super::staking_stats::active_stake::<T>(&nominator).unwrap_or_default(),
+                    targets: nominations.targets.into_iter().collect(),
+                };
+                (nominator, nomination)
+            })
+            .collect()
+    })
+}
+
+/// The churn of a nominator between two blocks.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct NominatorChurn<A> {
+    pub joined: bool,
+    pub left: bool,
+    /// Whether the nominator kept nominating with a different active stake, i.e. it bonded extra,
+    /// unbonded or rebonded.
+    pub rebonded: bool,
+    pub stake_before: u128,
+    pub stake_after: u128,
+    pub targets_added: Vec<A>,
+    pub targets_removed: Vec<A>,
+}
+
+impl<A: Ord + Clone> NominatorChurn<A> {
+    /// Computes the churn of a nominator from its nominations `before` and `after`.
+    pub fn of_nominator(before: Option<&Nomination<A>>, after: Option<&Nomination<A>>) -> Self {
+        let empty = BTreeSet::new();
+        let (targets_before, targets_after) = (
+            before.map_or(&empty, |n| &n.targets),
+            after.map_or(&empty, |n| &n.targets),
+        );
+
+        Self {
+            joined: before.is_none() && after.is_some(),
+            left: before.is_some() && after.is_none(),
+            rebonded: matches!((before, after), (Some(b), Some(a)) if b.stake != a.stake),
+            stake_before: before.map_or(0, |n| n.stake),
+            stake_after: after.map_or(0, |n| n.stake),
+            targets_added: targets_after.difference(targets_before).cloned().collect(),
+            targets_removed: targets_before.difference(targets_after).cloned().collect(),
+        }
+    }
+
+    /// Whether the nominator changed its targets while nominating at both blocks.
+    pub fn changed_targets(&self) -> bool {
+        !self.joined
+            && !self.left
+            && (!self.targets_added.is_empty() || !self.targets_removed.is_empty())
+    }
+}
+
+/// The churn of the nominators of a validator between two blocks, where the stake of a nominator
+/// backs each of its targets in full (i.e. the approval stake).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ValidatorChurn {
+    pub nominators_before: u32,
+    pub nominators_after: u32,
+    /// Nominators which started nominating the validator.
+    pub joined: u32,
+    /// Nominators which stopped nominating the validator.
+    pub left: u32,
+    pub approval_before: u128,
+    pub approval_after: u128,
+    /// Stake of the new nominators and stake added by the existing ones.
+    pub inflow: u128,
+    /// Stake of the nominators which left and stake removed by the remaining ones.
+    pub outflow: u128,
+}
+
+/// Computes the churn of the nominators of each validator nominated `before` or `after`.
+pub(crate) fn validator_churn<A: Ord + Clone>(
+    before: &BTreeMap<A, Nomination<A>>,
+    after: &BTreeMap<A, Nomination<A>>,
+) -> BTreeMap<A, ValidatorChurn> {
+    let mut churn = BTreeMap::<A, ValidatorChurn>::new();
+    let nominators = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+
+    for nominator in nominators {
+        let (b, a) = (before.get(nominator), after.get(nominator));
+        let targets = b
+            .iter()
+            .chain(a.iter())
+            .flat_map(|n| n.targets.iter())
+            .collect::<BTreeSet<_>>();
+
+        for target in targets {
+            let backing = |n: Option<&Nomination<A>>| {
+                n.filter(|n| n.targets.contains(target)).map(|n| n.stake)
+            };
+            let entry = churn.entry(target.clone()).or_default();
+            match (backing(b), backing(a)) {
+                (None, None) => (),
+                (None, Some(stake)) => {
+                    entry.joined += 1;
+                    entry.inflow += stake;
+                }
+                (Some(stake), None) => {
+                    entry.left += 1;
+                    entry.outflow += stake;
+                }
+                (Some(stake_before), Some(stake_after)) => {
+                    entry.inflow += stake_after.saturating_sub(stake_before);
+                    entry.outflow += stake_before.saturating_sub(stake_after);
+                }
+            }
+            if let Some(stake) = backing(b) {
+                entry.nominators_before += 1;
+                entry.approval_before += stake;
+            }
+            if let Some(stake) = backing(a) {
+                entry.nominators_after += 1;
+                entry.approval_after += stake;
+            }
+        }
+    }
+
+    churn
+}
+
+/// Returns the active era of the block and the backing of each validator of its active set, as
+/// per the era exposures.
+///
+/// If the exposures of the era are not in the externalities, the set falls back to the session
+/// validators, with no backing.
+pub(crate) fn active_set<T: Staking::Config>(
+    ext: &mut Ext,
+) -> Result<(EraIndex, BTreeMap<AccountIdOf<T>, u128>), anyhow::Error>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    let era = super::active_era::<T>(ext)
+        .ok_or(TimetravelError::missing("Active era"))?
+        .index;
+
+    let set = super::era_exposures::<T>(ext, era)
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    if !set.is_empty() {
+        return Ok((era, set));
+    }
+
+    crate::warnings::warn(
+        "no_exposures",
+        format!(
+            "No exposures for era {}, using the session validators without backing.",
+            era
+        ),
+    );
+    let validators: Vec<AccountIdOf<T>> = ext
+        .execute_with(|| {
+            frame_support::storage::unhashed::get(
+                &[
+                    sp_core::hashing::twox_128(b"Session"),
+                    sp_core::hashing::twox_128(b"Validators"),
+                ]
+                .concat(),
+            )
+        })
+        .ok_or(TimetravelError::missing("Session validators"))?;
+
+    Ok((era, validators.into_iter().map(|v| (v, 0)).collect()))
+}
+
+/// A change of the active validator set between two blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SetChange {
+    Entered,
+    Exited,
+}
+
+impl std::fmt::Display for SetChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetChange::Entered => write!(f, "entered"),
+            SetChange::Exited => write!(f, "exited"),
+        }
+    }
+}
+
+/// Returns the validators which entered or exited the active set between `before` and `after`,
+/// with their backing in the set they are part of.
+pub(crate) fn set_changes<A: Ord + Clone>(
+    before: &BTreeMap<A, u128>,
+    after: &BTreeMap<A, u128>,
+) -> Vec<(A, SetChange, u128)> {
+    let exited = before
+        .iter()
+        .filter(|(validator, _)| !after.contains_key(validator))
+        .map(|(validator, backing)| (validator.clone(), SetChange::Exited, *backing));
+    let entered = after
+        .iter()
+        .filter(|(validator, _)| !before.contains_key(validator))
+        .map(|(validator, backing)| (validator.clone(), SetChange::Entered, *backing));
+
+    exited.chain(entered).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nomination(stake: u128, targets: &[u8]) -> Nomination<u8> {
+        Nomination {
+            stake,
+            targets: targets.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn nominator_churn_works() {
+        let joined = NominatorChurn::of_nominator(None, Some(&nomination(10, &[1])));
+        assert!(joined.joined && !joined.changed_targets());
+        assert_eq!(joined.targets_added, vec![1]);
+
+        let churn = NominatorChurn::of_nominator(
+            Some(&nomination(10, &[1, 2])),
+            Some(&nomination(15, &[2, 3])),
+        );
+        assert!(churn.rebonded && churn.changed_targets());
+        assert_eq!(
+            (churn.targets_added, churn.targets_removed),
+            (vec![3], vec![1])
+        );
+    }
+
+    #[test]
+    fn validator_churn_works() {
+        // nominator 10 moves from target 1 to 3 and bonds extra, 11 leaves and 12 joins.
+        let before = BTreeMap::from([(10, nomination(100, &[1, 2])), (11, nomination(50, &[1]))]);
+        let after = BTreeMap::from([(10, nomination(120, &[2, 3])), (12, nomination(30, &[1]))]);
+
+        let churn = validator_churn(&before, &after);
+        assert_eq!(
+            churn[&1],
+            ValidatorChurn {
+                nominators_before: 2,
+                nominators_after: 1,
+                joined: 1,
+                left: 2,
+                approval_before: 150,
+                approval_after: 30,
+                inflow: 30,
+                outflow: 150,
+            }
+        );
+        assert_eq!(
+            (churn[&2].inflow, churn[&2].outflow, churn[&2].joined),
+            (20, 0, 0)
+        );
+        assert_eq!((churn[&3].inflow, churn[&3].approval_after), (120, 120));
+    }
+
+    #[test]
+    fn set_changes_works() {
+        let before = BTreeMap::from([(1, 100), (2, 200), (3, 300)]);
+        let after = BTreeMap::from([(2, 250), (3, 300), (4, 150)]);
+
+        assert_eq!(
+            set_changes(&before, &after),
+            vec![(1, SetChange::Exited, 100), (4, SetChange::Entered, 150)]
+        );
+        assert!(set_changes(&before, &before).is_empty());
+    }
+
+    fn ledger(total: u128, active: u128, compounding: bool) -> LedgerState {
+        LedgerState {
+            total,
+            active,
+            unlocking: total - active,
+            compounding,
+        }
+    }
+
+    #[test]
+    fn of_ledger_works() {
+        // new bond and full exit.
+        assert_eq!(
+            StakeFlows::of_ledger(None, Some(ledger(100, 100, false)), 0).new_bonds,
+            100
+        );
+        let exit = StakeFlows::of_ledger(Some(ledger(100, 0, false)), None, 10);
+        assert_eq!((exit.withdrawals, exit.slashes), (90, 10));
+
+        // unbond of 30 and compounded rewards of 5.
+        let flows =
+            StakeFlows::of_ledger(Some(ledger(100, 100, true)), Some(ledger(105, 75, true)), 0);
+        assert_eq!(
+            flows,
+            StakeFlows {
+                rewards: 5,
+                retained: 70,
+                unbonds: 30,
+                ..Default::default()
+            }
+        );
+
+        // rebond of 10 and withdrawal of 20 out of 40 unlocking.
+        let flows =
+            StakeFlows::of_ledger(Some(ledger(100, 60, false)), Some(ledger(80, 70, false)), 0);
+        assert_eq!(
+            flows,
+            StakeFlows {
+                retained: 60,
+                rebonds: 10,
+                withdrawals: 20,
+                ..Default::default()
+            }
+        );
+
+        // slash of 10 and bond extra of 20.
+        let flows = StakeFlows::of_ledger(
+            Some(ledger(100, 100, false)),
+            Some(ledger(110, 110, false)),
+            10,
+        );
+        assert_eq!(
+            flows,
+            StakeFlows {
+                bond_extra: 20,
+                retained: 90,
+                slashes: 10,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn stake_flows_works() {
+        let before = BTreeMap::from([(1, ledger(100, 100, false)), (2, ledger(50, 0, false))]);
+        let after = BTreeMap::from([(1, ledger(100, 100, false)), (3, ledger(10, 10, false))]);
+
+        let flows = stake_flows(&before, &after, &BTreeMap::new());
+        assert_eq!(
+            flows,
+            StakeFlows {
+                new_bonds: 10,
+                retained: 100,
+                withdrawals: 50,
+                ..Default::default()
+            }
+        );
+        assert_eq!(flows.links().iter().map(|(_, _, v)| v).sum::<u128>(), 160);
+    }
+}
@@ -0,0 +1,153 @@
+//! Gadgets to compute the era payouts and the inflation of the staking pallet.
+//!
+//! The payouts are computed by the `EraPayout` of the compiled runtime, as the staking pallet does
+//! at the end of each era, from the total issuance, the total stake of the era and the duration
+//! of the era.
+
+use crate::prelude::*;
+
+use codec::Decode;
+use sp_core::hashing::twox_128;
+use sp_runtime::{Perbill, SaturatedConversion};
+use Staking::EraPayout as _;
+
+/// Milliseconds per year, as used by the runtimes to annualize the inflation (i.e. 365.25 days).
+pub(crate) const MILLISECONDS_PER_YEAR: u64 = 1_000 * 3_600 * 24 * 36_525 / 100;
+
+/// Number of staking rates at which the payout is evaluated to find the ideal staking rate.
+const STAKING_RATE_STEPS: u32 = 1_000;
+
+/// The payout of an era.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct EraPayout {
+    pub total_staked: u128,
+    pub total_issuance: u128,
+    pub era_duration_ms: u64,
+    /// The payout of the validators and their nominators.
+    pub validator_payout: u128,
+    /// The rest of the era inflation, e.g. paid to the treasury.
+    pub remainder: u128,
+}
+
+impl EraPayout {
+    /// The annualized inflation of the era, relative to the total issuance. Returns `None` if the
+    /// total issuance or the era duration are zero.
+    pub fn inflation(&self) -> Option<f64> {
+        if self.total_issuance == 0 || self.era_duration_ms == 0 {
+            return None;
+        }
+        let minted = self.validator_payout.saturating_add(self.remainder) as f64;
+        let eras_per_year = MILLISECONDS_PER_YEAR as f64 / self.era_duration_ms as f64;
+
+        Some(minted / self.total_issuance as f64 * eras_per_year)
+    }
+}
+
+/// Returns the timestamp of the block of the externalities, as per `Timestamp::Now`.
+pub(crate) fn timestamp_now(ext: &mut Ext) -> Option<u64> {
+    ext.execute_with(|| {
+        sp_io::storage::get(&[twox_128(b"Timestamp"), twox_128(b"Now")].concat())
+            .and_then(|raw| u64::decode(&mut &raw[..]).ok())
+    })
+}
+
+/// Computes the payout of the active era with the `EraPayout` of the runtime, as if the era
+/// lasted `era_duration_ms` or, if none is provided, as if it ended at the block of the
+/// externalities.
+///
+/// Returns `None` if there is no active era, or if the total issuance (or the timestamp, when
+/// needed) is not in the externalities.
+pub(crate) fn era_payout<T: Staking::Config>(
+    ext: &mut Ext,
+    era_duration_ms: Option<u64>,
+) -> Option<EraPayout> {
+    let total_issuance = super::staking_stats::total_issuance(ext)?;
+    let now = timestamp_now(ext);
+
+    ext.execute_with(|| {
+        let active_era = <Staking::ActiveEra<T>>::get()?;
+        let era_duration_ms = match era_duration_ms {
+            Some(duration) => duration,
+            None => now?.saturating_sub(active_era.start?),
+        };
+        let total_staked = <Staking::ErasTotalStake<T>>::get(active_era.index);
+
+        let (validator_payout, remainder) = T::EraPayout::era_payout(
+            total_staked,
+            total_issuance.saturated_into(),
+            era_duration_ms,
+        );
+
+        Some(EraPayout {
+            total_staked: total_staked.saturated_into(),
+            total_issuance,
+            era_duration_ms,
+            validator_payout: validator_payout.saturated_into(),
+            remainder: remainder.saturated_into(),
+        })
+    })
+}
+
+/// Returns the ideal staking rate of the `EraPayout` of the runtime, i.e. the staking rate at
+/// which the payout of the stakers is the highest, given the total issuance and the era duration.
+///
+/// The payout is evaluated over a grid of [`STAKING_RATE_STEPS`] staking rates, since the
+/// parameters of the inflation curve are not exposed by the runtimes. Returns `None` if the
+/// payout does not depend on the staking rate (e.g. a fixed inflation).
+pub(crate) fn ideal_staking_rate<T: Staking::Config>(
+    ext: &mut Ext,
+    total_issuance: u128,
+    era_duration_ms: u64,
+) -> Option<f64> {
+    ext.execute_with(|| {
+        let payouts = (0..=STAKING_RATE_STEPS).map(|step| {
+            let total_staked = Perbill::from_rational(step, STAKING_RATE_STEPS) * total_issuance;
+            let (payout, _) = T::EraPayout::era_payout(
+                total_staked.saturated_into(),
+                total_issuance.saturated_into(),
+                era_duration_ms,
+            );
+            payout.saturated_into::<u128>()
+        });
+
+        peak(payouts).map(|step| step as f64 / STAKING_RATE_STEPS as f64)
+    })
+}
+
+/// Returns the index of the first highest value, or `None` if all the values are equal.
+fn peak(values: impl IntoIterator<Item = u128>) -> Option<usize> {
+    let values = values.into_iter().collect::<Vec<_>>();
+    let max = values.iter().max()?;
+    if values.iter().all(|value| value == max) {
+        return None;
+    }
+
+    values.iter().position(|value| value == max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inflation_works() {
+        // 1% of the issuance minted in an era of a hundredth of a year.
+        let payout = EraPayout {
+            total_staked: 500,
+            total_issuance: 10_000,
+            era_duration_ms: MILLISECONDS_PER_YEAR / 100,
+            validator_payout: 80,
+            remainder: 20,
+        };
+        assert!((payout.inflation().unwrap() - 1.0).abs() < 1e-9);
+
+        assert_eq!(EraPayout::default().inflation(), None);
+    }
+
+    #[test]
+    fn peak_works() {
+        assert_eq!(peak([1, 5, 9, 9, 4]), Some(2));
+        assert_eq!(peak([3, 3, 3]), None);
+        assert_eq!(peak([]), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Gadgets to report the memory used by the storage of an externalities.
+//!
+//! The storage is grouped by the pallet prefix of its keys (i.e. the `twox128` hash of the pallet
+//! name), so that the pallets which dominate the memory of a snapshot can be dropped from the
+//! extraction when the operation does not rely on them. Well-known keys (e.g. `:code`) are
+//! grouped by their full key.
+
+use crate::prelude::*;
+
+use sp_core::hashing::twox_128;
+use std::collections::BTreeMap;
+
+/// The storage under a key prefix.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct PrefixUsage {
+    pub prefix: Vec<u8>,
+    /// The name of the pallet (or well-known key) of the prefix, if known.
+    pub name: Option<String>,
+    pub keys: u64,
+    pub key_bytes: u64,
+    pub value_bytes: u64,
+}
+
+impl PrefixUsage {
+    /// The bytes of the keys and values under the prefix. The in-memory size of the
+    /// externalities is larger, due to the trie nodes and the overlay.
+    pub fn bytes(&self) -> u64 {
+        self.key_bytes + self.value_bytes
+    }
+}
+
+/// Accumulates the storage usage of `pairs`, given as `(key, value length)`, per prefix as they
+/// are iterated. The `pallets` names are used to label their prefixes. The result is sorted by
+/// descending bytes.
+pub(crate) fn usage_by_prefix<K: AsRef<[u8]>>(
+    pairs: impl IntoIterator<Item = (K, usize)>,
+    pallets: &[&str],
+) -> Vec<PrefixUsage> {
+    let names = pallets
+        .iter()
+        .map(|p| (twox_128(p.as_bytes()).to_vec(), p.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut usage = BTreeMap::<Vec<u8>, PrefixUsage>::new();
+    for (key, value_len) in pairs {
+        let key = key.as_ref();
+        let (prefix, name) = if key.starts_with(b":") || key.len() < 16 {
+            (key.to_vec(), Some(String::from_utf8_lossy(key).to_string()))
+        } else {
+            let prefix = key[..16].to_vec();
+            let name = names.get(&prefix).cloned();
+            (prefix, name)
+        };
+
+        let entry = usage.entry(prefix.clone()).or_insert(PrefixUsage {
+            prefix,
+            name,
+            ..Default::default()
+        });
+        entry.keys += 1;
+        entry.key_bytes += key.len() as u64;
+        entry.value_bytes += value_len as u64;
+    }
+
+    let mut usage = usage.into_values().collect::<Vec<_>>();
+    usage.sort_by(|a, b| b.bytes().cmp(&a.bytes()));
+    usage
+}
+
+/// Returns the storage usage of `ext` per prefix, labelled with the `pallets` names.
+pub(crate) fn memory_usage(ext: &mut Ext, pallets: &[&str]) -> Vec<PrefixUsage> {
+    ext.execute_with(|| {
+        // the keys are aggregated as they are iterated, rather than collected, since the storage
+        // may not fit in memory twice.
+        let mut key = vec![];
+        let pairs = std::iter::from_fn(|| {
+            let next = sp_io::storage::next_key(&key)?;
+            let value_len = sp_io::storage::read(&next, &mut [], 0).unwrap_or_default() as usize;
+            key = next.clone();
+            Some((next, value_len))
+        });
+
+        usage_by_prefix(pairs, pallets)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_by_prefix_works() {
+        let staking = twox_128(b"Staking");
+        let unknown = twox_128(b"Unknown");
+        let key = |prefix: &[u8; 16], suffix: &[u8]| [prefix.as_slice(), suffix].concat();
+
+        let pairs = vec![
+            (key(&staking, &[1u8; 32]), 10),
+            (key(&staking, &[2u8; 32]), 20),
+            (key(&unknown, &[1u8; 16]), 1),
+            (b":code".to_vec(), 1_000),
+        ];
+
+        let usage = usage_by_prefix(
+            pairs.iter().map(|(k, l)| (k.as_slice(), *l)),
+            &["Staking", "Balances"],
+        );
+
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].name, Some(":code".to_string()));
+        assert_eq!(usage[0].bytes(), 1_005);
+        assert_eq!(
+            usage[1],
+            PrefixUsage {
+                prefix: staking.to_vec(),
+                name: Some("Staking".to_string()),
+                keys: 2,
+                key_bytes: 96,
+                value_bytes: 30,
+            }
+        );
+        assert_eq!(usage[2].name, None);
+        assert_eq!(usage[2].keys, 1);
+    }
+}
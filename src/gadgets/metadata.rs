@@ -0,0 +1,744 @@
+//! Gadgets to fetch and inspect the runtime metadata of a block.
+//!
+//! The metadata is fetched from the remote node at a given block, which means that it reflects the
+//! runtime that was in force at that block, rather than the runtime compiled into the CLI.
+
+use crate::prelude::*;
+use crate::rpc::SharedRpcClient;
+use crate::TimetravelError;
+
+use codec::{Compact, Decode, Encode};
+use frame_metadata::{
+    v14::{
+        PalletConstantMetadata, PalletStorageMetadata, StorageEntryMetadata, StorageEntryType,
+        StorageHasher,
+    },
+    RuntimeMetadata, RuntimeMetadataPrefixed,
+};
+use scale_info::{form::PortableForm, PortableRegistry, TypeDef, TypeDefPrimitive};
+use sp_core::{
+    crypto::Ss58Codec,
+    hashing::{blake2_256, twox_128},
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Version-agnostic view over the pallets of the runtime metadata (V14 onwards).
+pub(crate) struct PalletsMetadata {
+    /// Registry of all the types used in the metadata.
+    pub types: PortableRegistry,
+    /// Metadata of the pallets.
+    pub pallets: Vec<PalletMetadata>,
+    /// Identifiers of the transaction extensions of the extrinsics, in order.
+    pub extensions: Vec<String>,
+}
+
+/// Metadata of a single pallet.
+pub(crate) struct PalletMetadata {
+    pub name: String,
+    pub storage: Option<PalletStorageMetadata<PortableForm>>,
+    pub constants: Vec<PalletConstantMetadata<PortableForm>>,
+}
+
+impl TryFrom<RuntimeMetadataPrefixed> for PalletsMetadata {
+    type Error = anyhow::Error;
+
+    fn try_from(metadata: RuntimeMetadataPrefixed) -> Result<Self, Self::Error> {
+        macro_rules! pallets_of {
+            ($metadata:ident) => {
+                PalletsMetadata {
+                    types: $metadata.types,
+                    pallets: $metadata
+                        .pallets
+                        .into_iter()
+                        .map(|p| PalletMetadata {
+                            name: p.name,
+                            storage: p.storage,
+                            constants: p.constants,
+                        })
+                        .collect(),
+                    extensions: $metadata
+                        .extrinsic
+                        .signed_extensions
+                        .into_iter()
+                        .map(|e| e.identifier)
+                        .collect(),
+                }
+            };
+        }
+
+        match metadata.1 {
+            RuntimeMetadata::V14(m) => Ok(pallets_of!(m)),
+            RuntimeMetadata::V15(m) => Ok(pallets_of!(m)),
+            other => Err(TimetravelError::Unsupported {
+                reason: format!("metadata version {:?}.", other.version()),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Fetches and decodes the runtime metadata at block `at`.
+pub(crate) async fn fetch_metadata(
+    rpc: &SharedRpcClient,
+    at: Hash,
+) -> Result<PalletsMetadata, anyhow::Error> {
+    let bytes = rpc.metadata(Some(at)).await?;
+    let metadata = RuntimeMetadataPrefixed::decode(&mut &bytes.0[..]).map_err(|e| {
+        TimetravelError::Decode {
+            error: format!("metadata at {:?}: {:?}.", at, e),
+        }
+    })?;
+
+    metadata.try_into()
+}
+
+/// A runtime constant and its (human readable) value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PalletConstant {
+    pub pallet: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// Returns all the constants of the `pallets` in the metadata. If `pallets` is empty, the
+/// constants of all the pallets are returned.
+pub(crate) fn pallet_constants(
+    metadata: &PalletsMetadata,
+    pallets: &[String],
+) -> Vec<PalletConstant> {
+    metadata
+        .pallets
+        .iter()
+        .filter(|p| pallets.is_empty() || pallets.contains(&p.name))
+        .flat_map(|p| {
+            p.constants.iter().map(|c| PalletConstant {
+                pallet: p.name.clone(),
+                name: c.name.clone(),
+                value: decode_value(&metadata.types, c.ty.id, &c.value),
+            })
+        })
+        .collect()
+}
+
+/// Decodes a SCALE encoded value of type `ty` into a human readable string.
+///
+/// Primitive types (and composites wrapping a single primitive, e.g. `Perbill`) are decoded,
+/// while all the other values are returned as hex strings.
+pub(crate) fn decode_value(types: &PortableRegistry, ty: u32, value: &[u8]) -> String {
+    decode_primitive(types, ty, value).unwrap_or_else(|| sp_core::bytes::to_hex(value, false))
+}
+
+fn decode_primitive(types: &PortableRegistry, ty: u32, value: &[u8]) -> Option<String> {
+    let input = &mut &value[..];
+
+    match &types.resolve(ty)?.type_def {
+        TypeDef::Primitive(primitive) => match primitive {
+            TypeDefPrimitive::Bool => bool::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::Str => String::decode(input).ok(),
+            TypeDefPrimitive::U8 => u8::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::U16 => u16::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::U32 => u32::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::U64 => u64::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::U128 => u128::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::I8 => i8::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::I16 => i16::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::I32 => i32::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::I64 => i64::decode(input).ok().map(|v| v.to_string()),
+            TypeDefPrimitive::I128 => i128::decode(input).ok().map(|v| v.to_string()),
+            _ => None,
+        },
+        TypeDef::Composite(composite) if composite.fields.len() == 1 => {
+            decode_primitive(types, composite.fields[0].ty.id, value)
+        }
+        _ => None,
+    }
+}
+
+/// The storage item of a storage key and its decoded map keys.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StorageKeyInfo {
+    pub pallet: String,
+    pub item: String,
+    /// The decoded map keys. Keys hashed with non-concat hashers are returned as their hex
+    /// encoded hash.
+    pub map_keys: Vec<String>,
+}
+
+/// Index of all the storage items in the metadata by their (hashed) prefix, used to reverse
+/// lookup storage keys.
+pub(crate) struct StorageKeyIndex<'a> {
+    types: &'a PortableRegistry,
+    items: BTreeMap<Vec<u8>, (&'a str, &'a StorageEntryMetadata<PortableForm>)>,
+}
+
+impl<'a> StorageKeyIndex<'a> {
+    /// Builds the index of all the storage items in `metadata`.
+    pub fn new(metadata: &'a PalletsMetadata) -> Self {
+        let items = metadata
+            .pallets
+            .iter()
+            .filter_map(|p| p.storage.as_ref())
+            .flat_map(|storage| {
+                storage.entries.iter().map(|entry| {
+                    let prefix = [
+                        twox_128(storage.prefix.as_bytes()),
+                        twox_128(entry.name.as_bytes()),
+                    ]
+                    .concat();
+                    (prefix, (storage.prefix.as_str(), entry))
+                })
+            })
+            .collect();
+
+        Self {
+            types: &metadata.types,
+            items,
+        }
+    }
+
+    /// Identifies the storage item of `key` and decodes its map keys, where the hashers permit.
+    ///
+    /// Returns `None` if the key does not belong to any storage item in the metadata.
+    pub fn lookup(&self, key: &[u8]) -> Option<StorageKeyInfo> {
+        if key.len() < 32 {
+            return None;
+        }
+        let (pallet, entry) = self.items.get(&key[..32])?;
+
+        let mut info = StorageKeyInfo {
+            pallet: pallet.to_string(),
+            item: entry.name.clone(),
+            map_keys: vec![],
+        };
+
+        if let StorageEntryType::Map {
+            hashers, key: ty, ..
+        } = &entry.ty
+        {
+            let key_types = match &self.types.resolve(ty.id)?.type_def {
+                TypeDef::Tuple(tuple) if hashers.len() > 1 => {
+                    tuple.fields.iter().map(|f| f.id).collect()
+                }
+                _ => vec![ty.id],
+            };
+
+            let input = &mut &key[32..];
+            for (hasher, ty) in hashers.iter().zip(key_types) {
+                let hash_len = match hasher {
+                    StorageHasher::Blake2_128
+                    | StorageHasher::Twox128
+                    | StorageHasher::Blake2_128Concat => 16,
+                    StorageHasher::Blake2_256 | StorageHasher::Twox256 => 32,
+                    StorageHasher::Twox64Concat => 8,
+                    StorageHasher::Identity => 0,
+                };
+                if input.len() < hash_len {
+                    break;
+                }
+                let (hash, rest) = input.split_at(hash_len);
+                *input = rest;
+
+                match hasher {
+                    StorageHasher::Blake2_128Concat
+                    | StorageHasher::Twox64Concat
+                    | StorageHasher::Identity => {
+                        let before = *input;
+                        if skip_value(self.types, ty, input).is_none() {
+                            break;
+                        }
+                        let value = &before[..before.len() - input.len()];
+                        info.map_keys.push(format_value(self.types, ty, value));
+                    }
+                    _ => info.map_keys.push(sp_core::bytes::to_hex(hash, false)),
+                }
+            }
+        }
+
+        Some(info)
+    }
+
+    /// Returns the type of the value stored under `key`, or `None` if the key does not belong to
+    /// any storage item in the metadata.
+    pub fn value_type(&self, key: &[u8]) -> Option<u32> {
+        let (_, entry) = self.items.get(key.get(..32)?)?;
+        match &entry.ty {
+            StorageEntryType::Plain(ty) => Some(ty.id),
+            StorageEntryType::Map { value, .. } => Some(value.id),
+        }
+    }
+
+    /// Decodes the `value` stored under `key` into a human readable string, or returns it as a hex
+    /// string if the key does not belong to any storage item or the value could not be decoded.
+    pub fn format_stored(&self, key: &[u8], value: &[u8]) -> String {
+        self.value_type(key)
+            .and_then(|ty| super::dynamic::decode(self.types, ty, &mut &value[..]))
+            .map_or_else(|| sp_core::bytes::to_hex(value, false), |v| v.to_string())
+    }
+}
+
+/// Formats a SCALE encoded value of type `ty`, rendering account ids in SS58.
+pub(crate) fn format_value(types: &PortableRegistry, ty: u32, value: &[u8]) -> String {
+    let is_account = types
+        .resolve(ty)
+        .and_then(|t| t.path.segments.last())
+        .map_or(false, |name| name == "AccountId32");
+
+    if is_account && value.len() == 32 {
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(value);
+        sp_core::crypto::AccountId32::from(raw).to_ss58check()
+    } else {
+        decode_value(types, ty, value)
+    }
+}
+
+/// Advances `input` past a SCALE encoded value of type `ty`. Returns `None` if the value could
+/// not be decoded.
+fn skip_value(types: &PortableRegistry, ty: u32, input: &mut &[u8]) -> Option<()> {
+    fn skip_bytes(input: &mut &[u8], len: usize) -> Option<()> {
+        if input.len() < len {
+            return None;
+        }
+        *input = &input[len..];
+        Some(())
+    }
+
+    match &types.resolve(ty)?.type_def {
+        TypeDef::Composite(composite) => composite
+            .fields
+            .iter()
+            .try_for_each(|f| skip_value(types, f.ty.id, input)),
+        TypeDef::Variant(variant) => {
+            let index = u8::decode(input).ok()?;
+            variant
+                .variants
+                .iter()
+                .find(|v| v.index == index)?
+                .fields
+                .iter()
+                .try_for_each(|f| skip_value(types, f.ty.id, input))
+        }
+        TypeDef::Sequence(sequence) => {
+            let len = Compact::<u32>::decode(input).ok()?.0;
+            (0..len).try_for_each(|_| skip_value(types, sequence.type_param.id, input))
+        }
+        TypeDef::Array(array) => {
+            (0..array.len).try_for_each(|_| skip_value(types, array.type_param.id, input))
+        }
+        TypeDef::Tuple(tuple) => tuple
+            .fields
+            .iter()
+            .try_for_each(|f| skip_value(types, f.id, input)),
+        TypeDef::Primitive(primitive) => match primitive {
+            TypeDefPrimitive::Bool | TypeDefPrimitive::U8 | TypeDefPrimitive::I8 => {
+                skip_bytes(input, 1)
+            }
+            TypeDefPrimitive::U16 | TypeDefPrimitive::I16 => skip_bytes(input, 2),
+            TypeDefPrimitive::U32 | TypeDefPrimitive::I32 | TypeDefPrimitive::Char => {
+                skip_bytes(input, 4)
+            }
+            TypeDefPrimitive::U64 | TypeDefPrimitive::I64 => skip_bytes(input, 8),
+            TypeDefPrimitive::U128 | TypeDefPrimitive::I128 => skip_bytes(input, 16),
+            TypeDefPrimitive::U256 | TypeDefPrimitive::I256 => skip_bytes(input, 32),
+            TypeDefPrimitive::Str => {
+                let len = Compact::<u32>::decode(input).ok()?.0;
+                skip_bytes(input, len as usize)
+            }
+        },
+        TypeDef::Compact(_) => Compact::<u128>::decode(input).ok().map(|_| ()),
+        TypeDef::BitSequence(_) => None,
+    }
+}
+
+/// The kind of change of a storage item between two runtimes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LayoutChangeKind {
+    Added,
+    Removed,
+    Retyped,
+}
+
+/// A change of a storage item between two runtimes.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StorageLayoutChange {
+    pub pallet: String,
+    pub item: String,
+    pub kind: LayoutChangeKind,
+    /// The parts of the storage item which changed, if retyped.
+    pub details: Vec<&'static str>,
+}
+
+/// Computes the storage items added, removed and retyped between the `old` and `new` metadata.
+///
+/// Since type ids are not stable across runtimes, the types of the storage items are compared
+/// structurally (see [`TypeHasher`]).
+pub(crate) fn storage_layout_changes(
+    old: &PalletsMetadata,
+    new: &PalletsMetadata,
+) -> Vec<StorageLayoutChange> {
+    fn entries_of(
+        metadata: &PalletsMetadata,
+    ) -> BTreeMap<(&str, &str), &StorageEntryMetadata<PortableForm>> {
+        metadata
+            .pallets
+            .iter()
+            .filter_map(|p| p.storage.as_ref().map(|s| (p.name.as_str(), s)))
+            .flat_map(|(pallet, storage)| {
+                storage
+                    .entries
+                    .iter()
+                    .map(move |e| ((pallet, e.name.as_str()), e))
+            })
+            .collect()
+    }
+
+    let (old_entries, new_entries) = (entries_of(old), entries_of(new));
+    let (mut old_hasher, mut new_hasher) =
+        (TypeHasher::new(&old.types), TypeHasher::new(&new.types));
+
+    let items = old_entries
+        .keys()
+        .chain(new_entries.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut changes = vec![];
+    for &(pallet, item) in items {
+        let (kind, details) = match (
+            old_entries.get(&(pallet, item)),
+            new_entries.get(&(pallet, item)),
+        ) {
+            (Some(_), None) => (LayoutChangeKind::Removed, vec![]),
+            (None, Some(_)) => (LayoutChangeKind::Added, vec![]),
+            (Some(old_entry), Some(new_entry)) => {
+                let mut details = vec![];
+                if old_entry.modifier != new_entry.modifier {
+                    details.push("modifier");
+                }
+                match (&old_entry.ty, &new_entry.ty) {
+                    (StorageEntryType::Plain(old_ty), StorageEntryType::Plain(new_ty)) => {
+                        if old_hasher.hash(old_ty.id) != new_hasher.hash(new_ty.id) {
+                            details.push("value");
+                        }
+                    }
+                    (
+                        StorageEntryType::Map {
+                            hashers: old_hashers,
+                            key: old_key,
+                            value: old_value,
+                        },
+                        StorageEntryType::Map {
+                            hashers: new_hashers,
+                            key: new_key,
+                            value: new_value,
+                        },
+                    ) => {
+                        if old_hashers != new_hashers {
+                            details.push("hashers");
+                        }
+                        if old_hasher.hash(old_key.id) != new_hasher.hash(new_key.id) {
+                            details.push("key");
+                        }
+                        if old_hasher.hash(old_value.id) != new_hasher.hash(new_value.id) {
+                            details.push("value");
+                        }
+                    }
+                    _ => details.push("kind"),
+                }
+
+                if details.is_empty() {
+                    continue;
+                }
+                (LayoutChangeKind::Retyped, details)
+            }
+            (None, None) => unreachable!("item is a key of one of the entries; qed."),
+        };
+
+        changes.push(StorageLayoutChange {
+            pallet: pallet.to_string(),
+            item: item.to_string(),
+            kind,
+            details,
+        });
+    }
+
+    changes
+}
+
+/// Computes a structural hash of the types of a registry, so that types can be compared across
+/// registries (e.g. of different runtimes) regardless of their ids.
+pub(crate) struct TypeHasher<'a> {
+    types: &'a PortableRegistry,
+    cache: HashMap<u32, [u8; 32]>,
+    visiting: HashSet<u32>,
+}
+
+impl<'a> TypeHasher<'a> {
+    pub fn new(types: &'a PortableRegistry) -> Self {
+        Self {
+            types,
+            cache: Default::default(),
+            visiting: Default::default(),
+        }
+    }
+
+    /// Hashes the path and the (recursive) definition of type `ty`. Recursive references to a
+    /// type which is being hashed are hashed by path only.
+    pub fn hash(&mut self, ty: u32) -> [u8; 32] {
+        if let Some(hash) = self.cache.get(&ty) {
+            return *hash;
+        }
+        let types = self.types;
+        let resolved = match types.resolve(ty) {
+            Some(resolved) => resolved,
+            None => return blake2_256(b"unknown"),
+        };
+        if !self.visiting.insert(ty) {
+            return blake2_256(&resolved.path.segments.encode());
+        }
+
+        let mut buf = resolved.path.segments.encode();
+        match &resolved.type_def {
+            TypeDef::Composite(composite) => {
+                buf.push(0);
+                for field in composite.fields.iter() {
+                    buf.extend(field.name.encode());
+                    buf.extend(self.hash(field.ty.id));
+                }
+            }
+            TypeDef::Variant(variant) => {
+                buf.push(1);
+                for v in variant.variants.iter() {
+                    buf.extend((&v.name, v.index).encode());
+                    for field in v.fields.iter() {
+                        buf.extend(field.name.encode());
+                        buf.extend(self.hash(field.ty.id));
+                    }
+                }
+            }
+            TypeDef::Sequence(sequence) => {
+                buf.push(2);
+                buf.extend(self.hash(sequence.type_param.id));
+            }
+            TypeDef::Array(array) => {
+                buf.push(3);
+                buf.extend(array.len.encode());
+                buf.extend(self.hash(array.type_param.id));
+            }
+            TypeDef::Tuple(tuple) => {
+                buf.push(4);
+                for field in tuple.fields.iter() {
+                    buf.extend(self.hash(field.id));
+                }
+            }
+            TypeDef::Primitive(primitive) => {
+                buf.push(5);
+                buf.extend(primitive.encode());
+            }
+            TypeDef::Compact(compact) => {
+                buf.push(6);
+                buf.extend(self.hash(compact.type_param.id));
+            }
+            TypeDef::BitSequence(bits) => {
+                buf.push(7);
+                buf.extend(self.hash(bits.bit_store_type.id));
+                buf.extend(self.hash(bits.bit_order_type.id));
+            }
+        }
+
+        self.visiting.remove(&ty);
+        let hash = blake2_256(&buf);
+        self.cache.insert(ty, hash);
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scale_info::{meta_type, Registry};
+
+    #[test]
+    fn decode_value_works() {
+        let mut registry = Registry::new();
+        let u32_ty = registry.register_type(&meta_type::<u32>()).id;
+        let perbill_ty = registry
+            .register_type(&meta_type::<sp_runtime::Perbill>())
+            .id;
+        let tuple_ty = registry.register_type(&meta_type::<(u8, u8)>()).id;
+        let types: PortableRegistry = registry.into();
+
+        assert_eq!(decode_value(&types, u32_ty, &42u32.encode()), "42");
+        assert_eq!(
+            decode_value(
+                &types,
+                perbill_ty,
+                &sp_runtime::Perbill::from_percent(10).encode()
+            ),
+            "100000000"
+        );
+        assert_eq!(
+            decode_value(&types, tuple_ty, &(1u8, 2u8).encode()),
+            "0x0102"
+        );
+    }
+
+    #[test]
+    fn storage_key_lookup_works() {
+        use frame_metadata::v14::StorageEntryModifier;
+        use sp_core::{
+            crypto::AccountId32,
+            hashing::{blake2_128, twox_64},
+        };
+
+        let mut registry = Registry::new();
+        let account_ty = registry.register_type(&meta_type::<AccountId32>());
+        let era_ty = registry.register_type(&meta_type::<u32>());
+        let double_ty = registry.register_type(&meta_type::<(u32, AccountId32)>());
+        let types: PortableRegistry = registry.into();
+
+        let entry = |name: &str, hashers, key| StorageEntryMetadata {
+            name: name.to_string(),
+            modifier: StorageEntryModifier::Optional,
+            ty: StorageEntryType::Map {
+                hashers,
+                key,
+                value: account_ty,
+            },
+            default: vec![],
+            docs: vec![],
+        };
+
+        let metadata = PalletsMetadata {
+            types,
+            extensions: vec![],
+            pallets: vec![PalletMetadata {
+                name: "Staking".to_string(),
+                storage: Some(PalletStorageMetadata {
+                    prefix: "Staking".to_string(),
+                    entries: vec![
+                        entry("Bonded", vec![StorageHasher::Twox64Concat], account_ty),
+                        entry("Opaque", vec![StorageHasher::Blake2_128], era_ty),
+                        entry(
+                            "ErasStakers",
+                            vec![StorageHasher::Twox64Concat, StorageHasher::Twox64Concat],
+                            double_ty,
+                        ),
+                    ],
+                }),
+                constants: vec![],
+            }],
+        };
+        let index = StorageKeyIndex::new(&metadata);
+
+        let account = AccountId32::from([1u8; 32]);
+        let prefix = |item: &str| [twox_128(b"Staking"), twox_128(item.as_bytes())].concat();
+
+        let key = [
+            prefix("Bonded"),
+            twox_64(account.as_ref()).to_vec(),
+            account.encode(),
+        ]
+        .concat();
+        assert_eq!(
+            index.lookup(&key),
+            Some(StorageKeyInfo {
+                pallet: "Staking".to_string(),
+                item: "Bonded".to_string(),
+                map_keys: vec![account.to_ss58check()],
+            })
+        );
+
+        let key = [
+            prefix("ErasStakers"),
+            twox_64(&10u32.encode()).to_vec(),
+            10u32.encode(),
+            twox_64(account.as_ref()).to_vec(),
+            account.encode(),
+        ]
+        .concat();
+        assert_eq!(
+            index.lookup(&key).unwrap().map_keys,
+            vec!["10".to_string(), account.to_ss58check()]
+        );
+
+        let hash = blake2_128(&10u32.encode());
+        let key = [prefix("Opaque"), hash.to_vec()].concat();
+        assert_eq!(
+            index.lookup(&key).unwrap().map_keys,
+            vec![sp_core::bytes::to_hex(&hash, false)]
+        );
+
+        assert_eq!(index.lookup(&prefix("Unknown")), None);
+    }
+
+    #[test]
+    fn storage_layout_changes_works() {
+        use frame_metadata::v14::StorageEntryModifier;
+
+        let metadata_with = |entries: Vec<(&str, StorageEntryType<PortableForm>)>,
+                             types: PortableRegistry| PalletsMetadata {
+            types,
+            extensions: vec![],
+            pallets: vec![PalletMetadata {
+                name: "Staking".to_string(),
+                storage: Some(PalletStorageMetadata {
+                    prefix: "Staking".to_string(),
+                    entries: entries
+                        .into_iter()
+                        .map(|(name, ty)| StorageEntryMetadata {
+                            name: name.to_string(),
+                            modifier: StorageEntryModifier::Default,
+                            ty,
+                            default: vec![],
+                            docs: vec![],
+                        })
+                        .collect(),
+                }),
+                constants: vec![],
+            }],
+        };
+
+        // registers the types in different order so that the type ids differ between runtimes.
+        let mut registry = Registry::new();
+        let old_u32 = registry.register_type(&meta_type::<u32>());
+        let old_u64 = registry.register_type(&meta_type::<u64>());
+        let old = metadata_with(
+            vec![
+                ("CurrentEra", StorageEntryType::Plain(old_u32)),
+                ("ValidatorCount", StorageEntryType::Plain(old_u32)),
+                ("Removed", StorageEntryType::Plain(old_u64)),
+            ],
+            registry.into(),
+        );
+
+        let mut registry = Registry::new();
+        let new_u64 = registry.register_type(&meta_type::<u64>());
+        let new_u32 = registry.register_type(&meta_type::<u32>());
+        let new = metadata_with(
+            vec![
+                ("CurrentEra", StorageEntryType::Plain(new_u32)),
+                ("ValidatorCount", StorageEntryType::Plain(new_u64)),
+                ("Added", StorageEntryType::Plain(new_u64)),
+            ],
+            registry.into(),
+        );
+
+        let changes = storage_layout_changes(&old, &new)
+            .into_iter()
+            .map(|c| (c.item, c.kind, c.details))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            changes,
+            vec![
+                ("Added".to_string(), LayoutChangeKind::Added, vec![]),
+                ("Removed".to_string(), LayoutChangeKind::Removed, vec![]),
+                (
+                    "ValidatorCount".to_string(),
+                    LayoutChangeKind::Retyped,
+                    vec!["value"]
+                ),
+            ]
+        );
+    }
+}
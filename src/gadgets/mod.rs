@@ -6,8 +6,7 @@
 use crate::configs::Solver;
 use crate::prelude::*;
 use crate::utils;
-
-use anyhow::anyhow;
+use crate::TimetravelError;
 
 use codec::Encode;
 use frame_election_provider_support::NposSolver;
@@ -16,51 +15,249 @@ use frame_election_provider_support::{
 };
 use frame_support::traits::Get;
 use frame_system::pallet_prelude::BlockNumberFor;
-use sp_npos_elections::{BalancingConfig, ElectionScore, EvaluateSupport};
+use sp_npos_elections::{BalancingConfig, ElectionResult, ElectionScore, EvaluateSupport};
 use sp_runtime::traits::Zero;
 use Staking::ActiveEraInfo;
 use EPM::{BalanceOf, RoundSnapshot, SnapshotWrapper, SolutionOrSnapshotSize};
 
+pub(crate) mod anonymize;
+pub(crate) mod authorship;
+pub(crate) mod chain_spec;
+pub(crate) mod compose;
+pub(crate) mod dynamic;
+pub(crate) mod entities;
+pub(crate) mod flows;
+pub(crate) mod inflation;
+pub(crate) mod memory;
+pub(crate) mod metadata;
+pub(crate) mod mutation;
+pub(crate) mod paged;
+pub(crate) mod rewards;
+pub(crate) mod solvers;
 pub(crate) mod staking_ledger;
+pub(crate) mod staking_stats;
+pub(crate) mod synthetic;
 
 /// Returns the current block number.
 pub(crate) fn block_number<T: EPM::Config>(ext: &mut Ext) -> BlockNumberFor<T> {
     ext.execute_with(|| <frame_system::Pallet<T>>::block_number())
 }
 
+/// Orders the externalities of an operation `op` over multiple blocks by block number, along with
+/// their tags (e.g. their block hash). Fails if there are less than `min` externalities.
+pub(crate) fn by_block_number<T: EPM::Config, X>(
+    op: &str,
+    exts: impl IntoIterator<Item = (X, Ext)>,
+    min: usize,
+) -> Result<Vec<(BlockNumberFor<T>, X, Ext)>, anyhow::Error> {
+    let mut exts = exts
+        .into_iter()
+        .map(|(tag, mut ext)| (block_number::<T>(&mut ext), tag, ext))
+        .collect::<Vec<_>>();
+
+    if exts.len() < min {
+        return Err(TimetravelError::Unsupported {
+            reason: format!(
+                "{} requires at least {} snapshots, got {}",
+                op,
+                min,
+                exts.len()
+            ),
+        }
+        .into());
+    }
+
+    exts.sort_by_key(|(block_number, _, _)| *block_number);
+    Ok(exts)
+}
+
+/// Splits the two externalities of an operation `op` into the (parent, child) pair, i.e. ordered by
+/// block number. Fails unless there are exactly two externalities.
+pub(crate) fn parent_and_child<T: EPM::Config>(
+    op: &str,
+    exts: Vec<Ext>,
+) -> Result<(Ext, Ext), anyhow::Error> {
+    if exts.len() != 2 {
+        return Err(TimetravelError::Unsupported {
+            reason: format!("{} expects 2 snapshots, got {}", op, exts.len()),
+        }
+        .into());
+    }
+
+    let mut exts = by_block_number::<T, _>(op, exts.into_iter().map(|ext| ((), ext)), 2)?;
+    let (_, _, child) = exts.pop().expect("len is 2; qed.");
+    let (_, _, parent) = exts.pop().expect("len is 2; qed.");
+
+    Ok((parent, child))
+}
+
+/// Returns whether the externalities holds any storage of `pallet`.
+pub(crate) fn has_pallet_storage(ext: &mut Ext, pallet: &str) -> bool {
+    let prefix = sp_core::hashing::twox_128(pallet.as_bytes());
+    ext.execute_with(|| {
+        sp_io::storage::next_key(&prefix).map_or(false, |key| key.starts_with(&prefix))
+    })
+}
+
+/// Returns the number of keys under `prefix` in the externalities.
+pub(crate) fn count_keys(ext: &mut Ext, prefix: &[u8]) -> u64 {
+    ext.execute_with(|| {
+        let mut count = 0;
+        let mut key = prefix.to_vec();
+        if sp_io::storage::exists(&key) {
+            count += 1;
+        }
+        while let Some(next) = sp_io::storage::next_key(&key).filter(|k| k.starts_with(prefix)) {
+            count += 1;
+            key = next;
+        }
+        count
+    })
+}
+
 /// Returns the current active era.
 pub(crate) fn active_era<T: Staking::Config>(ext: &mut Ext) -> Option<ActiveEraInfo> {
     ext.execute_with(|| <Staking::ActiveEra<T>>::get())
 }
 
-/// Returns the snapshot bounds and encoded size.
-///
-/// If the snapshot does not exist in the current externalities, it creates a new one using the
-/// same algorithm as the runtime.
-pub(crate) fn snapshot_data_or_force<T: EPM::Config>(
+/// Returns the desired number of election winners, or zero if it is not set.
+pub(crate) fn desired_targets<T: EPM::Config>(ext: &mut Ext) -> u32 {
+    ext.execute_with(|| <EPM::DesiredTargets<T>>::get().unwrap_or_default())
+}
+
+/// Caps the desired number of election winners to the number of electable `targets`, warning if
+/// it is capped.
+pub(crate) fn cap_desired_targets(desired_targets: u32, targets: u32) -> u32 {
+    if desired_targets > targets {
+        crate::warnings::warn(
+            "capped_desired_targets",
+            format!(
+                "desired_targets: {} > targets.len(): {}, capping desired_targets",
+                desired_targets, targets
+            ),
+        );
+        return targets;
+    }
+    desired_targets
+}
+
+/// Overrides the desired number of election winners of the current snapshot with
+/// `desired_targets`, capped to the number of targets of the snapshot, and returns the number of
+/// winners set.
+pub(crate) fn override_desired_targets<T: EPM::Config>(
     ext: &mut Ext,
-) -> (SolutionOrSnapshotSize, usize) {
+    desired_targets: u32,
+) -> Result<u32, anyhow::Error> {
+    let result = ext.execute_with(|| {
+        let metadata =
+            <EPM::SnapshotMetadata<T>>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets = cap_desired_targets(desired_targets, metadata.targets);
+
+        log::info!(
+            target: LOG_TARGET,
+            "override_desired_targets: {} desired targets, {} on-chain.",
+            desired_targets,
+            <EPM::DesiredTargets<T>>::get().unwrap_or_default(),
+        );
+        <EPM::DesiredTargets<T>>::put(desired_targets);
+
+        Ok(desired_targets)
+    });
+    crate::trace::record_writes(ext);
+    result
+}
+
+/// Runs `simulation` over `ext` and reverts the changes it makes to the storage once it returns,
+/// so that repeated simulations start from the pristine state without copying or reloading the
+/// externalities.
+///
+/// The changes of the simulation are kept in a storage transaction of the overlay, on top of the
+/// untouched backend, i.e. the backend is copied on write only. Hence, the simulation must not
+/// commit the overlay into the backend (e.g. with `commit_all`). The writes of the simulation are
+/// traced before they are reverted.
+pub(crate) fn simulate<R>(ext: &mut Ext, simulation: impl FnOnce(&mut Ext) -> R) -> R {
+    ext.execute_with(sp_io::storage::start_transaction);
+    let result = simulation(ext);
+    crate::trace::record_writes(ext);
+    ext.execute_with(sp_io::storage::rollback_transaction);
+    result
+}
+
+/// Where the election snapshot used by an operation comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SnapshotSource {
+    /// The snapshot of the election provider at the block, i.e. the one the submissions are
+    /// mined from.
+    OnChain,
+    /// A snapshot created from the staking state at the block, as the runtime would at the start
+    /// of the signed phase.
+    Created,
+}
+
+/// The election snapshot of an externalities, and the phase of the election provider it was
+/// retrieved at.
+#[derive(Debug, Clone)]
+pub(crate) struct ElectionSnapshot {
+    pub phase: String,
+    pub source: SnapshotSource,
+    pub metadata: SolutionOrSnapshotSize,
+    /// The encoded size of the snapshot.
+    pub size: usize,
+}
+
+/// Whether the election provider holds a snapshot in `phase`, i.e. from the start of the signed
+/// phase until the election.
+pub(crate) fn snapshot_expected<Bn: PartialEq + Eq>(phase: &EPM::Phase<Bn>) -> bool {
+    !phase.is_off()
+}
+
+/// Returns the election snapshot, along with the phase of the election provider.
+///
+/// The on-chain snapshot is used whenever it exists, i.e. in the signed, unsigned and emergency
+/// phases. Otherwise, e.g. in the off phase, a new snapshot is created using the same algorithm
+/// as the runtime. A snapshot created in a phase where one is expected is reported as a
+/// `missing_snapshot` warning, since it may differ from the snapshot of the election.
+pub(crate) fn election_snapshot<T: EPM::Config>(ext: &mut Ext) -> ElectionSnapshot {
     ext.execute_with(|| {
-        if <EPM::Snapshot<T>>::get().is_some() {
+        let phase = <EPM::CurrentPhase<T>>::get();
+
+        let source = if <EPM::Snapshot<T>>::exists() {
             log::info!(
                 target: LOG_TARGET,
-                "snapshot_data_or_force: snapshot already exists."
+                "election_snapshot: using the on-chain snapshot (phase {:?}).",
+                phase,
             );
+            SnapshotSource::OnChain
         } else {
-            log::info!(
-                target: LOG_TARGET,
-                "snapshot_data_or_force: creating a snapshot now."
-            );
+            if snapshot_expected(&phase) {
+                crate::warnings::warn(
+                    "missing_snapshot",
+                    format!(
+                        "election_snapshot: no snapshot in phase {:?}, creating a snapshot now.",
+                        phase
+                    ),
+                );
+            } else {
+                log::info!(
+                    target: LOG_TARGET,
+                    "election_snapshot: creating a snapshot now (phase {:?}).",
+                    phase,
+                );
+            }
             <EPM::Pallet<T>>::create_snapshot().unwrap();
+            SnapshotSource::Created
         };
 
-        (
-            <EPM::SnapshotMetadata<T>>::get().expect("snapshot metadata should exist by now. qed."),
-            <EPM::Pallet<T>>::snapshot()
+        ElectionSnapshot {
+            phase: format!("{:?}", phase),
+            source,
+            metadata: <EPM::SnapshotMetadata<T>>::get()
+                .expect("snapshot metadata should exist by now. qed."),
+            size: <EPM::Pallet<T>>::snapshot()
                 .expect("snapshot should exist by now. qed.")
                 .encode()
                 .len(),
-        )
+        }
     })
 }
 
@@ -69,14 +266,18 @@ pub(crate) fn snapshot_data_or_force<T: EPM::Config>(
 /// The new snapshot is unbounded in terms of the number of voters, i.e., all the voters in the
 /// voter list will be used in the creation of the new snashot. The target bound remains
 /// `MaxElectableTargets`.
+///
+/// The desired number of winners is the one of the runtime, unless overridden by
+/// `desired_targets`. Either way, it is capped to the number of targets of the snapshot.
 pub(crate) fn compute_and_store_unbounded_snapshot<T>(
     ext: &mut Ext,
+    desired_targets: Option<u32>,
 ) -> Result<(SolutionOrSnapshotSize, usize), anyhow::Error>
 where
     T: EPM::Config + Staking::Config,
     EPM::Pallet<T>: ElectionProviderBase,
 {
-    ext.execute_with(|| {
+    let result = ext.execute_with(|| {
         SnapshotWrapper::<T>::kill();
         assert!(<EPM::Snapshot<T>>::get().is_none());
 
@@ -86,25 +287,18 @@ where
             <<T as EPM::Config>::DataProvider as ElectionDataProvider>::electable_targets(
                 election_bounds.targets,
             )
-            .map_err(|e| anyhow!(e.to_string()))?;
+            .map_err(|e| TimetravelError::failed("creating snapshot", e))?;
         let voters = <<T as EPM::Config>::DataProvider as ElectionDataProvider>::electing_voters(
             election_bounds.voters,
         )
-        .map_err(|e| anyhow!(e.to_string()))?;
-
-        let mut desired_targets =
-            <EPM::Pallet<T> as ElectionProviderBase>::desired_targets_checked()
-                .map_err(|e| anyhow!(e.to_string()))?;
-        let max_desired_targets: u32 = targets.len() as u32;
-        if desired_targets > max_desired_targets {
-            log::warn!(
-                target: LOG_TARGET,
-                "desired_targets: {} > targets.len(): {}, capping desired_targets",
-                desired_targets,
-                max_desired_targets
-            );
-            desired_targets = max_desired_targets;
-        }
+        .map_err(|e| TimetravelError::failed("creating snapshot", e))?;
+
+        let desired_targets = match desired_targets {
+            Some(desired_targets) => desired_targets,
+            None => <EPM::Pallet<T> as ElectionProviderBase>::desired_targets_checked()
+                .map_err(|e| TimetravelError::failed("creating snapshot", e))?,
+        };
+        let desired_targets = cap_desired_targets(desired_targets, targets.len() as u32);
 
         // mimic `EPM::create_snashot_internal` and store voter-unbounded snapshot.
         let metadata = SolutionOrSnapshotSize {
@@ -124,9 +318,139 @@ where
             .len();
 
         Ok((metadata, snapshot_len))
+    });
+    crate::trace::record_writes(ext);
+    result
+}
+
+/// The encoded length and weight of a solution, and their limits as per the miner config.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SolutionSize {
+    pub length: u32,
+    pub max_length: u32,
+    pub weight: u64,
+    pub max_weight: u64,
+}
+
+/// Returns the encoded length and weight of a solution mined from the current snapshot.
+pub(crate) fn solution_size<T: EPM::Config>(
+    ext: &mut Ext,
+    raw_solution: &EPM::RawSolution<EPM::SolutionOf<T::MinerConfig>>,
+) -> Result<SolutionSize, anyhow::Error> {
+    use EPM::MinerConfig;
+
+    ext.execute_with(|| {
+        let metadata = <EPM::SnapshotMetadata<T>>::get()
+            .ok_or(TimetravelError::missing("Snapshot metadata"))?;
+        let desired_targets =
+            <EPM::DesiredTargets<T>>::get().ok_or(TimetravelError::missing("Desired targets"))?;
+
+        let weight = <T::MinerConfig as MinerConfig>::solution_weight(
+            metadata.voters,
+            metadata.targets,
+            raw_solution.solution.voter_count() as u32,
+            desired_targets,
+        );
+
+        Ok(SolutionSize {
+            length: raw_solution.solution.encode().len() as u32,
+            max_length: <T::MinerConfig as MinerConfig>::MaxLength::get(),
+            weight: weight.ref_time(),
+            max_weight: <T::MinerConfig as MinerConfig>::MaxWeight::get().ref_time(),
+        })
     })
 }
 
+/// The state of the election provider at a block.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ElectionStatus {
+    pub round: u32,
+    pub phase: String,
+    /// Whether the election provider is in the `Emergency` phase.
+    pub emergency: bool,
+    /// Whether an `ElectionFailed` event, or a transition to the `Emergency` phase, was deposited
+    /// in the block.
+    pub election_failed: bool,
+    /// How the queued solution was computed (signed, unsigned, fallback or emergency), if a
+    /// solution is queued.
+    pub queued_compute: Option<String>,
+    pub queued_score: Option<ElectionScore>,
+}
+
+/// Returns the round, phase and queued solution of the election provider, along with whether the
+/// election failed in the block.
+pub(crate) fn election_status<T>(ext: &mut Ext) -> ElectionStatus
+where
+    T: EPM::Config,
+    <T as frame_system::Config>::RuntimeEvent: TryInto<EPM::Event<T>>,
+{
+    ext.execute_with(|| {
+        let queued = <EPM::QueuedSolution<T>>::get();
+        let phase = <EPM::CurrentPhase<T>>::get();
+        let election_failed = <frame_system::Pallet<T>>::read_events_no_consensus().any(|record| {
+            matches!(
+                record.event.try_into(),
+                Ok(EPM::Event::<T>::ElectionFailed)
+                    | Ok(EPM::Event::<T>::PhaseTransitioned {
+                        to: EPM::Phase::Emergency,
+                        ..
+                    })
+            )
+        });
+
+        ElectionStatus {
+            round: <EPM::Pallet<T>>::round(),
+            phase: format!("{:?}", phase),
+            emergency: phase.is_emergency(),
+            election_failed,
+            queued_compute: queued.as_ref().map(|q| format!("{:?}", q.compute)),
+            queued_score: queued.map(|q| q.score),
+        }
+    })
+}
+
+/// A signed submission of the current election round.
+#[derive(Debug, Clone)]
+pub(crate) struct SignedSubmission<AccountId> {
+    /// The index of the submission in `SignedSubmissionsMap`.
+    pub index: u32,
+    pub who: AccountId,
+    /// The score claimed by the submitted solution.
+    pub score: ElectionScore,
+}
+
+/// Returns the signed submissions of the current round, by index.
+pub(crate) fn signed_submissions<T: EPM::Config>(
+    ext: &mut Ext,
+) -> Vec<SignedSubmission<AccountIdOf<T>>> {
+    ext.execute_with(|| {
+        let mut submissions = <EPM::SignedSubmissionsMap<T>>::iter()
+            .map(|(index, submission)| SignedSubmission {
+                index,
+                who: submission.who,
+                score: submission.raw_solution.score,
+            })
+            .collect::<Vec<_>>();
+        submissions.sort_by_key(|submission| submission.index);
+        submissions
+    })
+}
+
+/// Returns the ratios of the minimal stake and the sum of stakes of `score` to the ones of the
+/// `reference` score, e.g. of a submitted solution to the best locally mined solution.
+pub(crate) fn score_ratios(
+    score: &ElectionScore,
+    reference: &ElectionScore,
+) -> (Option<f64>, Option<f64>) {
+    let ratio =
+        |value: u128, reference: u128| (reference > 0).then(|| value as f64 / reference as f64);
+
+    (
+        ratio(score.minimal_stake, reference.minimal_stake),
+        ratio(score.sum_stake, reference.sum_stake),
+    )
+}
+
 /// Calculates the minimum active stake for a existing snapshot.
 pub(crate) fn min_active_stake<T: EPM::Config + Staking::Config>(ext: &mut Ext) -> BalanceOf<T>
 where
@@ -199,14 +523,29 @@ where
     >,
 {
     ext.execute_with(|| {
-        let (raw_solution, _, _) = <EPM::Pallet<T>>::mine_solution()
-            .map_err(|e| anyhow!("Error mining solution: {:?}.", e))?;
+        let RoundSnapshot { voters, targets } =
+            <EPM::Snapshot<T>>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets =
+            <EPM::DesiredTargets<T>>::get().ok_or(TimetravelError::missing("Desired targets"))?;
+
+        let (solution, score, _, _) =
+            EPM::Miner::<T::MinerConfig>::mine_solution_with_snapshot::<S>(
+                voters,
+                targets,
+                desired_targets,
+            )
+            .map_err(|e| TimetravelError::failed("mining solution", e))?;
+        let raw_solution = EPM::RawSolution {
+            solution,
+            score,
+            round: <EPM::Pallet<T>>::round(),
+        };
         if do_feasibility {
             let _ = <EPM::Pallet<T>>::feasibility_check(
                 raw_solution.clone(),
                 EPM::ElectionCompute::Signed,
             )
-            .map_err(|e| anyhow!("Error calculating feasibility check: {:?}.", e))?;
+            .map_err(|e| TimetravelError::failed("calculating feasibility check", e))?;
         }
 
         let voter_count = raw_solution.solution.voter_count();
@@ -230,7 +569,157 @@ frame_support::parameter_types! {
     pub static Balancing: Option<BalancingConfig> = Some( BalancingConfig { iterations: BalanceIterations::get(), tolerance: 0 } );
 }
 
+/// Path of the output of the solver debug mode, if enabled.
+static SOLVER_DEBUG: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+/// Enables the debug mode of [`mine_with`], which writes the score of each balancing iteration of
+/// the mined solutions to `output_path`, see [`balancing_steps`].
+pub(crate) fn set_solver_debug(output_path: &str) {
+    let _ = SOLVER_DEBUG.set(output_path.to_string());
+}
+
+/// The CSV representation of a balancing iteration of a solver, in the solver debug mode.
+#[derive(Debug, serde::Serialize)]
+struct BalancingStepCsv {
+    block_number: u32,
+    solver: String,
+    iteration: usize,
+    min_stake: u128,
+    sum_stake: u128,
+    sum_stake_squared: u128,
+    voters: usize,
+    edges: usize,
+    /// Whether the iteration did not change the score, i.e. the balancing converged.
+    converged: bool,
+}
+
+/// The state of an election solution after a number of balancing iterations.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BalancingStep {
+    pub iteration: usize,
+    /// The score of the (untrimmed) solution.
+    pub score: ElectionScore,
+    /// The number of voters and of edges (voter-target pairs) of the solution.
+    pub voters: usize,
+    pub edges: usize,
+}
+
+/// Mines the election of the snapshot with `solver` once per number of balancing iterations, from
+/// none to the iterations of the solver, to expose the scores of the intermediate balancing
+/// iterations. Since the balancing is deterministic, the solution after `i` iterations is the
+/// solution mined with `i` iterations.
+pub(crate) fn balancing_steps<T: EPM::Config>(
+    solver: &Solver,
+    ext: &mut Ext,
+) -> Result<Vec<BalancingStep>, anyhow::Error> {
+    ext.execute_with(|| {
+        let RoundSnapshot { voters, targets } =
+            EPM::Snapshot::<T>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets = EPM::DesiredTargets::<T>::get()
+            .ok_or(TimetravelError::missing("Desired targets"))?
+            as usize;
+
+        let stakes = voters
+            .iter()
+            .map(|(who, stake, _)| (who.clone(), *stake))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        let iterations = match solver {
+            Solver::SeqPhragmen { iterations }
+            | Solver::PhragMMS { iterations }
+            | Solver::ParallelPhragmen { iterations } => *iterations,
+        };
+
+        (0..=iterations)
+            .map(|iteration| {
+                let balancing = (iteration > 0).then_some(BalancingConfig {
+                    iterations: iteration,
+                    tolerance: 0,
+                });
+                let (targets, voters) = (targets.clone(), voters.clone());
+
+                let result =
+                    match solver {
+                        Solver::SeqPhragmen { .. } => sp_npos_elections::seq_phragmen::<
+                            _,
+                            sp_runtime::Perbill,
+                        >(
+                            desired_targets, targets, voters, balancing
+                        ),
+                        Solver::PhragMMS { .. } => sp_npos_elections::phragmms::<
+                            _,
+                            sp_runtime::Perbill,
+                        >(
+                            desired_targets, targets, voters, balancing
+                        ),
+                        Solver::ParallelPhragmen { .. } => {
+                            solvers::par_seq_phragmen::<_, sp_runtime::Perbill>(
+                                desired_targets,
+                                targets,
+                                voters,
+                                balancing,
+                            )
+                        }
+                    }
+                    .map_err(|e| TimetravelError::failed("mining balancing step", e))?;
+
+                let voters = result.assignments.len();
+                let edges = result
+                    .assignments
+                    .iter()
+                    .map(|a| a.distribution.len())
+                    .sum();
+                let staked = sp_npos_elections::assignment_ratio_to_staked_normalized(
+                    result.assignments,
+                    |who| stakes.get(who).cloned().unwrap_or_default(),
+                )
+                .map_err(|e| TimetravelError::failed("normalizing balancing step", e))?;
+
+                Ok(BalancingStep {
+                    iteration,
+                    score: sp_npos_elections::to_supports(&staked).evaluate(),
+                    voters,
+                    edges,
+                })
+            })
+            .collect()
+    })
+}
+
+/// Writes the [`balancing_steps`] of `solver` to the output of the solver debug mode.
+fn debug_solver<T: EPM::Config>(
+    solver: &Solver,
+    ext: &mut Ext,
+    output_path: &str,
+) -> Result<(), anyhow::Error> {
+    let block_number = block_number::<T>(ext);
+    let steps = balancing_steps::<T>(solver, ext)?;
+
+    let mut previous: Option<ElectionScore> = None;
+    for step in steps {
+        crate::output::write(
+            BalancingStepCsv {
+                block_number: sp_runtime::SaturatedConversion::saturated_into(block_number),
+                solver: format!("{:?}", solver),
+                iteration: step.iteration,
+                min_stake: step.score.minimal_stake,
+                sum_stake: step.score.sum_stake,
+                sum_stake_squared: step.score.sum_stake_squared,
+                voters: step.voters,
+                edges: step.edges,
+                converged: previous == Some(step.score),
+            },
+            output_path,
+        )?;
+        previous = Some(step.score);
+    }
+
+    Ok(())
+}
+
 /// Mines an election solution given a solver.
+///
+/// In the solver debug mode (see [`set_solver_debug`]), the scores of the intermediate balancing
+/// iterations are written too.
 pub(crate) fn mine_with<T>(
     solver: &Solver,
     ext: &mut Ext,
@@ -244,6 +733,10 @@ where
 
     use frame_election_provider_support::{PhragMMS, SequentialPhragmen};
 
+    if let Some(output_path) = SOLVER_DEBUG.get() {
+        debug_solver::<T>(solver, ext, output_path)?;
+    }
+
     match solver {
         Solver::SeqPhragmen { iterations } => {
             BalanceIterations::set(*iterations);
@@ -263,11 +756,417 @@ where
                 PhragMMS<<T as frame_system::Config>::AccountId, sp_runtime::Perbill, Balancing>,
             >(ext, do_feasibility)
         }
+        Solver::ParallelPhragmen { iterations } => {
+            BalanceIterations::set(*iterations);
+            mine_solution::<
+                T,
+                solvers::ParallelPhragmen<
+                    <T as frame_system::Config>::AccountId,
+                    sp_runtime::Perbill,
+                    Balancing,
+                >,
+            >(ext, do_feasibility)
+        }
     }
 }
 
+/// Mines an election solution with the given solver and returns the backing of each winner, as
+/// computed by the feasibility check of the solution.
+///
+/// The snapshot is created if it does not exist yet.
+pub(crate) fn mined_supports<T>(
+    solver: &Solver,
+    ext: &mut Ext,
+) -> Result<Vec<(AccountIdOf<T>, u128)>, anyhow::Error>
+where
+    T: EPM::Config,
+    T::Solver: NposSolver<Error = sp_npos_elections::Error>,
+{
+    let _ = election_snapshot::<T>(ext);
+    let raw_solution = mine_with::<T>(solver, ext, false)?;
+
+    ext.execute_with(|| {
+        let ready_solution =
+            <EPM::Pallet<T>>::feasibility_check(raw_solution, EPM::ElectionCompute::Signed)
+                .map_err(|e| TimetravelError::failed("calculating feasibility check", e))?;
+
+        Ok(ready_solution
+            .supports
+            .into_iter()
+            .map(|(who, support)| (who, support.total))
+            .collect())
+    })
+}
+
+/// Mines a solution with the given solver from the current snapshot and returns its score and
+/// winners. Unlike [`mined_supports`], the solution is not checked for feasibility, e.g. against
+/// the minimum untrusted score.
+pub(crate) fn mined_winners<T>(
+    solver: &Solver,
+    ext: &mut Ext,
+) -> Result<(ElectionScore, Vec<AccountIdOf<T>>), anyhow::Error>
+where
+    T: EPM::Config,
+    T::Solver: NposSolver<Error = sp_npos_elections::Error>,
+{
+    let raw_solution = mine_with::<T>(solver, ext, false)?;
+
+    ext.execute_with(|| {
+        let RoundSnapshot { targets, .. } =
+            <EPM::Snapshot<T>>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let winners = raw_solution
+            .solution
+            .unique_targets()
+            .into_iter()
+            .filter_map(|index| TryInto::<usize>::try_into(index).ok())
+            .filter_map(|index| targets.get(index).cloned())
+            .collect();
+
+        Ok((raw_solution.score, winners))
+    })
+}
+
+/// Removes the `excluded` accounts from the `targets` and appends the `included` accounts which
+/// are not targets yet. Returns the new targets and the number of targets removed and added.
+pub(crate) fn edit_targets<A: Ord + Clone>(
+    targets: Vec<A>,
+    excluded: &std::collections::BTreeSet<A>,
+    included: &[A],
+) -> (Vec<A>, u32, u32) {
+    let before = targets.len();
+    let mut targets = targets
+        .into_iter()
+        .filter(|target| !excluded.contains(target))
+        .collect::<Vec<_>>();
+    let removed = (before - targets.len()) as u32;
+
+    let mut added = 0;
+    for target in included {
+        if !targets.contains(target) {
+            targets.push(target.clone());
+            added += 1;
+        }
+    }
+
+    (targets, removed, added)
+}
+
+/// Drops the `excluded` targets from the election snapshot and injects the `included` ones, e.g.
+/// to simulate a validator retiring, being slashed out of the set or joining it, and returns the
+/// number of targets removed and added.
+///
+/// The votes of the voters are left untouched, so the votes for the dropped targets are ignored
+/// by the solvers and an injected target is only backed by the voters already voting for it. The
+/// desired targets are capped to the new number of targets.
+pub(crate) fn edit_snapshot_targets<T: EPM::Config>(
+    ext: &mut Ext,
+    excluded: &std::collections::BTreeSet<AccountIdOf<T>>,
+    included: &[AccountIdOf<T>],
+) -> Result<(u32, u32), anyhow::Error> {
+    let result = ext.execute_with(|| {
+        let RoundSnapshot { voters, targets } =
+            <EPM::Snapshot<T>>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let (targets, removed, added) = edit_targets(targets, excluded, included);
+
+        let metadata = SolutionOrSnapshotSize {
+            voters: voters.len() as u32,
+            targets: targets.len() as u32,
+        };
+        let desired_targets = cap_desired_targets(
+            <EPM::DesiredTargets<T>>::get().unwrap_or_default(),
+            metadata.targets,
+        );
+
+        <EPM::Snapshot<T>>::put(RoundSnapshot { voters, targets });
+        <EPM::SnapshotMetadata<T>>::put(metadata);
+        <EPM::DesiredTargets<T>>::put(desired_targets);
+
+        log::info!(
+            target: LOG_TARGET,
+            "edit_snapshot_targets: {} targets removed, {} added, {} targets left.",
+            removed,
+            added,
+            metadata.targets,
+        );
+
+        Ok((removed, added))
+    });
+    crate::trace::record_writes(ext);
+    result
+}
+
+/// Drops the nominators whose active bond is lower than `min_bond` from the election snapshot,
+/// e.g. to simulate a higher `MinNominatorBond`, and returns the number of voters dropped and the
+/// encoded size of the new snapshot.
+///
+/// The validators, whose self-votes are voters of the snapshot, are kept regardless of their bond.
+pub(crate) fn filter_snapshot_voters<T: EPM::Config + Staking::Config>(
+    ext: &mut Ext,
+    min_bond: u128,
+) -> Result<(u32, usize), anyhow::Error> {
+    let result = ext.execute_with(|| {
+        let RoundSnapshot { voters, targets } =
+            <EPM::Snapshot<T>>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+
+        let before = voters.len();
+        let voters = voters
+            .into_iter()
+            .filter(|(who, _, _)| {
+                <Staking::Validators<T>>::contains_key(who)
+                    || staking_stats::active_stake::<T>(who).unwrap_or_default() >= min_bond
+            })
+            .collect::<Vec<_>>();
+        let dropped = (before - voters.len()) as u32;
+
+        let metadata = SolutionOrSnapshotSize {
+            voters: voters.len() as u32,
+            targets: targets.len() as u32,
+        };
+        let snapshot = RoundSnapshot { voters, targets };
+        let size = snapshot.encode().len();
+
+        <EPM::Snapshot<T>>::put(snapshot);
+        <EPM::SnapshotMetadata<T>>::put(metadata);
+
+        log::info!(
+            target: LOG_TARGET,
+            "filter_snapshot_voters: {} voters below {} dropped, {} voters left.",
+            dropped,
+            min_bond,
+            metadata.voters,
+        );
+
+        Ok((dropped, size))
+    });
+    crate::trace::record_writes(ext);
+    result
+}
+
+/// Returns the minimum stake of the voters of the election snapshot, i.e. the minimum active stake
+/// as per the snapshot, or `None` if it does not exist or has no voters.
+pub(crate) fn snapshot_min_stake<T: EPM::Config>(ext: &mut Ext) -> Option<u64> {
+    ext.execute_with(|| {
+        <EPM::Snapshot<T>>::get()?
+            .voters
+            .iter()
+            .map(|(_, stake, _)| *stake)
+            .min()
+    })
+}
+
+/// An emergency election result, ready to be submitted by governance.
+#[derive(Debug, Clone)]
+pub(crate) struct EmergencyResult {
+    /// The SCALE encoded `set_emergency_election_result` runtime call.
+    pub call: Vec<u8>,
+    pub winners: u32,
+    pub max_backers: u32,
+    /// The outcome of dispatching the call as root on the externalities.
+    pub verification: Result<(), String>,
+}
+
+/// Mines a solution with the given solver and encodes its supports as a
+/// `set_emergency_election_result` call.
+///
+/// The call is verified by dispatching it as root on the externalities, which checks it against
+/// the current phase and bounds. Note that the dispatch mutates the state of the externalities.
+pub(crate) fn emergency_result<T>(
+    solver: &Solver,
+    ext: &mut Ext,
+) -> Result<EmergencyResult, anyhow::Error>
+where
+    T: EPM::Config,
+    T::Solver: NposSolver<Error = sp_npos_elections::Error>,
+    <T as frame_system::Config>::RuntimeCall: From<EPM::Call<T>>,
+{
+    use sp_runtime::traits::Dispatchable;
+
+    let _ = election_snapshot::<T>(ext);
+    let raw_solution = mine_with::<T>(solver, ext, false)?;
+
+    ext.execute_with(|| {
+        let ready_solution =
+            <EPM::Pallet<T>>::feasibility_check(raw_solution, EPM::ElectionCompute::Emergency)
+                .map_err(|e| TimetravelError::failed("calculating feasibility check", e))?;
+
+        let winners = ready_solution.supports.len() as u32;
+        let max_backers = ready_solution
+            .supports
+            .iter()
+            .map(|(_, support)| support.voters.len() as u32)
+            .max()
+            .unwrap_or_default();
+
+        let call: <T as frame_system::Config>::RuntimeCall =
+            EPM::Call::<T>::set_emergency_election_result {
+                supports: ready_solution.supports,
+            }
+            .into();
+        let encoded = call.encode();
+
+        let verification = call
+            .dispatch(frame_system::RawOrigin::Root.into())
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.error));
+
+        Ok(EmergencyResult {
+            call: encoded,
+            winners,
+            max_backers,
+            verification,
+        })
+    })
+}
+
+/// The outcome of the feasibility check of a submitted solution.
+#[derive(Debug, Clone)]
+pub(crate) struct Feasibility {
+    /// The round the solution was submitted for.
+    pub round: u32,
+    /// The score claimed by the solution.
+    pub claimed_score: ElectionScore,
+    /// The verified score and number of winners, or the reason the solution is not feasible.
+    pub verification: Result<(ElectionScore, u32), String>,
+}
+
+/// Runs the feasibility check of the SCALE encoded `RawSolution` against the election snapshot
+/// of the externalities, as it is done for signed submissions.
+///
+/// The check is read-only, i.e. the solution is not queued.
+pub(crate) fn feasibility_check<T: EPM::Config>(
+    encoded_solution: &[u8],
+    ext: &mut Ext,
+) -> Result<Feasibility, anyhow::Error> {
+    use codec::DecodeAll;
+
+    let raw_solution =
+        EPM::RawSolution::<EPM::SolutionOf<T::MinerConfig>>::decode_all(&mut &encoded_solution[..])
+            .map_err(|e| TimetravelError::Decode {
+                error: format!("raw solution: {:?}", e),
+            })?;
+
+    ext.execute_with(|| {
+        if EPM::Snapshot::<T>::get().is_none() {
+            return Err(TimetravelError::missing("Snapshot").into());
+        }
+
+        let round = raw_solution.round;
+        let claimed_score = raw_solution.score;
+        let verification =
+            <EPM::Pallet<T>>::feasibility_check(raw_solution, EPM::ElectionCompute::Signed)
+                .map(|ready| (ready.score, ready.supports.len() as u32))
+                .map_err(|e| format!("{:?}", e));
+
+        Ok(Feasibility {
+            round,
+            claimed_score,
+            verification,
+        })
+    })
+}
+
+/// Returns the total backing of each validator exposed in `era`.
+///
+/// Reads the legacy `ErasStakers` exposures and falls back to the paged exposures overview if the
+/// former is not populated for the era.
+pub(crate) fn era_exposures<T: Staking::Config>(
+    ext: &mut Ext,
+    era: sp_staking::EraIndex,
+) -> Vec<(AccountIdOf<T>, u128)>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    ext.execute_with(|| {
+        #[allow(deprecated)]
+        let exposures = <Staking::ErasStakers<T>>::iter_prefix(era)
+            .map(|(who, exposure)| (who, exposure.total.into()))
+            .collect::<Vec<_>>();
+
+        if !exposures.is_empty() {
+            return exposures;
+        }
+
+        <Staking::ErasStakersOverview<T>>::iter_prefix(era)
+            .map(|(who, overview)| (who, overview.total.into()))
+            .collect()
+    })
+}
+
+/// Computes an approximate NPoS election score given the current snapshot.
+///
+/// The supports are computed by a single sequential Phragmen pass without balancing, and the score
+/// is evaluated directly from them, skipping the solution encoding, trimming and feasibility
+/// checks of the miner. The resulting score is a lower bound of the exact (balanced) score.
+///
+/// Returns the score along with the number of winners elected.
+pub(crate) fn mine_approximate<T: EPM::Config>(
+    ext: &mut Ext,
+) -> Result<(ElectionScore, u32), anyhow::Error> {
+    ext.execute_with(|| {
+        log::info!(target: LOG_TARGET, "Mining approximate NPoS.");
+
+        let RoundSnapshot { voters, targets } =
+            EPM::Snapshot::<T>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets =
+            EPM::DesiredTargets::<T>::get().ok_or(TimetravelError::missing("Desired targets"))?;
+
+        let stakes = voters
+            .iter()
+            .map(|(who, stake, _)| (who.clone(), *stake))
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        let ElectionResult { assignments, .. } = sp_npos_elections::seq_phragmen::<_, sp_runtime::Perbill>(
+            desired_targets as usize,
+            targets,
+            voters,
+            None,
+        )
+        .map_err(|e| TimetravelError::failed("mining approximate solution", e))?;
+
+        let staked = sp_npos_elections::assignment_ratio_to_staked_normalized(assignments, |who| {
+            stakes.get(who).cloned().unwrap_or_default()
+        })
+        .map_err(|e| TimetravelError::failed("normalizing approximate solution", e))?;
+
+        let supports = sp_npos_elections::to_supports(&staked);
+        let score = supports.evaluate();
+
+        log::info!(target: LOG_TARGET, "mined an approximate npos-like solution with score = {:?}.", score);
+
+        Ok((score, supports.len() as u32))
+    })
+}
+
+/// Computes the score of a multi-winner approval voting election given the current snapshot, as
+/// a baseline of the NPoS and DPoS elections. See [`solvers::approval_voting`].
+///
+/// Returns the score along with the number of winners elected.
+pub(crate) fn mine_approval<T: EPM::Config>(
+    ext: &mut Ext,
+) -> Result<(ElectionScore, u32), anyhow::Error> {
+    ext.execute_with(|| {
+        log::info!(target: LOG_TARGET, "Mining approval voting.");
+
+        let RoundSnapshot { voters, targets } =
+            EPM::Snapshot::<T>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets =
+            EPM::DesiredTargets::<T>::get().ok_or(TimetravelError::missing("Desired targets"))?;
+
+        let supports = solvers::approval_voting(desired_targets as usize, targets, voters)
+            .into_iter()
+            .map(|(who, total)| (who, sp_npos_elections::Support { total, voters: vec![] }))
+            .collect::<Vec<_>>();
+        let winners = supports.len() as u32;
+        let score = sp_npos_elections::Supports::from(supports).evaluate();
+
+        log::info!(target: LOG_TARGET, "mined an approval voting solution with score = {:?}.", score);
+
+        Ok((score, winners))
+    })
+}
+
 /// Mines a Delegated Proof-of-Stake (DPoS) given the current snapshot and returns the election
-/// score.
+/// score, along with the number of winners elected.
 ///
 /// In this DPoS flavour, the vote weight (stake) of the nominators' votes are distributed equaly
 /// across their targets. The number of voters considered for the election is defined by the
@@ -275,7 +1174,7 @@ where
 pub(crate) fn mine_dpos<T>(
     ext: &mut Ext,
     distribution_type: utils::ShareDistribution,
-) -> Result<ElectionScore, anyhow::Error>
+) -> Result<(ElectionScore, u32), anyhow::Error>
 where
     T: EPM::Config + Staking::Config,
 {
@@ -283,10 +1182,10 @@ where
         log::info!(target: LOG_TARGET, "Mining DPoS with {:?}.", distribution_type);
 
         let RoundSnapshot { voters, targets } =
-            EPM::Snapshot::<T>::get().ok_or(anyhow!("Snapshot did not exist."))?;
+            EPM::Snapshot::<T>::get().ok_or(TimetravelError::missing("Snapshot"))?;
         let snapshot_targets = targets;
         let desired_targets =
-            EPM::DesiredTargets::<T>::get().ok_or(anyhow!("Desired targets did not exist."))?;
+            EPM::DesiredTargets::<T>::get().ok_or(TimetravelError::missing("Desired targets"))?;
 
         let skip_targets = 0;
         let mut num_votes_per_voter = vec![];
@@ -296,11 +1195,13 @@ where
 
         voters.into_iter().for_each(|(who, stake, targets)| {
             if targets.is_empty() || stake == 0 {
-                log::warn!(
-                    target: LOG_TARGET,
-                    "Bad voter with stake {:?}, targets: {:?}. skipping.",
-                    stake,
-                    targets.len()
+                crate::warnings::warn(
+                    "bad_voter",
+                    format!(
+                        "Bad voter with stake {:?}, targets: {:?}. skipping.",
+                        stake,
+                        targets.len()
+                    ),
                 );
                 return;
             }
@@ -327,6 +1228,7 @@ where
             .rev()
             .take(desired_targets as usize)
             .collect::<Vec<_>>();
+        let winners = supports.len() as u32;
         let supports_sorted = sp_npos_elections::Supports::from(supports);
 
         let score = supports_sorted.evaluate();
@@ -343,10 +1245,51 @@ where
             num_votes_per_voter.iter().sum::<usize>() as f32 / num_votes_per_voter.len() as f32,
         );
 
-        Ok(score)
+        Ok((score, winners))
+    })
+}
+
+/// Metrics derived from an election score.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ScoreMetrics {
+    /// Minimal stake as a fraction of the average backing of the winners.
+    pub min_stake_ratio: f64,
+    /// Variance of the backing of the winners.
+    pub backing_variance: f64,
+    /// Sum of the squared backings normalized by its lowest possible value given the same sum of
+    /// stake, i.e. `1.0` if all the winners have exactly the same backing.
+    pub normalized_sum_squared: f64,
+}
+
+/// Computes the [`ScoreMetrics`] of an election score with `winners` elected targets, i.e. the
+/// winners the election actually elected, which may be fewer than the desired targets (e.g. if
+/// fewer targets have backing).
+///
+/// Returns `None` if there are no winners or no stake backing them, since the metrics are not
+/// defined.
+pub(crate) fn score_metrics(score: &ElectionScore, winners: u32) -> Option<ScoreMetrics> {
+    if winners == 0 || score.sum_stake == 0 {
+        return None;
+    }
+
+    let winners = winners as f64;
+    let sum_stake = score.sum_stake as f64;
+    let average_backing = sum_stake / winners;
+
+    Some(ScoreMetrics {
+        min_stake_ratio: score.minimal_stake as f64 / average_backing,
+        backing_variance: (score.sum_stake_squared as f64 / winners) - average_backing.powi(2),
+        normalized_sum_squared: score.sum_stake_squared as f64 / (sum_stake.powi(2) / winners),
     })
 }
 
+/// The number of winners elected by a mined solution.
+pub(crate) fn solution_winners<T: EPM::Config>(
+    raw_solution: &EPM::RawSolution<EPM::SolutionOf<T::MinerConfig>>,
+) -> u32 {
+    raw_solution.solution.unique_targets().len() as u32
+}
+
 /// Playground gadget.
 pub(crate) fn playground<T>(exts: Vec<Ext>) -> Result<(), anyhow::Error>
 where
@@ -363,3 +1306,113 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulate_works() {
+        let mut ext = Ext::default();
+        ext.execute_with(|| sp_io::storage::set(b"key", b"pristine"));
+
+        for value in [b"first", b"other"] {
+            let simulated = simulate(&mut ext, |ext| {
+                ext.execute_with(|| {
+                    sp_io::storage::set(b"key", value);
+                    sp_io::storage::set(b"new", value);
+                    sp_io::storage::get(b"key")
+                })
+            });
+            assert_eq!(simulated.as_deref(), Some(&value[..]));
+        }
+
+        ext.execute_with(|| {
+            assert_eq!(
+                sp_io::storage::get(b"key").as_deref(),
+                Some(&b"pristine"[..])
+            );
+            assert_eq!(sp_io::storage::get(b"new"), None);
+        });
+    }
+
+    #[test]
+    fn snapshot_expected_works() {
+        assert!(!snapshot_expected(&EPM::Phase::<u32>::Off));
+        assert!(snapshot_expected(&EPM::Phase::<u32>::Signed));
+        assert!(snapshot_expected(&EPM::Phase::<u32>::Unsigned((true, 10))));
+        assert!(snapshot_expected(&EPM::Phase::<u32>::Emergency));
+    }
+
+    #[test]
+    fn edit_targets_works() {
+        let excluded = [2, 9].into_iter().collect();
+
+        assert_eq!(
+            edit_targets(vec![1, 2, 3], &excluded, &[4, 1, 5]),
+            (vec![1, 3, 4, 5], 1, 2)
+        );
+        assert_eq!(
+            edit_targets(vec![1, 3], &Default::default(), &[]),
+            (vec![1, 3], 0, 0)
+        );
+        // an excluded target can be injected back.
+        assert_eq!(edit_targets(vec![2], &excluded, &[2]), (vec![2], 1, 1));
+    }
+
+    #[test]
+    fn cap_desired_targets_works() {
+        assert_eq!(cap_desired_targets(10, 20), 10);
+        assert_eq!(cap_desired_targets(20, 20), 20);
+        assert_eq!(cap_desired_targets(30, 20), 20);
+        assert_eq!(cap_desired_targets(30, 0), 0);
+    }
+
+    #[test]
+    fn score_ratios_works() {
+        let score = |minimal_stake, sum_stake| ElectionScore {
+            minimal_stake,
+            sum_stake,
+            sum_stake_squared: 0,
+        };
+
+        assert_eq!(
+            score_ratios(&score(90, 150), &score(100, 200)),
+            (Some(0.9), Some(0.75))
+        );
+        assert_eq!(
+            score_ratios(&score(10, 20), &score(0, 10)),
+            (None, Some(2.0))
+        );
+    }
+
+    #[test]
+    fn score_metrics_works() {
+        // backings of 10, 20 and 30.
+        let score = ElectionScore {
+            minimal_stake: 10,
+            sum_stake: 60,
+            sum_stake_squared: 1400,
+        };
+        let metrics = score_metrics(&score, 3).unwrap();
+
+        assert_eq!(metrics.min_stake_ratio, 0.5);
+        assert!((metrics.backing_variance - 200.0 / 3.0).abs() < 1e-9);
+        assert!((metrics.normalized_sum_squared - 1400.0 / 1200.0).abs() < 1e-9);
+
+        // perfectly balanced winners.
+        let score = ElectionScore {
+            minimal_stake: 20,
+            sum_stake: 60,
+            sum_stake_squared: 1200,
+        };
+        let metrics = score_metrics(&score, 3).unwrap();
+
+        assert_eq!(metrics.min_stake_ratio, 1.0);
+        assert_eq!(metrics.backing_variance, 0.0);
+        assert_eq!(metrics.normalized_sum_squared, 1.0);
+
+        assert_eq!(score_metrics(&score, 0), None);
+        assert_eq!(score_metrics(&ElectionScore::default(), 3), None);
+    }
+}
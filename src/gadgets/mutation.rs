@@ -0,0 +1,201 @@
+//! Gadgets to mutate arbitrary storage keys of the externalities, e.g. of the snapshots loaded by
+//! `transform --mutate`.
+//!
+//! The mutations are given as a spec of hex encoded storage keys to set (to a hex encoded value)
+//! or to remove, in JSON:
+//!
+//! ```json
+//! {
+//!   "set": { "0x26aa394eea5630e07c48ae0c9558cef7...": "0x0100000000000000" },
+//!   "remove": ["0x5f3e4907f716ac89b6347d15ececedca..."]
+//! }
+//! ```
+//!
+//! or, if the spec file has a `.toml` extension, in TOML:
+//!
+//! ```toml
+//! remove = ["0x5f3e4907f716ac89b6347d15ececedca..."]
+//!
+//! [set]
+//! "0x26aa394eea5630e07c48ae0c9558cef7..." = "0x0100000000000000"
+//! ```
+
+use crate::prelude::*;
+use crate::TimetravelError;
+
+use serde::Deserialize;
+use sp_core::Bytes;
+use std::collections::BTreeMap;
+
+/// The storage keys to set or remove.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MutationSpec {
+    /// The values to set, by storage key.
+    #[serde(default)]
+    pub set: BTreeMap<Bytes, Bytes>,
+    /// The storage keys to remove.
+    #[serde(default)]
+    pub remove: Vec<Bytes>,
+}
+
+impl MutationSpec {
+    /// Reads the spec in `path`, as TOML if the file has a `.toml` extension and as JSON
+    /// otherwise.
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let spec = std::fs::read_to_string(path).map_err(|e| TimetravelError::Unsupported {
+            reason: format!("could not read the mutation spec in {}: {}", path, e),
+        })?;
+        Self::parse(&spec, path.ends_with(".toml"))
+    }
+
+    /// Parses a spec in JSON, or in TOML if `toml` is set. A key can not be both set and
+    /// removed.
+    pub fn parse(spec: &str, toml: bool) -> Result<Self, anyhow::Error> {
+        let spec: Self = match toml {
+            true => toml::from_str(spec).map_err(|e| e.to_string()),
+            false => serde_json::from_str(spec).map_err(|e| e.to_string()),
+        }
+        .map_err(|error| TimetravelError::Decode {
+            error: format!("invalid mutation spec: {}", error),
+        })?;
+
+        if let Some(key) = spec.remove.iter().find(|key| spec.set.contains_key(key)) {
+            return Err(TimetravelError::Unsupported {
+                reason: format!(
+                    "key {} is both set and removed by the mutation spec.",
+                    sp_core::bytes::to_hex(key, false)
+                ),
+            }
+            .into());
+        }
+
+        Ok(spec)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// The mutation of a storage key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Mutation {
+    /// The key did not exist and was set.
+    Inserted,
+    /// The key existed and was set to another value.
+    Updated,
+    /// The key existed and was set to the same value.
+    Unchanged,
+    /// The key existed and was removed.
+    Removed,
+    /// The key did not exist and could not be removed.
+    Absent,
+}
+
+impl std::fmt::Display for Mutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutation::Inserted => write!(f, "inserted"),
+            Mutation::Updated => write!(f, "updated"),
+            Mutation::Unchanged => write!(f, "unchanged"),
+            Mutation::Removed => write!(f, "removed"),
+            Mutation::Absent => write!(f, "absent"),
+        }
+    }
+}
+
+/// Applies the mutations of the `spec` to the externalities, and returns the mutation of each
+/// key of the spec, in key order.
+pub(crate) fn mutate(ext: &mut Ext, spec: &MutationSpec) -> BTreeMap<Vec<u8>, Mutation> {
+    ext.execute_with(|| {
+        let mut mutations = BTreeMap::new();
+
+        for (key, value) in spec.set.iter() {
+            let mutation = match sp_io::storage::get(key) {
+                None => Mutation::Inserted,
+                Some(old) if old[..] == value[..] => Mutation::Unchanged,
+                Some(_) => Mutation::Updated,
+            };
+            sp_io::storage::set(key, value);
+            mutations.insert(key.to_vec(), mutation);
+        }
+
+        for key in spec.remove.iter() {
+            let mutation = match sp_io::storage::exists(key) {
+                true => Mutation::Removed,
+                false => Mutation::Absent,
+            };
+            sp_io::storage::clear(key);
+            mutations.insert(key.to_vec(), mutation);
+        }
+
+        mutations
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutation_spec_works() {
+        let json = r#"{ "set": { "0x01": "0xaa", "0x02": "0xbb" }, "remove": ["0x03"] }"#;
+        let toml = "remove = [\"0x03\"]\n\n[set]\n\"0x01\" = \"0xaa\"\n\"0x02\" = \"0xbb\"\n";
+
+        let spec = MutationSpec::parse(json, false).unwrap();
+        assert_eq!(MutationSpec::parse(toml, true).unwrap(), spec);
+        assert_eq!(spec.set.get(&Bytes(vec![1])), Some(&Bytes(vec![0xaa])));
+        assert_eq!(spec.remove, vec![Bytes(vec![3])]);
+
+        assert!(MutationSpec::parse("{}", false).unwrap().is_empty());
+        assert!(MutationSpec::parse(
+            r#"{ "set": { "0x01": "0xaa" }, "remove": ["0x01"] }"#,
+            false
+        )
+        .is_err());
+        assert!(MutationSpec::parse(r#"{ "insert": {} }"#, false).is_err());
+        assert!(MutationSpec::parse(r#"{ "set": { "0x01": "not hex" } }"#, false).is_err());
+    }
+
+    #[test]
+    fn mutate_works() {
+        let mut ext = Ext::default();
+        ext.execute_with(|| {
+            sp_io::storage::set(&[1], &[0xaa]);
+            sp_io::storage::set(&[2], &[0xbb]);
+            sp_io::storage::set(&[3], &[0xcc]);
+        });
+
+        let spec = MutationSpec {
+            set: BTreeMap::from([
+                (Bytes(vec![1]), Bytes(vec![0xaa])),
+                (Bytes(vec![2]), Bytes(vec![0xff])),
+                (Bytes(vec![4]), Bytes(vec![0xdd])),
+            ]),
+            remove: vec![Bytes(vec![3]), Bytes(vec![5])],
+        };
+
+        assert_eq!(
+            mutate(&mut ext, &spec),
+            BTreeMap::from([
+                (vec![1], Mutation::Unchanged),
+                (vec![2], Mutation::Updated),
+                (vec![3], Mutation::Removed),
+                (vec![4], Mutation::Inserted),
+                (vec![5], Mutation::Absent),
+            ])
+        );
+        ext.execute_with(|| {
+            assert_eq!(
+                sp_io::storage::get(&[2]).map(|v| v.to_vec()),
+                Some(vec![0xff])
+            );
+            assert_eq!(sp_io::storage::get(&[3]), None);
+            assert_eq!(
+                sp_io::storage::get(&[4]).map(|v| v.to_vec()),
+                Some(vec![0xdd])
+            );
+        });
+    }
+}
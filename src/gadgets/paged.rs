@@ -0,0 +1,301 @@
+//! Gadgets to mine paged elections.
+//!
+//! The multi-block election provider splits the voters of the snapshot into pages, which are
+//! fetched and mined one by one, and the final supports are the aggregation of the supports of all
+//! the pages. These gadgets emulate the paging over the single-page snapshot of the current
+//! election provider: the winners are elected over all the voters, and the voters of each page are
+//! then mined separately against the shared winners, within the bounds of a page (the maximum
+//! winners per page and backers per winner). The targets are not paged, as in the multi-block
+//! provider, whose target snapshot is a single page.
+
+use crate::configs::Solver;
+use crate::prelude::*;
+use crate::TimetravelError;
+
+use frame_election_provider_support::{NposSolver, PhragMMS, SequentialPhragmen};
+use sp_npos_elections::{ElectionResult, ElectionScore, EvaluateSupport, Support, Supports};
+use std::collections::{BTreeMap, BTreeSet};
+use EPM::RoundSnapshot;
+
+/// The solution of a single page of a paged election.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct PageSolution {
+    pub page: u32,
+    pub voters: u32,
+    /// Voters of the page which back at least one winner.
+    pub active_voters: u32,
+    /// Winners backed by the voters of the page.
+    pub winners: u32,
+    /// Backers (i.e. edges) dropped by the maximum backers per winner of the page.
+    pub trimmed_backers: u32,
+    /// Winners dropped by the maximum winners per page.
+    pub trimmed_winners: u32,
+    /// The score of the (partial) supports of the page.
+    pub score: ElectionScore,
+}
+
+/// Splits the voters into `pages` pages, following the multi-block election provider: the first
+/// voters (i.e. the ones with the highest stake in the voter list order) are in the most
+/// significant page, `pages - 1`. The result is indexed by page.
+pub(crate) fn paginate<V: Clone>(voters: &[V], pages: u32) -> Vec<Vec<V>> {
+    let pages = pages.max(1) as usize;
+    let page_size = ((voters.len() + pages - 1) / pages).max(1);
+
+    let mut paged = voters
+        .chunks(page_size)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    paged.resize(pages, vec![]);
+    paged.reverse();
+    paged
+}
+
+/// The bounds of each page of the solution of a paged election, unbounded if `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct PageBounds {
+    pub max_winners_per_page: Option<u32>,
+    pub max_backers_per_winner: Option<u32>,
+}
+
+/// Solves the election of `desired_targets` winners with the given solver.
+fn solve<T: EPM::Config>(
+    solver: &Solver,
+    desired_targets: usize,
+    targets: Vec<AccountIdOf<T>>,
+    voters: Vec<(AccountIdOf<T>, u64, Vec<AccountIdOf<T>>)>,
+) -> Result<ElectionResult<AccountIdOf<T>, sp_runtime::Perbill>, anyhow::Error> {
+    use super::{solvers::ParallelPhragmen, BalanceIterations, Balancing};
+
+    type Accuracy = sp_runtime::Perbill;
+    match solver {
+        Solver::SeqPhragmen { iterations } => {
+            BalanceIterations::set(*iterations);
+            SequentialPhragmen::<AccountIdOf<T>, Accuracy, Balancing>::solve(
+                desired_targets,
+                targets,
+                voters,
+            )
+        }
+        Solver::PhragMMS { iterations } => {
+            BalanceIterations::set(*iterations);
+            PhragMMS::<AccountIdOf<T>, Accuracy, Balancing>::solve(desired_targets, targets, voters)
+        }
+        Solver::ParallelPhragmen { iterations } => {
+            BalanceIterations::set(*iterations);
+            ParallelPhragmen::<AccountIdOf<T>, Accuracy, Balancing>::solve(
+                desired_targets,
+                targets,
+                voters,
+            )
+        }
+    }
+    .map_err(|e| TimetravelError::failed("mining paged solution", e).into())
+}
+
+/// Trims the supports of a page to the `bounds`. The backers of each winner beyond
+/// `max_backers_per_winner` are dropped, lowest stake first, and so are the winners beyond
+/// `max_winners_per_page`, lowest backing first. Returns the trimmed supports and the number of
+/// dropped backers (i.e. edges) and winners.
+pub(crate) fn trim_page<A: Clone>(
+    supports: Supports<A>,
+    bounds: &PageBounds,
+) -> (Supports<A>, u32, u32) {
+    let mut trimmed_backers = 0;
+    let mut supports = supports
+        .into_iter()
+        .map(|(winner, mut support)| {
+            if let Some(max) = bounds.max_backers_per_winner {
+                if support.voters.len() > max as usize {
+                    support.voters.sort_by(|(_, a), (_, b)| b.cmp(a));
+                    trimmed_backers += (support.voters.len() - max as usize) as u32;
+                    support.voters.truncate(max as usize);
+                    support.total = support.voters.iter().map(|(_, stake)| stake).sum();
+                }
+            }
+            (winner, support)
+        })
+        .collect::<Vec<_>>();
+
+    let mut trimmed_winners = 0;
+    if let Some(max) = bounds.max_winners_per_page {
+        if supports.len() > max as usize {
+            supports.sort_by(|(_, a), (_, b)| b.total.cmp(&a.total));
+            trimmed_winners = (supports.len() - max as usize) as u32;
+            supports.truncate(max as usize);
+        }
+    }
+
+    (supports, trimmed_backers, trimmed_winners)
+}
+
+/// Aggregates the supports of the pages into the supports of the full solution.
+pub(crate) fn aggregate<A: Ord + Clone>(pages: &[Supports<A>]) -> Supports<A> {
+    let mut aggregated = BTreeMap::<A, Support<A>>::new();
+    for (winner, support) in pages.iter().flatten() {
+        let entry = aggregated.entry(winner.clone()).or_default();
+        entry.total += support.total;
+        entry.voters.extend(support.voters.iter().cloned());
+    }
+    aggregated.into_iter().collect()
+}
+
+/// Mines a paged solution of the snapshot with the given solver. The winners are elected over all
+/// the voters, then the voters of each of the `pages` pages are mined against the winners and
+/// their supports trimmed to the `bounds`. Returns the score of the aggregated supports of the
+/// pages and the solution of each page.
+pub(crate) fn mine_paged<T>(
+    solver: &Solver,
+    ext: &mut Ext,
+    pages: u32,
+    bounds: PageBounds,
+) -> Result<(ElectionScore, Vec<PageSolution>), anyhow::Error>
+where
+    T: EPM::Config,
+{
+    ext.execute_with(|| {
+        let RoundSnapshot { voters, targets } =
+            EPM::Snapshot::<T>::get().ok_or(TimetravelError::missing("Snapshot"))?;
+        let desired_targets = EPM::DesiredTargets::<T>::get()
+            .ok_or(TimetravelError::missing("Desired targets"))?
+            as usize;
+
+        let voters = voters
+            .into_iter()
+            .map(|(who, stake, votes)| (who, stake, votes.into_iter().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let stakes = voters
+            .iter()
+            .map(|(who, stake, _)| (who.clone(), *stake))
+            .collect::<BTreeMap<_, _>>();
+        let stake_of = |who: &AccountIdOf<T>| stakes.get(who).cloned().unwrap_or_default();
+
+        // 1. the winners shared by all the pages.
+        let ElectionResult { winners, .. } =
+            solve::<T>(solver, desired_targets, targets, voters.clone())?;
+        let winners = winners
+            .into_iter()
+            .map(|(winner, _)| winner)
+            .collect::<Vec<_>>();
+        let elected = winners.iter().cloned().collect::<BTreeSet<_>>();
+
+        // 2. the voters of each page, mined against the winners only.
+        let mut page_solutions = vec![];
+        let mut page_supports = vec![];
+        for (page, page_voters) in paginate(&voters, pages).into_iter().enumerate() {
+            let page_len = page_voters.len() as u32;
+            let page_voters = page_voters
+                .into_iter()
+                .map(|(who, stake, votes)| {
+                    let votes = votes.into_iter().filter(|t| elected.contains(t)).collect();
+                    (who, stake, votes)
+                })
+                .filter(|(_, _, votes): &(_, _, Vec<_>)| !votes.is_empty())
+                .collect::<Vec<_>>();
+
+            let (supports, trimmed_backers, trimmed_winners) = match page_voters.is_empty() {
+                true => (vec![], 0, 0),
+                false => {
+                    let ElectionResult { assignments, .. } =
+                        solve::<T>(solver, winners.len(), winners.clone(), page_voters)?;
+                    let staked = sp_npos_elections::assignment_ratio_to_staked_normalized(
+                        assignments,
+                        stake_of,
+                    )
+                    .map_err(|e| TimetravelError::failed("normalizing paged solution", e))?;
+                    trim_page(sp_npos_elections::to_supports(&staked), &bounds)
+                }
+            };
+
+            page_solutions.push(PageSolution {
+                page: page as u32,
+                voters: page_len,
+                active_voters: supports
+                    .iter()
+                    .flat_map(|(_, s)| s.voters.iter().map(|(who, _)| who))
+                    .collect::<BTreeSet<_>>()
+                    .len() as u32,
+                winners: supports.len() as u32,
+                trimmed_backers,
+                trimmed_winners,
+                score: supports.evaluate(),
+            });
+            page_supports.push(supports);
+        }
+
+        let score = aggregate(&page_supports).evaluate();
+        log::info!(
+            target: LOG_TARGET,
+            "mined a paged npos-like solution over {} pages with score = {:?}.",
+            pages,
+            score
+        );
+
+        Ok((score, page_solutions))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paginate_works() {
+        let voters = (1..=10).collect::<Vec<u32>>();
+
+        // the first voters are in the most significant page.
+        assert_eq!(
+            paginate(&voters, 3),
+            vec![vec![9, 10], vec![5, 6, 7, 8], vec![1, 2, 3, 4]]
+        );
+        assert_eq!(paginate(&voters, 1), vec![voters.clone()]);
+        assert_eq!(paginate(&voters, 0), vec![voters.clone()]);
+
+        // more pages than voters results in empty least significant pages.
+        assert_eq!(
+            paginate(&voters[..2], 4),
+            vec![vec![], vec![], vec![2], vec![1]]
+        );
+        assert_eq!(paginate::<u32>(&[], 2), vec![vec![], vec![]]);
+    }
+
+    #[test]
+    fn trim_and_aggregate_work() {
+        let support = |voters: Vec<(u32, u128)>| Support {
+            total: voters.iter().map(|(_, stake)| stake).sum(),
+            voters,
+        };
+        let page = vec![
+            (1, support(vec![(10, 5), (11, 20), (12, 10)])),
+            (2, support(vec![(11, 1)])),
+            (3, support(vec![(12, 8)])),
+        ];
+
+        // unbounded pages are left as is.
+        assert_eq!(
+            trim_page(page.clone(), &PageBounds::default()),
+            (page.clone(), 0, 0)
+        );
+
+        let bounds = PageBounds {
+            max_winners_per_page: Some(2),
+            max_backers_per_winner: Some(2),
+        };
+        let (trimmed, backers, winners) = trim_page(page.clone(), &bounds);
+        assert_eq!((backers, winners), (1, 1));
+        assert_eq!(
+            trimmed,
+            vec![
+                (1, support(vec![(11, 20), (12, 10)])),
+                (3, support(vec![(12, 8)]))
+            ]
+        );
+
+        assert_eq!(
+            aggregate(&[trimmed, vec![(1, support(vec![(13, 2)]))]]),
+            vec![
+                (1, support(vec![(11, 20), (12, 10), (13, 2)])),
+                (3, support(vec![(12, 8)]))
+            ]
+        );
+    }
+}
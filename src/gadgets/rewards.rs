@@ -0,0 +1,438 @@
+//! Gadgets to compute the staking rewards of the nominators from the era history.
+//!
+//! The reward of a nominator in an era is derived as the runtime pays out the stakers: the era
+//! validator reward is split between the validators by reward points, the commission of each
+//! validator is taken from its share and the rest is split by stake between the validator and
+//! its nominators, as per the era exposures. The rewards are the earned ones, whether they were
+//! claimed or not, and the era history is bounded by the `HistoryDepth` of the staking pallet.
+
+use crate::prelude::*;
+
+use frame_support::traits::Get;
+use sp_arithmetic::{helpers_128bit::multiply_by_rational_with_rounding, Rounding};
+use sp_runtime::Perbill;
+use sp_staking::EraIndex;
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// The reward earned by a nominator in an era, and the stake it was earned with.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct EraReward {
+    pub reward: u128,
+    pub stake: u128,
+}
+
+/// Returns the range of past eras whose rewards are kept in the era history, i.e. the last
+/// `HistoryDepth` eras before the active era, if any.
+pub(crate) fn history_eras<T: Staking::Config>(ext: &mut Ext) -> Option<RangeInclusive<EraIndex>> {
+    let active = ext.execute_with(|| <Staking::ActiveEra<T>>::get())?.index;
+    let last = active.checked_sub(1)?;
+
+    Some(last.saturating_sub(T::HistoryDepth::get().saturating_sub(1))..=last)
+}
+
+/// Returns the share of the era reward of a validator (and its nominators) with
+/// `validator_points` out of `total_points`, before the commission.
+pub(crate) fn validator_payout(era_reward: u128, total_points: u32, validator_points: u32) -> u128 {
+    multiply_by_rational_with_rounding(
+        era_reward,
+        validator_points as u128,
+        total_points as u128,
+        Rounding::Down,
+    )
+    .unwrap_or_default()
+}
+
+/// Returns the reward of a nominator exposed with `stake` behind a validator with a total
+/// exposure of `exposure_total`, given the era reward and points and the validator commission.
+pub(crate) fn nominator_reward(
+    era_reward: u128,
+    total_points: u32,
+    validator_points: u32,
+    commission: Perbill,
+    stake: u128,
+    exposure_total: u128,
+) -> u128 {
+    let validator_payout = validator_payout(era_reward, total_points, validator_points);
+    let leftover = validator_payout.saturating_sub(commission * validator_payout);
+
+    multiply_by_rational_with_rounding(leftover, stake, exposure_total, Rounding::Down)
+        .unwrap_or_default()
+}
+
+/// Returns the total exposure of `validator` in `era` and the stake of each of its nominators,
+/// from the legacy or the paged exposures.
+fn nominator_exposures<T: Staking::Config>(
+    era: EraIndex,
+    validator: &AccountIdOf<T>,
+) -> (u128, Vec<(AccountIdOf<T>, u128)>)
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    #[allow(deprecated)]
+    let legacy = <Staking::ErasStakers<T>>::get(era, validator);
+    if legacy.total.into() > 0 {
+        let others = legacy
+            .others
+            .into_iter()
+            .map(|e| (e.who, e.value.into()))
+            .collect();
+        return (legacy.total.into(), others);
+    }
+
+    let total = <Staking::ErasStakersOverview<T>>::get(era, validator)
+        .map_or(0, |overview| overview.total.into());
+    let others = <Staking::ErasStakersPaged<T>>::iter_prefix((era, validator.clone()))
+        .flat_map(|(_, page)| page.others.into_iter())
+        .map(|e| (e.who, e.value.into()))
+        .collect();
+
+    (total, others)
+}
+
+/// Returns the own stake and the total exposure of `validator` in `era`, from the legacy or the
+/// paged exposures.
+fn validator_exposure<T: Staking::Config>(era: EraIndex, validator: &AccountIdOf<T>) -> (u128, u128)
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    #[allow(deprecated)]
+    let legacy = <Staking::ErasStakers<T>>::get(era, validator);
+    if legacy.total.into() > 0 {
+        return (legacy.own.into(), legacy.total.into());
+    }
+
+    <Staking::ErasStakersOverview<T>>::get(era, validator).map_or((0, 0), |overview| {
+        (overview.own.into(), overview.total.into())
+    })
+}
+
+/// The reward of a validator in an era, and the exposure it was earned with.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ValidatorReward {
+    pub points: u32,
+    pub commission: Perbill,
+    /// The stake of the validator itself.
+    pub own: u128,
+    /// The total exposure, i.e. the own stake and the stake of the nominators.
+    pub total: u128,
+    /// The share of the era reward of the validator and its nominators, before the commission.
+    pub payout: u128,
+}
+
+impl ValidatorReward {
+    /// The estimated APR of the nominators of the validator, i.e. the payout after commission per
+    /// unit of exposed stake, times the eras per year.
+    pub fn nominator_apr(&self, eras_per_year: f64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let leftover = self.payout.saturating_sub(self.commission * self.payout);
+
+        leftover as f64 / self.total as f64 * eras_per_year
+    }
+}
+
+/// Returns the reward of each validator with era points in `era`.
+pub(crate) fn era_validator_rewards<T: Staking::Config>(
+    ext: &mut Ext,
+    era: EraIndex,
+) -> BTreeMap<AccountIdOf<T>, ValidatorReward>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    ext.execute_with(|| {
+        let era_reward = <Staking::ErasValidatorReward<T>>::get(era).map_or(0, Into::into);
+        let points = <Staking::ErasRewardPoints<T>>::get(era);
+
+        points
+            .individual
+            .into_iter()
+            .map(|(validator, validator_points)| {
+                let (own, total) = validator_exposure::<T>(era, &validator);
+                let reward = ValidatorReward {
+                    points: validator_points,
+                    commission: <Staking::ErasValidatorPrefs<T>>::get(era, &validator).commission,
+                    own,
+                    total,
+                    payout: validator_payout(era_reward, points.total, validator_points),
+                };
+                (validator, reward)
+            })
+            .collect()
+    })
+}
+
+/// Returns the reward and exposed stake of each nominator in `era`, summed over the validators
+/// it backs.
+pub(crate) fn era_nominator_rewards<T: Staking::Config>(
+    ext: &mut Ext,
+    era: EraIndex,
+) -> BTreeMap<AccountIdOf<T>, EraReward>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    ext.execute_with(|| {
+        let mut rewards = BTreeMap::<_, EraReward>::new();
+
+        let era_reward = match <Staking::ErasValidatorReward<T>>::get(era) {
+            Some(era_reward) => era_reward.into(),
+            None => return rewards,
+        };
+        let points = <Staking::ErasRewardPoints<T>>::get(era);
+
+        for (validator, validator_points) in points.individual {
+            let commission = <Staking::ErasValidatorPrefs<T>>::get(era, &validator).commission;
+            let (exposure_total, nominators) = nominator_exposures::<T>(era, &validator);
+
+            for (nominator, stake) in nominators {
+                let reward = nominator_reward(
+                    era_reward,
+                    points.total,
+                    validator_points,
+                    commission,
+                    stake,
+                    exposure_total,
+                );
+
+                let entry = rewards.entry(nominator).or_default();
+                entry.reward = entry.reward.saturating_add(reward);
+                entry.stake = entry.stake.saturating_add(stake);
+            }
+        }
+
+        rewards
+    })
+}
+
+/// Returns the bonded account of each nomination pool, with the id and current commission of the
+/// pool. The commission of the pools in past eras is not kept in state.
+pub(crate) fn pool_accounts<T: Pools::Config>(
+    ext: &mut Ext,
+) -> BTreeMap<AccountIdOf<T>, (Pools::PoolId, Perbill)> {
+    ext.execute_with(|| {
+        Pools::BondedPools::<T>::iter()
+            .map(|(id, pool)| {
+                let commission = pool
+                    .commission
+                    .current
+                    .map_or(Perbill::zero(), |(commission, _)| commission);
+                (
+                    Pools::Pallet::<T>::generate_bonded_account(id),
+                    (id, commission),
+                )
+            })
+            .collect()
+    })
+}
+
+/// The annualized yields of the nomination pools and of the solo nominators in an era.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct YieldComparison {
+    pub pools: u32,
+    pub pool_stake: u128,
+    /// Median yield of the pools, net of the pool commission.
+    pub median_pool_yield: f64,
+    /// Yield of all the pools together (i.e. weighted by stake), net of the pool commission.
+    pub pool_yield: f64,
+    pub solo_nominators: u32,
+    pub solo_stake: u128,
+    pub median_solo_yield: f64,
+    /// Yield of all the solo nominators together (i.e. weighted by stake).
+    pub solo_yield: f64,
+}
+
+impl YieldComparison {
+    /// Compares the era rewards of the `pools` (with their commission) and of the `solo`
+    /// nominators, annualized over `eras_per_year`.
+    pub fn new(pools: &[(EraReward, Perbill)], solo: &[EraReward], eras_per_year: f64) -> Self {
+        let net = pools
+            .iter()
+            .map(|(r, commission)| EraReward {
+                reward: r.reward.saturating_sub(*commission * r.reward),
+                stake: r.stake,
+            })
+            .collect::<Vec<_>>();
+
+        let median = |rewards: &[EraReward]| {
+            let mut yields = rewards
+                .iter()
+                .filter(|r| r.stake > 0)
+                .map(|r| r.reward as f64 / r.stake as f64 * eras_per_year)
+                .collect::<Vec<_>>();
+            yields.sort_by(|a, b| a.total_cmp(b));
+            percentile(&yields, 0.5)
+        };
+        let total = |rewards: &[EraReward]| {
+            let reward = rewards.iter().map(|r| r.reward).sum::<u128>();
+            let stake = rewards.iter().map(|r| r.stake).sum::<u128>();
+            let yield_ = match stake {
+                0 => 0.0,
+                stake => reward as f64 / stake as f64 * eras_per_year,
+            };
+            (stake, yield_)
+        };
+
+        let (pool_stake, pool_yield) = total(&net);
+        let (solo_stake, solo_yield) = total(solo);
+
+        Self {
+            pools: net.len() as u32,
+            pool_stake,
+            median_pool_yield: median(&net),
+            pool_yield,
+            solo_nominators: solo.len() as u32,
+            solo_stake,
+            median_solo_yield: median(solo),
+            solo_yield,
+        }
+    }
+}
+
+/// The realized returns of a stake over a range of eras.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Returns {
+    /// Number of eras in which the stake was exposed.
+    pub eras: u32,
+    pub total_reward: u128,
+    /// Average stake over the eras in which the stake was exposed.
+    pub avg_stake: u128,
+    /// Annual percentage rate, i.e. the average return per era times the eras per year.
+    pub apr: f64,
+    /// Annual percentage yield, i.e. the average return per era compounded over the eras per
+    /// year (as if the rewards were restaked).
+    pub apy: f64,
+}
+
+/// Computes the returns of the per era rewards of an account.
+pub(crate) fn returns(rewards: &[EraReward], eras_per_year: f64) -> Returns {
+    let exposed = rewards.iter().filter(|r| r.stake > 0).collect::<Vec<_>>();
+    if exposed.is_empty() {
+        return Returns::default();
+    }
+
+    let total_reward = exposed.iter().map(|r| r.reward).sum::<u128>();
+    let total_stake = exposed.iter().map(|r| r.stake).sum::<u128>();
+    let per_era = total_reward as f64 / total_stake as f64;
+
+    Returns {
+        eras: exposed.len() as u32,
+        total_reward,
+        avg_stake: total_stake / exposed.len() as u128,
+        apr: per_era * eras_per_year,
+        apy: (1.0 + per_era).powf(eras_per_year) - 1.0,
+    }
+}
+
+/// Returns the `p`-th percentile (`0.0..=1.0`) of the sorted `values`, by the nearest rank.
+pub(crate) fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nominator_reward_works() {
+        // validator with half of the points, 10% commission and the nominator with a quarter of
+        // the exposure.
+        assert_eq!(
+            nominator_reward(1_000, 100, 50, Perbill::from_percent(10), 25, 100),
+            112
+        );
+        // no commission.
+        assert_eq!(
+            nominator_reward(1_000, 100, 100, Perbill::zero(), 1, 1),
+            1_000
+        );
+        // degenerated points and exposures.
+        assert_eq!(nominator_reward(1_000, 0, 0, Perbill::zero(), 1, 1), 0);
+        assert_eq!(nominator_reward(1_000, 100, 50, Perbill::zero(), 1, 0), 0);
+    }
+
+    #[test]
+    fn nominator_apr_works() {
+        let reward = ValidatorReward {
+            points: 50,
+            commission: Perbill::from_percent(10),
+            own: 100,
+            total: 1_000,
+            payout: validator_payout(1_000, 100, 50),
+        };
+        assert_eq!(reward.payout, 500);
+        // 450 after commission over an exposure of 1_000.
+        assert!((reward.nominator_apr(365.0) - 0.45 * 365.0).abs() < 1e-9);
+
+        assert_eq!(ValidatorReward::default().nominator_apr(365.0), 0.0);
+    }
+
+    #[test]
+    fn returns_works() {
+        let rewards = vec![
+            EraReward {
+                reward: 10,
+                stake: 1_000,
+            },
+            EraReward {
+                reward: 0,
+                stake: 0,
+            },
+            EraReward {
+                reward: 30,
+                stake: 3_000,
+            },
+        ];
+
+        let returns = returns(&rewards, 365.0);
+        assert_eq!(returns.eras, 2);
+        assert_eq!(returns.total_reward, 40);
+        assert_eq!(returns.avg_stake, 2_000);
+        assert!((returns.apr - 3.65).abs() < 1e-9);
+        assert!((returns.apy - (1.01f64.powf(365.0) - 1.0)).abs() < 1e-9);
+
+        assert_eq!(super::returns(&[], 365.0), Returns::default());
+    }
+
+    #[test]
+    fn yield_comparison_works() {
+        let reward = |reward, stake| EraReward { reward, stake };
+        let pools = vec![
+            (reward(20, 1_000), Perbill::from_percent(50)),
+            (reward(20, 1_000), Perbill::zero()),
+            (reward(40, 2_000), Perbill::from_percent(10)),
+        ];
+        let solo = vec![reward(10, 1_000), reward(30, 1_000), reward(0, 0)];
+
+        let comparison = YieldComparison::new(&pools, &solo, 100.0);
+        assert_eq!(comparison.pools, 3);
+        assert_eq!(comparison.pool_stake, 4_000);
+        // net yields of 1.0, 2.0 and 1.8.
+        assert!((comparison.median_pool_yield - 1.8).abs() < 1e-9);
+        assert!((comparison.pool_yield - 66.0 / 4_000.0 * 100.0).abs() < 1e-9);
+        assert_eq!(comparison.solo_nominators, 3);
+        assert_eq!(comparison.solo_stake, 2_000);
+        assert!((comparison.median_solo_yield - 1.0).abs() < 1e-9);
+        assert!((comparison.solo_yield - 2.0).abs() < 1e-9);
+
+        assert_eq!(
+            YieldComparison::new(&[], &[], 100.0),
+            YieldComparison::default()
+        );
+    }
+
+    #[test]
+    fn percentile_works() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 0.1), 1.0);
+        assert_eq!(percentile(&values, 0.5), 5.0);
+        assert_eq!(percentile(&values, 0.9), 9.0);
+        assert_eq!(percentile(&values, 1.0), 10.0);
+        assert_eq!(percentile(&[], 0.5), 0.0);
+    }
+}
@@ -0,0 +1,561 @@
+//! NPoS solvers implemented in-crate.
+//!
+//! Currently exposes [`ParallelPhragmen`], a multi-threaded implementation of the sequential
+//! Phragmén method which can be used as a drop-in replacement of
+//! [`frame_election_provider_support::SequentialPhragmen`] when mining elections over large
+//! snapshots, and [`approval_voting`], a multi-winner approval voting baseline.
+
+use frame_election_provider_support::{NposSolver, WeightInfo};
+use frame_support::{traits::Get, weights::Weight};
+use rayon::prelude::*;
+use sp_arithmetic::{
+    helpers_128bit::multiply_by_rational_with_rounding, traits::Bounded, PerThing, PerThing128,
+    Rational128, Rounding,
+};
+use sp_npos_elections::{
+    Assignment, BalancingConfig, ElectionResult, ExtendedBalance, IdentifierT, VoteWeight,
+};
+use std::{collections::BTreeMap, marker::PhantomData};
+
+/// Denominator used to compute the candidate scores, same as in `sp_npos_elections`.
+const DEN: ExtendedBalance = ExtendedBalance::MAX;
+
+/// A multi-threaded sequential Phragmén solver.
+///
+/// The score of each candidate in each round and the voter load updates are computed in parallel
+/// using `rayon`. The balancing phase is inherently sequential (the rebalance of a voter depends on
+/// the rebalance of all the voters processed before it) and runs in a single thread.
+///
+/// ### Determinism
+///
+/// The results are deterministic and equal to the ones computed by
+/// [`sp_npos_elections::seq_phragmen`], regardless of the number of threads used: the candidate
+/// scores are computed independently from each other with exact rational arithmetic and the winner
+/// of each round is selected sequentially, with ties broken by the candidate position in the
+/// targets list.
+pub(crate) struct ParallelPhragmen<AccountId, Accuracy, Balancing = ()>(
+    PhantomData<(AccountId, Accuracy, Balancing)>,
+);
+
+impl<AccountId: IdentifierT, Accuracy: PerThing128, Balancing: Get<Option<BalancingConfig>>>
+    NposSolver for ParallelPhragmen<AccountId, Accuracy, Balancing>
+{
+    type AccountId = AccountId;
+    type Accuracy = Accuracy;
+    type Error = sp_npos_elections::Error;
+
+    fn solve(
+        winners: usize,
+        targets: Vec<Self::AccountId>,
+        voters: Vec<(
+            Self::AccountId,
+            VoteWeight,
+            impl IntoIterator<Item = Self::AccountId>,
+        )>,
+    ) -> Result<ElectionResult<Self::AccountId, Self::Accuracy>, Self::Error> {
+        par_seq_phragmen(winners, targets, voters, Balancing::get())
+    }
+
+    fn weight<T: WeightInfo>(voters: u32, targets: u32, vote_degree: u32) -> Weight {
+        T::phragmen(voters, targets, vote_degree)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Edge {
+    candidate: usize,
+    load: Rational128,
+    weight: ExtendedBalance,
+}
+
+#[derive(Clone, Debug)]
+struct Voter {
+    budget: ExtendedBalance,
+    load: Rational128,
+    edges: Vec<Edge>,
+}
+
+#[derive(Clone, Debug)]
+struct Candidate {
+    approval_stake: ExtendedBalance,
+    backed_stake: ExtendedBalance,
+    elected: bool,
+    round: usize,
+    score: Rational128,
+    /// Indices of the voters that have an edge to this candidate.
+    backers: Vec<usize>,
+}
+
+/// Parallel version of [`sp_npos_elections::seq_phragmen`].
+pub(crate) fn par_seq_phragmen<AccountId: IdentifierT, P: PerThing128>(
+    to_elect: usize,
+    targets: Vec<AccountId>,
+    voters: Vec<(AccountId, VoteWeight, impl IntoIterator<Item = AccountId>)>,
+    balancing: Option<BalancingConfig>,
+) -> Result<ElectionResult<AccountId, P>, sp_npos_elections::Error> {
+    let (target_ids, mut candidates, voter_ids, mut voters) = setup_inputs(targets, voters);
+
+    seq_phragmen_core(to_elect, &mut candidates, &mut voters)?;
+
+    if let Some(ref config) = balancing {
+        balance(&mut candidates, &mut voters, config);
+    }
+
+    let mut winners = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.elected)
+        .take(to_elect)
+        .collect::<Vec<_>>();
+    winners.sort_by_key(|(_, c)| c.round);
+
+    let winners = winners
+        .into_iter()
+        .map(|(idx, c)| (target_ids[idx].clone(), c.backed_stake))
+        .collect::<Vec<_>>();
+
+    let mut assignments = voter_ids
+        .into_iter()
+        .zip(voters)
+        .filter_map(|(who, voter)| {
+            let distribution = voter
+                .edges
+                .into_iter()
+                .filter_map(|e| {
+                    let per_thing = P::from_rational(e.weight, voter.budget);
+                    if per_thing.is_zero() {
+                        None
+                    } else {
+                        Some((target_ids[e.candidate].clone(), per_thing))
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if distribution.is_empty() {
+                None
+            } else {
+                Some(Assignment { who, distribution })
+            }
+        })
+        .collect::<Vec<_>>();
+
+    assignments
+        .iter_mut()
+        .try_for_each(|a| a.try_normalize())
+        .map_err(sp_npos_elections::Error::ArithmeticError)?;
+
+    Ok(ElectionResult {
+        winners,
+        assignments,
+    })
+}
+
+/// Multi-winner approval voting, as a baseline of the NPoS solvers.
+///
+/// The `to_elect` candidates with the highest approval stake are elected, with ties broken by the
+/// candidate position in the targets list, and the stake of each voter is split equally between
+/// the winners it approves (the remainder of the split going to the first of them). Returns the
+/// winners and their backing, by descending approval stake.
+pub(crate) fn approval_voting<AccountId: IdentifierT>(
+    to_elect: usize,
+    targets: Vec<AccountId>,
+    voters: Vec<(AccountId, VoteWeight, impl IntoIterator<Item = AccountId>)>,
+) -> Vec<(AccountId, ExtendedBalance)> {
+    let (target_ids, candidates, _, voters) = setup_inputs(targets, voters);
+
+    let mut ranked = (0..candidates.len()).collect::<Vec<_>>();
+    ranked.sort_by(|a, b| {
+        candidates[*b]
+            .approval_stake
+            .cmp(&candidates[*a].approval_stake)
+    });
+    ranked.truncate(to_elect);
+
+    let mut backings = ranked
+        .iter()
+        .map(|idx| (*idx, 0))
+        .collect::<BTreeMap<_, ExtendedBalance>>();
+    for voter in voters {
+        let approved = voter
+            .edges
+            .iter()
+            .map(|e| e.candidate)
+            .filter(|idx| backings.contains_key(idx))
+            .collect::<Vec<_>>();
+        if approved.is_empty() {
+            continue;
+        }
+
+        let share = voter.budget / approved.len() as ExtendedBalance;
+        let remainder = voter.budget % approved.len() as ExtendedBalance;
+        for (i, idx) in approved.into_iter().enumerate() {
+            let backing = backings
+                .get_mut(&idx)
+                .expect("approved winners are backed; qed.");
+            *backing = backing.saturating_add(if i == 0 { share + remainder } else { share });
+        }
+    }
+
+    ranked
+        .into_iter()
+        .map(|idx| (target_ids[idx].clone(), backings[&idx]))
+        .collect()
+}
+
+/// Converts the solver inputs into index-based candidates and voters. Votes for unknown or
+/// duplicate targets are ignored.
+fn setup_inputs<AccountId: IdentifierT>(
+    targets: Vec<AccountId>,
+    voters: Vec<(AccountId, VoteWeight, impl IntoIterator<Item = AccountId>)>,
+) -> (Vec<AccountId>, Vec<Candidate>, Vec<AccountId>, Vec<Voter>) {
+    let mut target_idx = BTreeMap::new();
+    let mut target_ids = vec![];
+    let mut candidates = vec![];
+
+    for target in targets.into_iter() {
+        if target_idx.contains_key(&target) {
+            continue;
+        }
+        target_idx.insert(target.clone(), target_ids.len());
+        target_ids.push(target);
+        candidates.push(Candidate {
+            approval_stake: 0,
+            backed_stake: 0,
+            elected: false,
+            round: 0,
+            score: Rational128::zero(),
+            backers: vec![],
+        });
+    }
+
+    let mut voter_ids = vec![];
+    let mut voter_states = vec![];
+
+    for (who, stake, votes) in voters.into_iter() {
+        let voter_idx = voter_ids.len();
+        let budget: ExtendedBalance = stake.into();
+        let mut edges: Vec<Edge> = vec![];
+
+        for vote in votes.into_iter() {
+            if let Some(&idx) = target_idx.get(&vote) {
+                if edges.iter().any(|e| e.candidate == idx) {
+                    continue;
+                }
+                let candidate = &mut candidates[idx];
+                candidate.approval_stake = candidate.approval_stake.saturating_add(budget);
+                candidate.backers.push(voter_idx);
+                edges.push(Edge {
+                    candidate: idx,
+                    load: Rational128::zero(),
+                    weight: 0,
+                });
+            }
+        }
+
+        voter_ids.push(who);
+        voter_states.push(Voter {
+            budget,
+            load: Rational128::zero(),
+            edges,
+        });
+    }
+
+    (target_ids, candidates, voter_ids, voter_states)
+}
+
+/// Core of the sequential Phragmén method, where the per-round candidate scoring and the voter
+/// load updates are performed in parallel.
+fn seq_phragmen_core(
+    to_elect: usize,
+    candidates: &mut [Candidate],
+    voters: &mut [Voter],
+) -> Result<(), sp_npos_elections::Error> {
+    for round in 0..to_elect {
+        let scores = {
+            let voters: &[Voter] = voters;
+            candidates
+                .par_iter()
+                .map(|candidate| {
+                    if candidate.elected {
+                        return candidate.score;
+                    }
+                    if candidate.approval_stake == 0 {
+                        return Rational128::from_unchecked(DEN, 0);
+                    }
+
+                    let mut score = Rational128::from(DEN / candidate.approval_stake, DEN);
+                    for voter in candidate.backers.iter().map(|idx| &voters[*idx]) {
+                        let temp_n = multiply_by_rational_with_rounding(
+                            voter.load.n(),
+                            voter.budget,
+                            candidate.approval_stake,
+                            Rounding::Down,
+                        )
+                        .unwrap_or(Bounded::max_value());
+                        let temp = Rational128::from(temp_n, voter.load.d());
+                        score = score.lazy_saturating_add(temp);
+                    }
+                    score
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (candidate, score) in candidates.iter_mut().zip(scores) {
+            candidate.score = score;
+        }
+
+        // `min_by_key` returns the first minimum, i.e. ties are broken by the candidate index.
+        let winner_idx = match candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.elected)
+            .min_by_key(|(_, c)| c.score)
+        {
+            Some((idx, _)) => idx,
+            None => break,
+        };
+
+        let winner = &mut candidates[winner_idx];
+        winner.elected = true;
+        winner.round = round;
+        let winner_score = winner.score;
+
+        voters.par_iter_mut().for_each(|voter| {
+            for edge in voter.edges.iter_mut() {
+                if edge.candidate == winner_idx {
+                    edge.load = winner_score.lazy_saturating_sub(voter.load);
+                    voter.load = winner_score;
+                }
+            }
+        });
+    }
+
+    let elected = candidates.iter().map(|c| c.elected).collect::<Vec<_>>();
+
+    voters.par_iter_mut().for_each(|voter| {
+        for edge in voter.edges.iter_mut() {
+            edge.weight = if elected[edge.candidate] {
+                multiply_by_rational_with_rounding(
+                    voter.budget,
+                    edge.load.n(),
+                    voter.load.n(),
+                    Rounding::Down,
+                )
+                .unwrap_or(Bounded::max_value())
+            } else {
+                0
+            };
+        }
+    });
+
+    for voter in voters.iter() {
+        for edge in voter.edges.iter() {
+            let candidate = &mut candidates[edge.candidate];
+            candidate.backed_stake = candidate.backed_stake.saturating_add(edge.weight);
+        }
+    }
+
+    voters.par_iter_mut().try_for_each(|voter| {
+        // remove all zero edges, which can become phantom edges during normalization.
+        voter.edges.retain(|e| e.weight > 0);
+
+        let weights = voter.edges.iter().map(|e| e.weight).collect::<Vec<_>>();
+        let normalized = sp_arithmetic::normalize(&weights, voter.budget)
+            .map_err(sp_npos_elections::Error::ArithmeticError)?;
+        for (edge, weight) in voter.edges.iter_mut().zip(normalized) {
+            edge.weight = weight;
+        }
+        Ok(())
+    })
+}
+
+/// Balances the edge weights of all voters, as in `sp_npos_elections::balance`. Returns the number
+/// of iterations executed.
+fn balance(candidates: &mut [Candidate], voters: &mut [Voter], config: &BalancingConfig) -> usize {
+    if config.iterations == 0 {
+        return 0;
+    }
+
+    let mut iter = 0;
+    loop {
+        let mut max_diff = 0;
+        for voter in voters.iter_mut() {
+            let diff = balance_voter(candidates, voter, config.tolerance);
+            if diff > max_diff {
+                max_diff = diff;
+            }
+        }
+
+        iter += 1;
+        if max_diff <= config.tolerance || iter >= config.iterations {
+            break iter;
+        }
+    }
+}
+
+/// Balances a single voter, returning the difference between the most and least backed of its
+/// elected targets.
+fn balance_voter(
+    candidates: &mut [Candidate],
+    voter: &mut Voter,
+    tolerance: ExtendedBalance,
+) -> ExtendedBalance {
+    let mut elected_edges = voter
+        .edges
+        .iter_mut()
+        .filter(|e| candidates[e.candidate].elected)
+        .collect::<Vec<_>>();
+
+    // either empty, or a self vote. Not much to do in either case.
+    if elected_edges.len() <= 1 {
+        return 0;
+    }
+
+    let stake_used = elected_edges
+        .iter()
+        .fold(0 as ExtendedBalance, |acc, e| acc.saturating_add(e.weight));
+    let backed_stakes = elected_edges
+        .iter()
+        .map(|e| candidates[e.candidate].backed_stake)
+        .collect::<Vec<_>>();
+    let backing_backed_stake = elected_edges
+        .iter()
+        .filter(|e| e.weight > 0)
+        .map(|e| candidates[e.candidate].backed_stake)
+        .collect::<Vec<_>>();
+
+    let difference = if let Some(max_stake) = backing_backed_stake.iter().max() {
+        let min_stake = backed_stakes
+            .iter()
+            .min()
+            .expect("iterator with positive length will have a min; qed.");
+        let difference = max_stake
+            .saturating_sub(*min_stake)
+            .saturating_add(voter.budget.saturating_sub(stake_used));
+        if difference < tolerance {
+            return difference;
+        }
+        difference
+    } else {
+        voter.budget
+    };
+
+    // remove all backings.
+    for edge in elected_edges.iter_mut() {
+        let candidate = &mut candidates[edge.candidate];
+        candidate.backed_stake = candidate.backed_stake.saturating_sub(edge.weight);
+        edge.weight = 0;
+    }
+
+    elected_edges.sort_by_key(|e| candidates[e.candidate].backed_stake);
+
+    let mut cumulative_backed_stake: ExtendedBalance = 0;
+    let mut last_index = elected_edges.len() - 1;
+    for (index, edge) in elected_edges.iter().enumerate() {
+        let backed_stake = candidates[edge.candidate].backed_stake;
+        let temp = backed_stake.saturating_mul(index as ExtendedBalance);
+        if temp.saturating_sub(cumulative_backed_stake) > voter.budget {
+            last_index = index.saturating_sub(1);
+            break;
+        }
+        cumulative_backed_stake = cumulative_backed_stake.saturating_add(backed_stake);
+    }
+
+    let last_stake = candidates[elected_edges[last_index].candidate].backed_stake;
+    let ways_to_split = last_index + 1;
+    let excess = voter
+        .budget
+        .saturating_add(cumulative_backed_stake)
+        .saturating_sub(last_stake.saturating_mul(ways_to_split as ExtendedBalance));
+
+    for edge in elected_edges.into_iter().take(ways_to_split) {
+        let candidate = &mut candidates[edge.candidate];
+        edge.weight = (excess / ways_to_split as ExtendedBalance)
+            .saturating_add(last_stake)
+            .saturating_sub(candidate.backed_stake);
+        candidate.backed_stake = candidate.backed_stake.saturating_add(edge.weight);
+    }
+
+    difference
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_npos_elections::{assignment_ratio_to_staked, to_supports, EvaluateSupport};
+    use sp_runtime::Perbill;
+
+    fn score_of(
+        result: ElectionResult<u32, Perbill>,
+        voters: &[(u32, VoteWeight, Vec<u32>)],
+    ) -> sp_npos_elections::ElectionScore {
+        let stake_of = |who: &u32| {
+            voters
+                .iter()
+                .find(|(v, _, _)| v == who)
+                .map(|(_, s, _)| *s)
+                .unwrap_or_default()
+        };
+        let staked = assignment_ratio_to_staked(result.assignments, stake_of);
+        to_supports(&staked).evaluate()
+    }
+
+    #[test]
+    fn parallel_and_sequential_scores_match() {
+        let targets = (1..=10).collect::<Vec<u32>>();
+        let voters = (100..200)
+            .map(|v: u32| {
+                let stake = (v as u64 * 7919) % 1_000 + 10;
+                let votes = vec![v % 10 + 1, (v * 3) % 10 + 1, (v * 7) % 10 + 1];
+                (v, stake, votes)
+            })
+            .collect::<Vec<_>>();
+
+        for balancing in [
+            None,
+            Some(BalancingConfig {
+                iterations: 10,
+                tolerance: 0,
+            }),
+        ] {
+            let sequential = sp_npos_elections::seq_phragmen::<u32, Perbill>(
+                4,
+                targets.clone(),
+                voters.clone(),
+                balancing,
+            )
+            .unwrap();
+            let parallel =
+                par_seq_phragmen::<u32, Perbill>(4, targets.clone(), voters.clone(), balancing)
+                    .unwrap();
+
+            assert_eq!(sequential.winners, parallel.winners);
+            assert_eq!(score_of(sequential, &voters), score_of(parallel, &voters));
+        }
+    }
+
+    #[test]
+    fn approval_voting_works() {
+        let voters = vec![
+            (10u32, 100u64, vec![1u32, 2]),
+            (20, 51, vec![2, 3]),
+            (30, 10, vec![3, 4, 4]),
+        ];
+
+        // approval stakes: 1 -> 100, 2 -> 151, 3 -> 61, 4 -> 10.
+        assert_eq!(
+            approval_voting(2, vec![1, 2, 3, 4], voters.clone()),
+            vec![(2, 50 + 51), (1, 50)]
+        );
+        assert_eq!(
+            approval_voting(3, vec![1, 2, 3, 4], voters.clone()),
+            vec![(2, 50 + 26), (1, 50), (3, 25 + 10)]
+        );
+
+        // ties are broken by the position in the targets list.
+        assert_eq!(
+            approval_voting(1, vec![4, 5], vec![(10u32, 10u64, vec![4u32, 5])]),
+            vec![(4, 10)]
+        );
+    }
+}
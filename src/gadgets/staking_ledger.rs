@@ -1,10 +1,239 @@
 use super::*;
 
+use sp_runtime::SaturatedConversion;
 use sp_staking::StakingAccount;
+use std::collections::{BTreeMap, BTreeSet};
 use Staking::{Bonded, Config, Ledger, Payee};
 
+/// The id of the staking lock in the `Balances` pallet.
+const STAKING_ID: [u8; 8] = *b"staking ";
+
+/// The report of the staking ledger checks at a block.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LedgerReport<A> {
+    pub ledgers: u32,
+    pub bonded: u32,
+    pub payees: u32,
+    /// Bonded stashes whose ledger is consistent.
+    pub ok_ledgers: u32,
+    /// Stashes of the ledgers whose controller is not bonded, or bonded to another stash.
+    pub bad_ledgers: Vec<A>,
+    /// Bonded stashes (and their controller) whose controller has no ledger.
+    pub none_ledgers: Vec<(A, A)>,
+    /// Bonded stashes whose ledger is of another stash, along with the latter.
+    pub inconsistent_ledgers: Vec<(A, A)>,
+    /// Stashes of the ledgers without a staking lock (or hold).
+    pub missing_locks: Vec<A>,
+    /// Stashes of the ledgers whose staking lock (or hold) is smaller than the ledger total.
+    pub smaller_locks: Vec<A>,
+    /// Stashes of the ledgers whose staking lock (or hold) is larger than the ledger total.
+    pub larger_locks: Vec<A>,
+    /// The inconsistencies of the payees with the bonded stashes.
+    pub payee_mismatches: PayeeMismatches<A>,
+}
+
+/// The inconsistencies of the payees (`Payee<T>`) with the bonded stashes (`Bonded<T>`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PayeeMismatches<A> {
+    /// Bonded stashes without a payee.
+    pub missing: Vec<A>,
+    /// Stashes with a payee which are not bonded.
+    pub dangling: Vec<A>,
+    /// Stashes whose rewards are paid to their controller, which was deprecated, i.e. which is
+    /// the stash itself.
+    pub deprecated_controller: Vec<A>,
+}
+
+impl<A: Ord + Clone> PayeeMismatches<A> {
+    /// Checks the `payees` of the stashes, as whether they are paid to the controller, against
+    /// the `bonded` controllers of the stashes.
+    pub fn of(bonded: &BTreeMap<A, A>, payees: &BTreeMap<A, bool>) -> Self {
+        PayeeMismatches {
+            missing: bonded
+                .keys()
+                .filter(|stash| !payees.contains_key(stash))
+                .cloned()
+                .collect(),
+            dangling: payees
+                .keys()
+                .filter(|stash| !bonded.contains_key(stash))
+                .cloned()
+                .collect(),
+            deprecated_controller: payees
+                .iter()
+                .filter(|(stash, to_controller)| {
+                    **to_controller && bonded.get(stash).map_or(false, |c| c == *stash)
+                })
+                .map(|(stash, _)| stash.clone())
+                .collect(),
+        }
+    }
+}
+
+impl<A> LedgerReport<A> {
+    /// Whether the numbers of ledgers, bonded stashes and payees match.
+    pub fn in_sync(&self) -> bool {
+        self.ledgers == self.bonded && self.ledgers == self.payees
+    }
+}
+
+impl<A: Ord + Clone> LedgerReport<A> {
+    /// The stashes whose ledger is bad, none or inconsistent.
+    pub fn faulty_stashes(&self) -> BTreeSet<A> {
+        self.bad_ledgers
+            .iter()
+            .chain(self.none_ledgers.iter().map(|(stash, _)| stash))
+            .chain(self.inconsistent_ledgers.iter().map(|(stash, _)| stash))
+            .cloned()
+            .collect()
+    }
+
+    /// The stashes whose ledger is faulty in this report but was not `before`, e.g. the stashes
+    /// bricked by a migration.
+    pub fn newly_faulty(&self, before: &Self) -> Vec<A> {
+        self.faulty_stashes()
+            .difference(&before.faulty_stashes())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The reports of the staking ledger checks over a block and its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LedgerChecks<A, B> {
+    pub parent_block: B,
+    pub child_block: B,
+    pub child: LedgerReport<A>,
+    pub parent: LedgerReport<A>,
+    /// The report at the parent block, after the deprecation of the controllers of the
+    /// `none_ledgers` of the child block is simulated.
+    pub parent_deprecated: LedgerReport<A>,
+}
+
+/// The report of a simulated `deprecate_controller_batch` migration at a block.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MigrationReport<A, B> {
+    pub block: B,
+    /// The stashes bonded to another account, i.e. the ledgers affected by the migration.
+    pub affected: u32,
+    /// The stashes whose ledger was moved to the stash.
+    pub migrated: Vec<A>,
+    /// The stashes skipped since their rewards are paid to their controller.
+    pub skipped_payee: Vec<A>,
+    /// The stashes skipped since their controller has no ledger of another stash.
+    pub skipped_ledger: Vec<A>,
+    pub before: LedgerReport<A>,
+    pub after: LedgerReport<A>,
+    /// The stashes whose ledger is consistent before the migration but not after, i.e. which can
+    /// no longer be managed.
+    pub bricked: Vec<A>,
+}
+
+/// The mismatch of a staking lock (or hold) with the total of its ledger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LockMismatch {
+    Missing,
+    Smaller,
+    Larger,
+}
+
+impl LockMismatch {
+    /// The mismatch of the `locked` balance of a stash (if any) with the `total` of its ledger.
+    pub fn of(total: u128, locked: Option<u128>) -> Option<Self> {
+        match locked {
+            None => Some(LockMismatch::Missing),
+            Some(locked) if locked < total => Some(LockMismatch::Smaller),
+            Some(locked) if locked > total => Some(LockMismatch::Larger),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Returns the balance of `stash` locked or held for staking in the `Balances` pallet, or `None`
+/// if it has neither a staking lock nor a staking hold. Must be called within the externalities.
+///
+/// Both the locks and the holds are read, since the staking pallet moved from locks to holds and
+/// the ledgers of a block may be on either side of the migration. The staking hold is the one of
+/// the `RuntimeHoldReason` of the runtime converted from `HoldReason::Staking`, so that it does
+/// not depend on the encoding of the hold reasons.
+fn staking_locked<T>(stash: &AccountIdOf<T>) -> Option<u128>
+where
+    T: Config + pallet_balances::Config,
+    <T as pallet_balances::Config>::RuntimeHoldReason: From<Staking::HoldReason>,
+{
+    let lock = pallet_balances::Locks::<T>::get(stash)
+        .into_iter()
+        .find(|lock| lock.id == STAKING_ID)
+        .map(|lock| lock.amount.saturated_into::<u128>());
+
+    let staking_reason =
+        <T as pallet_balances::Config>::RuntimeHoldReason::from(Staking::HoldReason::Staking);
+    let hold = pallet_balances::Holds::<T>::get(stash)
+        .into_iter()
+        .find(|hold| hold.id == staking_reason)
+        .map(|hold| hold.amount.saturated_into::<u128>());
+
+    match (lock, hold) {
+        (None, None) => None,
+        (lock, hold) => Some(lock.unwrap_or_default() + hold.unwrap_or_default()),
+    }
+}
+
+/// Cross-checks the total of each ledger with the balance of its stash locked (or held) for
+/// staking, and returns the stashes with a missing, smaller or larger lock.
+fn lock_checks<T>() -> BTreeMap<AccountIdOf<T>, LockMismatch>
+where
+    T: Config + pallet_balances::Config,
+    Staking::BalanceOf<T>: Into<u128>,
+    <T as pallet_balances::Config>::RuntimeHoldReason: From<Staking::HoldReason>,
+{
+    let mut mismatches = BTreeMap::new();
+
+    for (_, ledger) in Ledger::<T>::iter() {
+        let total: u128 = ledger.total.into();
+        let locked = staking_locked::<T>(&ledger.stash);
+
+        if let Some(mismatch) = LockMismatch::of(total, locked) {
+            log::debug!(
+                target: LOG_TARGET,
+                "ledger's total does not match the staking lock. stash: {:?} ({:?}: {} != {:?})",
+                ledger.stash,
+                mismatch,
+                total,
+                locked,
+            );
+            mismatches.insert(ledger.stash, mismatch);
+        }
+    }
+
+    mismatches
+}
+
+/// Checks that every bonded stash has a payee, that every payee is of a bonded stash, and flags
+/// the payees which pay the rewards to a deprecated controller.
+fn payee_checks<T: Config>() -> PayeeMismatches<AccountIdOf<T>> {
+    let bonded = Bonded::<T>::iter().collect::<BTreeMap<_, _>>();
+    let payees = Payee::<T>::iter()
+        .map(|(stash, payee)| {
+            #[allow(deprecated)]
+            let to_controller = matches!(payee, Staking::RewardDestination::Controller);
+            (stash, to_controller)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mismatches = PayeeMismatches::of(&bonded, &payees);
+    for stash in mismatches.deprecated_controller.iter() {
+        log::debug!(
+            target: LOG_TARGET,
+            "payee is the deprecated controller of stash {:?}",
+            stash,
+        );
+    }
+    mismatches
+}
+
 /// For each ledger:
-/// * `Bonded<T>` and `Payee<T>` are set.
+/// * `Bonded<T>` is set.
 /// * stash in `Bonded<T>` is the same as in the ledger.
 fn ledger_checks<T: Config>() -> Vec<AccountIdOf<T>> {
     let mut bad_stashes = vec![];
@@ -14,7 +243,7 @@ fn ledger_checks<T: Config>() -> Vec<AccountIdOf<T>> {
 
         if let Some(bonded_controller) = Bonded::<T>::get(&stash) {
             if controller != bonded_controller {
-                log::error!(
+                log::debug!(
                     target: LOG_TARGET,
                     "ledger's controller does not match bonded controller. stash: {:?} (controllers: {:?} != {:?})",
                     stash,
@@ -24,7 +253,7 @@ fn ledger_checks<T: Config>() -> Vec<AccountIdOf<T>> {
                 bad_stashes.push(stash);
             }
         } else {
-            log::error!(
+            log::debug!(
                 target: LOG_TARGET,
                 "ledger's controller does not have a bonded stash. {:?}",
                 stash,
@@ -49,16 +278,17 @@ fn bonded_checks<T: Config>() -> (
         let ledger = Ledger::<T>::get(&controller);
 
         if ledger.is_none() {
-            none_ledgers.push((stash.clone(), controller));
-            log::error!(
+            log::debug!(
+                target: LOG_TARGET,
                 "{:?} with bonded does not have a ledger associated with the controller",
                 stash,
             );
+            none_ledgers.push((stash, controller));
         } else {
             let ledger = ledger.expect("exists; qed.");
             if ledger.stash != stash {
+                log::debug!(target: LOG_TARGET, "stash in ledger does not match expected {} != {}", ledger.stash, stash);
                 inconsistent_ledgers.push((stash.clone(), ledger.stash.clone()));
-                log::error!(target: LOG_TARGET, "stash in ledger does not match expected {} != {}", ledger.stash, stash);
             }
             ok_ledgers.push(stash);
         }
@@ -66,127 +296,285 @@ fn bonded_checks<T: Config>() -> (
     (none_ledgers, inconsistent_ledgers, ok_ledgers)
 }
 
+/// Checks the staking ledgers of the current state. Must be called within the externalities.
+fn ledger_report<T>() -> LedgerReport<AccountIdOf<T>>
+where
+    T: Config + pallet_balances::Config,
+    Staking::BalanceOf<T>: Into<u128>,
+    <T as pallet_balances::Config>::RuntimeHoldReason: From<Staking::HoldReason>,
+{
+    let (none_ledgers, inconsistent_ledgers, ok_ledgers) = bonded_checks::<T>();
+    let locks = lock_checks::<T>();
+    let with_mismatch = |mismatch: LockMismatch| {
+        locks
+            .iter()
+            .filter(|(_, m)| **m == mismatch)
+            .map(|(stash, _)| stash.clone())
+            .collect::<Vec<_>>()
+    };
+
+    LedgerReport {
+        ledgers: Ledger::<T>::iter().count() as u32,
+        bonded: Bonded::<T>::iter().count() as u32,
+        payees: Payee::<T>::iter().count() as u32,
+        ok_ledgers: (ok_ledgers.len() - inconsistent_ledgers.len()) as u32,
+        bad_ledgers: ledger_checks::<T>(),
+        none_ledgers,
+        inconsistent_ledgers,
+        missing_locks: with_mismatch(LockMismatch::Missing),
+        smaller_locks: with_mismatch(LockMismatch::Smaller),
+        larger_locks: with_mismatch(LockMismatch::Larger),
+        payee_mismatches: payee_checks::<T>(),
+    }
+}
+
 /// Staking ledger consistency checks.
-pub(crate) fn staking_ledger_checks<T>(mut exts: Vec<Ext>) -> Result<(), anyhow::Error>
+///
+/// The ledgers of the child block are checked first, then the ledgers of the parent block before
+/// and after simulating the deprecation of the controllers whose ledger is missing in the child
+/// block. Besides the consistency of the ledgers with the bonded stashes, the total of each ledger
+/// is cross-checked with the balance locked (or held) for staking in the `Balances` pallet.
+pub(crate) fn staking_ledger_checks<T>(
+    exts: Vec<Ext>,
+) -> Result<LedgerChecks<AccountIdOf<T>, BlockNumberFor<T>>, anyhow::Error>
 where
-    T: EPM::Config + Staking::Config,
+    T: EPM::Config + Staking::Config + pallet_balances::Config,
+    Staking::BalanceOf<T>: Into<u128>,
+    <T as pallet_balances::Config>::RuntimeHoldReason: From<Staking::HoldReason>,
+    <T as frame_system::Config>::RuntimeCall: From<Staking::Call<T>>,
 {
-    assert!(exts.len() == 2, "expected to have len 2");
+    let (mut ext_parent, mut ext_child) = parent_and_child::<T>("staking_ledger_checks", exts)?;
 
-    // select parent and child block externalities.
-    let mut ext0 = exts.split_off(1);
-    let (mut ext_parent, mut ext_child) = {
-        let mut ext = ext0.pop().unwrap();
-        let mut ext_other = exts.pop().unwrap();
+    // 1. process child first to obtain the faulty ledgers.
+    let child_block = block_number::<T>(&mut ext_child);
+    let child = ext_child.execute_with(ledger_report::<T>);
+    log::info!(
+        target: LOG_TARGET,
+        "Ledgers at child block #{:?}: #ledgers: {}, #bonded: {}, #payees: {}, #none_ledgers: {}, #inconsistent_ledgers: {}, #bad_ledgers: {}, #missing_locks: {}, #smaller_locks: {}, #larger_locks: {}",
+        child_block,
+        child.ledgers,
+        child.bonded,
+        child.payees,
+        child.none_ledgers.len(),
+        child.inconsistent_ledgers.len(),
+        child.bad_ledgers.len(),
+        child.missing_locks.len(),
+        child.smaller_locks.len(),
+        child.larger_locks.len(),
+    );
 
-        let bn1 = block_number::<T>(&mut ext);
-        let bn2 = block_number::<T>(&mut ext_other);
+    // 2. check parent block state of faulty ledgers, before and after deprecating their
+    // controllers.
+    let parent_block = block_number::<T>(&mut ext_parent);
+    let parent = ext_parent.execute_with(ledger_report::<T>);
+    let controllers = child
+        .none_ledgers
+        .iter()
+        .map(|(_, controller)| controller.clone())
+        .collect::<Vec<_>>();
+    let parent_deprecated = simulate(&mut ext_parent, |ext| {
+        ext.execute_with(|| {
+            deprecate_controllers::<T>(controllers);
+            ledger_report::<T>()
+        })
+    });
+    log::info!(
+        target: LOG_TARGET,
+        "Ledgers at parent block #{:?}: #ledgers: {}, #bonded: {}, #payees: {} (after deprecate: #ledgers: {}, #bonded: {}, #payees: {})",
+        parent_block,
+        parent.ledgers,
+        parent.bonded,
+        parent.payees,
+        parent_deprecated.ledgers,
+        parent_deprecated.bonded,
+        parent_deprecated.payees,
+    );
 
-        match bn1 > bn2 {
-            true => (ext_other, ext),
-            false => (ext, ext_other),
-        }
-    };
+    Ok(LedgerChecks {
+        parent_block,
+        child_block,
+        child,
+        parent,
+        parent_deprecated,
+    })
+}
 
-    let mut bad_ledgers = vec![];
-    let mut none_ledgers = vec![];
-    let mut inconsistent_ledgers = vec![];
-    let mut ok_ledgers = vec![];
+/// Simulates the migration of all the stashes bonded to another account with the
+/// `deprecate_controller_batch` call of the runtime (see [`deprecate_controllers`]), and reports
+/// the consistency of the ledgers before and after it.
+///
+/// The runtime skips the controllers which are their stash or have no ledger, and the stashes
+/// whose rewards are paid to their controller: these are reported as skipped, and the stashes
+/// bonded to themselves after the migration as migrated. The stashes whose ledger is consistent
+/// before the migration but not after are reported as bricked. The migration is reverted once
+/// reported.
+pub(crate) fn controller_deprecation<T>(
+    mut ext: Ext,
+) -> Result<MigrationReport<AccountIdOf<T>, BlockNumberFor<T>>, anyhow::Error>
+where
+    T: EPM::Config + Config + pallet_balances::Config,
+    Staking::BalanceOf<T>: Into<u128>,
+    <T as pallet_balances::Config>::RuntimeHoldReason: From<Staking::HoldReason>,
+    <T as frame_system::Config>::RuntimeCall: From<Staking::Call<T>>,
+{
+    let block = block_number::<T>(&mut ext);
 
-    // 1. process child first to obtain the faulty ledgers and generate report.
-    let bn = block_number::<T>(&mut ext_child);
-    ext_child.execute_with(|| {
-        log::info!(target: LOG_TARGET, " ------ Running logic for child block #{:?}..", bn);
+    let (before, affected, controllers, skipped_payee, skipped_ledger) = ext.execute_with(|| {
+        let before = ledger_report::<T>();
 
-        let ledgers = Ledger::<T>::iter().count();
-        let bonded = Bonded::<T>::iter().count();
-        let payees = Payee::<T>::iter().count();
+        let controllers = Bonded::<T>::iter()
+            .filter(|(stash, controller)| stash != controller)
+            .map(|(_, controller)| controller)
+            .collect::<BTreeSet<_>>();
+        let affected = Bonded::<T>::iter()
+            .filter(|(stash, controller)| stash != controller)
+            .count() as u32;
 
-        log::info!(
-            target: LOG_TARGET,
-            "#ledgers: {}, #bonded: {}, #payees: {}",
-            ledgers, bonded, payees,
-        );
+        let (mut skipped_payee, mut skipped_ledger) = (vec![], vec![]);
+        for controller in controllers.iter().cloned() {
+            let ledger = match <Staking::Pallet<T>>::ledger(StakingAccount::Controller(
+                controller.clone(),
+            )) {
+                Ok(ledger) if ledger.stash != controller => ledger,
+                _ => {
+                    skipped_ledger.extend(
+                        Bonded::<T>::iter()
+                            .filter(|(stash, c)| *c == controller && *stash != controller)
+                            .map(|(stash, _)| stash),
+                    );
+                    continue;
+                }
+            };
 
-        // make sure #s are the same.
-        if ledgers != payees || ledgers != bonded {
-            log::error!(
-                target: LOG_TARGET,
-                "#s out of sync: #ledgers: {}, #bonded: {}, #payees: {}",
-                ledgers, bonded, payees,
-            );
+            #[allow(deprecated)]
+            if Payee::<T>::get(&ledger.stash) == Some(Staking::RewardDestination::Controller) {
+                skipped_payee.push(ledger.stash);
+            }
         }
 
-        bad_ledgers = ledger_checks::<T>();
-        (none_ledgers, inconsistent_ledgers, ok_ledgers) = bonded_checks::<T>();
+        (before, affected, controllers, skipped_payee, skipped_ledger)
     });
 
-    log::warn!(
-        target: LOG_TARGET,
-        " Report: # none_ledgers: {}; #consistent_ledgers {}, #bad_ledgers: {}",
-        none_ledgers.len(),
-        inconsistent_ledgers.len(),
-        bad_ledgers.len(),
-    );
+    Ok(simulate(&mut ext, |ext| {
+        ext.execute_with(|| {
+            let stashes = Bonded::<T>::iter()
+                .filter(|(stash, controller)| stash != controller)
+                .map(|(stash, _)| stash)
+                .collect::<Vec<_>>();
+            deprecate_controllers::<T>(controllers.into_iter().collect());
+            let migrated = stashes
+                .into_iter()
+                .filter(|stash| Bonded::<T>::get(stash).as_ref() == Some(stash))
+                .collect::<Vec<_>>();
 
-    // 2, check parent block state of faulty ledgers.
-    let bn = block_number::<T>(&mut ext_parent);
-    ext_parent.execute_with(|| {
-        log::info!(target: LOG_TARGET, " ------ Running logic for parent block #{:?}..", bn);
+            let after = ledger_report::<T>();
+            let bricked = after.newly_faulty(&before);
+            for stash in bricked.iter() {
+                crate::warnings::warn(
+                    "bricked_stash",
+                    format!(
+                        "the deprecation of the controllers bricks stash {:?}",
+                        stash
+                    ),
+                );
+            }
 
-        log::info!(
-            target: LOG_TARGET,
-            "#ledgers: {}, #bonded: {}, #payees: {}",
-            Ledger::<T>::iter().count(),
-            Bonded::<T>::iter().count(),
-            Payee::<T>::iter().count(),
-        );
+            MigrationReport {
+                block,
+                affected,
+                migrated,
+                skipped_payee,
+                skipped_ledger,
+                before,
+                after,
+                bricked,
+            }
+        })
+    }))
+}
 
-        // check if size of none_ledgers is the same as iterating over all staking ledgers and
-        // check if their bonded stash is the same as the ledger stash.
-        let inconsistent_ledgers = ledger_checks::<T>();
-        log::warn!(
-            target: LOG_TARGET,
-            " Report: none_ledger: {:?}, inconsistent_ledgers: {:?}, ok_ledgers: {:?}, total_ledgers: {:?}",
-            none_ledgers.len(),
-            inconsistent_ledgers.len(),
-            ok_ledgers.len(),
-            Ledger::<T>::iter().count(),
-        );
+/// Dispatches the `deprecate_controller_batch` call of the runtime for the `controllers`, in
+/// batches of at most `MaxControllersInDeprecationBatch` controllers. The call is dispatched with
+/// the root origin, which the admin origin of the staking pallet of the runtimes accepts. A batch
+/// which fails is reverted by the runtime and raises a warning. Must be called within the
+/// externalities, e.g. of a simulation.
+fn deprecate_controllers<T>(controllers: Vec<AccountIdOf<T>>)
+where
+    T: Config,
+    <T as frame_system::Config>::RuntimeCall: From<Staking::Call<T>>,
+{
+    use sp_runtime::traits::Dispatchable;
+
+    let max_batch = <T as Config>::MaxControllersInDeprecationBatch::get().max(1) as usize;
+    for batch in controllers.chunks(max_batch) {
+        let call: <T as frame_system::Config>::RuntimeCall =
+            Staking::Call::<T>::deprecate_controller_batch {
+                controllers: batch
+                    .to_vec()
+                    .try_into()
+                    .expect("the batch is within the bound; qed."),
+            }
+            .into();
 
-        // -- simulate deprecate_controller of faulty ledgers.
-        deprecate_controller_simulation::<T>(none_ledgers);
+        if let Err(e) = call.dispatch(frame_system::RawOrigin::Root.into()) {
+            crate::warnings::warn(
+                "failed_deprecation",
+                format!(
+                    "the deprecation of a batch of {} controllers failed: {:?}",
+                    batch.len(),
+                    e.error
+                ),
+            );
+        }
+    }
+}
 
-        let ledgers = Ledger::<T>::iter().count();
-        let bonded = Bonded::<T>::iter().count();
-        let payees = Payee::<T>::iter().count();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        log::info!(
-            target: LOG_TARGET,
-            "After deprecate: #ledgers: {}, #bonded: {}, #payees: {}",
-            ledgers, bonded, payees,
-        );
-    });
+    #[test]
+    fn lock_mismatch_works() {
+        assert_eq!(LockMismatch::of(100, None), Some(LockMismatch::Missing));
+        assert_eq!(LockMismatch::of(100, Some(90)), Some(LockMismatch::Smaller));
+        assert_eq!(LockMismatch::of(100, Some(110)), Some(LockMismatch::Larger));
+        assert_eq!(LockMismatch::of(100, Some(100)), None);
+        assert_eq!(LockMismatch::of(0, Some(0)), None);
+    }
 
-    Ok(())
-}
+    #[test]
+    fn newly_faulty_works() {
+        let before = LedgerReport {
+            bad_ledgers: vec![1],
+            none_ledgers: vec![(2, 20)],
+            ..Default::default()
+        };
+        let after = LedgerReport {
+            bad_ledgers: vec![1],
+            none_ledgers: vec![(3, 30)],
+            inconsistent_ledgers: vec![(4, 40), (2, 20)],
+            ..Default::default()
+        };
 
-fn deprecate_controller_simulation<T: Config>(batch: Vec<(AccountIdOf<T>, AccountIdOf<T>)>) {
-    for (stash, controller) in batch {
-        let ledger = <Staking::Pallet<T>>::ledger(StakingAccount::Controller(controller.clone()))
-            .expect("ledger should exist for controller");
-        let ledger_stash = ledger.stash.clone();
+        assert_eq!(after.faulty_stashes(), BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(after.newly_faulty(&before), vec![3, 4]);
+        assert!(before.newly_faulty(&before).is_empty());
+    }
 
-        if stash != ledger_stash {
-            log::warn!(target: LOG_TARGET,
-                "ledger stash != stash in batch {:?} {:?}",
-                ledger_stash,
-                stash,
-            );
-        }
+    #[test]
+    fn payee_mismatches_work() {
+        // stash 1 is bonded to controller 10, 2 to itself and 3 to itself, without a payee.
+        let bonded = BTreeMap::from([(1, 10), (2, 2), (3, 3)]);
+        // 1 and 2 are paid to their controller, 4 is not bonded.
+        let payees = BTreeMap::from([(1, true), (2, true), (4, false)]);
 
-        Bonded::<T>::insert(&stash, &stash);
-        Ledger::<T>::remove(controller);
-        Ledger::<T>::insert(stash, ledger);
+        assert_eq!(
+            PayeeMismatches::of(&bonded, &payees),
+            PayeeMismatches {
+                missing: vec![3],
+                dangling: vec![4],
+                deprecated_controller: vec![2],
+            }
+        );
     }
 }
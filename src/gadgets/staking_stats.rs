@@ -0,0 +1,697 @@
+//! Gadgets to compute statistics over the staking state of an externalities.
+//!
+//! The statistics are cheap to compute when compared to the election gadgets, since they only
+//! iterate over the staking storage, and are meant to be collected per block over long ranges.
+
+use crate::prelude::*;
+
+use frame_support::traits::Get;
+use sp_runtime::{Perbill, SaturatedConversion};
+use std::collections::BTreeMap;
+use Staking::NominationsQuota;
+
+/// The number of accounts and their active stake.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Stakers {
+    pub count: u32,
+    pub stake: u128,
+}
+
+impl Stakers {
+    fn add(&mut self, stake: u128) {
+        self.count += 1;
+        self.stake = self.stake.saturating_add(stake);
+    }
+}
+
+/// The reward destinations chosen by the bonded accounts.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct PayeeDistribution {
+    pub staked: Stakers,
+    pub stash: Stakers,
+    pub controller: Stakers,
+    pub account: Stakers,
+    /// Accounts with the `None` reward destination or without a reward destination set.
+    pub none: Stakers,
+}
+
+/// Returns the active stake of a stash, if bonded.
+pub(crate) fn active_stake<T: Staking::Config>(stash: &AccountIdOf<T>) -> Option<u128> {
+    Staking::Bonded::<T>::get(stash)
+        .and_then(|controller| Staking::Ledger::<T>::get(&controller))
+        .map(|ledger| ledger.active.saturated_into::<u128>())
+}
+
+/// Computes the distribution of the reward destinations of all the bonded accounts, weighted by
+/// their active stake.
+pub(crate) fn payee_distribution<T: Staking::Config>(ext: &mut Ext) -> PayeeDistribution {
+    ext.execute_with(|| {
+        let mut distribution = PayeeDistribution::default();
+
+        for stash in Staking::Bonded::<T>::iter_keys() {
+            let stake = active_stake::<T>(&stash).unwrap_or_default();
+
+            #[allow(deprecated)]
+            let stakers = match Staking::Payee::<T>::get(&stash) {
+                Some(Staking::RewardDestination::Staked) => &mut distribution.staked,
+                Some(Staking::RewardDestination::Stash) => &mut distribution.stash,
+                Some(Staking::RewardDestination::Controller) => &mut distribution.controller,
+                Some(Staking::RewardDestination::Account(_)) => &mut distribution.account,
+                Some(Staking::RewardDestination::None) | None => &mut distribution.none,
+            };
+            stakers.add(stake);
+        }
+
+        distribution
+    })
+}
+
+/// The distribution of the number of targets per nominator.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct NominationsDistribution {
+    pub nominators: u32,
+    pub max_nominations: u32,
+    /// Nominators with `max_nominations` targets.
+    pub at_cap: u32,
+    /// Nominators with as many targets as allowed by the nominations quota of their stake.
+    pub at_quota: u32,
+    pub avg_targets: f64,
+    pub stake_weighted_avg_targets: f64,
+    /// The nominators and their stake, per number of targets.
+    pub histogram: BTreeMap<u32, Stakers>,
+}
+
+impl NominationsDistribution {
+    /// Computes the distribution from the `(targets, stake, quota)` of each nominator.
+    pub fn from_nominators(
+        nominators: impl IntoIterator<Item = (u32, u128, u32)>,
+        max_nominations: u32,
+    ) -> Self {
+        let mut distribution = Self {
+            max_nominations,
+            ..Default::default()
+        };
+        let (mut total_targets, mut weighted_targets, mut total_stake) = (0f64, 0f64, 0f64);
+
+        for (targets, stake, quota) in nominators {
+            distribution.nominators += 1;
+            distribution.at_cap += (targets >= max_nominations) as u32;
+            distribution.at_quota += (targets >= quota) as u32;
+            distribution
+                .histogram
+                .entry(targets)
+                .or_default()
+                .add(stake);
+
+            total_targets += targets as f64;
+            weighted_targets += targets as f64 * stake as f64;
+            total_stake += stake as f64;
+        }
+
+        if distribution.nominators > 0 {
+            distribution.avg_targets = total_targets / distribution.nominators as f64;
+        }
+        if total_stake > 0.0 {
+            distribution.stake_weighted_avg_targets = weighted_targets / total_stake;
+        }
+
+        distribution
+    }
+}
+
+/// Computes the distribution of the number of targets per nominator.
+pub(crate) fn nominations_distribution<T: Staking::Config>(
+    ext: &mut Ext,
+) -> NominationsDistribution {
+    ext.execute_with(|| {
+        let nominators = Staking::Nominators::<T>::iter()
+            .map(|(nominator, nominations)| {
+                let stake = Staking::Bonded::<T>::get(&nominator)
+                    .and_then(|controller| Staking::Ledger::<T>::get(&controller))
+                    .map(|ledger| ledger.active)
+                    .unwrap_or_default();
+                let quota =
+                    <T::NominationsQuota as NominationsQuota<Staking::BalanceOf<T>>>::get_quota(
+                        stake,
+                    );
+
+                (
+                    nominations.targets.len() as u32,
+                    stake.saturated_into::<u128>(),
+                    quota,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        NominationsDistribution::from_nominators(
+            nominators,
+            <Staking::MaxNominationsOf<T> as Get<u32>>::get(),
+        )
+    })
+}
+
+/// A bucket of the voter distribution, e.g. a decile of the voters by stake.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VoterBucket {
+    /// The kind of the bucket: `decile`, `votes` (voters per number of votes) or `role`
+    /// (nominators and validators, i.e. their self-stake).
+    pub kind: &'static str,
+    pub bucket: String,
+    pub voters: Stakers,
+    pub min_stake: u128,
+    pub max_stake: u128,
+}
+
+/// The distribution of the stake of the voters of an election.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct VoterDistribution {
+    pub voters: Stakers,
+    pub mean_stake: f64,
+    pub median_stake: f64,
+    /// The Gini coefficient of the stakes, from 0 (equal stakes) to 1.
+    pub gini: f64,
+    pub buckets: Vec<VoterBucket>,
+}
+
+impl VoterDistribution {
+    /// Computes the distribution of the `voters`, given as `(stake, votes, is validator)`.
+    pub fn from_voters(mut voters: Vec<(u128, u32, bool)>) -> Self {
+        voters.sort_by_key(|(stake, _, _)| *stake);
+
+        let mut distribution = VoterDistribution::default();
+        if voters.is_empty() {
+            return distribution;
+        }
+
+        let bucket = |kind, bucket: String, voters: &[(u128, u32, bool)]| {
+            let mut bucket = VoterBucket {
+                kind,
+                bucket,
+                voters: Stakers::default(),
+                min_stake: u128::MAX,
+                max_stake: 0,
+            };
+            for (stake, _, _) in voters {
+                bucket.voters.add(*stake);
+                bucket.min_stake = bucket.min_stake.min(*stake);
+                bucket.max_stake = bucket.max_stake.max(*stake);
+            }
+            bucket
+        };
+
+        let n = voters.len();
+        for decile in 1..=10 {
+            let range = n * (decile - 1) / 10..n * decile / 10;
+            if !range.is_empty() {
+                distribution
+                    .buckets
+                    .push(bucket("decile", decile.to_string(), &voters[range]));
+            }
+        }
+
+        let mut by_votes = BTreeMap::<u32, Vec<_>>::new();
+        let mut by_role = BTreeMap::<&str, Vec<_>>::new();
+        for voter in &voters {
+            by_votes.entry(voter.1).or_default().push(*voter);
+            let role = if voter.2 { "validator" } else { "nominator" };
+            by_role.entry(role).or_default().push(*voter);
+        }
+        for (votes, voters) in by_votes {
+            distribution
+                .buckets
+                .push(bucket("votes", votes.to_string(), &voters));
+        }
+        for (role, voters) in by_role {
+            distribution
+                .buckets
+                .push(bucket("role", role.to_string(), &voters));
+        }
+
+        let stakes = voters
+            .iter()
+            .map(|(stake, _, _)| *stake as f64)
+            .collect::<Vec<_>>();
+        let total = stakes.iter().sum::<f64>();
+
+        for (stake, _, _) in &voters {
+            distribution.voters.add(*stake);
+        }
+        distribution.mean_stake = total / n as f64;
+        distribution.median_stake = match n % 2 {
+            0 => (stakes[n / 2 - 1] + stakes[n / 2]) / 2.0,
+            _ => stakes[n / 2],
+        };
+        if total > 0.0 {
+            // over the ascending stakes, `G = 2 * sum(i * x_i) / (n * sum(x_i)) - (n + 1) / n`.
+            let weighted = stakes
+                .iter()
+                .enumerate()
+                .map(|(i, stake)| (i + 1) as f64 * stake)
+                .sum::<f64>();
+            distribution.gini = 2.0 * weighted / (n as f64 * total) - (n as f64 + 1.0) / n as f64;
+        }
+
+        distribution
+    }
+}
+
+/// Computes the distribution of the voters of the election snapshot, which must exist.
+///
+/// The validators are the voters of the snapshot with a validator intention, whose stake is their
+/// self-stake.
+pub(crate) fn voter_distribution<T: EPM::Config + Staking::Config>(
+    ext: &mut Ext,
+) -> Result<VoterDistribution, anyhow::Error> {
+    ext.execute_with(|| {
+        let snapshot =
+            EPM::Snapshot::<T>::get().ok_or(crate::TimetravelError::missing("Snapshot"))?;
+
+        let voters = snapshot
+            .voters
+            .into_iter()
+            .map(|(who, stake, votes)| {
+                (
+                    stake as u128,
+                    votes.len() as u32,
+                    Staking::Validators::<T>::contains_key(&who),
+                )
+            })
+            .collect();
+
+        Ok(VoterDistribution::from_voters(voters))
+    })
+}
+
+/// Statistics of the validator commissions, given a (hypothetical) minimum commission.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct CommissionStats {
+    pub validators: u32,
+    pub avg_commission: f64,
+    pub median_commission: f64,
+    pub min_commission: f64,
+    /// Validators with a commission lower than `min_commission`.
+    pub below_min: u32,
+    /// The average increase of commission of the validators below `min_commission`, if they were
+    /// bumped to it.
+    pub avg_increase: f64,
+    /// The fraction of the era payout moving from nominators to validators if the validators below
+    /// `min_commission` were bumped to it, assuming equal era points per validator.
+    pub payout_shift: f64,
+}
+
+impl CommissionStats {
+    /// Computes the statistics of `commissions` given `min_commission`. All commissions are
+    /// fractions in `[0, 1]`.
+    pub fn new(commissions: &[f64], min_commission: f64) -> Self {
+        let mut stats = Self {
+            validators: commissions.len() as u32,
+            min_commission,
+            ..Default::default()
+        };
+        if commissions.is_empty() {
+            return stats;
+        }
+
+        let mut sorted = commissions.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("commissions are not NaN; qed."));
+        let mid = sorted.len() / 2;
+        stats.median_commission = if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+        stats.avg_commission = sorted.iter().sum::<f64>() / sorted.len() as f64;
+
+        let increases = sorted
+            .iter()
+            .filter(|c| **c < min_commission)
+            .map(|c| min_commission - c)
+            .collect::<Vec<_>>();
+        stats.below_min = increases.len() as u32;
+        if !increases.is_empty() {
+            stats.avg_increase = increases.iter().sum::<f64>() / increases.len() as f64;
+        }
+        stats.payout_shift = increases.iter().sum::<f64>() / sorted.len() as f64;
+
+        stats
+    }
+}
+
+/// Returns the commission of all the validators and the `MinCommission`, as fractions.
+pub(crate) fn validator_commissions<T: Staking::Config>(ext: &mut Ext) -> (Vec<f64>, f64) {
+    let to_fraction = |p: Perbill| p.deconstruct() as f64 / Perbill::ACCURACY as f64;
+
+    ext.execute_with(|| {
+        (
+            Staking::Validators::<T>::iter_values()
+                .map(|prefs| to_fraction(prefs.commission))
+                .collect(),
+            to_fraction(Staking::MinCommission::<T>::get()),
+        )
+    })
+}
+
+/// The approval stake of a validator, i.e. its self stake and the full stake of its nominators.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ApprovalStake {
+    pub self_stake: u128,
+    pub nominators: u32,
+    pub approval_stake: u128,
+}
+
+/// Computes the approval stake of all the validators.
+///
+/// The approval stake is an upper bound of the backing that a validator may get in an election,
+/// since the stake of the nominators is split among all their elected targets.
+pub(crate) fn approval_stakes<T: Staking::Config>(
+    ext: &mut Ext,
+) -> BTreeMap<AccountIdOf<T>, ApprovalStake> {
+    ext.execute_with(|| {
+        let mut approvals = Staking::Validators::<T>::iter_keys()
+            .map(|validator| {
+                let self_stake = active_stake::<T>(&validator).unwrap_or_default();
+                let approval = ApprovalStake {
+                    self_stake,
+                    nominators: 0,
+                    approval_stake: self_stake,
+                };
+                (validator, approval)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for (nominator, nominations) in Staking::Nominators::<T>::iter() {
+            let stake = active_stake::<T>(&nominator).unwrap_or_default();
+            for target in nominations.targets.iter() {
+                if let Some(approval) = approvals.get_mut(target) {
+                    approval.nominators += 1;
+                    approval.approval_stake = approval.approval_stake.saturating_add(stake);
+                }
+            }
+        }
+
+        approvals
+    })
+}
+
+/// A summary of the staking state, cheap enough to be computed for every block of a long range.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct StakingSummary {
+    pub era: u32,
+    /// The total stake backing the active era.
+    pub total_staked: u128,
+    /// The total issuance, if the key was extracted into the externalities.
+    pub total_issuance: Option<u128>,
+    pub validators: u32,
+    pub nominators: u32,
+    pub active_validators: u32,
+    pub avg_backing: u128,
+    pub median_backing: u128,
+}
+
+impl StakingSummary {
+    /// The ratio of the total issuance which is staked, if the total issuance is known.
+    pub fn staking_rate(&self) -> Option<f64> {
+        self.total_issuance
+            .filter(|issuance| *issuance > 0)
+            .map(|issuance| self.total_staked as f64 / issuance as f64)
+    }
+}
+
+/// Returns the total issuance of the balances pallet, if the key exists in the externalities.
+pub(crate) fn total_issuance(ext: &mut Ext) -> Option<u128> {
+    use codec::Decode;
+    use sp_core::hashing::twox_128;
+
+    ext.execute_with(|| {
+        sp_io::storage::get(&[twox_128(b"Balances"), twox_128(b"TotalIssuance")].concat())
+            .and_then(|raw| u128::decode(&mut &raw[..]).ok())
+    })
+}
+
+/// Computes the staking summary at the active era, given the backing of the active validators.
+pub(crate) fn staking_summary<T: Staking::Config>(
+    ext: &mut Ext,
+    era: u32,
+    mut backings: Vec<u128>,
+) -> StakingSummary {
+    let total_issuance = total_issuance(ext);
+
+    ext.execute_with(|| {
+        backings.sort();
+        let active_validators = backings.len() as u32;
+
+        StakingSummary {
+            era,
+            total_staked: Staking::ErasTotalStake::<T>::get(era).saturated_into::<u128>(),
+            total_issuance,
+            validators: Staking::Validators::<T>::count(),
+            nominators: Staking::Nominators::<T>::count(),
+            active_validators,
+            avg_backing: backings
+                .iter()
+                .sum::<u128>()
+                .checked_div(active_validators as u128)
+                .unwrap_or_default(),
+            median_backing: backings
+                .get(backings.len() / 2)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    })
+}
+
+/// The performance of an elected validator in an era.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ValidatorPerformance {
+    pub points: u32,
+    /// The era points relative to the highest era points of the era, in `[0, 1]`.
+    pub relative_points: f64,
+    pub commission: f64,
+    /// The fraction of the stake slashed in the era, if any.
+    pub slash: f64,
+}
+
+/// Returns the performance of each validator elected in `era`, as per the era exposures.
+pub(crate) fn validator_performances<T: Staking::Config>(
+    ext: &mut Ext,
+    era: sp_staking::EraIndex,
+) -> BTreeMap<AccountIdOf<T>, ValidatorPerformance>
+where
+    Staking::BalanceOf<T>: Into<u128>,
+{
+    let to_fraction = |p: Perbill| p.deconstruct() as f64 / Perbill::ACCURACY as f64;
+    let elected = crate::gadgets::era_exposures::<T>(ext, era);
+
+    ext.execute_with(|| {
+        let points = Staking::ErasRewardPoints::<T>::get(era).individual;
+        let max_points = points.values().max().cloned().unwrap_or_default();
+
+        elected
+            .into_iter()
+            .map(|(validator, _)| {
+                let validator_points = points.get(&validator).cloned().unwrap_or_default();
+                let performance = ValidatorPerformance {
+                    points: validator_points,
+                    relative_points: match max_points {
+                        0 => 0.0,
+                        max => validator_points as f64 / max as f64,
+                    },
+                    commission: to_fraction(
+                        Staking::ErasValidatorPrefs::<T>::get(era, &validator).commission,
+                    ),
+                    slash: Staking::ValidatorSlashInEra::<T>::get(era, &validator)
+                        .map_or(0.0, |(fraction, _)| to_fraction(fraction)),
+                };
+                (validator, performance)
+            })
+            .collect()
+    })
+}
+
+/// The weights of the components of the validator score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ScoreWeights {
+    pub points: f64,
+    pub commission: f64,
+    pub slash: f64,
+    pub uptime: f64,
+}
+
+impl ScoreWeights {
+    /// Computes the score of a validator in `[0, 1]`, as the weighted average of its relative era
+    /// points, one minus its commission, one minus its slashed fraction and its `uptime` (the
+    /// fraction of the eras in which it was elected).
+    pub fn score(&self, performance: &ValidatorPerformance, uptime: f64) -> f64 {
+        let total = self.points + self.commission + self.slash + self.uptime;
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        (self.points * performance.relative_points
+            + self.commission * (1.0 - performance.commission)
+            + self.slash * (1.0 - performance.slash)
+            + self.uptime * uptime)
+            / total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nominations_distribution_works() {
+        let distribution = NominationsDistribution::from_nominators(
+            vec![(16, 100, 16), (16, 300, 16), (4, 100, 16), (8, 0, 8)],
+            16,
+        );
+
+        assert_eq!(distribution.nominators, 4);
+        assert_eq!(distribution.at_cap, 2);
+        assert_eq!(distribution.at_quota, 3);
+        assert_eq!(distribution.avg_targets, 11.0);
+        // (16 * 100 + 16 * 300 + 4 * 100) / 500.
+        assert_eq!(distribution.stake_weighted_avg_targets, 13.6);
+        assert_eq!(
+            distribution.histogram.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    4,
+                    Stakers {
+                        count: 1,
+                        stake: 100
+                    }
+                ),
+                (8, Stakers { count: 1, stake: 0 }),
+                (
+                    16,
+                    Stakers {
+                        count: 2,
+                        stake: 400
+                    }
+                ),
+            ]
+        );
+
+        assert_eq!(
+            NominationsDistribution::from_nominators(vec![], 16),
+            NominationsDistribution {
+                max_nominations: 16,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn voter_distribution_works() {
+        let distribution = VoterDistribution::from_voters(vec![
+            (40, 16, false),
+            (10, 1, true),
+            (30, 16, false),
+            (20, 2, false),
+        ]);
+
+        assert_eq!(
+            distribution.voters,
+            Stakers {
+                count: 4,
+                stake: 100
+            }
+        );
+        assert_eq!(distribution.mean_stake, 25.0);
+        assert_eq!(distribution.median_stake, 25.0);
+        // 2 * (10 + 40 + 90 + 160) / (4 * 100) - 5 / 4.
+        assert_eq!(distribution.gini, 0.25);
+
+        let buckets = |kind| {
+            distribution
+                .buckets
+                .iter()
+                .filter(|b| b.kind == kind)
+                .map(|b| (b.bucket.as_str(), b.voters.count, b.voters.stake))
+                .collect::<Vec<_>>()
+        };
+        // with 4 voters, the deciles 3, 5, 8 and 10 have a voter each.
+        assert_eq!(
+            buckets("decile"),
+            vec![("3", 1, 10), ("5", 1, 20), ("8", 1, 30), ("10", 1, 40)]
+        );
+        assert_eq!(
+            buckets("votes"),
+            vec![("1", 1, 10), ("2", 1, 20), ("16", 2, 70)]
+        );
+        assert_eq!(
+            buckets("role"),
+            vec![("nominator", 3, 90), ("validator", 1, 10)]
+        );
+
+        assert_eq!(
+            VoterDistribution::from_voters(vec![]),
+            VoterDistribution::default()
+        );
+    }
+
+    #[test]
+    fn commission_stats_works() {
+        let commissions = vec![0.0, 0.01, 0.05, 0.1, 1.0];
+
+        let stats = CommissionStats::new(&commissions, 0.05);
+        assert_eq!(stats.validators, 5);
+        assert_eq!(stats.median_commission, 0.05);
+        assert_eq!(stats.avg_commission, 1.16 / 5.0);
+        assert_eq!(stats.below_min, 2);
+        assert_eq!(stats.avg_increase, (0.05 + 0.04) / 2.0);
+        assert_eq!(stats.payout_shift, (0.05 + 0.04) / 5.0);
+
+        let stats = CommissionStats::new(&commissions[..4], 0.0);
+        assert_eq!(stats.median_commission, (0.01 + 0.05) / 2.0);
+        assert_eq!(stats.below_min, 0);
+        assert_eq!(stats.payout_shift, 0.0);
+
+        assert_eq!(
+            CommissionStats::new(&[], 0.1),
+            CommissionStats {
+                min_commission: 0.1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn score_works() {
+        let performance = ValidatorPerformance {
+            points: 80,
+            relative_points: 0.8,
+            commission: 0.1,
+            slash: 0.0,
+        };
+        let weights = ScoreWeights {
+            points: 1.0,
+            commission: 1.0,
+            slash: 1.0,
+            uptime: 1.0,
+        };
+
+        assert!((weights.score(&performance, 0.5) - (0.8 + 0.9 + 1.0 + 0.5) / 4.0).abs() < 1e-9);
+        assert_eq!(
+            ScoreWeights {
+                points: 1.0,
+                commission: 0.0,
+                slash: 0.0,
+                uptime: 0.0
+            }
+            .score(&performance, 0.5),
+            0.8
+        );
+        assert_eq!(
+            ScoreWeights {
+                points: 0.0,
+                commission: 0.0,
+                slash: 0.0,
+                uptime: 0.0
+            }
+            .score(&performance, 0.5),
+            0.0
+        );
+    }
+}
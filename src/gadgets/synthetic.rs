@@ -0,0 +1,195 @@
+//! Gadgets to populate an externalities with a synthetic electorate.
+//!
+//! The synthetic electorate replaces the validators, nominators and voter list of the staking
+//! pallet with accounts generated from a set of parameters, and recreates the election snapshot.
+//! The remaining state of the externalities (e.g. runtime configs, total issuance) is kept.
+//!
+//! Alternatively, an existing electorate can be scaled up by duplicating its nominators, to
+//! forecast how the election behaves as the chain grows.
+
+use crate::configs::StakeDistribution;
+use crate::prelude::*;
+use crate::utils::{sample_stake, SplitMix64};
+use crate::TimetravelError;
+
+use codec::{Decode, Encode};
+use frame_election_provider_support::SortedListProvider;
+use frame_support::BoundedVec;
+use sp_core::hashing::blake2_256;
+use sp_runtime::{traits::TrailingZeroInput, SaturatedConversion};
+use std::{collections::BTreeSet, ops::Range};
+use EPM::{SnapshotWrapper, SolutionOrSnapshotSize};
+
+/// Parameters of a synthetic electorate.
+#[derive(Debug, Clone)]
+pub(crate) struct ElectorateParams {
+    pub validators: u32,
+    pub nominators: u32,
+    /// Number of targets nominated by each nominator.
+    pub nominations: u32,
+    /// Distribution of the stake of validators and nominators.
+    pub distribution: StakeDistribution,
+    pub min_stake: u128,
+    pub max_stake: u128,
+    /// Number of winners of the election. If none, the validator count of the externalities is
+    /// kept.
+    pub desired_targets: Option<u32>,
+    /// Seed of the generator. The same parameters and seed always result in the same electorate.
+    pub seed: u64,
+}
+
+/// Replaces the electorate of `ext` by a synthetic one and creates the election snapshot. Returns
+/// the snapshot bounds and encoded size.
+pub(crate) fn populate_electorate<T>(
+    ext: &mut Ext,
+    params: &ElectorateParams,
+) -> Result<(SolutionOrSnapshotSize, usize), anyhow::Error>
+where
+    T: EPM::Config + Staking::Config,
+{
+    ext.execute_with(|| {
+        let _ = Staking::Validators::<T>::clear(u32::MAX, None);
+        let _ = Staking::Nominators::<T>::clear(u32::MAX, None);
+        let _ = Staking::Bonded::<T>::clear(u32::MAX, None);
+        let _ = Staking::Ledger::<T>::clear(u32::MAX, None);
+        let _ = Staking::Payee::<T>::clear(u32::MAX, None);
+        <T as Staking::Config>::VoterList::unsafe_clear();
+
+        let mut rng = SplitMix64::new(params.seed);
+
+        let account_of = |role: &[u8], i: u32| -> AccountIdOf<T> {
+            Decode::decode(&mut TrailingZeroInput::new(&blake2_256(
+                &(role, i).encode(),
+            )))
+            .expect("infinite input; qed.")
+        };
+        let bond = |who: &AccountIdOf<T>, stake: u128| {
+            let stake: Staking::BalanceOf<T> = stake.saturated_into();
+            Staking::Bonded::<T>::insert(who, who);
+            Staking::Ledger::<T>::insert(who, Staking::StakingLedger::<T>::new(who.clone(), stake));
+            Staking::Payee::<T>::insert(who, Staking::RewardDestination::Staked);
+        };
+        let weight_of = Staking::Pallet::<T>::weight_of_fn();
+
+        let validators = (0..params.validators)
+            .map(|i| account_of(b"validator", i))
+            .collect::<Vec<_>>();
+
+        for validator in validators.iter() {
+            bond(
+                validator,
+                sample_stake(
+                    &params.distribution,
+                    &mut rng,
+                    params.min_stake,
+                    params.max_stake,
+                ),
+            );
+            Staking::Validators::<T>::insert(validator, Staking::ValidatorPrefs::default());
+            <T as Staking::Config>::VoterList::on_insert(validator.clone(), weight_of(validator))
+                .map_err(|e| TimetravelError::failed("generating electorate", e))?;
+        }
+
+        let nominations = params.nominations.min(params.validators) as usize;
+        for i in 0..params.nominators {
+            let nominator = account_of(b"nominator", i);
+            bond(
+                &nominator,
+                sample_stake(
+                    &params.distribution,
+                    &mut rng,
+                    params.min_stake,
+                    params.max_stake,
+                ),
+            );
+
+            let mut targets = BTreeSet::new();
+            while targets.len() < nominations {
+                targets.insert(rng.below(params.validators as u64) as usize);
+            }
+
+            Staking::Nominators::<T>::insert(
+                &nominator,
+                Staking::Nominations {
+                    targets: BoundedVec::truncate_from(
+                        targets.into_iter().map(|t| validators[t].clone()).collect(),
+                    ),
+                    submitted_in: 0,
+                    suppressed: false,
+                },
+            );
+            <T as Staking::Config>::VoterList::on_insert(nominator.clone(), weight_of(&nominator))
+                .map_err(|e| TimetravelError::failed("generating electorate", e))?;
+        }
+
+        if let Some(desired_targets) = params.desired_targets {
+            Staking::ValidatorCount::<T>::put(desired_targets);
+        }
+
+        SnapshotWrapper::<T>::kill();
+        <EPM::Pallet<T>>::create_snapshot()
+            .map_err(|e| TimetravelError::failed("generating electorate", e))?;
+
+        Ok((
+            <EPM::SnapshotMetadata<T>>::get().expect("snapshot metadata should exist by now. qed."),
+            <EPM::Pallet<T>>::snapshot()
+                .expect("snapshot should exist by now. qed.")
+                .encode()
+                .len(),
+        ))
+    })
+}
+
+/// Duplicates each of the `nominators` once per copy index in `copies`. The duplicates nominate
+/// the same targets as the original nominator, with its active stake jittered by up to 10%.
+/// Returns the number of nominators added.
+pub(crate) fn duplicate_nominators<T: Staking::Config>(
+    ext: &mut Ext,
+    nominators: &[AccountIdOf<T>],
+    copies: Range<u32>,
+    seed: u64,
+) -> Result<u32, anyhow::Error> {
+    ext.execute_with(|| {
+        let mut rng = SplitMix64::new(seed ^ copies.start as u64);
+        let weight_of = Staking::Pallet::<T>::weight_of_fn();
+        let mut added = 0;
+
+        for nominator in nominators {
+            let (nominations, active) = match (
+                Staking::Nominators::<T>::get(nominator),
+                Staking::Bonded::<T>::get(nominator).and_then(|c| Staking::Ledger::<T>::get(&c)),
+            ) {
+                (Some(nominations), Some(ledger)) => {
+                    (nominations, ledger.active.saturated_into::<u128>())
+                }
+                _ => continue,
+            };
+
+            for copy in copies.clone() {
+                let duplicate: AccountIdOf<T> = Decode::decode(&mut TrailingZeroInput::new(
+                    &blake2_256(&(b"stress", nominator, copy).encode()),
+                ))
+                .expect("infinite input; qed.");
+                let stake: Staking::BalanceOf<T> =
+                    ((active as f64 * (0.9 + 0.2 * rng.next_f64())) as u128).saturated_into();
+
+                Staking::Bonded::<T>::insert(&duplicate, &duplicate);
+                Staking::Ledger::<T>::insert(
+                    &duplicate,
+                    Staking::StakingLedger::<T>::new(duplicate.clone(), stake),
+                );
+                Staking::Payee::<T>::insert(&duplicate, Staking::RewardDestination::Staked);
+                Staking::Nominators::<T>::insert(&duplicate, nominations.clone());
+                <T as Staking::Config>::VoterList::on_insert(
+                    duplicate.clone(),
+                    weight_of(&duplicate),
+                )
+                .map_err(|e| TimetravelError::failed("generating electorate", e))?;
+
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    })
+}
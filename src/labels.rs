@@ -0,0 +1,140 @@
+//! Account labels of the outputs.
+//!
+//! The `--labels` file maps accounts to human names (e.g. exchanges, foundation accounts or known
+//! validators). It is a CSV file with an `address` and a `label` column, where the addresses are
+//! SS58 encoded (in the format of any chain) or hex encoded public keys:
+//!
+//! ```csv
+//! address,label
+//! 13UVJyLnbVp9RBZYFwFGyDvVd1y27Tt8tkntv6Q7JVPhFsTB,Foundation
+//! ```
+//!
+//! When labels are set, every output record with an `account` column is joined with its label in
+//! an extra `account_label` column, empty for the accounts without a label. The other columns
+//! which hold accounts (see [`crate::output::ACCOUNT_COLUMNS`]) are joined with their labels in the
+//! same way, e.g. `author` in `author_label`, and the lists of accounts with the list of their
+//! labels.
+
+use crate::TimetravelError;
+
+use sp_core::crypto::{AccountId32, Ss58Codec};
+use std::collections::BTreeMap;
+
+/// The name of the column which holds the labels of the `account` column.
+pub(crate) const LABEL_COLUMN: &str = "account_label";
+
+/// The labels of the accounts, by public key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Labels(BTreeMap<[u8; 32], String>);
+
+impl Labels {
+    /// Reads the labels from the CSV file in `path`.
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let labels = std::fs::read_to_string(path).map_err(|e| TimetravelError::Unsupported {
+            reason: format!("could not read the labels in {}: {}", path, e),
+        })?;
+        Self::parse(&labels)
+    }
+
+    /// Parses the labels from a CSV with an `address` and a `label` column. An address labeled
+    /// more than once keeps its last label.
+    pub fn parse(csv: &str) -> Result<Self, anyhow::Error> {
+        let decode_error = |e: csv::Error| TimetravelError::Decode {
+            error: format!("labels: {}", e),
+        };
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers = reader.headers().map_err(decode_error)?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim() == name)
+                .ok_or_else(|| TimetravelError::Unsupported {
+                    reason: format!("the labels have no `{}` column.", name),
+                })
+        };
+        let (address, label) = (column("address")?, column("label")?);
+
+        let mut labels = BTreeMap::new();
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(decode_error)?;
+            let (Some(address), Some(label)) = (record.get(address), record.get(label)) else {
+                continue;
+            };
+            let account =
+                account_key(address.trim()).ok_or_else(|| TimetravelError::Unsupported {
+                    reason: format!(
+                        "invalid address {:?} in the labels (row {}).",
+                        address,
+                        line + 1
+                    ),
+                })?;
+            labels.insert(account, label.trim().to_string());
+        }
+
+        Ok(Labels(labels))
+    }
+
+    /// The label of an account, given as in the outputs.
+    pub fn label_of(&self, account: &str) -> Option<&str> {
+        account_key(account).and_then(|key| self.0.get(&key).map(String::as_str))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// The public key of an SS58 (of any format) or hex encoded account.
+fn account_key(account: &str) -> Option<[u8; 32]> {
+    if let Ok((account, _)) = AccountId32::from_ss58check_with_version(account) {
+        return Some(account.into());
+    }
+
+    let hex = account.strip_prefix("0x")?;
+    sp_core::bytes::from_hex(hex)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::crypto::Ss58AddressFormatRegistry;
+
+    #[test]
+    fn labels_work() {
+        let account = AccountId32::from([1u8; 32]);
+        let polkadot =
+            account.to_ss58check_with_version(Ss58AddressFormatRegistry::PolkadotAccount.into());
+        let kusama =
+            account.to_ss58check_with_version(Ss58AddressFormatRegistry::KusamaAccount.into());
+        let hex = format!("0x{}", "02".repeat(32));
+
+        let labels = Labels::parse(&format!(
+            "address,label,notes\n{},Foundation,\n{}, Exchange ,hot wallet\n",
+            polkadot, hex
+        ))
+        .unwrap();
+        assert_eq!(labels.len(), 2);
+
+        // the addresses match in any format.
+        assert_eq!(labels.label_of(&polkadot), Some("Foundation"));
+        assert_eq!(labels.label_of(&kusama), Some("Foundation"));
+        assert_eq!(
+            labels.label_of(&AccountId32::from([2u8; 32]).to_ss58check()),
+            Some("Exchange")
+        );
+        assert_eq!(
+            labels.label_of(&AccountId32::from([3u8; 32]).to_ss58check()),
+            None
+        );
+        assert_eq!(labels.label_of("not an account"), None);
+
+        assert!(Labels::parse("account,label\n").is_err());
+        assert!(Labels::parse("address,label\nnot an account,Foundation\n").is_err());
+    }
+}
@@ -43,7 +43,8 @@
 //! download the block storage keys from a remote node.
 //!
 //! The output of the operation is written in the for of a CSV file in the `output_path` (set by
-//! default as `./output.csv`).
+//! default as `./output/<chain>/<operation>/<YYYY-MM>.csv`, as per the `output_layout`). Other
+//! output formats can be selected with `--format`.
 //!
 //! For more information and configuration options, check `substrate-timetravel extract help`.
 //!
@@ -67,7 +68,8 @@
 //!  $ cargo build
 //!  $ RUST_LOG=info ./target/debug/substrate-timetravel transform --live --at=0x1477d54ad233824dd60afe1efc76413523c2737fd0cbabe2271568f75f560c74 min-active-stake --uri=wss://rpc.polkadot.io:443
 //! ````
-//! The result of the operation is saved in `./output.csv` in the form of
+//! The result of the operation is saved in `./output/polkadot/min-active-stake/<YYYY-MM>.csv` in
+//! the form of
 //!
 //! ```csv
 //! block_number,min_active_stake
@@ -84,48 +86,118 @@
 
 #![feature(async_closure)]
 
+mod budget;
+mod build_info;
 mod commands;
 mod configs;
+mod driver;
 mod gadgets;
+mod labels;
+mod manifest;
 mod operations;
+mod output;
 mod prelude;
 mod rpc;
+mod scraper;
+mod signing;
+mod trace;
 mod utils;
+mod warnings;
 
-use configs::{Command, Opt};
+use configs::Opt;
 use prelude::*;
 
 use clap::Parser;
 use jsonrpsee::ws_client::{WsClient, WsClientBuilder};
-use rpc::{RpcApiClient, SharedRpcClient};
-use serde::Serialize;
-use std::{ops::Deref, sync::Arc, time::Duration};
+use std::sync::Arc;
 use thiserror::Error;
 
-/// Errors of the CLI.
+/// Errors of the CLI, by class.
+///
+/// The commands return [`anyhow::Error`]s, which wrap a `TimetravelError` when raised by the
+/// crate. The class of any error is given by [`TimetravelError::class`].
 #[derive(Error, Debug)]
-pub(crate) enum Error {
-    #[error("Externalities error {error:?}")]
-    Externalities { error: String },
+pub(crate) enum TimetravelError {
+    /// A request to the remote node failed or returned an unexpected response.
+    #[error("RPC error {error:?}")]
+    Rpc { error: String },
+    /// The externalities (or the stored snapshot) could not be built or lack the required state.
+    #[error("Snapshot error {error:?}")]
+    Snapshot { error: String },
+    /// The (SCALE) decoding of a storage value or the metadata failed.
+    #[error("Decode error {error:?}")]
+    Decode { error: String },
+    /// An operation (or one of its gadgets) failed.
+    #[error("Operation {op} failed: {reason}")]
+    OperationFailed { op: String, reason: String },
+    /// The request is not supported, e.g. by the runtime or the metadata version at a block.
+    #[error("Unsupported: {reason}")]
+    Unsupported { reason: String },
+}
+
+impl TimetravelError {
+    /// Returns the class of an error, i.e. `rpc`, `snapshot`, `decode`, `operation`,
+    /// `unsupported` or `other`. Errors of the RPC client and the SCALE codec are classified as
+    /// `rpc` and `decode` errors, respectively.
+    pub fn class(error: &anyhow::Error) -> &'static str {
+        if let Some(error) = error.downcast_ref::<TimetravelError>() {
+            return match error {
+                TimetravelError::Rpc { .. } => "rpc",
+                TimetravelError::Snapshot { .. } => "snapshot",
+                TimetravelError::Decode { .. } => "decode",
+                TimetravelError::OperationFailed { .. } => "operation",
+                TimetravelError::Unsupported { .. } => "unsupported",
+            };
+        }
+
+        if error.downcast_ref::<jsonrpsee::core::Error>().is_some() {
+            "rpc"
+        } else if error.downcast_ref::<codec::Error>().is_some() {
+            "decode"
+        } else {
+            "other"
+        }
+    }
+
+    /// The error of a failed operation (or gadget) `op`.
+    pub fn failed(op: &str, reason: impl std::fmt::Debug) -> Self {
+        TimetravelError::OperationFailed {
+            op: op.to_string(),
+            reason: format!("{:?}", reason),
+        }
+    }
+
+    /// The error of a missing storage item of the externalities.
+    pub fn missing(item: &str) -> Self {
+        TimetravelError::Snapshot {
+            error: format!("{} did not exist.", item),
+        }
+    }
 }
 
 /// Selector for diferent runtimes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub(crate) enum AnyRuntime {
+    #[default]
     Polkadot,
     Kusama,
     Westend,
 }
 
-pub(crate) static mut RUNTIME: AnyRuntime = AnyRuntime::Polkadot;
+/// Settings of the externalities, set once from the CLI.
+pub(crate) static EXT_CONFIG: std::sync::OnceLock<configs::ExtConfig> = std::sync::OnceLock::new();
 
 macro_rules! construct_runtime_prelude {
     ($runtime:ident) => {
         paste::paste! {
         pub(crate) mod [<$runtime _runtime_exports>] {
             pub(crate) use crate::prelude::*;
-            pub(crate) use [<$runtime _runtime>]::{Block, Runtime};
+            pub(crate) use [<$runtime _runtime>]::{AllPalletsWithSystem, Block, Runtime};
             pub(crate) use crate::commands::[<extract_cmd_ $runtime>] as extract_cmd;
             pub(crate) use crate::commands::[<transform_cmd_ $runtime>] as transform_cmd;
+            pub(crate) use crate::commands::[<generate_cmd_ $runtime>] as generate_cmd;
+            pub(crate) use crate::commands::[<load_cmd_ $runtime>] as load_cmd;
+            pub(crate) use crate::commands::[<inspect_cmd_ $runtime>] as inspect_cmd;
         }}
     };
 }
@@ -136,9 +208,9 @@ construct_runtime_prelude!(westend);
 
 #[macro_export]
 macro_rules! any_runtime {
-	($($code:tt)*) => {
-		unsafe {
-			match $crate::RUNTIME {
+	($runtime:expr => $($code:tt)*) => {
+		{
+			match $runtime {
 				//$crate::AnyRuntime::Polkadot => {
 				//	#[allow(unused)]
 				// use $crate::polkadot_runtime_exports::*;
@@ -168,155 +240,15 @@ macro_rules! any_runtime {
 async fn main() {
     env_logger::init();
 
-    let Opt {
-        uri,
-        command,
-        connection_timeout,
-        request_timeout,
-        snapshot_path,
-        output_path,
-    } = Opt::parse();
-
-    let rpc = loop {
-        match SharedRpcClient::new(
-            &uri,
-            Duration::from_secs(connection_timeout as u64),
-            Duration::from_secs(request_timeout as u64),
-        )
-        .await
-        {
-            Ok(client) => break client,
-            Err(why) => {
-                log::warn!(
-                    target: LOG_TARGET,
-                    "failed to connect to client due to {:?}, retrying soon..",
-                    why
-                );
-                tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
-            }
-        }
+    let outcome = match driver::Driver::new(Opt::parse()) {
+        Ok(driver) => driver.run().await,
+        Err(e) => Err(e),
     };
 
-    let chain: String = rpc
-        .system_chain()
-        .await
-        .expect("system_chain infallible; qed.");
-    match chain.to_lowercase().as_str() {
-        "polkadot" | "development" => {
-            sp_core::crypto::set_default_ss58_version(
-                sp_core::crypto::Ss58AddressFormatRegistry::PolkadotAccount.into(),
-            );
-            sub_tokens::dynamic::set_name("DOT");
-            sub_tokens::dynamic::set_decimal_points(10_000_000_000);
-            // safety: this program will always be single threaded, thus accessing global static is
-            // safe.
-            unsafe {
-                RUNTIME = AnyRuntime::Polkadot;
-            }
-        }
-        "kusama" | "kusama-dev" => {
-            sp_core::crypto::set_default_ss58_version(
-                sp_core::crypto::Ss58AddressFormatRegistry::KusamaAccount.into(),
-            );
-            sub_tokens::dynamic::set_name("KSM");
-            sub_tokens::dynamic::set_decimal_points(1_000_000_000_000);
-            // safety: this program will always be single threaded, thus accessing global static is
-            // safe.
-            unsafe {
-                RUNTIME = AnyRuntime::Kusama;
-            }
-        }
-        "westend" => {
-            sp_core::crypto::set_default_ss58_version(
-                sp_core::crypto::Ss58AddressFormatRegistry::PolkadotAccount.into(),
-            );
-            sub_tokens::dynamic::set_name("WND");
-            sub_tokens::dynamic::set_decimal_points(1_000_000_000_000);
-            // safety: this program will always be single threaded, thus accessing global static is
-            // safe.
-            unsafe {
-                RUNTIME = AnyRuntime::Westend;
-            }
-        }
-        _ => {
-            eprintln!("unexpected chain: {:?}", chain);
-            return;
-        }
+    if let Err(e) = outcome {
+        log::error!(target: LOG_TARGET, "{:?}", e);
+        std::process::exit(1);
     }
-    log::info!(target: LOG_TARGET, "connected to chain {:?}", chain);
-
-    let outcome = any_runtime! {
-        match command {
-            Command::Extract(config) => {
-                let block_hashes = match config.bn {
-                    Some(bh) => bh,
-                    None => {
-                        log::error!(target: LOG_TARGET, "Config: expected a valid block hash (--at).");
-                        return;
-                    }
-                };
-                let file_paths = block_hashes.iter().map(|h| format!("{}/{}.data", snapshot_path, h)).collect::<Vec<_>>();
-
-                extract_cmd(rpc.uri().to_string(), config.pallets, block_hashes, file_paths, false).await
-                .map_err(|e| {
-                    log::error!(target: LOG_TARGET, "Extract error: {:?}", e);
-                }).unwrap();
-            },
-            Command::Transform(config) => {
-                let block_hashes = match config.bn {
-                    Some(hs) =>  {
-                        hs
-                    },
-                    None => {
-                        log::error!(target: LOG_TARGET, "Config: expected a valid block hash (--at).");
-                        return;
-                    }
-                };
-
-                let snapshot_paths = block_hashes.iter().map(|h| format!("{}/{}.data", snapshot_path, h)).collect::<Vec<_>>();
-
-                transform_cmd(
-                    rpc.uri().to_string(),
-                    config.operation,
-                    block_hashes,
-                    output_path,
-                    snapshot_paths,
-                    config.compute_unbounded,
-                    config.live
-                ).await
-                .map_err(|e| {
-                    log::error!(target: LOG_TARGET, "Transform error: {:?}", e);
-                }).unwrap();
-            },
-        };
-    };
-
-    log::info!(
-        target: LOG_TARGET,
-        "round of execution finished. outcome = {:?}",
-        outcome
-    );
-}
-
-pub(crate) fn write_csv<E: Serialize>(entry: E, output_path: &str) -> Result<(), anyhow::Error> {
-    let headers = if std::path::Path::new(output_path).exists() {
-        false
-    } else {
-        true
-    };
-    let csv = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(output_path)?;
-
-    let mut buffer = csv::WriterBuilder::new()
-        .has_headers(headers)
-        .from_writer(csv);
-    buffer.serialize(entry)?;
-    buffer.flush()?;
-
-    Ok(())
 }
 
 #[cfg(test)]
@@ -330,17 +262,27 @@ mod tests {
 
     #[test]
     fn any_runtime_works() {
-        unsafe {
-            RUNTIME = AnyRuntime::Polkadot;
-        }
-        let polkadot_version = any_runtime! { get_version::<Runtime>() };
-
-        unsafe {
-            RUNTIME = AnyRuntime::Kusama;
-        }
-        let kusama_version = any_runtime! { get_version::<Runtime>() };
+        let polkadot_version = any_runtime! { AnyRuntime::Polkadot => get_version::<Runtime>() };
+        let kusama_version = any_runtime! { AnyRuntime::Kusama => get_version::<Runtime>() };
 
         assert_eq!(polkadot_version.spec_name, "polkadot".into());
         assert_eq!(kusama_version.spec_name, "kusama".into());
     }
+
+    #[test]
+    fn error_class_works() {
+        let class = |e: anyhow::Error| TimetravelError::class(&e);
+
+        assert_eq!(
+            class(TimetravelError::missing("Snapshot").into()),
+            "snapshot"
+        );
+        assert_eq!(
+            class(TimetravelError::failed("mining solution", "too many winners").into()),
+            "operation"
+        );
+        assert_eq!(class(jsonrpsee::core::Error::RequestTimeout.into()), "rpc");
+        assert_eq!(class(codec::Error::from("invalid").into()), "decode");
+        assert_eq!(class(anyhow::anyhow!("other")), "other");
+    }
 }
@@ -0,0 +1,240 @@
+//! Job manifests to orchestrate multi-step studies.
+//!
+//! A manifest describes a DAG of jobs, each of them a `substrate-timetravel` command (e.g. an
+//! `extract` followed by a set of `transform`s over the extracted snapshots). The jobs run in
+//! dependency order and their completion is recorded in a state file next to the manifest, so that
+//! an interrupted run can be resumed without re-running the completed jobs.
+//!
+//! ```yaml
+//! args: ["--uri", "wss://rpc.polkadot.io:443"]
+//! jobs:
+//!   - name: extract
+//!     args: ["extract", "--bn", "0x.."]
+//!   - name: election-analysis
+//!     args: ["transform", "--bn", "0x..", "election-analysis"]
+//!     depends_on: ["extract"]
+//! ```
+
+use crate::prelude::*;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A manifest of jobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// Arguments prepended to the arguments of every job, e.g. the `--uri`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub jobs: Vec<Job>,
+}
+
+/// A job of a manifest, i.e. a CLI command and the jobs it depends on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Job {
+    pub name: String,
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+/// The state of a manifest run, i.e. the completed jobs and the hash of their arguments.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RunState {
+    pub completed: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Returns the jobs in an order which respects their dependencies. Independent jobs keep the
+    /// manifest order.
+    pub fn ordered_jobs(&self) -> Result<Vec<&Job>, anyhow::Error> {
+        let names = self
+            .jobs
+            .iter()
+            .map(|j| j.name.as_str())
+            .collect::<BTreeSet<_>>();
+        if names.len() != self.jobs.len() {
+            return Err(anyhow!("job names must be unique."));
+        }
+        for job in self.jobs.iter() {
+            if let Some(dep) = job.depends_on.iter().find(|d| !names.contains(d.as_str())) {
+                return Err(anyhow!("job {} depends on unknown job {}.", job.name, dep));
+            }
+        }
+
+        let mut ordered: Vec<&Job> = vec![];
+        let mut done = BTreeSet::new();
+        while ordered.len() < self.jobs.len() {
+            let ready = self
+                .jobs
+                .iter()
+                .filter(|j| !done.contains(j.name.as_str()))
+                .filter(|j| j.depends_on.iter().all(|d| done.contains(d.as_str())))
+                .collect::<Vec<_>>();
+
+            if ready.is_empty() {
+                return Err(anyhow!("the job dependencies have a cycle."));
+            }
+            for job in ready {
+                done.insert(job.name.as_str());
+                ordered.push(job);
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// The hash of the arguments of a job, used to invalidate the completed jobs whose arguments
+    /// changed since.
+    pub fn job_hash(&self, job: &Job) -> String {
+        let args = self
+            .args
+            .iter()
+            .chain(job.args.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        sp_core::bytes::to_hex(
+            &sp_core::hashing::blake2_128(args.join("\0").as_bytes()),
+            false,
+        )
+    }
+}
+
+/// Runs the jobs of the manifest in `manifest_path`, skipping the jobs completed in previous runs
+/// unless `force` is set. Each job runs as a child process of the current executable.
+///
+/// The jobs which depend on a failed job are skipped, and an error is returned once all the other
+/// jobs ran.
+pub(crate) async fn run_manifest(manifest_path: String, force: bool) -> Result<(), anyhow::Error> {
+    let manifest: Manifest = serde_yaml::from_str(&std::fs::read_to_string(&manifest_path)?)?;
+    let state_path = format!("{}.state", manifest_path);
+
+    let mut state: RunState = match std::fs::read_to_string(&state_path) {
+        Ok(state) if !force => serde_yaml::from_str(&state)?,
+        _ => Default::default(),
+    };
+
+    let exe = std::env::current_exe()?;
+    let mut failed = BTreeSet::new();
+
+    for job in manifest.ordered_jobs()? {
+        let hash = manifest.job_hash(job);
+
+        if state.completed.get(&job.name) == Some(&hash) {
+            log::info!(target: LOG_TARGET, "Job {} already completed, skipping.", job.name);
+            continue;
+        }
+        if let Some(dep) = job.depends_on.iter().find(|d| failed.contains(*d)) {
+            log::warn!(target: LOG_TARGET, "Job {} skipped, dependency {} failed.", job.name, dep);
+            failed.insert(job.name.clone());
+            continue;
+        }
+
+        log::info!(target: LOG_TARGET, "Job {} starting: {:?}.", job.name, job.args);
+        let status = tokio::process::Command::new(&exe)
+            .args(manifest.args.iter().chain(job.args.iter()))
+            .status()
+            .await?;
+
+        if status.success() {
+            state.completed.insert(job.name.clone(), hash);
+            std::fs::write(&state_path, serde_yaml::to_string(&state)?)?;
+            log::info!(target: LOG_TARGET, "Job {} completed.", job.name);
+        } else {
+            log::error!(target: LOG_TARGET, "Job {} failed with {}.", job.name, status);
+            state.completed.remove(&job.name);
+            failed.insert(job.name.clone());
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("jobs failed or skipped: {:?}", failed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str, depends_on: &[&str]) -> Job {
+        Job {
+            name: name.to_string(),
+            args: vec![name.to_string()],
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn ordered_jobs_works() {
+        let manifest = Manifest {
+            args: vec![],
+            jobs: vec![
+                job("report", &["analysis", "extract"]),
+                job("analysis", &["extract"]),
+                job("extract", &[]),
+                job("constants", &[]),
+            ],
+        };
+
+        let order = manifest
+            .ordered_jobs()
+            .unwrap()
+            .into_iter()
+            .map(|j| j.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["extract", "constants", "analysis", "report"]);
+
+        let cyclic = Manifest {
+            args: vec![],
+            jobs: vec![job("a", &["b"]), job("b", &["a"])],
+        };
+        assert!(cyclic.ordered_jobs().is_err());
+
+        let unknown = Manifest {
+            args: vec![],
+            jobs: vec![job("a", &["c"])],
+        };
+        assert!(unknown.ordered_jobs().is_err());
+
+        let duplicated = Manifest {
+            args: vec![],
+            jobs: vec![job("a", &[]), job("a", &[])],
+        };
+        assert!(duplicated.ordered_jobs().is_err());
+    }
+
+    #[test]
+    fn manifest_parsing_works() {
+        let manifest: Manifest = serde_yaml::from_str(
+            r#"
+args: ["--uri", "ws://localhost:9944"]
+jobs:
+  - name: extract
+    args: ["extract", "--bn", "0x01"]
+  - name: analysis
+    args: ["transform", "--bn", "0x01", "election-analysis"]
+    depends_on: ["extract"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.jobs.len(), 2);
+        assert!(manifest.jobs[0].depends_on.is_empty());
+        assert_eq!(manifest.jobs[1].depends_on, vec!["extract".to_string()]);
+
+        // the hash changes with the (global) arguments.
+        let mut other = manifest.clone();
+        assert_eq!(
+            manifest.job_hash(&manifest.jobs[0]),
+            other.job_hash(&other.jobs[0])
+        );
+        other.args.clear();
+        assert_ne!(
+            manifest.job_hash(&manifest.jobs[0]),
+            other.job_hash(&other.jobs[0])
+        );
+    }
+}
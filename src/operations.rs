@@ -6,17 +6,22 @@
 //! The result of an operation may be stored in disk to posterior analysis. Currently, the results
 //! are written into a CSV file.
 
-use crate::configs::Solver;
+use crate::configs::{ScoringMode, Solver, SolverKind};
 use crate::gadgets::{self, staking_ledger::staking_ledger_checks};
+use crate::output::Emitter;
 use crate::prelude::*;
+use crate::rpc::SharedRpcClient;
 use crate::utils::ShareDistribution;
+use crate::TimetravelError;
 use sp_npos_elections::ElectionScore;
+use sp_staking::EraIndex;
 
 use Staking::ActiveEraInfo;
 use EPM::{BalanceOf, SolutionOrSnapshotSize};
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::marker::PhantomData;
 
 /// Operations fetch and mutate state from an externalities. Each operation can be called as a
@@ -27,13 +32,701 @@ pub(crate) enum Operation {
     /// Calculates the staking minimum active stake.
     MinActiveStake,
     /// Performs analysus of the election and staking data.
-    ElectionAnalysis,
-    /// Performs checks and analysis of the staking ledger.
+    ElectionAnalysis {
+        /// The solver used to mine the Phragmen elections.
+        #[arg(long, value_enum, default_value_t = SolverKind::SeqPhragmen)]
+        solver: SolverKind,
+        /// The number of balancing iterations of the solver.
+        #[arg(long, default_value_t = 10)]
+        iterations: usize,
+        /// Overrides the desired number of winners of the elections, capped to the number of
+        /// targets. If none is provided, the on-chain desired targets are used.
+        #[arg(long)]
+        desired_targets: Option<u32>,
+    },
+    /// Performs checks and analysis of the staking ledger, and reports the ledger counts and the
+    /// offending stashes. Expects a block and its parent; if only one block is given, its parent
+    /// is used.
     StakingLedgerChecks,
+    /// Simulates the deprecation of all the controllers at each block, by dispatching the
+    /// `deprecate_controller_batch` call of the staking pallet, and reports the consistency
+    /// of the ledgers before and after the migration and the stashes it would brick.
+    ControllerDeprecation,
+    /// Compares the on-chain era exposures with the election solution mined locally from the
+    /// snapshot of the preceding block. Expects two blocks: one with the election snapshot and
+    /// the first block of the next era. If only the latter is given, its parent is used.
+    ExposureComparison,
+    /// Dumps the runtime constants in force at the block(s), as per the on-chain metadata.
+    Constants {
+        /// The pallets whose constants are dumped. If none is provided, all pallets are used.
+        #[arg(long)]
+        pallets: Vec<String>,
+    },
+    /// Compares the state of consecutive blocks, reporting the storage keys added, removed and
+    /// changed per storage item. The keys and values are decoded with the on-chain metadata of
+    /// the blocks. If only one block is given, it is compared with its parent.
+    StateDiff {
+        /// The pallets whose storage is compared. If none is provided, all pallets are used.
+        #[arg(long)]
+        pallets: Vec<String>,
+        /// Outputs one entry per changed key, with its decoded values, rather than the number of
+        /// changed keys per storage item.
+        #[arg(long, default_value_t = false)]
+        keys: bool,
+    },
+    /// Exports the header of each block (number, hash, parent, roots, timestamp and author), as
+    /// a backbone table for the outputs of other operations. Either the blocks of `--bn` or the
+    /// range `--from..--to` are exported. The timestamps which can not be read (e.g. from a
+    /// pruned node) are interpolated from the surrounding blocks.
+    Headers {
+        /// The first block number of the range to export.
+        #[arg(long)]
+        from: Option<BlockNumber>,
+        /// The last block number of the range to export. If none is provided, the latest
+        /// finalized block is used.
+        #[arg(long, requires = "from")]
+        to: Option<BlockNumber>,
+        /// Exports every `step`-th block of the range only.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        step: u32,
+        /// Expected block time in milliseconds, used to extrapolate the timestamps of the blocks
+        /// outside of the blocks with a known timestamp.
+        #[arg(long, default_value_t = crate::utils::DEFAULT_BLOCK_TIME_MS)]
+        block_time_ms: u64,
+    },
+    /// Tracks the values of storage items for a set of accounts over the blocks of `--bn` or the
+    /// range `--from..--to`, with batched point queries to the remote node rather than
+    /// snapshots, and exports them as a time series. The values are decoded with the on-chain
+    /// metadata.
+    Track {
+        /// The accounts whose values of the map storage items are tracked (SS58 encoded).
+        #[arg(long = "account")]
+        accounts: Vec<String>,
+        /// The storage items to track, as `Pallet::Item`, e.g. `System::Account` or
+        /// `Staking::Ledger`. Map items are read under each account, plain items once per block.
+        #[arg(long = "item", required = true)]
+        items: Vec<String>,
+        /// The first block number of the range to track.
+        #[arg(long)]
+        from: Option<BlockNumber>,
+        /// The last block number of the range to track. If none is provided, the latest
+        /// finalized block is used.
+        #[arg(long, requires = "from")]
+        to: Option<BlockNumber>,
+        /// Tracks every `step`-th block of the range only.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        step: u32,
+    },
+    /// Remaps the accounts of the staking pallet to deterministic pseudonyms and stores the
+    /// anonymized snapshot(s) next to the original ones, with an `.anon` extension.
+    Anonymize {
+        /// Secret seed from which the pseudonyms are derived. The same seed always results in
+        /// the same pseudonyms.
+        #[arg(long, env = "ANONYMIZE_SEED")]
+        seed: String,
+    },
+    /// Scales the electorate by duplicating its nominators and re-runs the election at each
+    /// scale, reporting the snapshot and solution sizes against the runtime bounds.
+    StressTest {
+        /// The scale factors of the number of nominators, e.g. `--factors 2,5,10`.
+        #[arg(long, value_delimiter = ',', default_values_t = [2, 5, 10])]
+        factors: Vec<u32>,
+        /// Seed of the stake jitter of the duplicated nominators.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Overrides the desired number of winners of the elections, capped to the number of
+        /// targets. If none is provided, the desired targets of the runtime are used.
+        #[arg(long)]
+        desired_targets: Option<u32>,
+    },
+    /// Simulates the election at each block with other desired numbers of winners, i.e. a grown
+    /// or shrunk validator set, and reports the scores next to the ones of the on-chain desired
+    /// targets.
+    DesiredTargetsWhatIf {
+        /// The desired numbers of winners to simulate, e.g. `--desired-targets 300,400,500`.
+        #[arg(long, value_delimiter = ',', required = true)]
+        desired_targets: Vec<u32>,
+    },
+    /// Simulates the election at each block without some targets (e.g. a validator retiring or
+    /// being slashed out of the set) or with others injected, and reports the scores and winners
+    /// next to the ones of the actual snapshot.
+    TargetsWhatIf {
+        /// The (SS58) accounts dropped from the targets of the snapshot.
+        #[arg(long, value_delimiter = ',')]
+        exclude_targets: Vec<String>,
+        /// The (SS58) accounts injected in the targets of the snapshot.
+        #[arg(long, value_delimiter = ',')]
+        include_targets: Vec<String>,
+    },
+    /// Simulates the election at each block with higher minimum nominator bonds, dropping the
+    /// nominators bonded below each bond from the snapshot, and reports the scores, snapshot size
+    /// and minimum active stake next to the ones of the on-chain `MinNominatorBond`.
+    MinBondWhatIf {
+        /// The minimum nominator bonds to simulate, in plancks, e.g. `--min-bonds 2500000000000`.
+        #[arg(long, value_delimiter = ',', required = true)]
+        min_bonds: Vec<u128>,
+    },
+    /// Summarizes the reward destinations (payees) of all the bonded accounts at each block.
+    PayeeDistribution,
+    /// Reports the distribution of the number of targets per nominator at each block.
+    NominationsDistribution,
+    /// Reports the stake distribution of the voters of the election snapshot at each block:
+    /// deciles, Gini coefficient, mean and median stake, votes per voter and the nominators
+    /// against the self-stake of the validators.
+    VoterDistribution,
+    /// Audits the validator commissions at each block and simulates the impact of hypothetical
+    /// minimum commissions.
+    CommissionAudit {
+        /// Hypothetical minimum commissions to simulate, in percent (e.g. `--min-commissions 5,10`).
+        /// If none is provided, only the on-chain `MinCommission` is used.
+        #[arg(long, value_delimiter = ',')]
+        min_commissions: Vec<f64>,
+    },
+    /// Reports the waiting validators at each block ranked by approval stake, i.e. the "next in
+    /// line" validators, and how far they are from the minimum active backing.
+    WaitingValidators {
+        /// Maximum number of waiting validators reported per block.
+        #[arg(long, default_value_t = 50)]
+        top: usize,
+    },
+    /// Summarizes the staking state at each block (total staked, staking rate, counts, backing
+    /// and minimum active stake), as a cheap alternative to the election analysis.
+    StakingSummary,
+    /// Reports the decentralization of the active validator set at each block: the backing of
+    /// each entity running validators and the Nakamoto coefficient of the set.
+    Decentralization {
+        /// Groups the validators probably run by the same operator into entities, by shared
+        /// controllers, payees, nominators and proxies. Otherwise, each validator is an entity.
+        #[arg(long, default_value_t = false)]
+        entities: bool,
+    },
+    /// Reports, per election round, whether the queued solution was computed by a signed or
+    /// unsigned submission, or by the fallback or emergency election.
+    ElectionHistory,
+    /// Compares the scores of the signed submissions and the queued solution of the election
+    /// provider at each block with the scores of the solutions mined locally from the same
+    /// snapshot.
+    SubmissionAnalysis,
+    /// Mines a solution at a block where the election failed and encodes it as a
+    /// `set_emergency_election_result` call, verified against the current bounds.
+    EmergencyResult,
+    /// Runs the feasibility check of a submitted solution against the election snapshot at each
+    /// block, and reports whether it passes and its verified score.
+    FeasibilityCheck {
+        /// Path of the SCALE encoded `RawSolution`, either as raw bytes or hex encoded.
+        #[arg(long)]
+        solution: String,
+    },
+    /// Mines a paged election at each block, as the multi-page election provider does, and
+    /// reports the score of each page's solution.
+    PagedElection {
+        /// Number of pages of the election snapshot.
+        #[arg(long, default_value_t = 32)]
+        pages: u32,
+        /// Maximum number of winners backed by the voters of a page. Unbounded if none.
+        #[arg(long)]
+        max_winners_per_page: Option<u32>,
+        /// Maximum number of backers of a winner in a page. Unbounded if none.
+        #[arg(long)]
+        max_backers_per_winner: Option<u32>,
+        /// Overrides the desired number of winners of the election, capped to the number of
+        /// targets. If none is provided, the on-chain desired targets are used.
+        #[arg(long)]
+        desired_targets: Option<u32>,
+    },
+    /// Attributes each block to its author and aggregates the authored blocks per validator and
+    /// era, next to the era reward points and slashes of each validator.
+    Authorship,
+    /// Reports the number of keys and bytes per pallet prefix of each snapshot, to identify the
+    /// pallets which dominate its memory usage.
+    MemoryReport,
+    /// Computes the realized APY of each nominator over a range of eras from the era exposures,
+    /// reward points and payouts, along with the distribution of the APY across nominators.
+    NominatorApy {
+        /// The first era of the range. If none is provided, the oldest era of the era history is
+        /// used.
+        #[arg(long)]
+        from_era: Option<EraIndex>,
+        /// The last era of the range. If none is provided, the era before the active era is used.
+        #[arg(long)]
+        to_era: Option<EraIndex>,
+        /// The (SS58) accounts of the nominators to report. If none is provided, all nominators
+        /// exposed in the range are reported.
+        #[arg(long, value_delimiter = ',')]
+        accounts: Vec<String>,
+        /// Number of eras per year, used to annualize the returns.
+        #[arg(long, default_value_t = 365.0)]
+        eras_per_year: f64,
+    },
+    /// Scores each validator elected in a range of eras by its era points, commission, slashes and
+    /// uptime (eras in the active set), combined with configurable weights.
+    ValidatorScore {
+        /// The first era of the range. If none is provided, the oldest era of the era history is
+        /// used.
+        #[arg(long)]
+        from_era: Option<EraIndex>,
+        /// The last era of the range. If none is provided, the era before the active era is used.
+        #[arg(long)]
+        to_era: Option<EraIndex>,
+        /// Weight of the era points, relative to the highest era points of the era.
+        #[arg(long, default_value_t = 1.0)]
+        points_weight: f64,
+        /// Weight of the commission (the lower, the better).
+        #[arg(long, default_value_t = 1.0)]
+        commission_weight: f64,
+        /// Weight of the slashes (the lower the slashed fraction, the better).
+        #[arg(long, default_value_t = 1.0)]
+        slash_weight: f64,
+        /// Weight of the uptime, i.e. the fraction of the eras of the range in which the
+        /// validator was elected.
+        #[arg(long, default_value_t = 1.0)]
+        uptime_weight: f64,
+    },
+    /// Compares, per era, the yield of the nomination pools (net of the pool commission) with the
+    /// yield of the solo nominators.
+    PoolYields {
+        /// The first era of the range. If none is provided, the oldest era of the era history is
+        /// used.
+        #[arg(long)]
+        from_era: Option<EraIndex>,
+        /// The last era of the range. If none is provided, the era before the active era is used.
+        #[arg(long)]
+        to_era: Option<EraIndex>,
+        /// Number of eras per year, used to annualize the yields.
+        #[arg(long, default_value_t = 365.0)]
+        eras_per_year: f64,
+    },
+    /// Exports the flows of stake (new bonds, unbonds, rebonds, compounded rewards, slashes)
+    /// between consecutive snapshots as Sankey links, aggregated from the diffs of the ledgers. If
+    /// only one block is given, it is paired with the first block of the next era.
+    StakeFlows,
+    /// Compares the nominations between consecutive snapshots (e.g. the first blocks of two eras)
+    /// and reports, per validator, the nominators which started or stopped nominating it and the
+    /// inflow and outflow of its approval stake. If only one block is given, it is paired with the
+    /// first block of the next era.
+    NominatorChurn {
+        /// Outputs one entry per nominator which joined, left, rebonded or changed targets,
+        /// rather than one entry per validator.
+        #[arg(long, default_value_t = false)]
+        nominators: bool,
+    },
+    /// Compares the active validator sets (i.e. the era exposures) of consecutive snapshots and
+    /// reports the validators which entered or exited the set, with their backing and the minimal
+    /// and total backing of both sets. If only one block is given, it is paired with the first
+    /// block of the next era.
+    ValidatorSetDiff,
+    /// Reports, per era, the era points, commission, own stake, total exposure and payout of each
+    /// validator, along with the estimated APR of its nominators.
+    RewardAnalysis {
+        /// The first era of the range. If none is provided, the oldest era of the era history is
+        /// used.
+        #[arg(long)]
+        from_era: Option<EraIndex>,
+        /// The last era of the range. If none is provided, the era before the active era is used.
+        #[arg(long)]
+        to_era: Option<EraIndex>,
+        /// Number of eras per year, used to annualize the nominator returns.
+        #[arg(long, default_value_t = 365.0)]
+        eras_per_year: f64,
+    },
+    /// Computes the payout of the active era with the `EraPayout` of the runtime, i.e. the
+    /// payout of the stakers, the remainder and the resulting annualized inflation.
+    EraPayout {
+        /// Duration of the era in milliseconds. If none is provided, the era is assumed to end at
+        /// the block, i.e. its duration is the time elapsed since its start.
+        #[arg(long)]
+        era_duration_ms: Option<u64>,
+    },
+    /// Tracks the total issuance, total stake, staking rate, ideal staking rate and inflation
+    /// (as per the `EraPayout` of the runtime) at each block, as a time series of the inflation.
+    Inflation {
+        /// Duration of the eras in milliseconds. If none is provided, the active era is assumed
+        /// to end at each block, i.e. its duration is the time elapsed since its start.
+        #[arg(long)]
+        era_duration_ms: Option<u64>,
+    },
     /// Playground operations -- go wild!
     Playground,
 }
 
+/// The pallets and storage items an operation relies on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Requirements {
+    /// Pallets which must be part of the runtime and whose storage must be in the snapshot.
+    pub pallets: &'static [&'static str],
+    /// Storage items, as `(pallet, item)`, which must be part of the runtime.
+    pub items: &'static [(&'static str, &'static str)],
+}
+
+impl Requirements {
+    /// The pallets to extract for the operation, or `None` if it does not require any pallet.
+    ///
+    /// The storage items required outside of these pallets are expected to be among the keys
+    /// extracted with every snapshot, e.g. `Balances::TotalIssuance` or `Session::Validators`.
+    pub fn pallets_to_extract(&self) -> Option<Vec<String>> {
+        (!self.pallets.is_empty()).then(|| self.pallets.iter().map(|p| p.to_string()).collect())
+    }
+
+    /// Returns the requirements not met by a runtime, given its storage items per pallet.
+    pub fn unmet_in_runtime(
+        &self,
+        runtime_items: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Vec<String> {
+        let missing_pallets = self
+            .pallets
+            .iter()
+            .filter(|pallet| !runtime_items.contains_key(**pallet))
+            .map(|pallet| pallet.to_string());
+        let missing_items = self
+            .items
+            .iter()
+            .filter(|(pallet, item)| {
+                !runtime_items
+                    .get(*pallet)
+                    .map_or(false, |items| items.contains(*item))
+            })
+            .map(|(pallet, item)| format!("{}::{}", pallet, item));
+
+        missing_pallets.chain(missing_items).collect()
+    }
+}
+
+/// The block an operation over a pair of blocks is paired with, when only one block is selected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Pairing {
+    /// The parent of the selected block.
+    Parent,
+    /// The first block of the era after the active era of the selected block.
+    NextEra,
+}
+
+impl Operation {
+    /// The name of the operation, as in its `Debug` representation.
+    pub(crate) fn name(&self) -> String {
+        format!("{:?}", self)
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The requirements of the operation over the runtime and the snapshot it runs on.
+    pub(crate) fn requirements(&self) -> Requirements {
+        const EPM: &str = "ElectionProviderMultiPhase";
+
+        match self {
+            Operation::Constants { .. }
+            | Operation::Headers { .. }
+            | Operation::Track { .. }
+            | Operation::StateDiff { .. }
+            | Operation::MemoryReport
+            | Operation::Playground => Requirements::default(),
+            Operation::StakingLedgerChecks | Operation::ControllerDeprecation => Requirements {
+                pallets: &["Staking", "Balances"],
+                items: &[],
+            },
+            Operation::Anonymize { .. }
+            | Operation::PayeeDistribution
+            | Operation::NominationsDistribution
+            | Operation::CommissionAudit { .. }
+            | Operation::WaitingValidators { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[],
+            },
+            Operation::NominatorApy { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Staking", "ErasValidatorReward"),
+                    ("Staking", "ErasRewardPoints"),
+                    ("Staking", "ErasValidatorPrefs"),
+                ],
+            },
+            Operation::ValidatorScore { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Staking", "ErasRewardPoints"),
+                    ("Staking", "ErasValidatorPrefs"),
+                    ("Staking", "ValidatorSlashInEra"),
+                ],
+            },
+            Operation::PoolYields { .. } => Requirements {
+                pallets: &["Staking", "NominationPools"],
+                items: &[
+                    ("Staking", "ErasValidatorReward"),
+                    ("Staking", "ErasRewardPoints"),
+                    ("Staking", "ErasValidatorPrefs"),
+                ],
+            },
+            Operation::NominatorChurn { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Staking", "Nominators"),
+                    ("Staking", "Ledger"),
+                    ("Staking", "ActiveEra"),
+                ],
+            },
+            Operation::ValidatorSetDiff => Requirements {
+                pallets: &["Staking"],
+                items: &[("Session", "Validators"), ("Staking", "ActiveEra")],
+            },
+            Operation::StakeFlows => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Staking", "Ledger"),
+                    ("Staking", "Payee"),
+                    ("Staking", "ActiveEra"),
+                ],
+            },
+            Operation::RewardAnalysis { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Staking", "ErasValidatorReward"),
+                    ("Staking", "ErasRewardPoints"),
+                    ("Staking", "ErasValidatorPrefs"),
+                ],
+            },
+            Operation::EraPayout { .. } | Operation::Inflation { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("Balances", "TotalIssuance"),
+                    ("Timestamp", "Now"),
+                    ("Staking", "ActiveEra"),
+                ],
+            },
+            Operation::Authorship => Requirements {
+                pallets: &["Staking"],
+                items: &[
+                    ("System", "Digest"),
+                    ("Session", "Validators"),
+                    ("Staking", "ErasRewardPoints"),
+                ],
+            },
+            Operation::StakingSummary => Requirements {
+                pallets: &["Staking"],
+                items: &[("Balances", "TotalIssuance")],
+            },
+            Operation::Decentralization { .. } => Requirements {
+                pallets: &["Staking"],
+                items: &[("Session", "Validators"), ("Staking", "ActiveEra")],
+            },
+            Operation::ElectionHistory => Requirements {
+                pallets: &[EPM],
+                items: &[
+                    (EPM, "Round"),
+                    (EPM, "CurrentPhase"),
+                    (EPM, "QueuedSolution"),
+                ],
+            },
+            Operation::VoterDistribution => Requirements {
+                pallets: &[EPM, "Staking", "VoterList"],
+                items: &[],
+            },
+            Operation::SubmissionAnalysis => Requirements {
+                pallets: &[EPM, "Staking"],
+                items: &[
+                    (EPM, "SignedSubmissionsMap"),
+                    (EPM, "QueuedSolution"),
+                    (EPM, "Snapshot"),
+                ],
+            },
+            Operation::FeasibilityCheck { .. } => Requirements {
+                pallets: &[EPM],
+                items: &[(EPM, "Snapshot"), (EPM, "DesiredTargets")],
+            },
+            Operation::MinActiveStake
+            | Operation::ExposureComparison
+            | Operation::EmergencyResult
+            | Operation::ElectionAnalysis { .. }
+            | Operation::StressTest { .. }
+            | Operation::DesiredTargetsWhatIf { .. }
+            | Operation::TargetsWhatIf { .. }
+            | Operation::MinBondWhatIf { .. }
+            | Operation::PagedElection { .. } => Requirements {
+                pallets: &[EPM, "Staking", "VoterList"],
+                items: &[],
+            },
+        }
+    }
+
+    /// The pallets extracted for the operation when its snapshots are not stored (e.g. with
+    /// `--live`), given its `requirements`: the pallets compared by a state diff (all of them if
+    /// none is provided), or the required pallets, or the election and staking pallets if it does
+    /// not require any.
+    pub(crate) fn pallets_to_extract(&self, requirements: &Requirements) -> Vec<String> {
+        if let Operation::StateDiff { pallets, .. } = self {
+            return pallets.clone();
+        }
+        let mut pallets = requirements.pallets_to_extract().unwrap_or_else(|| {
+            ["ElectionProviderMultiPhase", "Staking", "VoterList"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        });
+        pallets.extend(self.optional_pallets().iter().map(|p| p.to_string()));
+        pallets
+    }
+
+    /// The pallets used by the operation if they are part of the runtime, which are extracted
+    /// along with its requirements but not required.
+    pub(crate) fn optional_pallets(&self) -> &'static [&'static str] {
+        match self {
+            Operation::Decentralization { entities: true } => &["Proxy"],
+            _ => &[],
+        }
+    }
+
+    /// How the second block of the operation is resolved when only one block is selected, or
+    /// `None` if the operation does not run over pairs of blocks.
+    pub(crate) fn pairing(&self) -> Option<Pairing> {
+        match self {
+            Operation::StakingLedgerChecks
+            | Operation::ExposureComparison
+            | Operation::StateDiff { .. } => Some(Pairing::Parent),
+            Operation::StakeFlows
+            | Operation::NominatorChurn { .. }
+            | Operation::ValidatorSetDiff => Some(Pairing::NextEra),
+            _ => None,
+        }
+    }
+
+    /// The requirements of the operation when the storage is decoded with the on-chain metadata,
+    /// or `None` if the operation relies on the compiled runtime.
+    pub(crate) fn metadata_requirements(&self) -> Option<Requirements> {
+        match self {
+            Operation::MinActiveStake => Some(Requirements {
+                pallets: &["Staking"],
+                items: &[("System", "Number"), ("Staking", "ActiveEra")],
+            }),
+            Operation::StateDiff { .. } => Some(Requirements::default()),
+            _ => None,
+        }
+    }
+
+    /// The mutations applied to the loaded state by the operation, given whether the unbounded
+    /// snapshot is computed, the `patches` overlaid on the snapshots and the `mutation_specs`
+    /// applied to them.
+    pub(crate) fn mutations(
+        &self,
+        compute_unbounded: bool,
+        patches: &[String],
+        mutation_specs: &[String],
+    ) -> Vec<String> {
+        let mut mutations =
+            patches
+                .iter()
+                .map(|patch| format!("overlays the storage of patch {}", patch))
+                .chain(mutation_specs.iter().map(|spec| {
+                    format!("sets or removes the storage keys of mutation spec {}", spec)
+                }))
+                .collect::<Vec<_>>();
+
+        if let Operation::ElectionAnalysis {
+            desired_targets: Some(desired_targets),
+            ..
+        }
+        | Operation::StressTest {
+            desired_targets: Some(desired_targets),
+            ..
+        }
+        | Operation::PagedElection {
+            desired_targets: Some(desired_targets),
+            ..
+        } = self
+        {
+            mutations.push(format!(
+                "overrides the desired targets with {}",
+                desired_targets
+            ));
+        }
+
+        match self {
+            Operation::ElectionAnalysis { .. } if compute_unbounded => mutations.push(
+                "stores the unbounded election snapshot, with all the voters and targets".into(),
+            ),
+            Operation::StakingLedgerChecks | Operation::ControllerDeprecation => {
+                mutations.push("simulates the deprecation of the controllers".into())
+            }
+            Operation::StressTest { .. } => {
+                mutations.push("inserts synthetic nominators and validators".into())
+            }
+            Operation::EmergencyResult => {
+                mutations.push("dispatches the emergency election result as root".into())
+            }
+            _ => (),
+        }
+
+        mutations
+    }
+}
+
+/// Checks that the `requirements` of `operation` are met by the runtime of each block, as per the
+/// on-chain metadata. Runtimes which differ from the runtime compiled into the CLI,
+/// `compiled_spec_name`, are only reported, since the storage they share is still decodable.
+///
+/// The metadata is fetched once per runtime version.
+pub(crate) async fn check_runtime_requirements(
+    rpc: &SharedRpcClient,
+    operation: &Operation,
+    requirements: &Requirements,
+    block_hashes: &[Hash],
+    compiled_spec_name: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let mut checked = BTreeSet::new();
+
+    for block_hash in block_hashes {
+        let version = rpc.runtime_version(Some(*block_hash)).await?;
+        if !checked.insert((version.spec_name.to_string(), version.spec_version)) {
+            continue;
+        }
+
+        if let Some(compiled_spec_name) =
+            compiled_spec_name.filter(|name| version.spec_name != *name)
+        {
+            crate::warnings::warn_run(
+                "runtime_mismatch",
+                format!(
+                    "Transform::{}: runtime {} at {:?} differs from the compiled runtime {}.",
+                    operation.name(),
+                    version.spec_name,
+                    block_hash,
+                    compiled_spec_name,
+                ),
+            );
+        }
+
+        let metadata = gadgets::metadata::fetch_metadata(rpc, *block_hash).await?;
+        let runtime_items = metadata
+            .pallets
+            .iter()
+            .map(|pallet| {
+                let items = pallet
+                    .storage
+                    .iter()
+                    .flat_map(|storage| storage.entries.iter().map(|entry| entry.name.clone()))
+                    .collect::<BTreeSet<_>>();
+                (pallet.name.clone(), items)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let unmet = requirements.unmet_in_runtime(&runtime_items);
+        if !unmet.is_empty() {
+            return Err(TimetravelError::Unsupported {
+                reason: format!(
+                    "operation {} is not compatible with runtime {} v{} at {:?}, which lacks {}.",
+                    operation.name(),
+                    version.spec_name,
+                    version.spec_version,
+                    block_hash,
+                    unmet.join(", "),
+                ),
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 /// The CSV representation of the `min_active_stake` operation result.
 pub(crate) struct MinActiveStakeCsv {
@@ -41,13 +734,81 @@ pub(crate) struct MinActiveStakeCsv {
     min_active_stake: u128,
 }
 
+/// The CSV representation of the `min_active_stake` operation result with the on-chain metadata,
+/// which is computed over the exposures of the active era rather than the election voters.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MinExposedStakeCsv {
+    block_number: u32,
+    /// Empty if no stake is exposed in the active era.
+    min_exposed_stake: Option<u128>,
+}
+
+/// Runs `operation` over the externalities, decoding their storage with the metadata of each
+/// block rather than the compiled runtime, which supports any Substrate chain.
+///
+/// The `min_active_stake` operation reports the `min_exposed_stake`, i.e. the lowest active stake
+/// of the nominators exposed in the active era, rather than the `min_active_stake` computed over
+/// the voters of the election snapshot.
+pub(crate) async fn transform_with_metadata(
+    rpc: &SharedRpcClient,
+    operation: &Operation,
+    exts: Vec<Ext>,
+    block_hashes: Vec<Hash>,
+    out: &mut Emitter,
+) -> Result<(), anyhow::Error> {
+    use gadgets::dynamic::DynamicStorage;
+
+    log::info!(
+        target: LOG_TARGET,
+        "Transform::{} starting with the on-chain metadata.",
+        operation.name()
+    );
+
+    for (mut ext, block_hash) in exts.into_iter().zip(block_hashes) {
+        out.begin_block();
+        let metadata = gadgets::metadata::fetch_metadata(rpc, block_hash).await?;
+        let storage = DynamicStorage::new(&metadata);
+        let block_number = storage
+            .value(&mut ext, "System", "Number")?
+            .and_then(|number| number.as_u128())
+            .ok_or(TimetravelError::missing("Block number"))? as u32;
+
+        match operation {
+            Operation::MinActiveStake => {
+                let min_exposed_stake = gadgets::dynamic::min_exposed_stake(&storage, &mut ext)?;
+
+                let csv_entry = MinExposedStakeCsv {
+                    block_number,
+                    min_exposed_stake,
+                };
+                out.emit(csv_entry)?;
+
+                log::info!(
+                    target: LOG_TARGET,
+                    "Transform::min_active_stake at #{}: min exposed stake {:?}.",
+                    block_number,
+                    min_exposed_stake,
+                );
+            }
+            _ => {
+                return Err(TimetravelError::Unsupported {
+                    reason: format!("operation {} with the on-chain metadata.", operation.name()),
+                }
+                .into())
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Calculates the minimum active stake for a given externalities.
 macro_rules! min_active_stake_for {
     ($runtime:ident) => {
         paste::paste! {
             pub(crate) fn [<min_active_stake_ $runtime>]<T: EPM::Config>(
                 mut exts: Vec<Ext>,
-                output_path: String,
+                out: &mut Emitter,
             ) -> Result<(), anyhow::Error> {
                 use $crate::[<$runtime _runtime_exports>]::*;
 
@@ -64,13 +825,13 @@ macro_rules! min_active_stake_for {
                     min_active_stake,
                 };
 
-                crate::write_csv(csv_entry, &output_path)?;
+                out.emit(csv_entry)?;
 
                 log::info!(
                     target: LOG_TARGET,
                     "Transform::min_active_stake result {}; CSV entry stored in {:?}",
                     min_active_stake,
-                    output_path
+                    out.output_path()
                 );
 
                 Ok(())
@@ -80,116 +841,134 @@ macro_rules! min_active_stake_for {
 }
 
 /// The CSV representation of the `election_analysis` operation result.
+///
+/// The columns of the elections which are not computed (e.g. the unbounded elections without
+/// `--compute-unbounded` or the exact Phragmen scores with `--scoring approximate`) and the
+/// metrics which are not defined (e.g. of an election without winners) are empty, i.e. `null`,
+/// rather than zero.
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct ElectionEntryCSV<T: EPM::Config> {
     block_number: u32,
-    active_era: u32,
-    phrag_min_stake: u128,
-    phrag_sum_stake: u128,
-    phrag_sum_stake_squared: u128,
-    phrag_unbound_min_stake: u128,
-    phrag_unbound_sum_stake: u128,
-    phrag_unbound_sum_stake_squared: u128,
-    dpos_min_stake_prorata: u128,
-    dpos_sum_stake_prorata: u128,
-    dpos_sum_stake_squared_prorata: u128,
-    dpos_min_stake_pareto: u128,
-    dpos_sum_stake_pareto: u128,
-    dpos_sum_stake_squared_pareto: u128,
-    dpos_unbound_min_stake_prorata: u128,
-    dpos_unbound_sum_stake_prorata: u128,
-    dpos_unbound_sum_stake_squared_prorata: u128,
-    dpos_unbound_min_stake_pareto: u128,
-    dpos_unbound_sum_stake_pareto: u128,
-    dpos_unbound_sum_stake_squared_pareto: u128,
+    active_era: Option<u32>,
+    /// The phase of the election provider at the block.
+    phase: String,
+    /// Whether the bounded elections are computed over the on-chain snapshot or over a snapshot
+    /// created at the block.
+    snapshot_source: String,
+    phrag: ElectionScoreCsv,
+    phrag_unbound: ElectionScoreCsv,
+    dpos_prorata: ElectionScoreCsv,
+    dpos_pareto: ElectionScoreCsv,
+    dpos_unbound_prorata: ElectionScoreCsv,
+    dpos_unbound_pareto: ElectionScoreCsv,
+    approx: ElectionScoreCsv,
+    approval: ElectionScoreCsv,
     voters: u32,
     targets: u32,
     snapshot_size: usize,
-    voters_unbound: u32,
-    targets_unbound: u32,
-    snapshot_size_unbound: usize,
+    voters_unbound: Option<u32>,
+    targets_unbound: Option<u32>,
+    snapshot_size_unbound: Option<usize>,
+    desired_targets: u32,
+    /// The desired targets of the `--desired-targets` override, if any. The override is capped
+    /// to the number of targets, in which case it is higher than the desired targets applied.
+    desired_targets_requested: Option<u32>,
+    desired_targets_unbound: Option<u32>,
     min_active_stake: u128,
     #[serde(skip)]
     _marker: PhantomData<T>,
 }
 
+/// The score of an election of the `election_analysis` operation and its metrics, see
+/// [`gadgets::score_metrics`]. The score is nested under its election in JSON and flattened into
+/// the `<election>_<field>` columns in CSV, e.g. `dpos_prorata_min_stake`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ElectionScoreCsv {
+    min_stake: Option<u128>,
+    sum_stake: Option<u128>,
+    sum_stake_squared: Option<u128>,
+    min_stake_ratio: Option<f64>,
+    backing_variance: Option<f64>,
+    normalized_sum_squared: Option<f64>,
+}
+
+impl ElectionScoreCsv {
+    /// The score of an election, if computed, along with the number of winners it elected.
+    fn new(elected: Option<(ElectionScore, u32)>) -> Self {
+        let score = elected.map(|(score, _)| score);
+        let metrics = elected.and_then(|(score, winners)| gadgets::score_metrics(&score, winners));
+
+        Self {
+            min_stake: score.map(|s| s.minimal_stake),
+            sum_stake: score.map(|s| s.sum_stake),
+            sum_stake_squared: score.map(|s| s.sum_stake_squared),
+            min_stake_ratio: metrics.map(|m| m.min_stake_ratio),
+            backing_variance: metrics.map(|m| m.backing_variance),
+            normalized_sum_squared: metrics.map(|m| m.normalized_sum_squared),
+        }
+    }
+}
+
+/// The unbounded elections of the `election_analysis` operation, computed over a snapshot with
+/// all the voters.
+/// The scores are given along with the number of winners each election elected.
+struct UnboundElections {
+    /// The exact Phragmen score, if computed.
+    phrag_score: Option<(ElectionScore, u32)>,
+    dpos_score_prorata: (ElectionScore, u32),
+    dpos_score_pareto: (ElectionScore, u32),
+    /// The desired targets of the unbounded elections.
+    winners: u32,
+    snapshot_metadata: SolutionOrSnapshotSize,
+    snapshot_size: usize,
+}
+
 impl<T: EPM::Config> ElectionEntryCSV<T> {
     fn new(
         block_number: BlockNumber,
         active_era: Option<ActiveEraInfo>,
-        phrag_solutions: (
-            &EPM::RawSolution<EPM::SolutionOf<T::MinerConfig>>,
-            &EPM::RawSolution<EPM::SolutionOf<T::MinerConfig>>,
-        ),
-        dpos_score_prorata: ElectionScore,
-        dpos_score_pareto: ElectionScore,
-        dpos_unbounded_score_prorata: ElectionScore,
-        dpos_unbounded_score_pareto: ElectionScore,
-        snapshot_metadata: SolutionOrSnapshotSize,
-        snapshot_size: usize,
-        snapshot_metadata_unbound: SolutionOrSnapshotSize,
-        snapshot_size_unbound: usize,
+        phrag_score: Option<(ElectionScore, u32)>,
+        dpos_score_prorata: (ElectionScore, u32),
+        dpos_score_pareto: (ElectionScore, u32),
+        approx_score: Option<(ElectionScore, u32)>,
+        approval_score: (ElectionScore, u32),
+        desired_targets: u32,
+        desired_targets_requested: Option<u32>,
+        snapshot: gadgets::ElectionSnapshot,
+        unbound: Option<UnboundElections>,
         min_active_stake: BalanceOf<T>,
     ) -> Self
     where
         BalanceOf<T>: Into<u128>,
     {
-        let active_era = match active_era {
-            Some(era) => era.index,
-            None => 0,
-        };
-
-        let (phrag_min_stake, phrag_sum_stake, phrag_sum_stake_squared) = {
-            let ElectionScore {
-                minimal_stake,
-                sum_stake,
-                sum_stake_squared,
-            } = phrag_solutions.0.score;
-            (minimal_stake, sum_stake, sum_stake_squared)
-        };
+        let phrag_unbound_score = unbound.as_ref().and_then(|u| u.phrag_score);
+        let dpos_unbound_score_prorata = unbound.as_ref().map(|u| u.dpos_score_prorata);
+        let dpos_unbound_score_pareto = unbound.as_ref().map(|u| u.dpos_score_pareto);
 
-        let (phrag_unbound_min_stake, phrag_unbound_sum_stake, phrag_unbound_sum_stake_squared) = {
-            let ElectionScore {
-                minimal_stake,
-                sum_stake,
-                sum_stake_squared,
-            } = phrag_solutions.1.score;
-            (minimal_stake, sum_stake, sum_stake_squared)
-        };
-
-        let SolutionOrSnapshotSize { voters, targets } = snapshot_metadata;
-        let (voters_unbound, targets_unbound) = (
-            snapshot_metadata_unbound.voters,
-            snapshot_metadata_unbound.targets,
-        );
+        let SolutionOrSnapshotSize { voters, targets } = snapshot.metadata;
 
         Self {
             block_number: block_number.into(),
-            active_era,
-            phrag_min_stake,
-            phrag_sum_stake,
-            phrag_sum_stake_squared,
-            phrag_unbound_min_stake,
-            phrag_unbound_sum_stake,
-            phrag_unbound_sum_stake_squared,
-            dpos_min_stake_prorata: dpos_score_prorata.minimal_stake,
-            dpos_sum_stake_prorata: dpos_score_prorata.sum_stake,
-            dpos_sum_stake_squared_prorata: dpos_score_prorata.sum_stake_squared,
-            dpos_min_stake_pareto: dpos_score_pareto.minimal_stake,
-            dpos_sum_stake_pareto: dpos_score_pareto.sum_stake,
-            dpos_sum_stake_squared_pareto: dpos_score_pareto.sum_stake_squared,
-            dpos_unbound_min_stake_prorata: dpos_unbounded_score_prorata.minimal_stake,
-            dpos_unbound_sum_stake_prorata: dpos_unbounded_score_prorata.sum_stake,
-            dpos_unbound_sum_stake_squared_prorata: dpos_unbounded_score_prorata.sum_stake_squared,
-            dpos_unbound_min_stake_pareto: dpos_unbounded_score_pareto.minimal_stake,
-            dpos_unbound_sum_stake_pareto: dpos_unbounded_score_pareto.sum_stake,
-            dpos_unbound_sum_stake_squared_pareto: dpos_unbounded_score_pareto.sum_stake_squared,
+            active_era: active_era.map(|era| era.index),
+            phase: snapshot.phase,
+            snapshot_source: format!("{:?}", snapshot.source),
+            phrag: ElectionScoreCsv::new(phrag_score),
+            phrag_unbound: ElectionScoreCsv::new(phrag_unbound_score),
+            dpos_prorata: ElectionScoreCsv::new(Some(dpos_score_prorata)),
+            dpos_pareto: ElectionScoreCsv::new(Some(dpos_score_pareto)),
+            dpos_unbound_prorata: ElectionScoreCsv::new(dpos_unbound_score_prorata),
+            dpos_unbound_pareto: ElectionScoreCsv::new(dpos_unbound_score_pareto),
+            approx: ElectionScoreCsv::new(approx_score),
+            approval: ElectionScoreCsv::new(Some(approval_score)),
             voters,
             targets,
-            snapshot_size,
-            voters_unbound,
-            targets_unbound,
-            snapshot_size_unbound,
+            snapshot_size: snapshot.size,
+            voters_unbound: unbound.as_ref().map(|u| u.snapshot_metadata.voters),
+            targets_unbound: unbound.as_ref().map(|u| u.snapshot_metadata.targets),
+            snapshot_size_unbound: unbound.as_ref().map(|u| u.snapshot_size),
+            desired_targets,
+            desired_targets_requested,
+            desired_targets_unbound: unbound.as_ref().map(|u| u.winners),
             min_active_stake: min_active_stake.into(),
             _marker: PhantomData,
         }
@@ -202,16 +981,37 @@ impl<T: EPM::Config> ElectionEntryCSV<T> {
 /// * Phragmen election score, given the current snapshot data;
 /// * MMS Phragmen election score, given the current snapshot data;
 /// * Delegated PoS election score, given the current snapshot data;
+/// * Approval voting election score (top targets by approval stake), given the current snapshot
+/// data;
 /// * "Unbounded" delegated PoS election score. Note that for this election scheme, the snapshot is
 /// recalculated using an unbounded number of voters (i.e. it takes as many voters as existing in
 /// the voters list).
+///
+/// The unbounded elections are only computed with `compute_unbounded`, since recomputing the
+/// snapshot roughly doubles the runtime and the memory of the operation. Otherwise, the unbounded
+/// snapshot is not created and the unbounded columns of the row are left empty.
+///
+/// Depending on the scoring mode, the Phragmen scores are computed exactly (by mining a full
+/// solution), approximately (from the unbalanced supports) or both.
+///
+/// The bounded elections are computed over the on-chain snapshot if it exists at the block (e.g.
+/// in the signed phase), or over a snapshot created at the block otherwise (e.g. in the off
+/// phase). The phase and the source of the snapshot are reported, since the elections over a
+/// created snapshot are not the elections of the chain.
+///
+/// The desired number of winners of all the elections can be overridden with `desired_targets`,
+/// e.g. to assess a change of the validator count. The override is capped to the number of
+/// targets of each snapshot.
 macro_rules! election_analysis_for {
     ($runtime:ident) => {
         paste::paste! {
             pub(crate) fn [<election_analysis_ $runtime>]<T: EPM::Config>(
                 mut exts: Vec<Ext>,
-                output_path: String,
+                out: &mut Emitter,
                 compute_unbounded: bool,
+                solver: Solver,
+                scoring: ScoringMode,
+                desired_targets_override: Option<u32>,
             ) -> Result<(), anyhow::Error> {
                 use $crate::[<$runtime _runtime_exports>]::*;
 
@@ -220,50 +1020,77 @@ macro_rules! election_analysis_for {
                 assert!(exts.len() == 1, "election_analysis_for expects only 1 snapshot at a time");
                 let mut ext = &mut exts[0];
 
-                let (snapshot_metadata, snapshot_size) = gadgets::snapshot_data_or_force::<Runtime>(&mut ext);
+                let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
                 let min_active_stake = gadgets::min_active_stake::<Runtime>(&mut ext);
                 let block_number = gadgets::block_number::<Runtime>(&mut ext);
                 let active_era = gadgets::active_era::<Runtime>(&mut ext);
+                let desired_targets = match desired_targets_override {
+                    Some(desired_targets) => gadgets::override_desired_targets::<Runtime>(&mut ext, desired_targets)?,
+                    None => gadgets::desired_targets::<Runtime>(&mut ext),
+                };
 
-                let phrag_raw_solution = gadgets::mine_with::<Runtime>(&Solver::SeqPhragmen{iterations: 10}, &mut ext, false)?;
+                let phrag_score = if scoring.exact() {
+                    let raw_solution = gadgets::mine_with::<Runtime>(&solver, &mut ext, false)?;
+                    Some((raw_solution.score, gadgets::solution_winners::<Runtime>(&raw_solution)))
+                } else {
+                    None
+                };
+                let approx_score = if scoring.approximate() {
+                    Some(gadgets::mine_approximate::<Runtime>(&mut ext)?)
+                } else {
+                    None
+                };
                 let dpos_score_prorata = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::ProRata)?;
                 let dpos_score_pareto = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::Pareto)?;
+                let approval_score = gadgets::mine_approval::<Runtime>(&mut ext)?;
 
-                let (
-                    snapshot_metadata_unbound,
-                    snapshot_size_unbound,
-                    phrag_unbound_raw_solution,
-                    dpos_unbound_score_prorata,
-                    dpos_unbound_score_pareto,
-                ) = if compute_unbounded {
-                    // force new unbounded snapshot to compute the unbounded npos and dpos elections.
-                    let (snapshot_metadata_unbound, snapshot_size_unbound) = gadgets::compute_and_store_unbounded_snapshot::<Runtime>(&mut ext)?;
-
-                    let phrag_unbound_raw_solution = gadgets::mine_with::<Runtime>(&Solver::SeqPhragmen{iterations: 10}, &mut ext, false)?;
-                    let dpos_unbound_score_prorata = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::ProRata)?;
-                    let dpos_unbound_score_pareto = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::Pareto)?;
-
-                    (snapshot_metadata_unbound, snapshot_size_unbound, phrag_unbound_raw_solution, dpos_unbound_score_prorata, dpos_unbound_score_pareto)
+                let unbound = if compute_unbounded {
+                    // force new unbounded snapshot to compute the unbounded npos and dpos
+                    // elections, leaving the bounded snapshot untouched.
+                    let unbound = gadgets::simulate(&mut ext, |mut ext| -> Result<_, anyhow::Error> {
+                        let (snapshot_metadata, snapshot_size) = gadgets::compute_and_store_unbounded_snapshot::<Runtime>(&mut ext, desired_targets_override)?;
+                        let winners = gadgets::desired_targets::<Runtime>(&mut ext);
+
+                        let phrag_score = if scoring.exact() {
+                            let raw_solution = gadgets::mine_with::<Runtime>(&solver, &mut ext, false)?;
+                            Some((raw_solution.score, gadgets::solution_winners::<Runtime>(&raw_solution)))
+                        } else {
+                            None
+                        };
+                        let dpos_score_prorata = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::ProRata)?;
+                        let dpos_score_pareto = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::Pareto)?;
+
+                        Ok(UnboundElections {
+                            phrag_score,
+                            dpos_score_prorata,
+                            dpos_score_pareto,
+                            winners,
+                            snapshot_metadata,
+                            snapshot_size,
+                        })
+                    })?;
+                    Some(unbound)
                 } else {
-                    (Default::default(), Default::default(), Default::default(), Default::default(), Default::default())
+                    log::info!(target: LOG_TARGET, "Transform::election_analysis: skipping the unbounded elections.");
+                    None
                 };
 
                 let csv_entry = ElectionEntryCSV::<Runtime>::new(
                     block_number,
                     active_era,
-                    (&phrag_raw_solution, &phrag_unbound_raw_solution),
+                    phrag_score,
                     dpos_score_prorata,
                     dpos_score_pareto,
-                    dpos_unbound_score_prorata,
-                    dpos_unbound_score_pareto,
-                    snapshot_metadata,
-                    snapshot_size,
-                    snapshot_metadata_unbound,
-                    snapshot_size_unbound,
+                    approx_score,
+                    approval_score,
+                    desired_targets,
+                    desired_targets_override,
+                    snapshot,
+                    unbound,
                     min_active_stake,
                 );
 
-                crate::write_csv(csv_entry, &output_path)?;
+                out.emit(csv_entry)?;
 
                 Ok(())
             }
@@ -271,17 +1098,80 @@ macro_rules! election_analysis_for {
     };
 }
 
-/// Performs staking ledger checks.
-macro_rules! staking_ledger_checks_for {
+/// The CSV representation of the `exposure_comparison` operation result, one entry per validator.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ExposureComparisonCsv {
+    block_number: u32,
+    era: u32,
+    /// The stash of the validator.
+    account: String,
+    onchain_backing: u128,
+    mined_backing: u128,
+    backing_diff: i128,
+}
+
+/// Compares the exposures of the active era with the supports of a locally mined solution.
+///
+/// The solution is mined from the snapshot of the parent externalities, while the exposures are
+/// read from the child externalities, which is expected to be the first block of the era elected
+/// by the snapshot. Validators that are only elected in one of the sides are reported with zero
+/// backing on the other side.
+macro_rules! exposure_comparison_for {
     ($runtime:ident) => {
         paste::paste! {
-            pub(crate) fn [<staking_ledger_checks_ $runtime>]<T: EPM::Config + Staking::Config>(
+            pub(crate) fn [<exposure_comparison_ $runtime>]<T: EPM::Config + Staking::Config>(
                 exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
             ) -> Result<(), anyhow::Error> {
                 use $crate::[<$runtime _runtime_exports>]::*;
 
-                log::info!(target: LOG_TARGET, "Transform::staking_ledger_checks starting.");
-                staking_ledger_checks::<Runtime>(exts)?;
+                log::info!(target: LOG_TARGET, "Transform::exposure_comparison starting.");
+
+                let (mut ext_parent, mut ext_child) = gadgets::parent_and_child::<Runtime>("exposure_comparison", exts)?;
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+                let mined = gadgets::mined_supports::<Runtime>(&solver, &mut ext_parent)?
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>();
+
+                let block_number = gadgets::block_number::<Runtime>(&mut ext_child);
+                let era = gadgets::active_era::<Runtime>(&mut ext_child)
+                    .ok_or(TimetravelError::missing("Active era"))?
+                    .index;
+                let onchain = gadgets::era_exposures::<Runtime>(&mut ext_child, era)
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>();
+
+                let validators = mined.keys().chain(onchain.keys()).collect::<std::collections::BTreeSet<_>>();
+
+                log::info!(
+                    target: LOG_TARGET,
+                    "Transform::exposure_comparison: {} mined winners, {} exposed validators in era {}.",
+                    mined.len(),
+                    onchain.len(),
+                    era,
+                );
+
+                for validator in validators {
+                    let onchain_backing = onchain.get(validator).cloned().unwrap_or_default();
+                    let mined_backing = mined.get(validator).cloned().unwrap_or_default();
+
+                    let csv_entry = ExposureComparisonCsv {
+                        block_number,
+                        era,
+                        account: validator.to_string(),
+                        onchain_backing,
+                        mined_backing,
+                        backing_diff: onchain_backing as i128 - mined_backing as i128,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
 
                 Ok(())
             }
@@ -289,36 +1179,3701 @@ macro_rules! staking_ledger_checks_for {
     };
 }
 
-/// Playground operation for testing.
-macro_rules! playground_for {
-    ($runtime:ident) => {
-        paste::paste! {
-            pub(crate) fn [<playground_ $runtime>]<T: EPM::Config>(
-                exts: Vec<Ext>,
-            ) -> Result<(), anyhow::Error> {
-                use $crate::[<$runtime _runtime_exports>]::*;
+/// The CSV representation of the `headers` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HeaderCsv {
+    block_number: u32,
+    block_hash: String,
+    parent_hash: String,
+    state_root: String,
+    extrinsics_root: String,
+    /// Milliseconds since the unix epoch, as per `Timestamp::Now`.
+    timestamp: Option<u64>,
+    /// Whether the timestamp is interpolated from the surrounding blocks.
+    timestamp_interpolated: bool,
+    authority_index: Option<u32>,
+    author: Option<String>,
+}
 
-                log::info!(target: LOG_TARGET, "Transform::playground starting.");
-                gadgets::playground::<Runtime>(exts)?;
+/// Exports the header of each block in `block_hashes`, or of each `step`-th block in the range
+/// `[from, to]` if `from` is set.
+///
+/// The timestamps are read from the state of each block, and the ones which can not be read are
+/// interpolated from the exported blocks with a timestamp, see [`crate::utils::BlockTimes`]. The
+/// rows are emitted as soon as their timestamp is known, i.e. the rows without a timestamp are held
+/// back until the next block with a timestamp.
+///
+/// The author is the session validator at the index of the Babe authority in the pre-runtime
+/// digest of the header. This operation only relies on RPC queries and does not require
+/// externalities.
+pub(crate) async fn headers(
+    rpc: &SharedRpcClient,
+    block_hashes: Vec<Hash>,
+    from: Option<BlockNumber>,
+    to: Option<BlockNumber>,
+    step: u32,
+    block_time_ms: u64,
+    out: &mut Emitter,
+) -> Result<(), anyhow::Error> {
+    use codec::Decode;
+    use sp_core::{hashing::twox_128, storage::StorageKey};
 
-                Ok(())
-            }
+    log::info!(target: LOG_TARGET, "Transform::headers starting.");
+
+    let block_hashes = blocks_of_range(rpc, block_hashes, from, to, step).await?;
+
+    let timestamp_key = StorageKey([twox_128(b"Timestamp"), twox_128(b"Now")].concat());
+    let validators_key = StorageKey([twox_128(b"Session"), twox_128(b"Validators")].concat());
+
+    let mut pending: Vec<HeaderCsv> = vec![];
+    let mut block_times = crate::utils::BlockTimes::new(block_time_ms);
+    let interpolated = |mut csv_entry: HeaderCsv, block_times: &crate::utils::BlockTimes| {
+        csv_entry.timestamp = block_times.timestamp_at(csv_entry.block_number);
+        csv_entry.timestamp_interpolated = csv_entry.timestamp.is_some();
+        csv_entry
+    };
+    for block_hash in block_hashes {
+        let header = rpc
+            .header(Some(block_hash))
+            .await?
+            .ok_or(TimetravelError::Rpc {
+                error: format!("Header of block {:?} not found.", block_hash),
+            })?;
+
+        let timestamp = rpc
+            .storage(&timestamp_key, Some(block_hash))
+            .await?
+            .and_then(|now| u64::decode(&mut &now.0[..]).ok());
+        if let Some(timestamp) = timestamp {
+            block_times.insert(header.number, timestamp);
+        }
+
+        let authority_index = crate::utils::babe_authority_index(&header.digest);
+        let author = match authority_index {
+            Some(index) => rpc
+                .storage(&validators_key, Some(block_hash))
+                .await?
+                .and_then(|validators| Vec::<AccountId>::decode(&mut &validators.0[..]).ok())
+                .and_then(|validators| validators.get(index as usize).cloned())
+                .map(|author| author.to_string()),
+            None => None,
+        };
+
+        let csv_entry = HeaderCsv {
+            block_number: header.number,
+            block_hash: format!("{:?}", block_hash),
+            parent_hash: format!("{:?}", header.parent_hash),
+            state_root: format!("{:?}", header.state_root),
+            extrinsics_root: format!("{:?}", header.extrinsics_root),
+            timestamp,
+            timestamp_interpolated: false,
+            authority_index,
+            author,
+        };
+
+        if timestamp.is_none() {
+            pending.push(csv_entry);
+            continue;
+        }
+        for csv_entry in pending.drain(..) {
+            out.emit(interpolated(csv_entry, &block_times))?;
         }
+        out.emit(csv_entry)?;
+    }
+
+    // the timestamps of the last rows are extrapolated from the last block with a timestamp.
+    for csv_entry in pending {
+        out.emit(interpolated(csv_entry, &block_times))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the hashes of each `step`-th block in the range `[from, to]` if `from` is set (up to
+/// the latest finalized block if `to` is not), or `block_hashes` otherwise.
+async fn blocks_of_range(
+    rpc: &SharedRpcClient,
+    block_hashes: Vec<Hash>,
+    from: Option<BlockNumber>,
+    to: Option<BlockNumber>,
+    step: u32,
+) -> Result<Vec<Hash>, anyhow::Error> {
+    let Some(from) = from else {
+        return Ok(block_hashes);
     };
+    let to = match to {
+        Some(to) => to,
+        None => crate::commands::finalized_number(rpc).await?,
+    };
+
+    let mut hashes = vec![];
+    for number in (from..=to).step_by(step as usize) {
+        hashes.push(crate::commands::block_hash_of(rpc, number).await?);
+    }
+    Ok(hashes)
 }
 
-//min_active_stake_for!(polkadot);
-//min_active_stake_for!(kusama);
-min_active_stake_for!(westend);
+/// The CSV representation of the `track` operation result, one entry per block, tracked storage
+/// item and account.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TrackCsv {
+    block_number: u32,
+    /// The account the storage item is read for, empty for plain storage items.
+    account: Option<String>,
+    block_hash: String,
+    pallet: String,
+    item: String,
+    key: String,
+    /// The decoded value, empty if the key is not set.
+    value: Option<String>,
+}
 
-//election_analysis_for!(polkadot);
-//election_analysis_for!(kusama);
-election_analysis_for!(westend);
+/// Parses SS58 encoded `accounts`.
+pub(crate) fn parse_accounts(accounts: &[String]) -> Result<Vec<AccountId>, anyhow::Error> {
+    use sp_core::crypto::Ss58Codec;
 
-//staking_ledger_checks_for(polkadot);
-//staking_ledger_checks_for(kusama);
-staking_ledger_checks_for!(westend);
+    accounts
+        .iter()
+        .map(|account| {
+            AccountId::from_ss58check(account).map_err(|e| {
+                TimetravelError::Unsupported {
+                    reason: format!("invalid account {}: {:?}", account, e),
+                }
+                .into()
+            })
+        })
+        .collect()
+}
 
-//playground_for(polkadot);
-//playground_for(kusama);
-playground_for!(westend);
+/// Parses storage `items` given as `Pallet::Item`.
+pub(crate) fn parse_items(items: &[String]) -> Result<Vec<(&str, &str)>, anyhow::Error> {
+    items
+        .iter()
+        .map(|item| {
+            item.split_once("::").ok_or_else(|| {
+                TimetravelError::Unsupported {
+                    reason: format!("invalid storage item {}, expected Pallet::Item.", item),
+                }
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// The storage keys of the `items`, as `(account, pallet, item, key)`. The plain items have a
+/// single key, while the map items have a key per account.
+pub(crate) fn item_keys<'a>(
+    storage: &gadgets::dynamic::DynamicStorage,
+    items: &[(&'a str, &'a str)],
+    accounts: &'a [AccountId],
+) -> Result<
+    Vec<(
+        Option<&'a AccountId>,
+        &'a str,
+        &'a str,
+        sp_core::storage::StorageKey,
+    )>,
+    anyhow::Error,
+> {
+    use codec::Encode;
+    use sp_core::storage::StorageKey;
+
+    let mut keys = vec![];
+    for (pallet, item) in items.iter().copied() {
+        match storage.key(pallet, item, None) {
+            Ok(key) => keys.push((None, pallet, item, StorageKey(key))),
+            Err(_) if !accounts.is_empty() => {
+                for account in accounts.iter() {
+                    let key = storage.key(pallet, item, Some(&account.encode()))?;
+                    keys.push((Some(account), pallet, item, StorageKey(key)));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Tracks the values of storage `items` (as `Pallet::Item`) for each of the `accounts` over the
+/// blocks in `block_hashes`, or each `step`-th block in the range `[from, to]` if `from` is set,
+/// and emits them as a time series.
+///
+/// The values are fetched with a single batched point query per block and decoded with the
+/// on-chain metadata, so that no snapshot is extracted. The map items are read under the account
+/// (e.g. `System::Account` or `Staking::Ledger`), while the plain items are read once per block.
+/// The metadata (and the storage keys) are refreshed whenever the `System::LastRuntimeUpgrade` of
+/// the blocks changes.
+pub(crate) async fn track(
+    rpc: &SharedRpcClient,
+    block_hashes: Vec<Hash>,
+    accounts: &[String],
+    items: &[String],
+    range: (Option<BlockNumber>, Option<BlockNumber>, u32),
+    out: &mut Emitter,
+) -> Result<(), anyhow::Error> {
+    use gadgets::{dynamic::DynamicStorage, metadata::fetch_metadata};
+    use sp_core::{hashing::twox_128, storage::StorageKey};
+
+    log::info!(target: LOG_TARGET, "Transform::track starting.");
+
+    let accounts = parse_accounts(accounts)?;
+    let items = parse_items(items)?;
+
+    let (from, to, step) = range;
+    let block_hashes = blocks_of_range(rpc, block_hashes, from, to, step).await?;
+    let upgrade_key = StorageKey([twox_128(b"System"), twox_128(b"LastRuntimeUpgrade")].concat());
+
+    // the tracked keys, as (account, pallet, item, key), under the metadata of the last upgrade.
+    let mut upgrade = None;
+    let mut metadata = None;
+    let mut tracked: Vec<(Option<&AccountId>, &str, &str, StorageKey)> = vec![];
+
+    let mut processed = 0;
+    for block_hash in block_hashes.iter().copied() {
+        if crate::budget::exhausted().is_some() {
+            break;
+        }
+        let block_number = rpc
+            .header(Some(block_hash))
+            .await?
+            .ok_or(TimetravelError::Rpc {
+                error: format!("Header of block {:?} not found.", block_hash),
+            })?
+            .number;
+
+        let values = loop {
+            let keys = std::iter::once(upgrade_key.clone())
+                .chain(tracked.iter().map(|(_, _, _, key)| key.clone()))
+                .collect::<Vec<_>>();
+            let values = rpc
+                .query_storage_at(keys, Some(block_hash))
+                .await?
+                .into_iter()
+                .flat_map(|set| set.changes)
+                .collect::<BTreeMap<_, _>>();
+
+            // the keys are only rebuilt after an upgrade, and the block queried again if the
+            // storage layout of the tracked items changed.
+            let block_upgrade = values.get(&upgrade_key).cloned().flatten();
+            if metadata.is_some() && block_upgrade == upgrade {
+                break values;
+            }
+            upgrade = block_upgrade;
+            let block_metadata = fetch_metadata(rpc, block_hash).await?;
+
+            let keys = item_keys(&DynamicStorage::new(&block_metadata), &items, &accounts)?;
+            metadata = Some(block_metadata);
+
+            let unchanged = keys.iter().map(|k| &k.3).eq(tracked.iter().map(|k| &k.3));
+            tracked = keys;
+            if unchanged {
+                break values;
+            }
+        };
+
+        let storage = DynamicStorage::new(metadata.as_ref().expect("fetched above; qed."));
+        for (account, pallet, item, key) in tracked.iter() {
+            let value = values.get(key).cloned().flatten();
+
+            let csv_entry = TrackCsv {
+                block_number,
+                account: account.map(|a| a.to_string()),
+                block_hash: format!("{:?}", block_hash),
+                pallet: pallet.to_string(),
+                item: item.to_string(),
+                key: sp_core::bytes::to_hex(&key.0, false),
+                value: storage
+                    .decode_stored(pallet, item, value.as_ref().map(|v| &v.0[..]))?
+                    .map(|v| v.to_string()),
+            };
+            out.emit(csv_entry)?;
+        }
+
+        crate::budget::spend();
+        processed += 1;
+    }
+    crate::budget::report_stop(processed, &block_hashes);
+
+    Ok(())
+}
+
+/// The CSV representation of the `constants` operation result, one entry per constant.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ConstantCsv {
+    block_number: u32,
+    block_hash: String,
+    pallet: String,
+    constant: String,
+    value: String,
+}
+
+/// Dumps the runtime constants of the given pallets at each block.
+///
+/// This operation only relies on RPC queries and does not require externalities.
+pub(crate) async fn constants(
+    rpc: &SharedRpcClient,
+    block_hashes: Vec<Hash>,
+    out: &mut Emitter,
+    pallets: &[String],
+) -> Result<(), anyhow::Error> {
+    log::info!(target: LOG_TARGET, "Transform::constants starting.");
+
+    for block_hash in block_hashes {
+        let block_number = rpc
+            .header(Some(block_hash))
+            .await?
+            .ok_or(TimetravelError::Rpc {
+                error: format!("Header of block {:?} not found.", block_hash),
+            })?
+            .number;
+        let metadata = gadgets::metadata::fetch_metadata(rpc, block_hash).await?;
+
+        let constants = gadgets::metadata::pallet_constants(&metadata, pallets);
+        log::info!(
+            target: LOG_TARGET,
+            "Transform::constants: {} constants at block #{}.",
+            constants.len(),
+            block_number,
+        );
+
+        for constant in constants {
+            let csv_entry = ConstantCsv {
+                block_number,
+                block_hash: format!("{:?}", block_hash),
+                pallet: constant.pallet,
+                constant: constant.name,
+                value: constant.value,
+            };
+
+            out.emit(csv_entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `state_diff` operation result, one entry per storage item (or
+/// per key, with `--keys`) changed between two consecutive blocks.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StateDiffCsv {
+    block_from: u32,
+    block_to: u32,
+    pallet: String,
+    item: String,
+    /// The hex encoded key, with `--keys`.
+    key: Option<String>,
+    /// The decoded map keys, with `--keys`.
+    map_keys: Option<String>,
+    /// The change of the key (`added`, `removed` or `changed`), with `--keys`.
+    change: Option<String>,
+    added: u32,
+    removed: u32,
+    changed: u32,
+    /// The decoded value at `block_from`, with `--keys`.
+    value_from: Option<String>,
+    /// The decoded value at `block_to`, with `--keys`.
+    value_to: Option<String>,
+}
+
+/// Compares the state of each pair of consecutive `blocks` (ordered by block number, with their
+/// block hash), restricted to the storage of `pallets` if any.
+///
+/// The keys are identified and their values decoded with the on-chain metadata of each block,
+/// regardless of the runtime mode, so that the values of both blocks are decoded with their own
+/// types. The keys of unknown storage items are reported under the `unknown` pallet.
+pub(crate) async fn state_diff(
+    rpc: &SharedRpcClient,
+    blocks: Vec<(u32, Hash, Ext)>,
+    pallets: &[String],
+    keys: bool,
+    out: &mut Emitter,
+) -> Result<(), anyhow::Error> {
+    use gadgets::compose::KeyChange;
+    use gadgets::metadata::{fetch_metadata, StorageKeyIndex};
+
+    log::info!(target: LOG_TARGET, "Transform::state_diff starting.");
+
+    let prefixes = pallets
+        .iter()
+        .map(|pallet| sp_core::hashing::twox_128(pallet.as_bytes()))
+        .collect::<Vec<_>>();
+    let in_pallets =
+        |key: &[u8]| prefixes.is_empty() || prefixes.iter().any(|p| key.starts_with(p));
+
+    let mut previous = None;
+    for (block_to, hash_to, mut ext) in blocks {
+        let after = gadgets::compose::storage_pairs(&mut ext)
+            .into_iter()
+            .filter(|(key, _)| in_pallets(key))
+            .collect::<BTreeMap<_, _>>();
+        let metadata_to = fetch_metadata(rpc, hash_to).await?;
+
+        let Some((block_from, metadata_from, before)) =
+            previous.replace((block_to, metadata_to, after))
+        else {
+            continue;
+        };
+        let (_, metadata_to, after) = previous.as_ref().expect("replaced above; qed.");
+        let (index_from, index_to) = (
+            StorageKeyIndex::new(&metadata_from),
+            StorageKeyIndex::new(metadata_to),
+        );
+
+        let diff = gadgets::compose::storage_diff(&before, after);
+        log::info!(
+            target: LOG_TARGET,
+            "Transform::state_diff #{} -> #{}: {} keys changed.",
+            block_from,
+            block_to,
+            diff.len(),
+        );
+
+        // the keys (and their items) are identified with the metadata of the block they are in.
+        let mut items = BTreeMap::<(String, String), (u32, u32, u32)>::new();
+        for (key, change) in diff {
+            let info = match change {
+                KeyChange::Removed => index_from.lookup(key),
+                _ => index_to.lookup(key),
+            };
+            let (pallet, item) = info
+                .as_ref()
+                .map_or(("unknown".to_string(), "unknown".to_string()), |i| {
+                    (i.pallet.clone(), i.item.clone())
+                });
+
+            if !keys {
+                let counts = items.entry((pallet, item)).or_default();
+                match change {
+                    KeyChange::Added => counts.0 += 1,
+                    KeyChange::Removed => counts.1 += 1,
+                    KeyChange::Changed => counts.2 += 1,
+                }
+                continue;
+            }
+
+            let csv_entry = StateDiffCsv {
+                block_from,
+                block_to,
+                pallet,
+                item,
+                key: Some(sp_core::bytes::to_hex(key, false)),
+                map_keys: info.map(|i| i.map_keys.join(";")),
+                change: Some(format!("{:?}", change).to_lowercase()),
+                added: (change == KeyChange::Added) as u32,
+                removed: (change == KeyChange::Removed) as u32,
+                changed: (change == KeyChange::Changed) as u32,
+                value_from: before
+                    .get(key)
+                    .map(|value| index_from.format_stored(key, value)),
+                value_to: after
+                    .get(key)
+                    .map(|value| index_to.format_stored(key, value)),
+            };
+            out.emit(csv_entry)?;
+        }
+
+        for ((pallet, item), (added, removed, changed)) in items {
+            let csv_entry = StateDiffCsv {
+                block_from,
+                block_to,
+                pallet,
+                item,
+                key: None,
+                map_keys: None,
+                change: None,
+                added,
+                removed,
+                changed,
+                value_from: None,
+                value_to: None,
+            };
+            out.emit(csv_entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The CSV representation of the `stress_test` operation result, one entry per scale factor.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StressTestCsv {
+    block_number: u32,
+    factor: u32,
+    voters: u32,
+    snapshot_voters: u32,
+    snapshot_targets: u32,
+    snapshot_size: usize,
+    desired_targets: u32,
+    solution_voters: u32,
+    solution_length: u32,
+    max_solution_length: u32,
+    solution_weight: u64,
+    max_solution_weight: u64,
+    phrag_min_stake: u128,
+    phrag_sum_stake: u128,
+    /// Whether the snapshot dropped voters or the solution was trimmed to fit the bounds.
+    bounds_exceeded: bool,
+}
+
+/// Scales the electorate of the externalities and forecasts at which scale the election bounds
+/// and solution limits are exceeded.
+///
+/// For each scale factor (in ascending order), the nominators of the original electorate are
+/// duplicated until the number of nominators is `factor` times the original, the snapshot is
+/// recomputed and a solution is mined. The bounds are exceeded when the snapshot does not include
+/// all the voters or when the mined solution is trimmed to fit the length and weight limits.
+///
+/// The desired number of winners, i.e. the validator count, can be overridden with
+/// `desired_targets`.
+macro_rules! stress_test_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<stress_test_ $runtime>]<T: EPM::Config + Staking::Config>(
+                mut exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+                mut factors: Vec<u32>,
+                seed: u64,
+                desired_targets: Option<u32>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use frame_election_provider_support::{NposSolution, SortedListProvider};
+
+                log::info!(target: LOG_TARGET, "Transform::stress_test starting.");
+
+                assert!(exts.len() == 1, "stress_test expects only 1 snapshot at a time");
+                let mut ext = &mut exts[0];
+
+                let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                let nominators = ext.execute_with(|| Staking::Nominators::<Runtime>::iter_keys().collect::<Vec<_>>());
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                factors.sort();
+                factors.dedup();
+
+                let mut copies = 1;
+                for factor in factors.into_iter().filter(|f| *f > 0) {
+                    let added = gadgets::synthetic::duplicate_nominators::<Runtime>(&mut ext, &nominators, copies..factor, seed)?;
+                    copies = copies.max(factor);
+
+                    let (snapshot_metadata, snapshot_size) = gadgets::compute_and_store_unbounded_snapshot::<Runtime>(&mut ext, desired_targets)?;
+                    let voters = ext.execute_with(|| <Runtime as Staking::Config>::VoterList::count());
+                    let winners = gadgets::desired_targets::<Runtime>(&mut ext);
+
+                    let raw_solution = gadgets::mine_with::<Runtime>(&solver, &mut ext, false)?;
+                    let solution_size = gadgets::solution_size::<Runtime>(&mut ext, &raw_solution)?;
+                    let solution_voters = raw_solution.solution.voter_count() as u32;
+
+                    // voters with no support in the solution are not accounted for, thus the
+                    // solution voters may be lower than the snapshot voters without trimming.
+                    let bounds_exceeded = snapshot_metadata.voters < voters ||
+                        solution_size.length >= solution_size.max_length ||
+                        solution_size.weight >= solution_size.max_weight;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::stress_test: x{} ({} nominators added), {} voters, {} in snapshot, {} in solution, bounds exceeded: {}.",
+                        factor,
+                        added,
+                        voters,
+                        snapshot_metadata.voters,
+                        solution_voters,
+                        bounds_exceeded,
+                    );
+
+                    let csv_entry = StressTestCsv {
+                        block_number,
+                        factor,
+                        voters,
+                        snapshot_voters: snapshot_metadata.voters,
+                        snapshot_targets: snapshot_metadata.targets,
+                        snapshot_size,
+                        desired_targets: winners,
+                        solution_voters,
+                        solution_length: solution_size.length,
+                        max_solution_length: solution_size.max_length,
+                        solution_weight: solution_size.weight,
+                        max_solution_weight: solution_size.max_weight,
+                        phrag_min_stake: raw_solution.score.minimal_stake,
+                        phrag_sum_stake: raw_solution.score.sum_stake,
+                        bounds_exceeded,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `desired_targets_what_if` operation result, one entry per block
+/// and desired targets, the first one being the on-chain desired targets.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DesiredTargetsWhatIfCsv {
+    block_number: u32,
+    phase: String,
+    snapshot_source: String,
+    /// Whether the entry is the election with the on-chain desired targets.
+    baseline: bool,
+    desired_targets: u32,
+    /// The desired targets applied, i.e. capped to the number of targets of the snapshot.
+    winners: u32,
+    phrag_min_stake: u128,
+    phrag_sum_stake: u128,
+    phrag_sum_stake_squared: u128,
+    dpos_min_stake: u128,
+    dpos_sum_stake: u128,
+    dpos_sum_stake_squared: u128,
+    /// The difference of `phrag_min_stake` with the one of the baseline election.
+    phrag_min_stake_diff: i128,
+    phrag_min_stake_ratio: Option<f64>,
+    phrag_backing_variance: Option<f64>,
+}
+
+/// Simulates the election with other desired numbers of winners.
+///
+/// For each desired targets, `EPM::DesiredTargets` is overridden (capped to the number of targets
+/// of the snapshot) and the Phragmen and (pro rata) DPoS elections are mined from the snapshot
+/// of the block. The override is simulated, i.e. rolled back before the next one, so that all the
+/// elections run over the same state.
+macro_rules! desired_targets_what_if_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<desired_targets_what_if_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+                desired_targets: Vec<u32>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::desired_targets_what_if starting.");
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
+                    let onchain = gadgets::desired_targets::<Runtime>(&mut ext);
+
+                    let mut baseline_min_stake = None;
+                    for (baseline, requested) in std::iter::once((true, onchain)).chain(desired_targets.iter().map(|d| (false, *d))) {
+                        let (winners, phrag_solution, dpos_score) = gadgets::simulate(&mut ext, |mut ext| -> Result<_, anyhow::Error> {
+                            let winners = gadgets::override_desired_targets::<Runtime>(&mut ext, requested)?;
+                            let raw_solution = gadgets::mine_with::<Runtime>(&solver, &mut ext, false)?;
+                            let (dpos_score, _) = gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::ProRata)?;
+                            Ok((winners, raw_solution, dpos_score))
+                        })?;
+                        let phrag_score = phrag_solution.score;
+                        let baseline_min_stake = *baseline_min_stake.get_or_insert(phrag_score.minimal_stake);
+                        let metrics = gadgets::score_metrics(&phrag_score, gadgets::solution_winners::<Runtime>(&phrag_solution));
+
+                        log::info!(
+                            target: LOG_TARGET,
+                            "Transform::desired_targets_what_if at #{}: {} desired targets ({} winners), phragmen min stake {}.",
+                            block_number,
+                            requested,
+                            winners,
+                            phrag_score.minimal_stake,
+                        );
+
+                        let csv_entry = DesiredTargetsWhatIfCsv {
+                            block_number,
+                            phase: snapshot.phase.clone(),
+                            snapshot_source: format!("{:?}", snapshot.source),
+                            baseline,
+                            desired_targets: requested,
+                            winners,
+                            phrag_min_stake: phrag_score.minimal_stake,
+                            phrag_sum_stake: phrag_score.sum_stake,
+                            phrag_sum_stake_squared: phrag_score.sum_stake_squared,
+                            dpos_min_stake: dpos_score.minimal_stake,
+                            dpos_sum_stake: dpos_score.sum_stake,
+                            dpos_sum_stake_squared: dpos_score.sum_stake_squared,
+                            phrag_min_stake_diff: phrag_score.minimal_stake as i128 - baseline_min_stake as i128,
+                            phrag_min_stake_ratio: metrics.map(|m| m.min_stake_ratio),
+                            phrag_backing_variance: metrics.map(|m| m.backing_variance),
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `targets_what_if` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TargetsWhatIfCsv {
+    block_number: u32,
+    phase: String,
+    snapshot_source: String,
+    targets: u32,
+    removed_targets: u32,
+    added_targets: u32,
+    winners: u32,
+    phrag_min_stake: u128,
+    phrag_sum_stake: u128,
+    phrag_sum_stake_squared: u128,
+    what_if_winners: u32,
+    what_if_phrag_min_stake: u128,
+    what_if_phrag_sum_stake: u128,
+    what_if_phrag_sum_stake_squared: u128,
+    phrag_min_stake_diff: i128,
+    /// The accounts elected in the simulation only, separated by `;`.
+    entered: String,
+    /// The accounts elected in the actual snapshot only, separated by `;`.
+    left: String,
+}
+
+/// Simulates the election with some targets dropped from or injected in the snapshot.
+///
+/// At each block, the Phragmen election is mined from the snapshot as is and from the snapshot
+/// with the `exclude_targets` dropped and the `include_targets` injected, e.g. to assess the
+/// effect of a validator retiring or being slashed out of the set. The accounts which enter or
+/// leave the set of winners are reported.
+macro_rules! targets_what_if_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<targets_what_if_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+                exclude_targets: Vec<String>,
+                include_targets: Vec<String>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use sp_core::crypto::Ss58Codec;
+
+                log::info!(target: LOG_TARGET, "Transform::targets_what_if starting.");
+
+                let parse = |account: &String| {
+                    AccountId::from_ss58check(account).map_err(|e| TimetravelError::Unsupported {
+                        reason: format!("invalid account {}: {:?}", account, e),
+                    })
+                };
+                let excluded = exclude_targets.iter().map(parse).collect::<Result<BTreeSet<_>, _>>()?;
+                let included = include_targets.iter().map(parse).collect::<Result<Vec<_>, _>>()?;
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
+
+                    let (score, winners) = gadgets::mined_winners::<Runtime>(&solver, &mut ext)?;
+                    let (removed, added, what_if_score, what_if_winners) = gadgets::simulate(&mut ext, |mut ext| -> Result<_, anyhow::Error> {
+                        let (removed, added) = gadgets::edit_snapshot_targets::<Runtime>(&mut ext, &excluded, &included)?;
+                        let (score, winners) = gadgets::mined_winners::<Runtime>(&solver, &mut ext)?;
+                        Ok((removed, added, score, winners))
+                    })?;
+
+                    if removed < excluded.len() as u32 {
+                        crate::warnings::warn("unknown_target", format!(
+                            "Transform::targets_what_if at #{}: {} of the excluded accounts are not targets.",
+                            block_number,
+                            excluded.len() as u32 - removed,
+                        ));
+                    }
+
+                    let (winners_set, what_if_set) = (
+                        winners.iter().collect::<BTreeSet<_>>(),
+                        what_if_winners.iter().collect::<BTreeSet<_>>(),
+                    );
+                    let join = |accounts: Vec<&&AccountId>| accounts.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(";");
+                    let entered = join(what_if_set.difference(&winners_set).collect());
+                    let left = join(winners_set.difference(&what_if_set).collect());
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::targets_what_if at #{}: phragmen min stake {} -> {}, entered: [{}], left: [{}].",
+                        block_number,
+                        score.minimal_stake,
+                        what_if_score.minimal_stake,
+                        entered,
+                        left,
+                    );
+
+                    let csv_entry = TargetsWhatIfCsv {
+                        block_number,
+                        phase: snapshot.phase,
+                        snapshot_source: format!("{:?}", snapshot.source),
+                        targets: snapshot.metadata.targets,
+                        removed_targets: removed,
+                        added_targets: added,
+                        winners: winners.len() as u32,
+                        phrag_min_stake: score.minimal_stake,
+                        phrag_sum_stake: score.sum_stake,
+                        phrag_sum_stake_squared: score.sum_stake_squared,
+                        what_if_winners: what_if_winners.len() as u32,
+                        what_if_phrag_min_stake: what_if_score.minimal_stake,
+                        what_if_phrag_sum_stake: what_if_score.sum_stake,
+                        what_if_phrag_sum_stake_squared: what_if_score.sum_stake_squared,
+                        phrag_min_stake_diff: what_if_score.minimal_stake as i128 - score.minimal_stake as i128,
+                        entered,
+                        left,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `min_bond_what_if` operation result, one entry per block and
+/// minimum bond, the first one being the on-chain `MinNominatorBond`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MinBondWhatIfCsv {
+    block_number: u32,
+    phase: String,
+    snapshot_source: String,
+    /// Whether the entry is the election with the on-chain `MinNominatorBond`.
+    baseline: bool,
+    min_bond: u128,
+    voters: u32,
+    dropped_voters: u32,
+    snapshot_size: usize,
+    winners: u32,
+    phrag_min_stake: u128,
+    phrag_sum_stake: u128,
+    phrag_sum_stake_squared: u128,
+    /// The difference of `phrag_min_stake` with the one of the baseline election.
+    phrag_min_stake_diff: i128,
+    /// The minimum stake of the voters of the snapshot, i.e. the minimum active stake.
+    min_active_stake: Option<u64>,
+}
+
+/// Simulates the election with higher minimum nominator bonds.
+///
+/// For each minimum bond, the nominators whose active bond is lower are dropped from the election
+/// snapshot (as if chilled) and the Phragmen election is mined. Each simulation is rolled back
+/// before the next one, so that all the elections run over the same state.
+macro_rules! min_bond_what_if_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<min_bond_what_if_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+                min_bonds: Vec<u128>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::min_bond_what_if starting.");
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
+                    let onchain: u128 = ext.execute_with(|| Staking::MinNominatorBond::<Runtime>::get().into());
+
+                    let mut baseline_min_stake = None;
+                    for (baseline, min_bond) in std::iter::once((true, onchain)).chain(min_bonds.iter().map(|b| (false, *b))) {
+                        let (dropped, snapshot_size, winners, score, min_active_stake) = gadgets::simulate(&mut ext, |mut ext| -> Result<_, anyhow::Error> {
+                            let (dropped, snapshot_size) = gadgets::filter_snapshot_voters::<Runtime>(&mut ext, min_bond)?;
+                            let (score, winners) = gadgets::mined_winners::<Runtime>(&solver, &mut ext)?;
+                            let min_active_stake = gadgets::snapshot_min_stake::<Runtime>(&mut ext);
+                            Ok((dropped, snapshot_size, winners.len() as u32, score, min_active_stake))
+                        })?;
+                        let baseline_min_stake = *baseline_min_stake.get_or_insert(score.minimal_stake);
+
+                        log::info!(
+                            target: LOG_TARGET,
+                            "Transform::min_bond_what_if at #{}: min bond {}, {} voters dropped, phragmen min stake {}.",
+                            block_number,
+                            min_bond,
+                            dropped,
+                            score.minimal_stake,
+                        );
+
+                        let csv_entry = MinBondWhatIfCsv {
+                            block_number,
+                            phase: snapshot.phase.clone(),
+                            snapshot_source: format!("{:?}", snapshot.source),
+                            baseline,
+                            min_bond,
+                            voters: snapshot.metadata.voters - dropped,
+                            dropped_voters: dropped,
+                            snapshot_size,
+                            winners,
+                            phrag_min_stake: score.minimal_stake,
+                            phrag_sum_stake: score.sum_stake,
+                            phrag_sum_stake_squared: score.sum_stake_squared,
+                            phrag_min_stake_diff: score.minimal_stake as i128 - baseline_min_stake as i128,
+                            min_active_stake,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `payee_distribution` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PayeeDistributionCsv {
+    block_number: u32,
+    staked: u32,
+    staked_stake: u128,
+    stash: u32,
+    stash_stake: u128,
+    controller: u32,
+    controller_stake: u128,
+    account: u32,
+    account_stake: u128,
+    none: u32,
+    none_stake: u128,
+}
+
+/// Computes the distribution of reward destinations of the bonded accounts, by number of accounts
+/// and active stake. One entry is written per externalities, so that the evolution of the
+/// compounding behaviour can be followed over a range of blocks.
+macro_rules! payee_distribution_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<payee_distribution_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::payee_distribution starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let distribution = gadgets::staking_stats::payee_distribution::<Runtime>(&mut ext);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::payee_distribution at #{}: {:?}",
+                        block_number,
+                        distribution,
+                    );
+
+                    let csv_entry = PayeeDistributionCsv {
+                        block_number,
+                        staked: distribution.staked.count,
+                        staked_stake: distribution.staked.stake,
+                        stash: distribution.stash.count,
+                        stash_stake: distribution.stash.stake,
+                        controller: distribution.controller.count,
+                        controller_stake: distribution.controller.stake,
+                        account: distribution.account.count,
+                        account_stake: distribution.account.stake,
+                        none: distribution.none.count,
+                        none_stake: distribution.none.stake,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `nominations_distribution` operation result, one entry per
+/// block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct NominationsDistributionCsv {
+    block_number: u32,
+    nominators: u32,
+    max_nominations: u32,
+    at_cap: u32,
+    at_quota: u32,
+    avg_targets: f64,
+    stake_weighted_avg_targets: f64,
+    /// The number of nominators per number of targets, as `targets:nominators` pairs separated
+    /// by `;`.
+    histogram: String,
+}
+
+/// Computes the distribution of the number of targets per nominator, how many nominators use all
+/// the nominations allowed (`MaxNominations`) and the stake-weighted average of targets.
+macro_rules! nominations_distribution_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<nominations_distribution_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::nominations_distribution starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let distribution = gadgets::staking_stats::nominations_distribution::<Runtime>(&mut ext);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::nominations_distribution at #{}: {} nominators, {} at cap, {:.2} stake-weighted average targets.",
+                        block_number,
+                        distribution.nominators,
+                        distribution.at_cap,
+                        distribution.stake_weighted_avg_targets,
+                    );
+
+                    let csv_entry = NominationsDistributionCsv {
+                        block_number,
+                        nominators: distribution.nominators,
+                        max_nominations: distribution.max_nominations,
+                        at_cap: distribution.at_cap,
+                        at_quota: distribution.at_quota,
+                        avg_targets: distribution.avg_targets,
+                        stake_weighted_avg_targets: distribution.stake_weighted_avg_targets,
+                        histogram: distribution
+                            .histogram
+                            .iter()
+                            .map(|(targets, stakers)| format!("{}:{}", targets, stakers.count))
+                            .collect::<Vec<_>>()
+                            .join(";"),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `voter_distribution` operation result, one entry per block and
+/// bucket. The statistics over all the voters are repeated in each entry of the block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct VoterDistributionCsv {
+    block_number: u32,
+    snapshot_source: String,
+    /// Either `decile`, `votes` or `role`.
+    kind: String,
+    /// The decile (1 to 10), the number of votes or the role (`nominator` or `validator`).
+    bucket: String,
+    voters: u32,
+    stake: u128,
+    min_stake: u128,
+    max_stake: u128,
+    /// The share of the stake of all the voters in the bucket.
+    stake_share: f64,
+    total_voters: u32,
+    total_stake: u128,
+    mean_stake: f64,
+    median_stake: f64,
+    gini: f64,
+}
+
+/// Computes the stake distribution of the voters of the election snapshot (the on-chain one or one
+/// created at the block), i.e. of the electorate as seen by the election rather than of all the
+/// bonded accounts.
+macro_rules! voter_distribution_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<voter_distribution_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::voter_distribution starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
+                    let distribution = gadgets::staking_stats::voter_distribution::<Runtime>(&mut ext)?;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::voter_distribution at #{}: {} voters, median stake {}, gini {:.3}.",
+                        block_number,
+                        distribution.voters.count,
+                        distribution.median_stake,
+                        distribution.gini,
+                    );
+
+                    for bucket in distribution.buckets.iter() {
+                        let csv_entry = VoterDistributionCsv {
+                            block_number,
+                            snapshot_source: format!("{:?}", snapshot.source),
+                            kind: bucket.kind.to_string(),
+                            bucket: bucket.bucket.clone(),
+                            voters: bucket.voters.count,
+                            stake: bucket.voters.stake,
+                            min_stake: bucket.min_stake,
+                            max_stake: bucket.max_stake,
+                            stake_share: bucket.voters.stake as f64 / distribution.voters.stake.max(1) as f64,
+                            total_voters: distribution.voters.count,
+                            total_stake: distribution.voters.stake,
+                            mean_stake: distribution.mean_stake,
+                            median_stake: distribution.median_stake,
+                            gini: distribution.gini,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `commission_audit` operation result, one entry per block and
+/// simulated minimum commission. Commissions are in percent.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CommissionAuditCsv {
+    block_number: u32,
+    validators: u32,
+    avg_commission: f64,
+    median_commission: f64,
+    onchain_min_commission: f64,
+    /// Validators below the on-chain `MinCommission`, which should never happen.
+    below_onchain_min: u32,
+    min_commission: f64,
+    below_min: u32,
+    avg_increase: f64,
+    /// Percentage of the era payout which would move from nominators to validators.
+    payout_shift: f64,
+}
+
+/// Reports the distribution of validator commissions and flags the validators below the on-chain
+/// `MinCommission`. For each hypothetical minimum commission, reports how many validators would
+/// be affected and how much of the era payout would move from nominators to validators.
+macro_rules! commission_audit_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<commission_audit_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                min_commissions: Vec<f64>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::staking_stats::CommissionStats;
+
+                log::info!(target: LOG_TARGET, "Transform::commission_audit starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let (commissions, onchain_min) = gadgets::staking_stats::validator_commissions::<Runtime>(&mut ext);
+                    let onchain = CommissionStats::new(&commissions, onchain_min);
+
+                    if onchain.below_min > 0 {
+                        crate::warnings::warn("below_min_commission", format!(
+                            "Transform::commission_audit at #{}: {} validators below the minimum commission of {}%.",
+                            block_number,
+                            onchain.below_min,
+                            onchain_min * 100.0,
+                        ));
+                    }
+
+                    let simulated = if min_commissions.is_empty() {
+                        vec![onchain.clone()]
+                    } else {
+                        min_commissions.iter().map(|m| CommissionStats::new(&commissions, m / 100.0)).collect()
+                    };
+
+                    for stats in simulated {
+                        let csv_entry = CommissionAuditCsv {
+                            block_number,
+                            validators: onchain.validators,
+                            avg_commission: onchain.avg_commission * 100.0,
+                            median_commission: onchain.median_commission * 100.0,
+                            onchain_min_commission: onchain_min * 100.0,
+                            below_onchain_min: onchain.below_min,
+                            min_commission: stats.min_commission * 100.0,
+                            below_min: stats.below_min,
+                            avg_increase: stats.avg_increase * 100.0,
+                            payout_shift: stats.payout_shift * 100.0,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `waiting_validators` operation result, one entry per waiting
+/// validator.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct WaitingValidatorCsv {
+    block_number: u32,
+    era: u32,
+    rank: usize,
+    /// The stash of the validator.
+    account: String,
+    self_stake: u128,
+    nominators: u32,
+    approval_stake: u128,
+    min_active_backing: u128,
+    /// Backing missing to reach the minimum active backing. Negative if the approval stake is
+    /// already above it.
+    distance: i128,
+}
+
+/// Compares the waiting validators with the active set of the era.
+///
+/// The active set and its backing are read from the exposures of the active era. The waiting
+/// validators are ranked by their approval stake (self stake plus the full stake of their
+/// nominators), which is compared with the lowest backing of the active set.
+macro_rules! waiting_validators_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<waiting_validators_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                top: usize,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::waiting_validators starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let era = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let active = gadgets::era_exposures::<Runtime>(&mut ext, era)
+                        .into_iter()
+                        .collect::<std::collections::BTreeMap<_, _>>();
+                    let min_active_backing = active.values().min().cloned().unwrap_or_default();
+
+                    let mut waiting = gadgets::staking_stats::approval_stakes::<Runtime>(&mut ext)
+                        .into_iter()
+                        .filter(|(validator, _)| !active.contains_key(validator))
+                        .collect::<Vec<_>>();
+                    waiting.sort_by(|(_, a), (_, b)| b.approval_stake.cmp(&a.approval_stake));
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::waiting_validators at #{}: {} active, {} waiting, min active backing {}.",
+                        block_number,
+                        active.len(),
+                        waiting.len(),
+                        min_active_backing,
+                    );
+
+                    for (rank, (validator, approval)) in waiting.into_iter().take(top).enumerate() {
+                        let csv_entry = WaitingValidatorCsv {
+                            block_number,
+                            era,
+                            rank: rank + 1,
+                            account: validator.to_string(),
+                            self_stake: approval.self_stake,
+                            nominators: approval.nominators,
+                            approval_stake: approval.approval_stake,
+                            min_active_backing,
+                            distance: min_active_backing as i128 - approval.approval_stake as i128,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `decentralization` operation result, one entry per entity of the
+/// active set and block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DecentralizationCsv {
+    block_number: u32,
+    era: u32,
+    /// The smallest stash of the entity.
+    account: String,
+    validators: u32,
+    /// The stashes of the validators of the entity, separated by `;`.
+    members: String,
+    /// The heuristics which grouped the validators of the entity, separated by `;`.
+    heuristics: String,
+    backing: u128,
+    backing_share: f64,
+    /// The number of entities of the active set.
+    entities: u32,
+    nakamoto_coefficient: u32,
+    /// The number of entities of the nominators, with `--entities`.
+    nominator_entities: Option<u32>,
+}
+
+/// Reports the backing of the entities of the active set of each block, largest first, along with
+/// the Nakamoto coefficient of the set.
+///
+/// With `entities`, the validators are grouped by the known-entity heuristics of
+/// [`gadgets::entities`], so that the decentralization is not overestimated by operators running
+/// many validators.
+macro_rules! decentralization_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<decentralization_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                entities: bool,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::entities::{self, Clusters};
+
+                log::info!(target: LOG_TARGET, "Transform::decentralization starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let (era, set) = gadgets::flows::active_set::<Runtime>(&mut ext)?;
+                    let validators = set.keys().cloned().collect::<BTreeSet<_>>();
+
+                    let clusters = match entities {
+                        true => {
+                            if !gadgets::has_pallet_storage(&mut ext, "Proxy") {
+                                crate::warnings::warn("missing_proxies", format!(
+                                    "Transform::decentralization #{}: no Proxy storage, the proxy heuristic is skipped.",
+                                    block_number,
+                                ));
+                            }
+                            entities::clusters::<Runtime>(&mut ext, &validators)
+                        }
+                        false => Clusters::default(),
+                    };
+                    let nominator_entities = match entities {
+                        true => {
+                            let nominators = ext.execute_with(|| Staking::Nominators::<Runtime>::iter_keys().collect::<BTreeSet<_>>());
+                            Some(clusters.entities(&nominators).len() as u32)
+                        }
+                        false => None,
+                    };
+                    let mut groups = clusters
+                        .entities(&validators)
+                        .into_values()
+                        .map(|entity| (entity.members.iter().map(|v| set[v]).sum::<u128>(), entity))
+                        .collect::<Vec<_>>();
+                    groups.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+                    let total = groups.iter().map(|(backing, _)| backing).sum::<u128>();
+                    let nakamoto_coefficient = entities::nakamoto_coefficient(groups.iter().map(|(backing, _)| *backing));
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::decentralization #{}: {} validators, {} entities, Nakamoto coefficient {}.",
+                        block_number,
+                        validators.len(),
+                        groups.len(),
+                        nakamoto_coefficient,
+                    );
+
+                    let join = |items: Vec<String>| items.join(";");
+                    for (backing, entity) in groups.iter() {
+                        let csv_entry = DecentralizationCsv {
+                            block_number,
+                            era,
+                            account: entity.members[0].to_string(),
+                            validators: entity.members.len() as u32,
+                            members: join(entity.members.iter().map(|m| m.to_string()).collect()),
+                            heuristics: join(entity.heuristics.iter().map(|h| h.to_string()).collect()),
+                            backing: *backing,
+                            backing_share: match total {
+                                0 => 0.0,
+                                _ => *backing as f64 / total as f64,
+                            },
+                            entities: groups.len() as u32,
+                            nakamoto_coefficient,
+                            nominator_entities,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `staking_summary` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StakingSummaryCsv {
+    block_number: u32,
+    era: u32,
+    total_staked: u128,
+    total_issuance: Option<u128>,
+    staking_rate: Option<f64>,
+    validators: u32,
+    nominators: u32,
+    active_validators: u32,
+    avg_backing: u128,
+    median_backing: u128,
+    min_active_stake: u128,
+}
+
+/// Computes a lightweight summary of the staking state at each block.
+///
+/// The staking rate requires the total issuance, which is only available in snapshots extracted
+/// with the `Balances::TotalIssuance` key.
+macro_rules! staking_summary_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<staking_summary_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::staking_summary starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let era = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let backings = gadgets::era_exposures::<Runtime>(&mut ext, era)
+                        .into_iter()
+                        .map(|(_, backing)| backing)
+                        .collect::<Vec<_>>();
+                    let summary = gadgets::staking_stats::staking_summary::<Runtime>(&mut ext, era, backings);
+                    let min_active_stake = gadgets::min_active_stake::<Runtime>(&mut ext);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::staking_summary at #{}: {:?}",
+                        block_number,
+                        summary,
+                    );
+
+                    let csv_entry = StakingSummaryCsv {
+                        block_number,
+                        era,
+                        total_staked: summary.total_staked,
+                        total_issuance: summary.total_issuance,
+                        staking_rate: summary.staking_rate(),
+                        validators: summary.validators,
+                        nominators: summary.nominators,
+                        active_validators: summary.active_validators,
+                        avg_backing: summary.avg_backing,
+                        median_backing: summary.median_backing,
+                        min_active_stake: min_active_stake.into(),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `election_history` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ElectionHistoryCsv {
+    block_number: u32,
+    round: u32,
+    phase: String,
+    queued_compute: Option<String>,
+    queued_minimal_stake: Option<u128>,
+    queued_sum_stake: Option<u128>,
+    /// How the election of the round was resolved, i.e. the compute of the last queued solution
+    /// seen in the round or `Emergency`, empty if unknown within the range.
+    round_compute: Option<String>,
+}
+
+/// Builds the timeline of the election provider over a range of blocks.
+///
+/// The queued solution only exists between the end of the unsigned phase and the election, so
+/// the range is expected to include the last blocks of each round. A round is reported as
+/// `Emergency` if a block is in the `Emergency` phase or deposits the `ElectionFailed` event.
+macro_rules! election_history_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<election_history_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::election_history starting.");
+
+                let mut entries = exts
+                    .into_iter()
+                    .map(|mut ext| {
+                        (gadgets::block_number::<Runtime>(&mut ext), gadgets::election_status::<Runtime>(&mut ext))
+                    })
+                    .collect::<Vec<_>>();
+                entries.sort_by_key(|(block_number, _)| *block_number);
+
+                // the last queued solution seen per round is the one used by the election.
+                let mut rounds = std::collections::BTreeMap::new();
+
+                for (_, status) in entries.iter() {
+                    if let Some(compute) = status.queued_compute.clone() {
+                        rounds.insert(status.round, compute);
+                    } else if status.emergency || status.election_failed {
+                        rounds.insert(status.round, "Emergency".to_string());
+                    }
+                }
+
+                for (block_number, status) in entries {
+                    let csv_entry = ElectionHistoryCsv {
+                        round_compute: rounds.get(&status.round).cloned(),
+                        block_number,
+                        round: status.round,
+                        phase: status.phase,
+                        queued_compute: status.queued_compute,
+                        queued_minimal_stake: status.queued_score.map(|s| s.minimal_stake),
+                        queued_sum_stake: status.queued_score.map(|s| s.sum_stake),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                for (round, compute) in rounds {
+                    log::info!(target: LOG_TARGET, "Transform::election_history round {}: {}.", round, compute);
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `submission_analysis` operation result, one entry per block and
+/// submission.
+///
+/// The mined columns are empty if the snapshot does not exist at the block. The ratios are the
+/// ones of the submission to the best mined solution, i.e. the one with the highest minimal stake.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SubmissionAnalysisCsv {
+    block_number: u32,
+    round: u32,
+    phase: String,
+    /// Either `signed`, for a signed submission, or `queued`, for the queued solution.
+    source: String,
+    index: Option<u32>,
+    submitter: Option<String>,
+    /// How the queued solution was computed.
+    compute: Option<String>,
+    min_stake: u128,
+    sum_stake: u128,
+    sum_stake_squared: u128,
+    seq_phragmen_min_stake: Option<u128>,
+    seq_phragmen_sum_stake: Option<u128>,
+    seq_phragmen_sum_stake_squared: Option<u128>,
+    phragmms_min_stake: Option<u128>,
+    phragmms_sum_stake: Option<u128>,
+    phragmms_sum_stake_squared: Option<u128>,
+    dpos_min_stake: Option<u128>,
+    dpos_sum_stake: Option<u128>,
+    dpos_sum_stake_squared: Option<u128>,
+    best_mined: Option<String>,
+    min_stake_ratio: Option<f64>,
+    sum_stake_ratio: Option<f64>,
+}
+
+/// Compares the on-chain election submissions with locally mined solutions.
+///
+/// The signed submissions (`SignedSubmissionsMap`) and the queued solution (`QueuedSolution`) of
+/// each block are compared with the sequential Phragmen, PhragMMS and (pro rata) DPoS solutions
+/// mined from the snapshot of the same block, to quantify how close the submitters are to the
+/// best solution. The snapshot is not created if it does not exist, since a new snapshot would
+/// not be the one the solutions were submitted for.
+macro_rules! submission_analysis_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<submission_analysis_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::submission_analysis starting.");
+
+                let seq_phragmen = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+                let phragmms = Solver::PhragMMS{iterations: 10};
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let status = gadgets::election_status::<Runtime>(&mut ext);
+                    let submissions = gadgets::signed_submissions::<Runtime>(&mut ext);
+
+                    let mut rows = submissions
+                        .into_iter()
+                        .map(|s| ("signed", Some(s.index), Some(s.who.to_string()), None, s.score))
+                        .collect::<Vec<_>>();
+                    if let (Some(compute), Some(score)) = (status.queued_compute.clone(), status.queued_score) {
+                        rows.push(("queued", None, None, Some(compute), score));
+                    }
+
+                    if rows.is_empty() {
+                        crate::warnings::warn("no_submissions", format!(
+                            "Transform::submission_analysis: no submissions at #{} (phase {}).",
+                            block_number,
+                            status.phase,
+                        ));
+                        continue;
+                    }
+
+                    let mined = match ext.execute_with(|| EPM::Snapshot::<Runtime>::exists()) {
+                        true => Some((
+                            gadgets::mine_with::<Runtime>(&seq_phragmen, &mut ext, false)?.score,
+                            gadgets::mine_with::<Runtime>(&phragmms, &mut ext, false)?.score,
+                            gadgets::mine_dpos::<Runtime>(&mut ext, ShareDistribution::ProRata)?.0,
+                        )),
+                        false => {
+                            crate::warnings::warn("no_snapshot", format!(
+                                "Transform::submission_analysis: no snapshot at #{}, the submissions are not compared.",
+                                block_number,
+                            ));
+                            None
+                        }
+                    };
+                    let best = mined.map(|(seq, mms, dpos)| {
+                        [("seq_phragmen", seq), ("phragmms", mms), ("dpos", dpos)]
+                            .into_iter()
+                            .max_by_key(|(_, score)| (score.minimal_stake, score.sum_stake))
+                            .expect("non-empty array; qed.")
+                    });
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::submission_analysis at #{}: {} submissions, best mined: {:?}.",
+                        block_number,
+                        rows.len(),
+                        best,
+                    );
+
+                    for (source, index, submitter, compute, score) in rows {
+                        let (min_stake_ratio, sum_stake_ratio) = best
+                            .map(|(_, best)| gadgets::score_ratios(&score, &best))
+                            .unwrap_or_default();
+
+                        let csv_entry = SubmissionAnalysisCsv {
+                            block_number,
+                            round: status.round,
+                            phase: status.phase.clone(),
+                            source: source.to_string(),
+                            index,
+                            submitter,
+                            compute,
+                            min_stake: score.minimal_stake,
+                            sum_stake: score.sum_stake,
+                            sum_stake_squared: score.sum_stake_squared,
+                            seq_phragmen_min_stake: mined.map(|(s, _, _)| s.minimal_stake),
+                            seq_phragmen_sum_stake: mined.map(|(s, _, _)| s.sum_stake),
+                            seq_phragmen_sum_stake_squared: mined.map(|(s, _, _)| s.sum_stake_squared),
+                            phragmms_min_stake: mined.map(|(_, s, _)| s.minimal_stake),
+                            phragmms_sum_stake: mined.map(|(_, s, _)| s.sum_stake),
+                            phragmms_sum_stake_squared: mined.map(|(_, s, _)| s.sum_stake_squared),
+                            dpos_min_stake: mined.map(|(_, _, s)| s.minimal_stake),
+                            dpos_sum_stake: mined.map(|(_, _, s)| s.sum_stake),
+                            dpos_sum_stake_squared: mined.map(|(_, _, s)| s.sum_stake_squared),
+                            best_mined: best.map(|(name, _)| name.to_string()),
+                            min_stake_ratio,
+                            sum_stake_ratio,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `emergency_result` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EmergencyResultCsv {
+    block_number: u32,
+    phase: String,
+    desired_targets: u32,
+    winners: u32,
+    max_backers: u32,
+    call_size: usize,
+    call_path: String,
+    verified: bool,
+    error: Option<String>,
+}
+
+/// Reconstructs an emergency election result.
+///
+/// At a block in the `Emergency` phase, mines a solution from the election snapshot (created if
+/// it does not exist) and encodes its supports as a `set_emergency_election_result` call. The
+/// hex encoded call is stored next to the output file, ready to be submitted by governance, and
+/// the outcome of dispatching it on the externalities is reported.
+macro_rules! emergency_result_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<emergency_result_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                parallel_solver: bool,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::emergency_result starting.");
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let status = gadgets::election_status::<Runtime>(&mut ext);
+                    if !status.emergency {
+                        crate::warnings::warn("not_in_emergency", format!(
+                            "Transform::emergency_result: #{} is in phase {}, the call is expected to fail.",
+                            block_number,
+                            status.phase,
+                        ));
+                    }
+
+                    let result = gadgets::emergency_result::<Runtime>(&solver, &mut ext)?;
+                    let desired_targets = gadgets::desired_targets::<Runtime>(&mut ext);
+
+                    let call_path = format!("{}.{}.call", out.output_path(), block_number);
+                    std::fs::write(&call_path, sp_core::bytes::to_hex(&result.call, false))?;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::emergency_result at #{}: {} winners, call of {} bytes stored in {}, verification: {:?}.",
+                        block_number,
+                        result.winners,
+                        result.call.len(),
+                        call_path,
+                        result.verification,
+                    );
+
+                    let csv_entry = EmergencyResultCsv {
+                        block_number,
+                        phase: status.phase,
+                        desired_targets,
+                        winners: result.winners,
+                        max_backers: result.max_backers,
+                        call_size: result.call.len(),
+                        call_path,
+                        verified: result.verification.is_ok(),
+                        error: result.verification.err(),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `feasibility_check` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FeasibilityCheckCsv {
+    block_number: u32,
+    round: u32,
+    claimed_min_stake: u128,
+    claimed_sum_stake: u128,
+    claimed_sum_stake_squared: u128,
+    feasible: bool,
+    verified_min_stake: Option<u128>,
+    verified_sum_stake: Option<u128>,
+    verified_sum_stake_squared: Option<u128>,
+    winners: Option<u32>,
+    error: Option<String>,
+}
+
+/// Checks the feasibility of a submitted solution.
+///
+/// The SCALE encoded `RawSolution` (e.g. the solution of a signed submission) is read from a
+/// file, either as raw bytes or hex encoded, and checked against the election snapshot of each
+/// block as the pallet does for signed submissions, i.e. with its winners, edges and claimed score
+/// verified.
+macro_rules! feasibility_check_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<feasibility_check_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                solution_path: String,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::feasibility_check starting.");
+
+                let content = std::fs::read(&solution_path)?;
+                let solution = match std::str::from_utf8(&content).map(str::trim) {
+                    Ok(hex) if hex.starts_with("0x") => sp_core::bytes::from_hex(hex).map_err(|e| {
+                        TimetravelError::Decode {
+                            error: format!("solution in {}: {:?}", solution_path, e),
+                        }
+                    })?,
+                    _ => content,
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let result = gadgets::feasibility_check::<Runtime>(&solution, &mut ext)?;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::feasibility_check at #{} for round {}: {:?}.",
+                        block_number,
+                        result.round,
+                        result.verification,
+                    );
+
+                    let verified = result.verification.as_ref().ok();
+                    let csv_entry = FeasibilityCheckCsv {
+                        block_number,
+                        round: result.round,
+                        claimed_min_stake: result.claimed_score.minimal_stake,
+                        claimed_sum_stake: result.claimed_score.sum_stake,
+                        claimed_sum_stake_squared: result.claimed_score.sum_stake_squared,
+                        feasible: result.verification.is_ok(),
+                        verified_min_stake: verified.map(|(score, _)| score.minimal_stake),
+                        verified_sum_stake: verified.map(|(score, _)| score.sum_stake),
+                        verified_sum_stake_squared: verified.map(|(score, _)| score.sum_stake_squared),
+                        winners: verified.map(|(_, winners)| *winners),
+                        error: result.verification.err(),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `paged_election` operation result, one entry per block and page.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PagedElectionCsv {
+    block_number: u32,
+    phase: String,
+    snapshot_source: String,
+    page: u32,
+    desired_targets: u32,
+    voters: u32,
+    active_voters: u32,
+    winners: u32,
+    trimmed_backers: u32,
+    trimmed_winners: u32,
+    minimal_stake: u128,
+    sum_stake: u128,
+    sum_stake_squared: u128,
+    total_minimal_stake: u128,
+    total_sum_stake: u128,
+    total_sum_stake_squared: u128,
+}
+
+/// Mines a paged election.
+///
+/// The voters of the election snapshot (the on-chain one, or one created at the block if it does
+/// not exist, as reported by the `snapshot_source` column) are split in pages as the
+/// multi-page election provider does. The winners are elected over all the voters, and the voters
+/// of each page are then mined separately against them, within the `max_winners_per_page` and
+/// `max_backers_per_winner` bounds of a page. Each page's solution, with the backers and winners
+/// trimmed by the bounds, is reported next to the score of the aggregation of all the pages.
+///
+/// The desired number of winners can be overridden with `desired_targets`, capped to the number
+/// of targets of the snapshot.
+macro_rules! paged_election_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<paged_election_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                pages: u32,
+                bounds: gadgets::paged::PageBounds,
+                parallel_solver: bool,
+                desired_targets: Option<u32>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::paged_election starting with {} pages.", pages);
+
+                let solver = if parallel_solver {
+                    Solver::ParallelPhragmen{iterations: 10}
+                } else {
+                    Solver::SeqPhragmen{iterations: 10}
+                };
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let snapshot = gadgets::election_snapshot::<Runtime>(&mut ext);
+                    let desired_targets = match desired_targets {
+                        Some(desired_targets) => gadgets::override_desired_targets::<Runtime>(&mut ext, desired_targets)?,
+                        None => gadgets::desired_targets::<Runtime>(&mut ext),
+                    };
+
+                    let (score, page_solutions) =
+                        gadgets::paged::mine_paged::<Runtime>(&solver, &mut ext, pages, bounds)?;
+
+                    for page_solution in page_solutions {
+                        let csv_entry = PagedElectionCsv {
+                            block_number,
+                            phase: snapshot.phase.clone(),
+                            snapshot_source: format!("{:?}", snapshot.source),
+                            page: page_solution.page,
+                            desired_targets,
+                            voters: page_solution.voters,
+                            active_voters: page_solution.active_voters,
+                            winners: page_solution.winners,
+                            trimmed_backers: page_solution.trimmed_backers,
+                            trimmed_winners: page_solution.trimmed_winners,
+                            minimal_stake: page_solution.score.minimal_stake,
+                            sum_stake: page_solution.score.sum_stake,
+                            sum_stake_squared: page_solution.score.sum_stake_squared,
+                            total_minimal_stake: score.minimal_stake,
+                            total_sum_stake: score.sum_stake,
+                            total_sum_stake_squared: score.sum_stake_squared,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `authorship` operation result, one entry per era and author.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AuthorshipCsv {
+    era: u32,
+    /// The stash of the validator.
+    account: String,
+    authored_blocks: u32,
+    first_block: u32,
+    last_block: u32,
+    /// Share of the blocks of the era (within the input blocks) authored by the validator.
+    authored_share: f64,
+    era_points: u32,
+    /// Share of the era reward points of the validator, to cross-check with `authored_share`.
+    points_share: f64,
+    slashed: bool,
+}
+
+/// Attributes blocks to their authors.
+///
+/// The author of each block is derived from its Babe pre-runtime digest and the session
+/// validators. The authored blocks are aggregated per era and validator, and reported next to the
+/// reward points of the validator at the latest block of the era and whether it was slashed in
+/// the era (e.g. due to an equivocation).
+macro_rules! authorship_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<authorship_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::authorship::{aggregate_authorship, block_author, era_reward_points, slashed_in_era};
+
+                log::info!(target: LOG_TARGET, "Transform::authorship starting.");
+
+                let mut blocks = vec![];
+                let mut points = BTreeMap::new();
+                let mut slashed = BTreeSet::new();
+                let mut unattributed = 0;
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let era = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+
+                    let author = match block_author::<Runtime>(&mut ext) {
+                        Some(author) => author.author,
+                        None => {
+                            crate::warnings::warn("unattributed_block", format!("Transform::authorship: author of #{} not found.", block_number));
+                            unattributed += 1;
+                            continue;
+                        }
+                    };
+
+                    if slashed_in_era::<Runtime>(&mut ext, era, &author) {
+                        slashed.insert((era, author.clone()));
+                    }
+                    // the reward points at the latest block of the era are kept.
+                    match points.get(&era) {
+                        Some((latest, _)) if *latest > block_number => (),
+                        _ => {
+                            points.insert(era, (block_number, era_reward_points::<Runtime>(&mut ext, era)));
+                        }
+                    }
+
+                    blocks.push((era, block_number, author));
+                }
+
+                let authored = aggregate_authorship(&blocks);
+
+                let mut era_blocks = BTreeMap::<u32, u32>::new();
+                for ((era, _), a) in authored.iter() {
+                    *era_blocks.entry(*era).or_default() += a.count;
+                }
+
+                for ((era, validator), a) in authored {
+                    let era_points = points.get(&era).map(|(_, p)| p).cloned().unwrap_or_default();
+                    let total_points = era_points.values().sum::<u32>();
+                    let validator_points = era_points.get(&validator).cloned().unwrap_or_default();
+
+                    let csv_entry = AuthorshipCsv {
+                        era,
+                        account: validator.to_string(),
+                        authored_blocks: a.count,
+                        first_block: a.first,
+                        last_block: a.last,
+                        authored_share: a.count as f64 / era_blocks[&era] as f64,
+                        era_points: validator_points,
+                        points_share: if total_points > 0 { validator_points as f64 / total_points as f64 } else { 0.0 },
+                        slashed: slashed.contains(&(era, validator)),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                log::info!(
+                    target: LOG_TARGET,
+                    "Transform::authorship: {} blocks attributed, {} unattributed.",
+                    blocks.len(),
+                    unattributed,
+                );
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `memory_report` operation result, one entry per block and prefix.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MemoryReportCsv {
+    block_number: u32,
+    prefix: String,
+    pallet: Option<String>,
+    keys: u64,
+    key_bytes: u64,
+    value_bytes: u64,
+    /// Share of the bytes of the snapshot under the prefix.
+    share: f64,
+}
+
+/// Reports the storage usage of each snapshot per pallet prefix.
+///
+/// The prefixes are labelled with the pallets of the compiled runtime. The bytes reported are the
+/// raw size of the keys and values, i.e. a lower bound of the memory used by the externalities.
+macro_rules! memory_report_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<memory_report_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use frame_support::traits::PalletsInfoAccess;
+
+                log::info!(target: LOG_TARGET, "Transform::memory_report starting.");
+
+                let pallets = AllPalletsWithSystem::infos().into_iter().map(|p| p.name).collect::<Vec<_>>();
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let usage = gadgets::memory::memory_usage(&mut ext, &pallets);
+                    let total = usage.iter().map(|u| u.bytes()).sum::<u64>().max(1);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::memory_report at #{}: {} keys, {} bytes, largest prefixes: {:?}.",
+                        block_number,
+                        usage.iter().map(|u| u.keys).sum::<u64>(),
+                        total,
+                        usage.iter().take(5).map(|u| (u.name.clone(), u.bytes())).collect::<Vec<_>>(),
+                    );
+
+                    for prefix_usage in usage {
+                        let csv_entry = MemoryReportCsv {
+                            block_number,
+                            prefix: sp_core::bytes::to_hex(&prefix_usage.prefix, false),
+                            share: prefix_usage.bytes() as f64 / total as f64,
+                            pallet: prefix_usage.name,
+                            keys: prefix_usage.keys,
+                            key_bytes: prefix_usage.key_bytes,
+                            value_bytes: prefix_usage.value_bytes,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `nominator_apy` operation result. The `account` scope entries
+/// report the returns of each nominator, and the `aggregate` scope entries report a statistic
+/// (e.g. `p50` or `mean`) of each column across the nominators.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct NominatorApyCsv {
+    block_number: u32,
+    era_from: u32,
+    era_to: u32,
+    scope: String,
+    /// The account of the nominator, or the statistic of an aggregate entry.
+    account: String,
+    eras: u32,
+    total_reward: u128,
+    avg_stake: u128,
+    apr: f64,
+    apy: f64,
+}
+
+/// Computes the realized returns of the nominators over a range of eras.
+///
+/// The range is bounded by the era history of each block, so the rewards of older eras require
+/// older blocks. Only the eras in which a nominator was exposed are accounted for in its returns.
+macro_rules! nominator_apy_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<nominator_apy_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                from_era: Option<EraIndex>,
+                to_era: Option<EraIndex>,
+                accounts: Vec<String>,
+                eras_per_year: f64,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::rewards::{percentile, returns, EraReward, Returns};
+                use sp_core::crypto::Ss58Codec;
+
+                log::info!(target: LOG_TARGET, "Transform::nominator_apy starting.");
+
+                let accounts = accounts
+                    .iter()
+                    .map(|account| {
+                        AccountId::from_ss58check(account).map_err(|e| TimetravelError::Unsupported {
+                            reason: format!("invalid account {}: {:?}", account, e),
+                        })
+                    })
+                    .collect::<Result<BTreeSet<_>, _>>()?;
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let history = gadgets::rewards::history_eras::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?;
+
+                    if from_era.map_or(false, |era| era < *history.start())
+                        || to_era.map_or(false, |era| era > *history.end())
+                    {
+                        crate::warnings::warn("truncated_era_range", format!(
+                            "Transform::nominator_apy at #{}: era range truncated to the era history {:?}.",
+                            block_number,
+                            history,
+                        ));
+                    }
+                    let from = from_era.unwrap_or(*history.start()).max(*history.start());
+                    let to = to_era.unwrap_or(*history.end()).min(*history.end());
+
+                    let mut rewards = BTreeMap::<AccountId, Vec<EraReward>>::new();
+                    for era in from..=to {
+                        let era_rewards = gadgets::rewards::era_nominator_rewards::<Runtime>(&mut ext, era);
+                        if era_rewards.is_empty() {
+                            crate::warnings::warn("missing_era_reward", format!(
+                                "Transform::nominator_apy at #{}: no rewards or exposures in era {}.",
+                                block_number,
+                                era,
+                            ));
+                        }
+
+                        for (nominator, reward) in era_rewards {
+                            if accounts.is_empty() || accounts.contains(&nominator) {
+                                rewards.entry(nominator).or_default().push(reward);
+                            }
+                        }
+                    }
+
+                    let all_returns = rewards
+                        .iter()
+                        .map(|(nominator, rewards)| (nominator, returns(rewards, eras_per_year)))
+                        .collect::<Vec<_>>();
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::nominator_apy at #{}: {} nominators over eras {}..={}.",
+                        block_number,
+                        all_returns.len(),
+                        from,
+                        to,
+                    );
+
+                    let csv_entry = |scope: &str, account: String, returns: Returns| NominatorApyCsv {
+                        block_number,
+                        era_from: from,
+                        era_to: to,
+                        scope: scope.to_string(),
+                        account,
+                        eras: returns.eras,
+                        total_reward: returns.total_reward,
+                        avg_stake: returns.avg_stake,
+                        apr: returns.apr,
+                        apy: returns.apy,
+                    };
+
+                    for (nominator, returns) in all_returns.iter() {
+                        out.emit(csv_entry("account", nominator.to_string(), *returns))?;
+                    }
+
+                    if all_returns.is_empty() {
+                        continue;
+                    }
+
+                    let sorted = |f: fn(&Returns) -> f64| {
+                        let mut values = all_returns.iter().map(|(_, r)| f(r)).collect::<Vec<_>>();
+                        values.sort_by(|a, b| a.total_cmp(b));
+                        values
+                    };
+                    let eras = sorted(|r| r.eras as f64);
+                    let total_rewards = sorted(|r| r.total_reward as f64);
+                    let avg_stakes = sorted(|r| r.avg_stake as f64);
+                    let aprs = sorted(|r| r.apr);
+                    let apys = sorted(|r| r.apy);
+
+                    let statistic = |f: &dyn Fn(&[f64]) -> f64| Returns {
+                        eras: f(&eras) as u32,
+                        total_reward: f(&total_rewards) as u128,
+                        avg_stake: f(&avg_stakes) as u128,
+                        apr: f(&aprs),
+                        apy: f(&apys),
+                    };
+
+                    for (name, p) in [("p10", 0.1), ("p25", 0.25), ("p50", 0.5), ("p75", 0.75), ("p90", 0.9)] {
+                        let returns = statistic(&|values| percentile(values, p));
+                        out.emit(csv_entry("aggregate", name.to_string(), returns))?;
+                    }
+                    let mean = statistic(&|values| values.iter().sum::<f64>() / values.len() as f64);
+                    out.emit(csv_entry("aggregate", "mean".to_string(), mean))?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `validator_score` operation result, one entry per validator
+/// and era.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ValidatorScoreCsv {
+    block_number: u32,
+    era: u32,
+    /// The stash of the validator.
+    account: String,
+    points: u32,
+    relative_points: f64,
+    commission: f64,
+    slash: f64,
+    uptime: f64,
+    score: f64,
+    /// The rank of the validator by score within the era.
+    rank: usize,
+}
+
+/// Scores the validators elected in each era of a range.
+///
+/// The uptime of a validator is the fraction of the eras of the range in which it was elected,
+/// since the active set is fixed within an era and the per session sets are not kept in state.
+macro_rules! validator_score_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<validator_score_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                from_era: Option<EraIndex>,
+                to_era: Option<EraIndex>,
+                weights: gadgets::staking_stats::ScoreWeights,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::validator_score starting with {:?}.", weights);
+
+                if weights.points + weights.commission + weights.slash + weights.uptime <= 0.0 {
+                    return Err(TimetravelError::Unsupported {
+                        reason: format!("score weights {:?} without a positive sum.", weights),
+                    }
+                    .into());
+                }
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let history = gadgets::rewards::history_eras::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?;
+                    let from = from_era.unwrap_or(*history.start()).max(*history.start());
+                    let to = to_era.unwrap_or(*history.end()).min(*history.end());
+
+                    let performances = (from..=to)
+                        .map(|era| (era, gadgets::staking_stats::validator_performances::<Runtime>(&mut ext, era)))
+                        .filter(|(_, performances)| !performances.is_empty())
+                        .collect::<Vec<_>>();
+
+                    let mut elected = BTreeMap::<_, u32>::new();
+                    for validator in performances.iter().flat_map(|(_, p)| p.keys()) {
+                        *elected.entry(validator.clone()).or_default() += 1;
+                    }
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::validator_score at #{}: {} validators over {} eras in {}..={}.",
+                        block_number,
+                        elected.len(),
+                        performances.len(),
+                        from,
+                        to,
+                    );
+
+                    for (era, era_performances) in performances.iter() {
+                        let mut scores = era_performances
+                            .iter()
+                            .map(|(validator, performance)| {
+                                let uptime = elected[validator] as f64 / performances.len() as f64;
+                                (validator, performance, uptime, weights.score(performance, uptime))
+                            })
+                            .collect::<Vec<_>>();
+                        scores.sort_by(|(_, _, _, a), (_, _, _, b)| b.total_cmp(a));
+
+                        for (rank, (validator, performance, uptime, score)) in scores.into_iter().enumerate() {
+                            let csv_entry = ValidatorScoreCsv {
+                                block_number,
+                                era: *era,
+                                account: validator.to_string(),
+                                points: performance.points,
+                                relative_points: performance.relative_points,
+                                commission: performance.commission * 100.0,
+                                slash: performance.slash * 100.0,
+                                uptime,
+                                score,
+                                rank: rank + 1,
+                            };
+
+                            out.emit(csv_entry)?;
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `pool_yields` operation result, one entry per era. The yields
+/// are annualized.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PoolYieldsCsv {
+    block_number: u32,
+    era: u32,
+    pools: u32,
+    pool_stake: u128,
+    median_pool_yield: f64,
+    pool_yield: f64,
+    solo_nominators: u32,
+    solo_stake: u128,
+    median_solo_yield: f64,
+    solo_yield: f64,
+    /// The median pool yield minus the median solo yield.
+    median_yield_gap: f64,
+}
+
+/// Compares the yields of the nomination pools and of the solo nominators in each era of a range.
+///
+/// The pools are identified by their bonded accounts, which nominate as any solo nominator. The
+/// current commission of each pool is applied to all the eras, since the commission of the pools
+/// in past eras is not kept in state.
+macro_rules! pool_yields_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<pool_yields_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                from_era: Option<EraIndex>,
+                to_era: Option<EraIndex>,
+                eras_per_year: f64,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::rewards::YieldComparison;
+
+                log::info!(target: LOG_TARGET, "Transform::pool_yields starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let history = gadgets::rewards::history_eras::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?;
+                    let from = from_era.unwrap_or(*history.start()).max(*history.start());
+                    let to = to_era.unwrap_or(*history.end()).min(*history.end());
+
+                    let pools = gadgets::rewards::pool_accounts::<Runtime>(&mut ext);
+                    if pools.is_empty() {
+                        crate::warnings::warn("no_pools", format!(
+                            "Transform::pool_yields at #{}: no nomination pools.",
+                            block_number,
+                        ));
+                    }
+
+                    for era in from..=to {
+                        let (pool_rewards, solo_rewards): (Vec<_>, Vec<_>) =
+                            gadgets::rewards::era_nominator_rewards::<Runtime>(&mut ext, era)
+                                .into_iter()
+                                .partition(|(nominator, _)| pools.contains_key(nominator));
+
+                        let pool_rewards = pool_rewards
+                            .into_iter()
+                            .map(|(pool, reward)| (reward, pools[&pool].1))
+                            .collect::<Vec<_>>();
+                        let solo_rewards = solo_rewards.into_iter().map(|(_, reward)| reward).collect::<Vec<_>>();
+
+                        let comparison = YieldComparison::new(&pool_rewards, &solo_rewards, eras_per_year);
+
+                        log::info!(
+                            target: LOG_TARGET,
+                            "Transform::pool_yields at #{}, era {}: median pool yield {:.4}, median solo yield {:.4}.",
+                            block_number,
+                            era,
+                            comparison.median_pool_yield,
+                            comparison.median_solo_yield,
+                        );
+
+                        let csv_entry = PoolYieldsCsv {
+                            block_number,
+                            era,
+                            pools: comparison.pools,
+                            pool_stake: comparison.pool_stake,
+                            median_pool_yield: comparison.median_pool_yield,
+                            pool_yield: comparison.pool_yield,
+                            solo_nominators: comparison.solo_nominators,
+                            solo_stake: comparison.solo_stake,
+                            median_solo_yield: comparison.median_solo_yield,
+                            solo_yield: comparison.solo_yield,
+                            median_yield_gap: comparison.median_pool_yield - comparison.median_solo_yield,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `stake_flows` operation result, one Sankey link per flow and
+/// pair of consecutive snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StakeFlowsCsv {
+    block_from: u32,
+    block_to: u32,
+    era_from: u32,
+    era_to: u32,
+    flow: String,
+    source: String,
+    target: String,
+    value: u128,
+}
+
+/// Exports the stake flows between each pair of consecutive snapshots (e.g. the first blocks of
+/// consecutive eras), ordered by block number.
+///
+/// The slashes are the ones recorded in the eras from the era of the first snapshot up to (and
+/// excluding) the era of the second one, as read from the second snapshot.
+macro_rules! stake_flows_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<stake_flows_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::flows;
+
+                log::info!(target: LOG_TARGET, "Transform::stake_flows starting.");
+
+                let exts = gadgets::by_block_number::<Runtime, _>("stake_flows", exts.into_iter().map(|ext| ((), ext)), 2)?;
+
+                let mut previous = None;
+                for (block_to, _, mut ext) in exts {
+                    out.begin_block();
+                    let era_to = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let after = flows::ledger_states::<Runtime>(&mut ext);
+
+                    let Some((block_from, era_from, before)) = previous.replace((block_to, era_to, after.clone())) else {
+                        continue;
+                    };
+
+                    let slashes = flows::slashes_in_eras::<Runtime>(&mut ext, era_from..era_to);
+                    let stake_flows = flows::stake_flows(&before, &after, &slashes);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::stake_flows #{} -> #{}: {:?}",
+                        block_from,
+                        block_to,
+                        stake_flows,
+                    );
+
+                    let names = ["new_bond", "bond_extra", "reward", "retained", "unbond", "rebond", "withdrawal", "slash"];
+                    for (flow, (source, target, value)) in names.into_iter().zip(stake_flows.links()) {
+                        let csv_entry = StakeFlowsCsv {
+                            block_from,
+                            block_to,
+                            era_from,
+                            era_to,
+                            flow: flow.to_string(),
+                            source: source.to_string(),
+                            target: target.to_string(),
+                            value,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `nominator_churn` operation result, one entry per validator and
+/// pair of consecutive snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ValidatorChurnCsv {
+    block_from: u32,
+    block_to: u32,
+    era_from: u32,
+    era_to: u32,
+    account: String,
+    nominators_before: u32,
+    nominators_after: u32,
+    joined: u32,
+    left: u32,
+    approval_before: u128,
+    approval_after: u128,
+    inflow: u128,
+    outflow: u128,
+}
+
+/// The CSV representation of the `nominator_churn --nominators` operation result, one entry per
+/// nominator which churned between consecutive snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct NominatorChurnCsv {
+    block_from: u32,
+    block_to: u32,
+    era_from: u32,
+    era_to: u32,
+    account: String,
+    joined: bool,
+    left: bool,
+    rebonded: bool,
+    changed_targets: bool,
+    stake_before: u128,
+    stake_after: u128,
+    /// The targets added, separated by `;`.
+    targets_added: String,
+    /// The targets removed, separated by `;`.
+    targets_removed: String,
+}
+
+/// Exports the churn of the nominators between each pair of consecutive snapshots (e.g. the first
+/// blocks of consecutive eras), ordered by block number.
+macro_rules! nominator_churn_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<nominator_churn_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                nominators: bool,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::flows;
+
+                log::info!(target: LOG_TARGET, "Transform::nominator_churn starting.");
+
+                let exts = gadgets::by_block_number::<Runtime, _>("nominator_churn", exts.into_iter().map(|ext| ((), ext)), 2)?;
+                let join = |accounts: &[AccountId]| accounts.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(";");
+
+                let mut previous = None;
+                for (block_to, _, mut ext) in exts {
+                    out.begin_block();
+                    let era_to = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let after = flows::nominations::<Runtime>(&mut ext);
+
+                    let Some((block_from, era_from, before)) = previous.replace((block_to, era_to, after.clone())) else {
+                        continue;
+                    };
+
+                    let churns = before
+                        .keys()
+                        .chain(after.keys())
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .map(|nominator| (nominator, flows::NominatorChurn::of_nominator(before.get(nominator), after.get(nominator))))
+                        .filter(|(_, churn)| churn.joined || churn.left || churn.rebonded || churn.changed_targets())
+                        .collect::<Vec<_>>();
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::nominator_churn #{} -> #{}: {} joined, {} left, {} rebonded, {} changed targets.",
+                        block_from,
+                        block_to,
+                        churns.iter().filter(|(_, c)| c.joined).count(),
+                        churns.iter().filter(|(_, c)| c.left).count(),
+                        churns.iter().filter(|(_, c)| c.rebonded).count(),
+                        churns.iter().filter(|(_, c)| c.changed_targets()).count(),
+                    );
+
+                    if nominators {
+                        for (nominator, churn) in churns {
+                            let csv_entry = NominatorChurnCsv {
+                                block_from,
+                                block_to,
+                                era_from,
+                                era_to,
+                                account: nominator.to_string(),
+                                joined: churn.joined,
+                                left: churn.left,
+                                rebonded: churn.rebonded,
+                                changed_targets: churn.changed_targets(),
+                                stake_before: churn.stake_before,
+                                stake_after: churn.stake_after,
+                                targets_added: join(&churn.targets_added),
+                                targets_removed: join(&churn.targets_removed),
+                            };
+
+                            out.emit(csv_entry)?;
+                        }
+                        continue;
+                    }
+
+                    for (validator, churn) in flows::validator_churn(&before, &after) {
+                        let csv_entry = ValidatorChurnCsv {
+                            block_from,
+                            block_to,
+                            era_from,
+                            era_to,
+                            account: validator.to_string(),
+                            nominators_before: churn.nominators_before,
+                            nominators_after: churn.nominators_after,
+                            joined: churn.joined,
+                            left: churn.left,
+                            approval_before: churn.approval_before,
+                            approval_after: churn.approval_after,
+                            inflow: churn.inflow,
+                            outflow: churn.outflow,
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `validator_set_diff` operation result, one entry per validator
+/// which entered or exited the active set between consecutive snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ValidatorSetDiffCsv {
+    block_from: u32,
+    block_to: u32,
+    era_from: u32,
+    era_to: u32,
+    account: String,
+    /// `entered` or `exited`.
+    change: String,
+    /// The backing of the validator in the set it is part of.
+    backing: u128,
+    validators_before: u32,
+    validators_after: u32,
+    min_backing_before: Option<u128>,
+    min_backing_after: Option<u128>,
+    total_backing_before: u128,
+    total_backing_after: u128,
+}
+
+/// Exports the changes of the active validator set between each pair of consecutive snapshots
+/// (e.g. the first blocks of consecutive eras), ordered by block number.
+///
+/// The minimal and total backing of the sets are the first two components of the score of the
+/// elections which selected them, so that the churn of the set can be correlated with the changes
+/// of the election score.
+macro_rules! validator_set_diff_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<validator_set_diff_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::flows;
+
+                log::info!(target: LOG_TARGET, "Transform::validator_set_diff starting.");
+
+                let exts = gadgets::by_block_number::<Runtime, _>("validator_set_diff", exts.into_iter().map(|ext| ((), ext)), 2)?;
+
+                let mut previous = None;
+                for (block_to, _, mut ext) in exts {
+                    out.begin_block();
+                    let (era_to, after) = flows::active_set::<Runtime>(&mut ext)?;
+
+                    let Some((block_from, era_from, before)) = previous.replace((block_to, era_to, after.clone())) else {
+                        continue;
+                    };
+
+                    let changes = flows::set_changes(&before, &after);
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::validator_set_diff #{} -> #{}: {} entered, {} exited.",
+                        block_from,
+                        block_to,
+                        changes.iter().filter(|(_, c, _)| *c == flows::SetChange::Entered).count(),
+                        changes.iter().filter(|(_, c, _)| *c == flows::SetChange::Exited).count(),
+                    );
+
+                    for (validator, change, backing) in changes {
+                        let csv_entry = ValidatorSetDiffCsv {
+                            block_from,
+                            block_to,
+                            era_from,
+                            era_to,
+                            account: validator.to_string(),
+                            change: change.to_string(),
+                            backing,
+                            validators_before: before.len() as u32,
+                            validators_after: after.len() as u32,
+                            min_backing_before: before.values().min().copied(),
+                            min_backing_after: after.values().min().copied(),
+                            total_backing_before: before.values().sum(),
+                            total_backing_after: after.values().sum(),
+                        };
+
+                        out.emit(csv_entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `reward_analysis` operation result, one entry per validator and
+/// era.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RewardAnalysisCsv {
+    block_number: u32,
+    era: u32,
+    /// The stash of the validator.
+    account: String,
+    points: u32,
+    /// Commission of the validator in the era, in percent.
+    commission: f64,
+    own_stake: u128,
+    total_exposure: u128,
+    /// Share of the era reward of the validator and its nominators, before the commission.
+    payout: u128,
+    /// Estimated annual rate of the nominators of the validator.
+    nominator_apr: f64,
+}
+
+/// Analyses the rewards of the validators in each era of a range.
+///
+/// The range is bounded by the era history of each block. The payouts are the earned ones,
+/// whether they were claimed or not.
+macro_rules! reward_analysis_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<reward_analysis_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                from_era: Option<EraIndex>,
+                to_era: Option<EraIndex>,
+                eras_per_year: f64,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::reward_analysis starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let history = gadgets::rewards::history_eras::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?;
+                    let from = from_era.unwrap_or(*history.start()).max(*history.start());
+                    let to = to_era.unwrap_or(*history.end()).min(*history.end());
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::reward_analysis at #{}: eras {}..={}.",
+                        block_number,
+                        from,
+                        to,
+                    );
+
+                    for era in from..=to {
+                        let rewards = gadgets::rewards::era_validator_rewards::<Runtime>(&mut ext, era);
+
+                        for (validator, reward) in rewards {
+                            let csv_entry = RewardAnalysisCsv {
+                                block_number,
+                                era,
+                                account: validator.to_string(),
+                                points: reward.points,
+                                commission: reward.commission.deconstruct() as f64
+                                    / sp_runtime::Perbill::ACCURACY as f64
+                                    * 100.0,
+                                own_stake: reward.own,
+                                total_exposure: reward.total,
+                                payout: reward.payout,
+                                nominator_apr: reward.nominator_apr(eras_per_year),
+                            };
+
+                            out.emit(csv_entry)?;
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `era_payout` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EraPayoutCsv {
+    block_number: u32,
+    era: u32,
+    total_staked: u128,
+    total_issuance: u128,
+    era_duration_ms: u64,
+    validator_payout: u128,
+    remainder: u128,
+    /// Annualized inflation of the era payout, relative to the total issuance.
+    inflation: Option<f64>,
+    /// The actual payout of the stakers in the previous era, for comparison.
+    previous_validator_payout: Option<u128>,
+}
+
+/// Computes the payout of the active era at each block with the `EraPayout` of the runtime.
+macro_rules! era_payout_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<era_payout_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                era_duration_ms: Option<u64>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::era_payout starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let era = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let payout = gadgets::inflation::era_payout::<Runtime>(&mut ext, era_duration_ms)
+                        .ok_or(TimetravelError::missing("Total issuance or timestamp"))?;
+                    let previous_validator_payout = ext.execute_with(|| {
+                        era.checked_sub(1)
+                            .and_then(<Staking::ErasValidatorReward<Runtime>>::get)
+                            .map(Into::into)
+                    });
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::era_payout at #{}: {:?}",
+                        block_number,
+                        payout,
+                    );
+
+                    let csv_entry = EraPayoutCsv {
+                        block_number,
+                        era,
+                        total_staked: payout.total_staked,
+                        total_issuance: payout.total_issuance,
+                        era_duration_ms: payout.era_duration_ms,
+                        validator_payout: payout.validator_payout,
+                        remainder: payout.remainder,
+                        inflation: payout.inflation(),
+                        previous_validator_payout,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `inflation` operation result, one entry per block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct InflationCsv {
+    block_number: u32,
+    era: u32,
+    /// Milliseconds since the unix epoch, as per `Timestamp::Now`.
+    timestamp: Option<u64>,
+    total_issuance: u128,
+    total_staked: u128,
+    staking_rate: Option<f64>,
+    /// The staking rate at which the payout of the stakers is the highest.
+    ideal_staking_rate: Option<f64>,
+    /// Annualized inflation, relative to the total issuance.
+    inflation: Option<f64>,
+    /// Annualized payout of the stakers, relative to the total stake.
+    staking_yield: Option<f64>,
+}
+
+/// Tracks the issuance, stake and inflation at each block, as computed by the `EraPayout` of the
+/// runtime.
+macro_rules! inflation_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<inflation_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+                era_duration_ms: Option<u64>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::inflation;
+
+                log::info!(target: LOG_TARGET, "Transform::inflation starting.");
+
+                for mut ext in exts {
+                    out.begin_block();
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let era = gadgets::active_era::<Runtime>(&mut ext)
+                        .ok_or(TimetravelError::missing("Active era"))?
+                        .index;
+                    let payout = inflation::era_payout::<Runtime>(&mut ext, era_duration_ms)
+                        .ok_or(TimetravelError::missing("Total issuance or timestamp"))?;
+                    let ideal_staking_rate = inflation::ideal_staking_rate::<Runtime>(
+                        &mut ext,
+                        payout.total_issuance,
+                        payout.era_duration_ms,
+                    );
+
+                    let staking_rate = (payout.total_issuance > 0)
+                        .then(|| payout.total_staked as f64 / payout.total_issuance as f64);
+                    let staking_yield = (payout.total_staked > 0 && payout.era_duration_ms > 0).then(|| {
+                        payout.validator_payout as f64 / payout.total_staked as f64
+                            * inflation::MILLISECONDS_PER_YEAR as f64
+                            / payout.era_duration_ms as f64
+                    });
+
+                    let csv_entry = InflationCsv {
+                        block_number,
+                        era,
+                        timestamp: inflation::timestamp_now(&mut ext),
+                        total_issuance: payout.total_issuance,
+                        total_staked: payout.total_staked,
+                        staking_rate,
+                        ideal_staking_rate,
+                        inflation: payout.inflation(),
+                        staking_yield,
+                    };
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::inflation at #{}: {:?}",
+                        block_number,
+                        csv_entry,
+                    );
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `anonymize` operation result, one entry per snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AnonymizeCsv {
+    block_number: u32,
+    accounts: usize,
+    rewritten_keys: usize,
+    snapshot_path: String,
+}
+
+/// Anonymizes the accounts of the snapshots and stores the anonymized snapshots to disk.
+macro_rules! anonymize_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<anonymize_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                snapshot_paths: Vec<String>,
+                out: &mut Emitter,
+                seed: String,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::anonymize starting.");
+
+                for (mut ext, snapshot_path) in exts.into_iter().zip(snapshot_paths) {
+                    out.begin_block();
+                    let snapshot = crate::scraper::read_snapshot(&snapshot_path).map_err(|e| {
+                        TimetravelError::Snapshot { error: format!("Anonymize requires a stored snapshot at {}: {:?}", snapshot_path, e) }
+                    })?;
+
+                    let block_number = gadgets::block_number::<Runtime>(&mut ext);
+                    let pseudonyms = gadgets::anonymize::pseudonyms::<Runtime>(&mut ext, &seed);
+                    let rewritten_keys = gadgets::anonymize::anonymize(&mut ext, &pseudonyms);
+
+                    crate::trace::commit_all(&mut ext)?;
+                    let (raw_storage, storage_root) = ext.into_raw_snapshot();
+                    let anonymized = crate::utils::replace_snapshot_storage(&snapshot, raw_storage, storage_root)?;
+
+                    let anonymized_path = format!("{}.anon", snapshot_path);
+                    let compress = crate::EXT_CONFIG.get().map_or(false, |c| c.compress);
+                    crate::scraper::write_snapshot(&anonymized_path, &anonymized, compress)?;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::anonymize: {} accounts remapped in {} keys, snapshot stored in {}.",
+                        pseudonyms.len(),
+                        rewritten_keys,
+                        anonymized_path,
+                    );
+
+                    let csv_entry = AnonymizeCsv {
+                        block_number,
+                        accounts: pseudonyms.len(),
+                        rewritten_keys,
+                        snapshot_path: anonymized_path,
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// The CSV representation of the `staking_ledger_checks` operation result, one entry per checked
+/// state: the child block, the parent block and the parent block after the simulated deprecation
+/// of the controllers.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StakingLedgerChecksCsv {
+    block_number: u32,
+    /// `child`, `parent` or `parent_deprecated`.
+    state: String,
+    ledgers: u32,
+    bonded: u32,
+    payees: u32,
+    /// Whether the numbers of ledgers, bonded stashes and payees match.
+    in_sync: bool,
+    ok_ledgers: u32,
+    bad_ledgers: u32,
+    none_ledgers: u32,
+    inconsistent_ledgers: u32,
+    /// The stashes of the bad ledgers, separated by `;`.
+    bad_ledger_stashes: String,
+    /// The stashes of the none ledgers, separated by `;`.
+    none_ledger_stashes: String,
+    /// The stashes of the inconsistent ledgers, separated by `;`.
+    inconsistent_ledger_stashes: String,
+    missing_locks: u32,
+    smaller_locks: u32,
+    larger_locks: u32,
+    /// The stashes of the ledgers without a staking lock (or hold), separated by `;`.
+    missing_lock_stashes: String,
+    /// The stashes of the ledgers whose staking lock (or hold) is smaller than their total,
+    /// separated by `;`.
+    smaller_lock_stashes: String,
+    /// The stashes of the ledgers whose staking lock (or hold) is larger than their total,
+    /// separated by `;`.
+    larger_lock_stashes: String,
+    missing_payees: u32,
+    dangling_payees: u32,
+    deprecated_controller_payees: u32,
+    /// The bonded stashes without a payee, separated by `;`.
+    missing_payee_stashes: String,
+    /// The stashes with a payee which are not bonded, separated by `;`.
+    dangling_payee_stashes: String,
+    /// The stashes whose rewards are paid to their deprecated controller, separated by `;`.
+    deprecated_controller_payee_stashes: String,
+}
+
+/// Performs staking ledger checks over a block and its parent, and reports the ledger counts and
+/// the offending stashes of each checked state.
+macro_rules! staking_ledger_checks_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<staking_ledger_checks_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+                use gadgets::staking_ledger::LedgerReport;
+
+                log::info!(target: LOG_TARGET, "Transform::staking_ledger_checks starting.");
+                let checks = staking_ledger_checks::<Runtime>(exts)?;
+
+                let join = |stashes: Vec<&AccountId>| stashes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(";");
+                let reports = [
+                    (checks.child_block, "child", checks.child),
+                    (checks.parent_block, "parent", checks.parent),
+                    (checks.parent_block, "parent_deprecated", checks.parent_deprecated),
+                ];
+                for (block_number, state, report) in reports {
+                    let LedgerReport { ledgers, bonded, payees, ok_ledgers, .. } = report;
+                    let csv_entry = StakingLedgerChecksCsv {
+                        block_number,
+                        state: state.to_string(),
+                        ledgers,
+                        bonded,
+                        payees,
+                        in_sync: report.in_sync(),
+                        ok_ledgers,
+                        bad_ledgers: report.bad_ledgers.len() as u32,
+                        none_ledgers: report.none_ledgers.len() as u32,
+                        inconsistent_ledgers: report.inconsistent_ledgers.len() as u32,
+                        bad_ledger_stashes: join(report.bad_ledgers.iter().collect()),
+                        none_ledger_stashes: join(report.none_ledgers.iter().map(|(stash, _)| stash).collect()),
+                        inconsistent_ledger_stashes: join(report.inconsistent_ledgers.iter().map(|(stash, _)| stash).collect()),
+                        missing_locks: report.missing_locks.len() as u32,
+                        smaller_locks: report.smaller_locks.len() as u32,
+                        larger_locks: report.larger_locks.len() as u32,
+                        missing_lock_stashes: join(report.missing_locks.iter().collect()),
+                        smaller_lock_stashes: join(report.smaller_locks.iter().collect()),
+                        larger_lock_stashes: join(report.larger_locks.iter().collect()),
+                        missing_payees: report.payee_mismatches.missing.len() as u32,
+                        dangling_payees: report.payee_mismatches.dangling.len() as u32,
+                        deprecated_controller_payees: report.payee_mismatches.deprecated_controller.len() as u32,
+                        missing_payee_stashes: join(report.payee_mismatches.missing.iter().collect()),
+                        dangling_payee_stashes: join(report.payee_mismatches.dangling.iter().collect()),
+                        deprecated_controller_payee_stashes: join(report.payee_mismatches.deprecated_controller.iter().collect()),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// CSV entry of the simulated deprecation of the controllers at a block.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ControllerDeprecationCsv {
+    block_number: u32,
+    /// The stashes bonded to another account.
+    affected: u32,
+    migrated: u32,
+    /// The stashes skipped since their rewards are paid to their controller.
+    skipped_payee: u32,
+    /// The stashes skipped since their controller has no ledger of another stash.
+    skipped_ledger: u32,
+    ledgers_before: u32,
+    ledgers_after: u32,
+    bonded_before: u32,
+    bonded_after: u32,
+    payees_before: u32,
+    payees_after: u32,
+    in_sync_before: bool,
+    in_sync_after: bool,
+    ok_ledgers_before: u32,
+    ok_ledgers_after: u32,
+    /// The bad, none and inconsistent ledgers before the migration.
+    faulty_ledgers_before: u32,
+    /// The bad, none and inconsistent ledgers after the migration.
+    faulty_ledgers_after: u32,
+    bricked: u32,
+    /// The stashes bricked by the migration, separated by `;`.
+    bricked_stashes: String,
+    /// The stashes skipped since their rewards are paid to their controller, separated by `;`.
+    skipped_payee_stashes: String,
+}
+
+/// Simulates the deprecation of all the controllers at each block, and reports the consistency of
+/// the ledgers before and after it and the stashes it would brick.
+macro_rules! controller_deprecation_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<controller_deprecation_ $runtime>]<T: EPM::Config + Staking::Config>(
+                exts: Vec<Ext>,
+                out: &mut Emitter,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::controller_deprecation starting.");
+
+                let join = |stashes: &[AccountId]| stashes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(";");
+                for ext in exts {
+                    out.begin_block();
+                    let report = gadgets::staking_ledger::controller_deprecation::<Runtime>(ext)?;
+
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Transform::controller_deprecation at #{}: #affected: {}, #migrated: {}, #skipped: {}, #bricked: {}",
+                        report.block,
+                        report.affected,
+                        report.migrated.len(),
+                        report.skipped_payee.len() + report.skipped_ledger.len(),
+                        report.bricked.len(),
+                    );
+
+                    let csv_entry = ControllerDeprecationCsv {
+                        block_number: report.block,
+                        affected: report.affected,
+                        migrated: report.migrated.len() as u32,
+                        skipped_payee: report.skipped_payee.len() as u32,
+                        skipped_ledger: report.skipped_ledger.len() as u32,
+                        ledgers_before: report.before.ledgers,
+                        ledgers_after: report.after.ledgers,
+                        bonded_before: report.before.bonded,
+                        bonded_after: report.after.bonded,
+                        payees_before: report.before.payees,
+                        payees_after: report.after.payees,
+                        in_sync_before: report.before.in_sync(),
+                        in_sync_after: report.after.in_sync(),
+                        ok_ledgers_before: report.before.ok_ledgers,
+                        ok_ledgers_after: report.after.ok_ledgers,
+                        faulty_ledgers_before: report.before.faulty_stashes().len() as u32,
+                        faulty_ledgers_after: report.after.faulty_stashes().len() as u32,
+                        bricked: report.bricked.len() as u32,
+                        bricked_stashes: join(&report.bricked),
+                        skipped_payee_stashes: join(&report.skipped_payee),
+                    };
+
+                    out.emit(csv_entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Playground operation for testing.
+macro_rules! playground_for {
+    ($runtime:ident) => {
+        paste::paste! {
+            pub(crate) fn [<playground_ $runtime>]<T: EPM::Config>(
+                exts: Vec<Ext>,
+            ) -> Result<(), anyhow::Error> {
+                use $crate::[<$runtime _runtime_exports>]::*;
+
+                log::info!(target: LOG_TARGET, "Transform::playground starting.");
+                gadgets::playground::<Runtime>(exts)?;
+
+                Ok(())
+            }
+        }
+    };
+}
+
+//min_active_stake_for!(polkadot);
+//min_active_stake_for!(kusama);
+min_active_stake_for!(westend);
+
+//election_analysis_for!(polkadot);
+//election_analysis_for!(kusama);
+election_analysis_for!(westend);
+
+//staking_ledger_checks_for(polkadot);
+//staking_ledger_checks_for(kusama);
+staking_ledger_checks_for!(westend);
+
+//controller_deprecation_for!(polkadot);
+//controller_deprecation_for!(kusama);
+controller_deprecation_for!(westend);
+
+//exposure_comparison_for!(polkadot);
+//exposure_comparison_for!(kusama);
+exposure_comparison_for!(westend);
+
+//stress_test_for!(polkadot);
+//stress_test_for!(kusama);
+stress_test_for!(westend);
+
+//payee_distribution_for!(polkadot);
+//payee_distribution_for!(kusama);
+payee_distribution_for!(westend);
+
+//nominations_distribution_for!(polkadot);
+//nominations_distribution_for!(kusama);
+nominations_distribution_for!(westend);
+
+//desired_targets_what_if_for!(polkadot);
+//desired_targets_what_if_for!(kusama);
+desired_targets_what_if_for!(westend);
+
+//targets_what_if_for!(polkadot);
+//targets_what_if_for!(kusama);
+targets_what_if_for!(westend);
+
+//min_bond_what_if_for!(polkadot);
+//min_bond_what_if_for!(kusama);
+min_bond_what_if_for!(westend);
+
+//voter_distribution_for!(polkadot);
+//voter_distribution_for!(kusama);
+voter_distribution_for!(westend);
+
+//commission_audit_for!(polkadot);
+//commission_audit_for!(kusama);
+commission_audit_for!(westend);
+
+//waiting_validators_for!(polkadot);
+//waiting_validators_for!(kusama);
+waiting_validators_for!(westend);
+
+//staking_summary_for!(polkadot);
+//staking_summary_for!(kusama);
+staking_summary_for!(westend);
+//decentralization_for!(polkadot);
+//decentralization_for!(kusama);
+decentralization_for!(westend);
+
+//election_history_for!(polkadot);
+//election_history_for!(kusama);
+election_history_for!(westend);
+
+//submission_analysis_for!(polkadot);
+//submission_analysis_for!(kusama);
+submission_analysis_for!(westend);
+
+//emergency_result_for!(polkadot);
+//emergency_result_for!(kusama);
+emergency_result_for!(westend);
+
+//feasibility_check_for!(polkadot);
+//feasibility_check_for!(kusama);
+feasibility_check_for!(westend);
+
+//paged_election_for!(polkadot);
+//paged_election_for!(kusama);
+paged_election_for!(westend);
+
+//authorship_for!(polkadot);
+//authorship_for!(kusama);
+authorship_for!(westend);
+
+//memory_report_for!(polkadot);
+//memory_report_for!(kusama);
+memory_report_for!(westend);
+
+//nominator_apy_for!(polkadot);
+//nominator_apy_for!(kusama);
+nominator_apy_for!(westend);
+
+//validator_score_for!(polkadot);
+//validator_score_for!(kusama);
+validator_score_for!(westend);
+
+//pool_yields_for!(polkadot);
+//pool_yields_for!(kusama);
+pool_yields_for!(westend);
+
+//nominator_churn_for!(polkadot);
+//nominator_churn_for!(kusama);
+nominator_churn_for!(westend);
+//validator_set_diff_for!(polkadot);
+//validator_set_diff_for!(kusama);
+validator_set_diff_for!(westend);
+
+//stake_flows_for!(polkadot);
+//stake_flows_for!(kusama);
+stake_flows_for!(westend);
+
+//reward_analysis_for!(polkadot);
+//reward_analysis_for!(kusama);
+reward_analysis_for!(westend);
+
+//era_payout_for!(polkadot);
+//era_payout_for!(kusama);
+era_payout_for!(westend);
+
+//inflation_for!(polkadot);
+//inflation_for!(kusama);
+inflation_for!(westend);
+
+//anonymize_for!(polkadot);
+//anonymize_for!(kusama);
+anonymize_for!(westend);
+
+//playground_for(polkadot);
+//playground_for(kusama);
+playground_for!(westend);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn election_analysis() -> Operation {
+        Operation::ElectionAnalysis {
+            solver: SolverKind::SeqPhragmen,
+            iterations: 10,
+            desired_targets: None,
+        }
+    }
+
+    #[test]
+    fn mutations_work() {
+        assert!(election_analysis().mutations(false, &[], &[]).is_empty());
+        assert_eq!(election_analysis().mutations(true, &[], &[]).len(), 1);
+        assert_eq!(
+            Operation::PagedElection {
+                pages: 4,
+                max_winners_per_page: None,
+                max_backers_per_winner: None,
+                desired_targets: Some(100),
+            }
+            .mutations(false, &[], &[]),
+            vec!["overrides the desired targets with 100".to_string()]
+        );
+        assert_eq!(
+            Operation::PayeeDistribution.mutations(true, &["patch.data".to_string()], &[]),
+            vec!["overlays the storage of patch patch.data".to_string()]
+        );
+        assert_eq!(
+            Operation::PayeeDistribution.mutations(false, &[], &["spec.json".to_string()]),
+            vec!["sets or removes the storage keys of mutation spec spec.json".to_string()]
+        );
+        assert_eq!(
+            Operation::StakingLedgerChecks
+                .mutations(false, &["patch.data".to_string()], &[])
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn requirements_work() {
+        let runtime_items = vec![
+            ("Staking", vec!["Ledger", "Bonded"]),
+            ("Balances", vec!["Account"]),
+        ]
+        .into_iter()
+        .map(|(pallet, items)| {
+            (
+                pallet.to_string(),
+                items.into_iter().map(|i| i.to_string()).collect(),
+            )
+        })
+        .collect::<BTreeMap<_, BTreeSet<_>>>();
+
+        assert!(Operation::PayeeDistribution
+            .requirements()
+            .unmet_in_runtime(&runtime_items)
+            .is_empty());
+        assert_eq!(
+            Operation::StakingSummary
+                .requirements()
+                .unmet_in_runtime(&runtime_items),
+            vec!["Balances::TotalIssuance".to_string()]
+        );
+        assert_eq!(
+            election_analysis()
+                .requirements()
+                .unmet_in_runtime(&runtime_items),
+            vec![
+                "ElectionProviderMultiPhase".to_string(),
+                "VoterList".to_string()
+            ]
+        );
+        assert_eq!(
+            Operation::ElectionHistory
+                .requirements()
+                .unmet_in_runtime(&runtime_items)
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn pallets_to_extract_works() {
+        assert_eq!(
+            Operation::Playground.requirements().pallets_to_extract(),
+            None
+        );
+        assert_eq!(
+            Operation::ElectionHistory
+                .requirements()
+                .pallets_to_extract(),
+            Some(vec!["ElectionProviderMultiPhase".to_string()])
+        );
+    }
+
+    #[test]
+    fn election_pallets_to_extract_work() {
+        let election_pallets = vec![
+            "ElectionProviderMultiPhase".to_string(),
+            "Staking".to_string(),
+            "VoterList".to_string(),
+        ];
+        let operations = [
+            election_analysis(),
+            Operation::MinActiveStake,
+            Operation::ExposureComparison,
+            Operation::EmergencyResult,
+            Operation::VoterDistribution,
+            Operation::StressTest {
+                factors: vec![2],
+                seed: 0,
+                desired_targets: None,
+            },
+            Operation::DesiredTargetsWhatIf {
+                desired_targets: vec![300],
+            },
+            Operation::TargetsWhatIf {
+                exclude_targets: vec![],
+                include_targets: vec![],
+            },
+            Operation::MinBondWhatIf { min_bonds: vec![1] },
+            Operation::PagedElection {
+                pages: 4,
+                max_winners_per_page: None,
+                max_backers_per_winner: None,
+                desired_targets: None,
+            },
+        ];
+        for operation in operations {
+            assert_eq!(
+                operation.pallets_to_extract(&operation.requirements()),
+                election_pallets,
+                "{}",
+                operation.name()
+            );
+        }
+
+        let feasibility_check = Operation::FeasibilityCheck {
+            solution: "solution.json".to_string(),
+        };
+        assert_eq!(
+            feasibility_check.pallets_to_extract(&feasibility_check.requirements()),
+            vec!["ElectionProviderMultiPhase".to_string()]
+        );
+
+        // a state diff extracts the compared pallets, or the whole state if none is provided.
+        let state_diff = |pallets: Vec<String>| Operation::StateDiff {
+            pallets,
+            keys: false,
+        };
+        assert_eq!(
+            state_diff(vec!["Staking".to_string()]).pallets_to_extract(&Requirements::default()),
+            vec!["Staking".to_string()]
+        );
+        assert!(state_diff(vec![])
+            .pallets_to_extract(&Requirements::default())
+            .is_empty());
+        assert_eq!(
+            Operation::Playground.pallets_to_extract(&Requirements::default()),
+            election_pallets
+        );
+
+        // the proxies are extracted for the entities, but not required.
+        let entities = Operation::Decentralization { entities: true };
+        assert_eq!(entities.requirements().pallets, &["Staking"]);
+        assert_eq!(
+            entities.pallets_to_extract(&entities.requirements()),
+            vec!["Staking".to_string(), "Proxy".to_string()]
+        );
+    }
+
+    #[test]
+    fn operation_name_works() {
+        assert_eq!(Operation::StakingSummary.name(), "StakingSummary");
+        assert_eq!(
+            Operation::PagedElection {
+                pages: 4,
+                max_winners_per_page: None,
+                max_backers_per_winner: None,
+                desired_targets: None,
+            }
+            .name(),
+            "PagedElection"
+        );
+    }
+}
@@ -0,0 +1,1231 @@
+//! Output subsystem of the CLI.
+//!
+//! Operations serialize each of their results once, as it completes, through the [`Emitter`] of
+//! the run, and the [`OutputSink`] of the selected [`OutputFormat`] formats the result (tagged
+//! with the CLI tags) and appends it to the output, see [`write`].
+//!
+//! Unless an explicit output path is set, the results of each command are written under an output
+//! layout which separates the chains, operations and months of the runs, e.g.
+//! `output/polkadot/election-analysis/2023-06.csv`, so that the outputs of different chains and
+//! operations are never mixed in the same file.
+//!
+//! The JSON outputs keep the values as serialized by the entries, e.g. `null` for `None` and the
+//! election scores nested, while the CSV outputs flatten them, see [`Record::flattened`].
+//!
+//! The Parquet backend is enabled with the `parquet` feature.
+//!
+//! Every entry emitted by an operation carries the warnings raised while it was computed, see
+//! [`crate::warnings`].
+//!
+//! The outputs of all the operations share the [`JOIN_KEYS`], so that they compose into a
+//! relational dataset: a column which identifies a chain, block, era or account is always named
+//! after its key, and the keys lead every record, in the same order. Columns which bound a range
+//! of a key are suffixed with `_from` and `_to` (e.g. `era_from`, `era_to`).
+//!
+//! With `--long-format`, each record is melted into one record per metric, with the join keys,
+//! tags and warnings of the record followed by the `algorithm`, `metric` and `value` of each
+//! column, see [`Record::melt`].
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+use crate::{
+    configs::OutputFormat,
+    labels::{Labels, LABEL_COLUMN},
+    warnings::{Scope, ScopeGuard},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    sync::{Mutex, OnceLock},
+};
+
+/// The default output layout, relative to the working directory.
+pub(crate) const DEFAULT_LAYOUT: &str = "output/{chain}/{operation}/{month}.{ext}";
+
+/// Output settings, set once from the CLI.
+#[derive(Debug, Clone)]
+pub(crate) struct OutputConfig {
+    pub format: OutputFormat,
+    /// Tags attached as extra fields to every output entry.
+    pub tags: Vec<(String, String)>,
+    /// Whether the records are written in long format.
+    pub long_format: bool,
+    /// Labels joined to the accounts of the records, see [`crate::labels`].
+    pub labels: Labels,
+}
+
+pub(crate) static CONFIG: OnceLock<OutputConfig> = OnceLock::new();
+
+/// The chain of the outputs, set once connected to the remote node.
+static CHAIN: OnceLock<String> = OnceLock::new();
+
+/// The join keys of the outputs, in the order they lead every record.
+pub(crate) const JOIN_KEYS: [&str; 4] = ["chain", "block_number", "era", "account"];
+
+/// Column names which denote a join key under another name, and the name of the key.
+const KEY_ALIASES: [(&str, &str); 6] = [
+    ("block", "block_number"),
+    ("block_num", "block_number"),
+    ("era_index", "era"),
+    ("validator", "account"),
+    ("nominator", "account"),
+    ("who", "account"),
+];
+
+/// The columns of the output entries which hold accounts besides the `account` join key, by the
+/// name of the type of the entry: a single account or a list of accounts separated by `;`, which
+/// are joined with their labels.
+pub(crate) const ACCOUNT_COLUMNS: [(&str, &[&str]); 7] = [
+    ("HeaderCsv", &["author"]),
+    ("SubmissionAnalysisCsv", &["submitter"]),
+    ("DecentralizationCsv", &["members"]),
+    ("TargetsWhatIfCsv", &["entered", "left"]),
+    ("NominatorChurnCsv", &["targets_added", "targets_removed"]),
+    (
+        "StakingLedgerChecksCsv",
+        &[
+            "bad_ledger_stashes",
+            "none_ledger_stashes",
+            "inconsistent_ledger_stashes",
+            "missing_lock_stashes",
+            "smaller_lock_stashes",
+            "larger_lock_stashes",
+            "missing_payee_stashes",
+            "dangling_payee_stashes",
+            "deprecated_controller_payee_stashes",
+        ],
+    ),
+    (
+        "ControllerDeprecationCsv",
+        &["bricked_stashes", "skipped_payee_stashes"],
+    ),
+];
+
+/// The account columns of the output entries of type `E`, see [`ACCOUNT_COLUMNS`].
+fn account_columns_of<E>() -> &'static [&'static str] {
+    let name = std::any::type_name::<E>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default();
+    ACCOUNT_COLUMNS
+        .iter()
+        .find(|(entry, _)| *entry == name)
+        .map_or(&[], |(_, columns)| columns)
+}
+
+/// The algorithms (e.g. the election solvers) named in the output columns, which are split out of
+/// the metrics in long format.
+const ALGORITHMS: [&str; 4] = ["phrag", "dpos", "approx", "approval"];
+
+/// Variants of the algorithms, named in the output columns next to the algorithm.
+const ALGORITHM_VARIANTS: [&str; 3] = ["unbound", "prorata", "pareto"];
+
+/// Sets the chain of the outputs, which is prepended as the `chain` key of every record.
+pub(crate) fn set_chain(chain: &str) {
+    let _ = CHAIN.set(chain_key(chain));
+}
+
+/// An output entry, i.e. the names and values of its fields.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Record {
+    pub header: Vec<String>,
+    pub values: Vec<Value>,
+}
+
+/// A value of an output record, as serialized by the entry: `None` is `Null` and the nested
+/// structs (e.g. the election scores) are kept nested, see [`Record::flattened`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    /// A number, in its serialized form, so that the `u128` balances keep their precision.
+    Number(String),
+    String(String),
+    List(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parses a value from its JSON serialization, keeping the order of the fields of the structs
+    /// and the digits of the numbers.
+    fn parse(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(match json.as_bytes().first() {
+            Some(b'{') => Value::Struct(
+                serde_json::from_str::<Fields>(json)?
+                    .0
+                    .into_iter()
+                    .map(|(key, value)| Ok((key, Value::parse(value.get())?)))
+                    .collect::<Result<_, serde_json::Error>>()?,
+            ),
+            Some(b'[') => Value::List(
+                serde_json::from_str::<Vec<Box<RawValue>>>(json)?
+                    .iter()
+                    .map(|value| Value::parse(value.get()))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Some(b'"') => Value::String(serde_json::from_str(json)?),
+            Some(b't') | Some(b'f') => Value::Bool(serde_json::from_str(json)?),
+            Some(b'n') => Value::Null,
+            _ => Value::Number(json.to_string()),
+        })
+    }
+
+    /// The value as JSON.
+    pub fn to_json(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => value.clone(),
+            Value::String(value) => json_string(value),
+            Value::List(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(Value::to_json)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Value::Struct(fields) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", json_string(key), value.to_json()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+
+    /// The value as a CSV field: `Null` is empty and the values of a list are separated by `;`.
+    pub fn to_csv(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) | Value::String(value) => value.clone(),
+            Value::List(values) => values
+                .iter()
+                .map(Value::to_csv)
+                .collect::<Vec<_>>()
+                .join(";"),
+            Value::Struct(_) => self.to_json(),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+/// The fields of a JSON object, in order.
+struct Fields(Vec<(String, Box<RawValue>)>);
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for FieldsVisitor {
+            type Value = Fields;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a struct")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Fields, A::Error> {
+                let mut fields = vec![];
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(Fields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+impl Record {
+    /// Serializes a struct entry into a record, with one extra field per tag.
+    pub fn new<E: Serialize>(entry: E, tags: &[(String, Value)]) -> Result<Self, anyhow::Error> {
+        let fields = match Value::parse(&serde_json::to_string(&entry)?)? {
+            Value::Struct(fields) => fields,
+            _ => return Err(anyhow!("expected a struct entry.")),
+        };
+
+        let (header, values) = fields.into_iter().chain(tags.iter().cloned()).unzip();
+        Ok(Record { header, values })
+    }
+
+    /// Enforces the join keys convention on the record: the `chain` key is prepended (if any) and
+    /// the key columns are moved to the front, in the order of [`JOIN_KEYS`]. Records with
+    /// duplicated columns or with columns named after an alias of a key are rejected.
+    pub fn keyed(self, chain: Option<&str>) -> Result<Self, anyhow::Error> {
+        let mut fields = chain
+            .map(|chain| ("chain".to_string(), Value::from(chain)))
+            .into_iter()
+            .chain(self.header.into_iter().zip(self.values))
+            .collect::<Vec<_>>();
+
+        let mut seen = std::collections::BTreeSet::new();
+        for (key, _) in fields.iter() {
+            if let Some((alias, join_key)) = KEY_ALIASES.iter().find(|(alias, _)| alias == key) {
+                return Err(anyhow!(
+                    "output column `{}` must be named after the join key `{}`.",
+                    alias,
+                    join_key
+                ));
+            }
+            if !seen.insert(key.as_str()) {
+                return Err(anyhow!("duplicated output column `{}`.", key));
+            }
+        }
+
+        let rank = |key: &str| {
+            JOIN_KEYS
+                .iter()
+                .position(|join_key| *join_key == key)
+                .unwrap_or(JOIN_KEYS.len())
+        };
+        // stable, so the non-key columns keep their order.
+        fields.sort_by_key(|(key, _)| rank(key));
+
+        let (header, values) = fields.into_iter().unzip();
+        Ok(Record { header, values })
+    }
+
+    /// Joins the label of the `account` column of the record (if any) in the [`LABEL_COLUMN`],
+    /// right after the join keys, and the labels of the other `account_columns` in a
+    /// `<column>_label` column right after each of them. The column is null for the accounts
+    /// without a label, so that all the records of an operation share the same columns, and
+    /// holds the labels of the accounts of a list separated by `;`, or is null if none of them
+    /// has a label.
+    pub fn labeled(mut self, labels: &Labels, account_columns: &[&str]) -> Self {
+        if labels.is_empty() {
+            return self;
+        }
+
+        let mut column = 0;
+        while column < self.header.len() {
+            let key = self.header[column].as_str();
+            if key == "account" || account_columns.contains(&key) {
+                let accounts = self.values[column].to_csv();
+                let accounts = accounts
+                    .split(';')
+                    .filter(|account| !account.is_empty())
+                    .map(|account| labels.label_of(account).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                let label = match accounts.iter().any(|label| !label.is_empty()) {
+                    true => Value::String(accounts.join(";")),
+                    false => Value::Null,
+                };
+
+                self.header
+                    .insert(column + 1, format!("{}_label", self.header[column]));
+                self.values.insert(column + 1, label);
+                column += 1;
+            }
+            column += 1;
+        }
+        self
+    }
+
+    /// Flattens the nested structs of the record into one column per field, named after the
+    /// column of the struct and the field, e.g. `phrag_min_stake` for the `min_stake` of the
+    /// `phrag` score, as written to the CSV outputs.
+    pub fn flattened(&self) -> Self {
+        fn flatten(key: String, value: &Value, record: &mut Record) {
+            match value {
+                Value::Struct(fields) => {
+                    for (field, value) in fields {
+                        flatten(format!("{}_{}", key, field), value, record);
+                    }
+                }
+                value => {
+                    record.header.push(key);
+                    record.values.push(value.clone());
+                }
+            }
+        }
+
+        let mut record = Record {
+            header: vec![],
+            values: vec![],
+        };
+        for (key, value) in self.header.iter().zip(self.values.iter()) {
+            flatten(key.clone(), value, &mut record);
+        }
+        record
+    }
+
+    /// Melts the (flattened) record into long format: one record per column other than the `ids`
+    /// columns, with the `ids` columns followed by the `algorithm` (null if none), `metric` and
+    /// `value` of the column.
+    pub fn melt(&self, ids: &[&str]) -> Vec<Self> {
+        let (ids, measures): (Vec<_>, Vec<_>) = self
+            .header
+            .iter()
+            .zip(self.values.iter())
+            .partition(|(key, _)| ids.contains(&key.as_str()));
+
+        let header = ids
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .chain(["algorithm", "metric", "value"].map(String::from))
+            .collect::<Vec<_>>();
+
+        measures
+            .into_iter()
+            .map(|(column, value)| {
+                let (algorithm, metric) = split_metric(column);
+                let algorithm = match algorithm.is_empty() {
+                    true => Value::Null,
+                    false => Value::String(algorithm),
+                };
+                Record {
+                    header: header.clone(),
+                    values: ids
+                        .iter()
+                        .map(|(_, value)| (*value).clone())
+                        .chain([algorithm, Value::String(metric), value.clone()])
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// The record as a JSON object, with the values as serialized by the entry.
+    pub fn to_json(&self) -> String {
+        Value::Struct(
+            self.header
+                .iter()
+                .cloned()
+                .zip(self.values.iter().cloned())
+                .collect(),
+        )
+        .to_json()
+    }
+}
+
+/// Splits an output column into its algorithm (empty if none) and metric, e.g.
+/// `dpos_unbound_min_stake_prorata` into `dpos_unbound_prorata` and `min_stake`. A column names
+/// an algorithm only if it contains one of the [`ALGORITHMS`].
+pub(crate) fn split_metric(column: &str) -> (String, String) {
+    let tokens = column.split('_').collect::<Vec<_>>();
+    if !tokens.iter().any(|token| ALGORITHMS.contains(token)) {
+        return (String::new(), column.to_string());
+    }
+
+    let (algorithm, metric): (Vec<_>, Vec<_>) = tokens
+        .into_iter()
+        .partition(|token| ALGORITHMS.contains(token) || ALGORITHM_VARIANTS.contains(token));
+
+    (algorithm.join("_"), metric.join("_"))
+}
+
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).expect("strings are serializable; qed.")
+}
+
+/// A backend which appends the output records to an output path.
+pub(crate) trait OutputSink {
+    /// Appends `record` to the output in `output_path`.
+    fn append(&self, record: &Record, output_path: &str) -> Result<(), anyhow::Error>;
+
+    /// Flushes the records appended to the output in `output_path` which the sink holds in memory,
+    /// if any.
+    fn flush(&self, _output_path: &str) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Finishes the outputs of the sink, once all the records are appended.
+    fn finish(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+/// Appends records to a CSV file, flattened, see [`Record::flattened`]. The header is written only
+/// if the file does not exist yet.
+pub(crate) struct CsvSink;
+
+impl OutputSink for CsvSink {
+    fn append(&self, record: &Record, output_path: &str) -> Result<(), anyhow::Error> {
+        let record = record.flattened();
+        let headers = !std::path::Path::new(output_path).exists();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output_path)?;
+
+        let mut writer = csv::Writer::from_writer(file);
+        if headers {
+            writer.write_record(&record.header)?;
+        }
+        writer.write_record(record.values.iter().map(Value::to_csv))?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Appends records to a JSON array of objects in a file.
+pub(crate) struct JsonSink;
+
+impl OutputSink for JsonSink {
+    fn append(&self, record: &Record, output_path: &str) -> Result<(), anyhow::Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(output_path)?;
+
+        if file.metadata()?.len() == 0 {
+            write!(file, "[\n{}\n]\n", record.to_json())?;
+            return Ok(());
+        }
+
+        // replaces the closing bracket of the array by the new object.
+        let mut tail = [0u8; 2];
+        file.seek(SeekFrom::End(-2))?;
+        file.read_exact(&mut tail)?;
+        if &tail != b"]\n" {
+            return Err(anyhow!(
+                "{} is not a JSON array written by substrate-timetravel.",
+                output_path
+            ));
+        }
+        file.seek(SeekFrom::End(-2))?;
+        write!(file, ",\n{}\n]\n", record.to_json())?;
+
+        Ok(())
+    }
+}
+
+/// Appends records to a JSON Lines file, one JSON object per line.
+pub(crate) struct JsonlSink;
+
+impl OutputSink for JsonlSink {
+    fn append(&self, record: &Record, output_path: &str) -> Result<(), anyhow::Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output_path)?;
+
+        writeln!(file, "{}", record.to_json())?;
+
+        Ok(())
+    }
+}
+
+/// Writes records to the standard output as CSV, flattened, ignoring the output path. The header is
+/// written whenever it differs from the header of the previous record.
+pub(crate) struct StdoutSink;
+
+static STDOUT_HEADER: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+impl OutputSink for StdoutSink {
+    fn append(&self, record: &Record, _output_path: &str) -> Result<(), anyhow::Error> {
+        let mut last_header = STDOUT_HEADER
+            .lock()
+            .map_err(|_| anyhow!("stdout header lock poisoned."))?;
+
+        let record = record.flattened();
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        if last_header.as_ref() != Some(&record.header) {
+            writer.write_record(&record.header)?;
+            *last_header = Some(record.header.clone());
+        }
+        writer.write_record(record.values.iter().map(Value::to_csv))?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl OutputFormat {
+    /// The sink of the format.
+    pub fn sink(&self) -> &'static dyn OutputSink {
+        match self {
+            OutputFormat::Csv => &CsvSink,
+            OutputFormat::Json => &JsonSink,
+            OutputFormat::Jsonl => &JsonlSink,
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => &self::parquet::ParquetSink,
+            OutputFormat::Stdout => &StdoutSink,
+        }
+    }
+
+    /// The file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv | OutputFormat::Stdout => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Appends an entry to the output in `output_path`, in the format and with the tags of the CLI,
+/// without warnings. See [`Emitter::emit`] for the entries of the operations.
+pub(crate) fn write<E: Serialize>(entry: E, output_path: &str) -> Result<(), anyhow::Error> {
+    write_with_warnings(entry, output_path, vec![])
+}
+
+/// Appends an entry to the output in `output_path`, in the format and with the tags of the CLI.
+/// The `warnings` raised while the entry was computed are attached as the `warnings` and
+/// `warnings_summary` fields.
+fn write_with_warnings<E: Serialize>(
+    entry: E,
+    output_path: &str,
+    warnings: Vec<crate::warnings::Warning>,
+) -> Result<(), anyhow::Error> {
+    let default_labels = Labels::default();
+    let (format, tags, long_format, labels) = match CONFIG.get() {
+        Some(config) => (
+            config.format,
+            config.tags.as_slice(),
+            config.long_format,
+            &config.labels,
+        ),
+        None => (OutputFormat::Csv, &[][..], false, &default_labels),
+    };
+
+    let tags = tags
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .chain([
+            (
+                "warnings".to_string(),
+                Value::Number(warnings.len().to_string()),
+            ),
+            (
+                "warnings_summary".to_string(),
+                Value::String(crate::warnings::summary(&warnings)),
+            ),
+        ])
+        .collect::<Vec<_>>();
+
+    let record = Record::new(entry, &tags)?
+        .keyed(CHAIN.get().map(String::as_str))?
+        .labeled(labels, account_columns_of::<E>());
+    let sink = format.sink();
+    match long_format {
+        true => {
+            let ids = JOIN_KEYS
+                .iter()
+                .copied()
+                .chain([LABEL_COLUMN])
+                .chain(tags.iter().map(|(key, _)| key.as_str()))
+                .collect::<Vec<_>>();
+            for record in record.flattened().melt(&ids) {
+                sink.append(&record, output_path)?;
+            }
+            Ok(())
+        }
+        false => sink.append(&record, output_path),
+    }
+}
+
+/// Number of rows emitted between two flushes of the sink by an [`Emitter`].
+pub(crate) const FLUSH_EVERY: usize = 10_000;
+
+/// Emits the rows of an operation to its output path as each row completes, rather than once the
+/// operation is done, so that the rows of a long run survive a crash and the memory of large
+/// exports stays flat. The sink is flushed every [`FLUSH_EVERY`] rows, e.g. closing the current
+/// part of a Parquet file, so a crash loses at most the rows since the last flush (none in CSV,
+/// whose rows are appended to the file as they are emitted).
+///
+/// Only the memory of the rows is bounded: the operations run over the externalities of all their
+/// blocks, which are loaded (or scraped) before the operation starts.
+///
+/// The emitter holds the [`Scope`] of the warnings of its rows, entered with [`Emitter::scope`]
+/// on the thread which computes them.
+pub(crate) struct Emitter {
+    output_path: String,
+    rows: usize,
+    warnings: Scope,
+}
+
+impl Emitter {
+    pub fn new(output_path: impl Into<String>) -> Self {
+        Self {
+            output_path: output_path.into(),
+            rows: 0,
+            warnings: Scope::default(),
+        }
+    }
+
+    /// Collects the warnings raised on the current thread for the rows of the emitter, until the
+    /// returned guard is dropped.
+    pub fn scope(&self) -> ScopeGuard {
+        self.warnings.enter()
+    }
+
+    /// Starts the rows of the next block. The warnings raised since the last row which were not
+    /// attached to it (e.g. of a block which emitted no row) are not attached to the rows of the
+    /// next block, and are reported as unattached when the run ends.
+    pub fn begin_block(&mut self) {
+        self.warnings.clear();
+    }
+
+    /// Writes a row to the output with the warnings raised since the previous row, see [`write`].
+    pub fn emit<E: Serialize>(&mut self, entry: E) -> Result<(), anyhow::Error> {
+        write_with_warnings(entry, &self.output_path, self.warnings.take())?;
+
+        self.rows += 1;
+        if self.rows % FLUSH_EVERY == 0 {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the rows held in memory by the sink of the CLI format, if any.
+    pub fn flush(&self) -> Result<(), anyhow::Error> {
+        let format = CONFIG
+            .get()
+            .map(|config| config.format)
+            .unwrap_or(OutputFormat::Csv);
+
+        format.sink().flush(&self.output_path)
+    }
+
+    /// The number of rows emitted.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn output_path(&self) -> &str {
+        &self.output_path
+    }
+}
+
+impl Drop for Emitter {
+    fn drop(&mut self) {
+        // the warnings raised after the last row are reported as unattached.
+        self.warnings.clear();
+    }
+}
+
+/// Finishes the output of the format of the CLI, e.g. closing the files which can not be
+/// appended to.
+pub(crate) fn finish() -> Result<(), anyhow::Error> {
+    let format = CONFIG
+        .get()
+        .map(|config| config.format)
+        .unwrap_or(OutputFormat::Csv);
+
+    format.sink().finish()
+}
+
+/// Returns the output path of a command: `output_path` if set, otherwise the `layout` with its
+/// `{chain}`, `{operation}`, `{month}` and `{ext}` placeholders replaced. The parent directories
+/// of the path are created if they do not exist.
+pub(crate) fn resolve_output_path(
+    output_path: Option<String>,
+    layout: &str,
+    chain: &str,
+    operation: &str,
+    format: OutputFormat,
+) -> Result<String, anyhow::Error> {
+    let path = match output_path {
+        Some(path) => path,
+        None => layout_path(
+            layout,
+            chain,
+            operation,
+            &current_month(),
+            format.extension(),
+        ),
+    };
+
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("Error creating output directory {:?}: {:?}.", parent, e))?;
+    }
+
+    Ok(path)
+}
+
+/// Returns the `chain` key of a chain name, i.e. the name lowercased and with its whitespaces
+/// replaced by dashes.
+pub(crate) fn chain_key(chain: &str) -> String {
+    chain
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Replaces the placeholders of `layout`, with the `chain` key of the chain name.
+pub(crate) fn layout_path(
+    layout: &str,
+    chain: &str,
+    operation: &str,
+    month: &str,
+    ext: &str,
+) -> String {
+    layout
+        .replace("{chain}", &chain_key(chain))
+        .replace("{operation}", operation)
+        .replace("{month}", month)
+        .replace("{ext}", ext)
+}
+
+/// Converts a `CamelCase` name (e.g. of an operation) into `kebab-case`.
+pub(crate) fn kebab_case(name: &str) -> String {
+    let mut kebab = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                kebab.push('-');
+            }
+            kebab.extend(c.to_lowercase());
+        } else {
+            kebab.push(c);
+        }
+    }
+    kebab
+}
+
+/// Returns the current month (UTC) as `YYYY-MM`.
+pub(crate) fn current_month() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month) = year_month(now);
+
+    format!("{:04}-{:02}", year, month)
+}
+
+/// Returns the year and month (UTC) of a unix timestamp in seconds.
+fn year_month(unix_secs: u64) -> (i64, u32) {
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html.
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as u32)
+}
+
+/// Returns the unix timestamp in seconds of the start (UTC) of a date.
+pub(crate) fn unix_secs_of(year: i64, month: u32, day: u32) -> i64 {
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    (era * 146_097 + day_of_era - 719_468) * 86_400
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_path_works() {
+        assert_eq!(
+            layout_path(
+                DEFAULT_LAYOUT,
+                "Polkadot",
+                "election-analysis",
+                "2023-06",
+                "csv"
+            ),
+            "output/polkadot/election-analysis/2023-06.csv"
+        );
+        assert_eq!(
+            layout_path(
+                "{chain}-{operation}.csv",
+                "Rococo Local",
+                "headers",
+                "2023-06",
+                "json"
+            ),
+            "rococo-local-headers.csv"
+        );
+        assert_eq!(
+            kebab_case("ElectionAnalysis"),
+            "election-analysis".to_string()
+        );
+        assert_eq!(kebab_case("Headers"), "headers".to_string());
+    }
+
+    #[test]
+    fn year_month_works() {
+        assert_eq!(year_month(0), (1970, 1));
+        // 2000-02-29T12:00:00Z.
+        assert_eq!(year_month(951_825_600), (2000, 2));
+        // 2023-12-31T23:59:59Z.
+        assert_eq!(year_month(1_704_067_199), (2023, 12));
+        // 2024-01-01T00:00:00Z.
+        assert_eq!(year_month(1_704_067_200), (2024, 1));
+
+        assert_eq!(unix_secs_of(1970, 1, 1), 0);
+        assert_eq!(unix_secs_of(2000, 2, 29), 951_782_400);
+        assert_eq!(unix_secs_of(2024, 1, 1), 1_704_067_200);
+    }
+
+    #[derive(Serialize)]
+    struct Entry {
+        block_number: u32,
+        value: u128,
+        label: Option<String>,
+    }
+
+    fn entry(block_number: u32, value: u128) -> Entry {
+        Entry {
+            block_number,
+            value,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn keyed_record_works() {
+        let record = |header: &[&str]| Record {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            values: header
+                .iter()
+                .map(|h| Value::String(format!("{}_value", h)))
+                .collect(),
+        };
+
+        let keyed = record(&["score", "account", "block_number", "label"])
+            .keyed(Some("polkadot"))
+            .unwrap();
+        assert_eq!(
+            keyed.header,
+            vec!["chain", "block_number", "account", "score", "label"]
+        );
+        assert_eq!(
+            keyed.values,
+            [
+                "polkadot",
+                "block_number_value",
+                "account_value",
+                "score_value",
+                "label_value"
+            ]
+            .map(Value::from)
+        );
+
+        let keyed = record(&["value", "era"]).keyed(None).unwrap();
+        assert_eq!(keyed.header, vec!["era", "value"]);
+
+        assert!(record(&["block_number", "validator"]).keyed(None).is_err());
+        assert!(record(&["chain", "value"]).keyed(Some("kusama")).is_err());
+    }
+
+    #[test]
+    fn labeled_record_works() {
+        use sp_core::crypto::{AccountId32, Ss58Codec};
+
+        let labeled = AccountId32::from([1u8; 32]).to_ss58check();
+        let unlabeled = AccountId32::from([2u8; 32]).to_ss58check();
+        let labels = Labels::parse(&format!("address,label\n{},Foundation\n", labeled)).unwrap();
+        let record = |account: &str| Record {
+            header: vec!["era".into(), "account".into(), "score".into()],
+            values: vec![
+                Value::Number("10".into()),
+                account.into(),
+                Value::Number("1".into()),
+            ],
+        };
+
+        let record_of = record(&labeled).labeled(&labels, &[]);
+        assert_eq!(
+            record_of.header,
+            vec!["era", "account", "account_label", "score"]
+        );
+        assert_eq!(
+            record_of.values,
+            vec![
+                Value::Number("10".into()),
+                labeled.as_str().into(),
+                "Foundation".into(),
+                Value::Number("1".into()),
+            ]
+        );
+        assert_eq!(
+            record(&unlabeled).labeled(&labels, &[]).values[2],
+            Value::Null
+        );
+
+        // records are unchanged without labels or accounts.
+        assert_eq!(
+            record(&labeled).labeled(&Labels::default(), &[]),
+            record(&labeled)
+        );
+        let era = Record {
+            header: vec!["era".into()],
+            values: vec![Value::Number("10".into())],
+        };
+        assert_eq!(era.clone().labeled(&labels, &[]), era);
+
+        // the other account columns of the entry, including lists of accounts.
+        let churn = Record {
+            header: vec!["era".into(), "targets_added".into(), "left".into()],
+            values: vec![
+                Value::Number("10".into()),
+                Value::String(format!("{};{}", unlabeled, labeled)),
+                Value::Number("2".into()),
+            ],
+        };
+        let columns = account_columns_of::<crate::operations::NominatorChurnCsv>();
+        assert_eq!(columns, &["targets_added", "targets_removed"]);
+        let labeled_churn = churn.labeled(&labels, columns);
+        assert_eq!(
+            labeled_churn.header,
+            vec!["era", "targets_added", "targets_added_label", "left"]
+        );
+        assert_eq!(labeled_churn.values[2], ";Foundation".into());
+    }
+
+    #[test]
+    fn melt_works() {
+        let record = Record {
+            header: ["block_number", "dpos_min_stake_prorata", "voters", "solver"]
+                .map(String::from)
+                .to_vec(),
+            values: vec![
+                Value::Number("10".into()),
+                Value::Number("100".into()),
+                Value::Number("3".into()),
+                "mms".into(),
+            ],
+        };
+
+        let long = record.melt(&["block_number", "solver"]);
+        assert_eq!(long.len(), 2);
+        for record in long.iter() {
+            assert_eq!(
+                record.header,
+                vec!["block_number", "solver", "algorithm", "metric", "value"]
+            );
+        }
+        assert_eq!(
+            long[0].to_json(),
+            r#"{"block_number":10,"solver":"mms","algorithm":"dpos_prorata","metric":"min_stake","value":100}"#
+        );
+        assert_eq!(
+            long[1].to_json(),
+            r#"{"block_number":10,"solver":"mms","algorithm":null,"metric":"voters","value":3}"#
+        );
+
+        assert_eq!(
+            split_metric("dpos_unbound_sum_stake_squared_pareto"),
+            (
+                "dpos_unbound_pareto".to_string(),
+                "sum_stake_squared".to_string()
+            )
+        );
+        assert_eq!(
+            split_metric("phrag_unbound_min_stake_ratio"),
+            ("phrag_unbound".to_string(), "min_stake_ratio".to_string())
+        );
+        assert_eq!(
+            split_metric("voters_unbound"),
+            (String::new(), "voters_unbound".to_string())
+        );
+    }
+
+    #[test]
+    fn csv_sink_works() {
+        let output_path = std::env::temp_dir().join("substrate_timetravel_csv_sink.csv");
+        let output_path = output_path.to_str().unwrap();
+        let _ = std::fs::remove_file(output_path);
+
+        let tags = vec![
+            ("experiment".to_string(), "iter-sweep".into()),
+            ("solver".to_string(), "mms".into()),
+        ];
+        for (block_number, value) in [(1, 10), (2, 20)] {
+            let record = Record::new(entry(block_number, value), &tags).unwrap();
+            CsvSink.append(&record, output_path).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(output_path).unwrap(),
+            "block_number,value,label,experiment,solver\n1,10,,iter-sweep,mms\n2,20,,iter-sweep,mms\n"
+        );
+        std::fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn emitter_works() {
+        let output_path = std::env::temp_dir().join("substrate_timetravel_emitter.csv");
+        let output_path = output_path.to_str().unwrap();
+        let _ = std::fs::remove_file(output_path);
+
+        let mut out = Emitter::new(output_path);
+        out.emit(entry(1, 10)).unwrap();
+        // the row is in the output as soon as it is emitted, under the header.
+        assert_eq!(
+            std::fs::read_to_string(output_path)
+                .unwrap()
+                .lines()
+                .count(),
+            2
+        );
+        out.emit(entry(2, 20)).unwrap();
+        out.flush().unwrap();
+
+        assert_eq!(out.rows(), 2);
+        assert_eq!(
+            std::fs::read_to_string(output_path)
+                .unwrap()
+                .lines()
+                .count(),
+            3
+        );
+        std::fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn json_sink_works() {
+        let output_path = std::env::temp_dir().join("substrate_timetravel_json_sink.json");
+        let output_path = output_path.to_str().unwrap();
+        let _ = std::fs::remove_file(output_path);
+
+        let big = u128::MAX;
+        let tags = vec![("solver".to_string(), "mms".into())];
+        for (block_number, value) in [(1, 10), (2, big)] {
+            let record = Record::new(entry(block_number, value), &tags).unwrap();
+            JsonSink.append(&record, output_path).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(output_path).unwrap(),
+            format!(
+                "[\n{}\n,\n{}\n]\n",
+                r#"{"block_number":1,"value":10,"label":null,"solver":"mms"}"#,
+                format!(
+                    r#"{{"block_number":2,"value":{},"label":null,"solver":"mms"}}"#,
+                    big
+                ),
+            )
+        );
+        std::fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn jsonl_sink_works() {
+        let output_path = std::env::temp_dir().join("substrate_timetravel_jsonl_sink.jsonl");
+        let output_path = output_path.to_str().unwrap();
+        let _ = std::fs::remove_file(output_path);
+
+        // an empty label is kept apart from a missing one.
+        for (block_number, value, label) in [(1, 10, None), (2, 20, Some(String::new()))] {
+            let entry = Entry {
+                label,
+                ..entry(block_number, value)
+            };
+            let record = Record::new(entry, &[]).unwrap();
+            JsonlSink.append(&record, output_path).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(output_path).unwrap(),
+            concat!(
+                r#"{"block_number":1,"value":10,"label":null}"#,
+                "\n",
+                r#"{"block_number":2,"value":20,"label":""}"#,
+                "\n"
+            )
+        );
+        std::fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn value_works() {
+        #[derive(Serialize)]
+        struct Score {
+            min_stake: u128,
+            ratio: Option<f64>,
+        }
+        #[derive(Serialize)]
+        struct Nested {
+            account: &'static str,
+            phrag: Score,
+            approved: bool,
+            members: Vec<&'static str>,
+        }
+
+        let record = Record::new(
+            Nested {
+                account: "0x1234",
+                phrag: Score {
+                    min_stake: u128::MAX,
+                    ratio: None,
+                },
+                approved: true,
+                members: vec!["a", "b"],
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            record.to_json(),
+            format!(
+                r#"{{"account":"0x1234","phrag":{{"min_stake":{},"ratio":null}},"approved":true,"members":["a","b"]}}"#,
+                u128::MAX
+            )
+        );
+
+        let flattened = record.flattened();
+        assert_eq!(
+            flattened.header,
+            vec![
+                "account",
+                "phrag_min_stake",
+                "phrag_ratio",
+                "approved",
+                "members"
+            ]
+        );
+        assert_eq!(
+            flattened
+                .values
+                .iter()
+                .map(Value::to_csv)
+                .collect::<Vec<_>>(),
+            vec![
+                "0x1234".to_string(),
+                u128::MAX.to_string(),
+                String::new(),
+                "true".to_string(),
+                "a;b".to_string()
+            ]
+        );
+
+        assert_eq!(
+            Value::parse(r#""a \"quoted\" label""#).unwrap(),
+            Value::from("a \"quoted\" label")
+        );
+        assert_eq!(Value::parse("-0.5e3").unwrap().to_json(), "-0.5e3");
+        assert!(Record::new(10u32, &[]).is_err());
+    }
+}
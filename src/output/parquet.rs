@@ -0,0 +1,507 @@
+//! Parquet output backend, enabled with the `parquet` feature.
+//!
+//! Parquet files can not be appended to once closed, so the records are buffered in one open
+//! writer per output path and the files are closed (and their footer written) when the output is
+//! finished. The schema of a file is inferred from the serialized types of the values of each
+//! column: integers are stored as `Decimal128(38, 0)` (to fit the balances), other numbers as
+//! `Float64`, booleans as `Boolean` and anything else (or a mix of types) as `Utf8`. Nulls carry
+//! no type, so the first records of a file are held back until each of its columns has a value (or
+//! up to [`MAX_PENDING`] records). In long format, the `value` column holds the values of all the
+//! metrics, so it is always stored as `Utf8`.
+//!
+//! The records are written in batches of [`BATCH_SIZE`] rows. A record which does not fit the
+//! schema of the file (e.g. an integer beyond the precision of `Decimal128(38, 0)`) is not
+//! rejected: it starts the next part of the file, with its columns widened to `Float64` (numbers)
+//! or `Utf8`.
+//!
+//! The writers are flushed by the [`super::Emitter`] of the operations, which closes the file
+//! (writing its footer) as a finalised part of the output, so that the records of a long run
+//! survive a crash and the memory of large exports stays bounded. The records after a flush are
+//! written to the next part, next to the output path and numbered before its extension, e.g.
+//! `2023-06.parquet`, `2023-06.1.parquet`, `2023-06.2.parquet`, with the schema of the previous
+//! part.
+
+use super::{OutputSink, Record, Value};
+
+use ::parquet::arrow::ArrowWriter;
+use anyhow::anyhow;
+use arrow::{
+    array::{ArrayRef, BooleanArray, Decimal128Array, Float64Array, StringArray},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of records held back before inferring the schema of a file. The columns
+/// without any value by then are stored as `Utf8`.
+const MAX_PENDING: usize = 1024;
+
+/// Number of records written to a file at once.
+const BATCH_SIZE: usize = 1024;
+
+/// Appends records to the parts of a Parquet file, closed when flushed or when the output is
+/// finished.
+pub(crate) struct ParquetSink;
+
+/// The output of a path: the records held back until the schema can be inferred, the schema, the
+/// open writer and the records not written yet of the current part of the file, or the schema and
+/// the last part of the file once it is flushed.
+enum Output {
+    Pending(Vec<Record>),
+    Open(SchemaRef, ArrowWriter<File>, usize, Vec<Record>),
+    Flushed(SchemaRef, usize),
+}
+
+/// The outputs of the output paths.
+static WRITERS: Mutex<BTreeMap<String, Output>> = Mutex::new(BTreeMap::new());
+
+impl OutputSink for ParquetSink {
+    fn append(&self, record: &Record, output_path: &str) -> Result<(), anyhow::Error> {
+        let record = record.flattened();
+        let mut writers = WRITERS
+            .lock()
+            .map_err(|_| anyhow!("parquet writers lock poisoned."))?;
+
+        if !writers.contains_key(output_path) {
+            if std::path::Path::new(output_path).exists() {
+                return Err(anyhow!(
+                    "{} exists and parquet files can not be appended to.",
+                    output_path
+                ));
+            }
+            writers.insert(output_path.to_string(), Output::Pending(vec![]));
+        }
+        let output = writers
+            .remove(output_path)
+            .expect("output inserted above; qed.");
+
+        let output = match output {
+            Output::Pending(mut records) => {
+                check_fields(records.first().unwrap_or(&record), &record, output_path)?;
+                records.push(record);
+                match records.len() >= MAX_PENDING || is_typed(&records) {
+                    true => open(output_path, 0, schema_of(&records), records)?,
+                    false => Output::Pending(records),
+                }
+            }
+            Output::Open(schema, mut writer, part, mut records) => {
+                check_schema(&schema, &record, output_path)?;
+                match fits(&schema, &record) {
+                    true => {
+                        records.push(record);
+                        if records.len() >= BATCH_SIZE {
+                            write_batch(&mut writer, &schema, &mut records)?;
+                        }
+                        Output::Open(schema, writer, part, records)
+                    }
+                    false => {
+                        write_batch(&mut writer, &schema, &mut records)?;
+                        writer.close()?;
+                        open(
+                            output_path,
+                            part + 1,
+                            widened(&schema, &record),
+                            vec![record],
+                        )?
+                    }
+                }
+            }
+            Output::Flushed(schema, part) => {
+                check_schema(&schema, &record, output_path)?;
+                open(
+                    output_path,
+                    part + 1,
+                    widened(&schema, &record),
+                    vec![record],
+                )?
+            }
+        };
+        writers.insert(output_path.to_string(), output);
+
+        Ok(())
+    }
+
+    fn flush(&self, output_path: &str) -> Result<(), anyhow::Error> {
+        let mut writers = WRITERS
+            .lock()
+            .map_err(|_| anyhow!("parquet writers lock poisoned."))?;
+
+        // the records held back until the schema is inferred are bounded by `MAX_PENDING`.
+        if let Some(Output::Open(..)) = writers.get(output_path) {
+            if let Some(Output::Open(schema, mut writer, part, mut records)) =
+                writers.remove(output_path)
+            {
+                write_batch(&mut writer, &schema, &mut records)?;
+                writer.close()?;
+                writers.insert(output_path.to_string(), Output::Flushed(schema, part));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), anyhow::Error> {
+        let mut writers = WRITERS
+            .lock()
+            .map_err(|_| anyhow!("parquet writers lock poisoned."))?;
+
+        for (output_path, output) in std::mem::take(&mut *writers) {
+            let output = match output {
+                Output::Pending(records) if !records.is_empty() => {
+                    open(&output_path, 0, schema_of(&records), records)?
+                }
+                output => output,
+            };
+            if let Output::Open(schema, mut writer, _, mut records) = output {
+                write_batch(&mut writer, &schema, &mut records)?;
+                writer.close()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `record` has the same fields as the `first` record of a file.
+fn check_fields(first: &Record, record: &Record, output_path: &str) -> Result<(), anyhow::Error> {
+    match first.header == record.header {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "record with fields {:?} does not match the fields of {}.",
+            record.header,
+            output_path
+        )),
+    }
+}
+
+/// Checks that `record` has the fields of the `schema` of a file.
+fn check_schema(
+    schema: &SchemaRef,
+    record: &Record,
+    output_path: &str,
+) -> Result<(), anyhow::Error> {
+    let matches = schema.fields().len() == record.header.len()
+        && schema
+            .fields()
+            .iter()
+            .zip(record.header.iter())
+            .all(|(field, name)| field.name() == name);
+
+    match matches {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "record with fields {:?} does not match the schema of {}.",
+            record.header,
+            output_path
+        )),
+    }
+}
+
+/// The path of the `part`-th part of the file of `output_path`, numbered before its extension.
+/// The first part is the output path itself.
+fn part_path(output_path: &str, part: usize) -> String {
+    if part == 0 {
+        return output_path.to_string();
+    }
+    let path = std::path::Path::new(output_path);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}.{}.{}",
+                stem.to_string_lossy(),
+                part,
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}.{}", output_path, part),
+    }
+}
+
+/// Whether each column of the `records` has a non-null value.
+fn is_typed(records: &[Record]) -> bool {
+    let columns = records.first().map_or(0, |r| r.values.len());
+    (0..columns).all(|i| records.iter().any(|r| r.values[i] != Value::Null))
+}
+
+/// Creates the `part`-th part of the file of `output_path` with `schema`, to which the `records`
+/// are written.
+fn open(
+    output_path: &str,
+    part: usize,
+    schema: SchemaRef,
+    records: Vec<Record>,
+) -> Result<Output, anyhow::Error> {
+    let writer = ArrowWriter::try_new(
+        File::create(part_path(output_path, part))?,
+        schema.clone(),
+        None,
+    )?;
+    let records = records
+        .into_iter()
+        .map(|record| match fits(&schema, &record) {
+            true => Ok(record),
+            false => Err(anyhow!(
+                "record {:?} does not fit the schema inferred for {}.",
+                record.values,
+                output_path
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Output::Open(schema, writer, part, records))
+}
+
+/// Writes the `records` to a file as a batch, and clears them.
+fn write_batch(
+    writer: &mut ArrowWriter<File>,
+    schema: &SchemaRef,
+    records: &mut Vec<Record>,
+) -> Result<(), anyhow::Error> {
+    if !records.is_empty() {
+        writer.write(&record_batch(records, schema.clone())?)?;
+        records.clear();
+    }
+    Ok(())
+}
+
+/// Whether `value` is an integer which fits a `Decimal128(38, 0)`.
+fn is_decimal(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    !digits.is_empty() && digits.len() <= 38 && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The type of the column of the non-null `values`.
+fn data_type_of<'a>(values: impl Iterator<Item = &'a Value>) -> DataType {
+    let mut data_type = None;
+    for value in values {
+        let value_type = match value {
+            Value::Null => continue,
+            Value::Number(n) if is_decimal(n) => DataType::Decimal128(38, 0),
+            Value::Number(_) => DataType::Float64,
+            Value::Bool(_) => DataType::Boolean,
+            _ => DataType::Utf8,
+        };
+        data_type = Some(match (data_type, value_type) {
+            (None, value_type) => value_type,
+            (Some(a), b) if a == b => a,
+            (Some(DataType::Decimal128(..)), DataType::Float64)
+            | (Some(DataType::Float64), DataType::Decimal128(..)) => DataType::Float64,
+            _ => DataType::Utf8,
+        });
+    }
+    data_type.unwrap_or(DataType::Utf8)
+}
+
+/// Infers the schema of a file from the values of each column of its first `records`.
+fn schema_of(records: &[Record]) -> SchemaRef {
+    let long_format = super::CONFIG
+        .get()
+        .map_or(false, |config| config.long_format);
+
+    let header = records
+        .first()
+        .map(|r| r.header.clone())
+        .unwrap_or_default();
+    let fields = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let data_type = match long_format && name == "value" {
+                true => DataType::Utf8,
+                false => data_type_of(records.iter().map(|r| &r.values[i])),
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect::<Vec<_>>();
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Whether a value fits a column of `data_type`.
+fn fits_type(data_type: &DataType, value: &Value) -> bool {
+    match (data_type, value) {
+        (_, Value::Null) | (DataType::Utf8, _) | (DataType::Boolean, Value::Bool(_)) => true,
+        (DataType::Decimal128(..), Value::Number(n)) => is_decimal(n),
+        (DataType::Float64, Value::Number(_)) => true,
+        _ => false,
+    }
+}
+
+/// Whether each value of `record` fits its column of `schema`.
+fn fits(schema: &SchemaRef, record: &Record) -> bool {
+    schema
+        .fields()
+        .iter()
+        .zip(record.values.iter())
+        .all(|(field, value)| fits_type(field.data_type(), value))
+}
+
+/// Widens the columns of `schema` which `record` does not fit: to `Float64` for the numbers and
+/// to `Utf8` otherwise.
+fn widened(schema: &SchemaRef, record: &Record) -> SchemaRef {
+    let fields = schema
+        .fields()
+        .iter()
+        .zip(record.values.iter())
+        .map(|(field, value)| {
+            let data_type = match (fits_type(field.data_type(), value), value) {
+                (true, _) => field.data_type().clone(),
+                (false, Value::Number(_)) if field.data_type() != &DataType::Boolean => {
+                    DataType::Float64
+                }
+                (false, _) => DataType::Utf8,
+            };
+            Field::new(field.name(), data_type, true)
+        })
+        .collect::<Vec<_>>();
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Converts the `records` into a batch of `schema`.
+fn record_batch(records: &[Record], schema: SchemaRef) -> Result<RecordBatch, anyhow::Error> {
+    let columns = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let values = records.iter().map(|r| &r.values[i]);
+            let mismatch = |value: &Value| {
+                anyhow!(
+                    "value {:?} of {} does not match its {} column.",
+                    value,
+                    field.name(),
+                    field.data_type()
+                )
+            };
+
+            let column: ArrayRef = match field.data_type() {
+                DataType::Decimal128(precision, scale) => Arc::new(
+                    values
+                        .map(|value| match value {
+                            Value::Null => Ok(None),
+                            Value::Number(n) => {
+                                n.parse::<i128>().map(Some).map_err(|_| mismatch(value))
+                            }
+                            _ => Err(mismatch(value)),
+                        })
+                        .collect::<Result<Decimal128Array, _>>()?
+                        .with_precision_and_scale(*precision, *scale)?,
+                ),
+                DataType::Boolean => Arc::new(
+                    values
+                        .map(|value| match value {
+                            Value::Null => Ok(None),
+                            Value::Bool(b) => Ok(Some(*b)),
+                            _ => Err(mismatch(value)),
+                        })
+                        .collect::<Result<BooleanArray, _>>()?,
+                ),
+                DataType::Float64 => Arc::new(
+                    values
+                        .map(|value| match value {
+                            Value::Null => Ok(None),
+                            Value::Number(n) => {
+                                n.parse::<f64>().map(Some).map_err(|_| mismatch(value))
+                            }
+                            _ => Err(mismatch(value)),
+                        })
+                        .collect::<Result<Float64Array, _>>()?,
+                ),
+                _ => Arc::new(
+                    values
+                        .map(|value| match value {
+                            Value::Null => None,
+                            value => Some(value.to_csv()),
+                        })
+                        .collect::<StringArray>(),
+                ),
+            };
+            Ok(column)
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+
+    fn record(values: Vec<Value>) -> Record {
+        Record {
+            header: vec!["block_number".into(), "score".into(), "label".into()],
+            values,
+        }
+    }
+
+    fn number(n: &str) -> Value {
+        Value::Number(n.to_string())
+    }
+
+    #[test]
+    fn part_path_works() {
+        assert_eq!(part_path("out/2023-06.parquet", 0), "out/2023-06.parquet");
+        assert_eq!(part_path("out/2023-06.parquet", 2), "out/2023-06.2.parquet");
+        assert_eq!(part_path("out/results", 1), "out/results.1");
+    }
+
+    #[test]
+    fn schema_of_works() {
+        let records = [
+            record(vec![number("1"), Value::Null, Value::Null]),
+            record(vec![number("2"), number("0.5"), Value::Null]),
+        ];
+        assert!(!is_typed(&records));
+        let schema = schema_of(&records);
+
+        assert_eq!(
+            schema
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                DataType::Decimal128(38, 0),
+                DataType::Float64,
+                DataType::Utf8
+            ]
+        );
+        let batch = record_batch(
+            &[
+                record(vec![number("2"), number("1"), "label".into()]),
+                record(vec![number("3"), Value::Null, "".into()]),
+            ],
+            schema.clone(),
+        )
+        .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        // the empty label is kept apart from a missing one.
+        assert_eq!(batch.column(2).null_count(), 0);
+
+        // the records which do not fit the schema widen its columns.
+        let overflow = record(vec![
+            number(&u128::MAX.to_string()),
+            number("1"),
+            Value::Bool(true),
+        ]);
+        assert!(!fits(&schema, &overflow));
+        assert!(record_batch(&[overflow.clone()], schema.clone()).is_err());
+        let widened = widened(&schema, &overflow);
+        assert_eq!(
+            widened
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>(),
+            vec![DataType::Float64, DataType::Float64, DataType::Utf8]
+        );
+        assert!(record_batch(&[overflow], widened).is_ok());
+    }
+}
@@ -30,6 +30,9 @@ pub use pallet_staking as Staking;
 
 pub use pallet_bags_list as BagsList;
 
+// The nomination pools pallet.
+pub use pallet_nomination_pools as Pools;
+
 /// The externalities type.
 //pub type Ext = sp_io::TestExternalities;
 use sp_runtime::traits::BlakeTwo256;
@@ -2,15 +2,18 @@
 
 use super::*;
 use jsonrpsee::{
-    core::{Error as RpcError, RpcResult},
+    core::{client::Subscription, Error as RpcError, RpcResult},
     proc_macros::rpc,
 };
 use pallet_transaction_payment::RuntimeDispatchInfo;
 use sc_transaction_pool_api::TransactionStatus;
-use sp_core::{storage::StorageKey, Bytes};
+use sp_core::{
+    storage::{StorageChangeSet, StorageKey},
+    Bytes,
+};
 use sp_version::RuntimeVersion;
 
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 #[derive(frame_support::DebugNoBound, thiserror::Error)]
 pub(crate) enum RpcHelperError {
@@ -24,6 +27,16 @@ impl std::fmt::Display for RpcHelperError {
     }
 }
 
+/// The read proof of storage keys at a block, as returned by `state_getReadProof`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadProof {
+    /// The block of the proof.
+    pub at: Hash,
+    /// The trie nodes of the proof.
+    pub proof: Vec<Bytes>,
+}
+
 #[rpc(client)]
 pub trait RpcApi {
     /// Fetch system name.
@@ -34,6 +47,46 @@ pub trait RpcApi {
     #[method(name = "state_getStorage")]
     async fn storage(&self, key: &StorageKey, hash: Option<Hash>) -> RpcResult<Option<Bytes>>;
 
+    /// Fetch a page of storage keys with a given prefix, starting after `start_key`.
+    #[method(name = "state_getKeysPaged")]
+    async fn storage_keys_paged(
+        &self,
+        prefix: Option<StorageKey>,
+        count: u32,
+        start_key: Option<StorageKey>,
+        hash: Option<Hash>,
+    ) -> RpcResult<Vec<StorageKey>>;
+
+    /// Fetch the values of a batch of storage keys.
+    #[method(name = "state_queryStorageAt")]
+    async fn query_storage_at(
+        &self,
+        keys: Vec<StorageKey>,
+        hash: Option<Hash>,
+    ) -> RpcResult<Vec<StorageChangeSet<Hash>>>;
+
+    /// Fetch the changes of a batch of storage keys in the blocks from `from` to `to`. The first
+    /// change set holds the values of the keys at `from`.
+    #[method(name = "state_queryStorage")]
+    async fn query_storage(
+        &self,
+        keys: Vec<StorageKey>,
+        from: Hash,
+        to: Option<Hash>,
+    ) -> RpcResult<Vec<StorageChangeSet<Hash>>>;
+
+    /// Fetch the read proof of a batch of storage keys.
+    #[method(name = "state_getReadProof")]
+    async fn read_proof(&self, keys: Vec<StorageKey>, hash: Option<Hash>) -> RpcResult<ReadProof>;
+
+    /// Fetch the SCALE encoded runtime metadata.
+    #[method(name = "state_getMetadata")]
+    async fn metadata(&self, at: Option<Hash>) -> RpcResult<Bytes>;
+
+    /// Fetch a block header. By default returns the latest header.
+    #[method(name = "chain_getHeader")]
+    async fn header(&self, hash: Option<Hash>) -> RpcResult<Option<Header>>;
+
     /// Fetch the runtime version.
     #[method(name = "state_getRuntimeVersion")]
     async fn runtime_version(&self, at: Option<Hash>) -> RpcResult<RuntimeVersion>;
@@ -54,7 +107,7 @@ pub trait RpcApi {
     ///
     /// By default returns latest block hash.
     #[method(name = "chain_getBlockHash", aliases = ["chain_getHead"], blocking)]
-    fn block_hash(&self, hash: Option<Hash>) -> RpcResult<Option<Hash>>;
+    fn block_hash(&self, number: Option<BlockNumber>) -> RpcResult<Option<Hash>>;
 
     /// Get hash of the last finalized block in the canon chain.
     #[method(name = "chain_getFinalizedHead", aliases = ["chain_getFinalisedHead"], blocking)]
@@ -91,14 +144,43 @@ pub trait RpcApi {
 type Uri = String;
 
 /// Wraps a shared web-socket JSON-RPC client that can be cloned.
+///
+/// In the tests, the client can be replaced by canned responses with `SharedRpcClient::mock`.
+/// Each request is bounded by the timeout of its method, i.e. the request timeout unless the
+/// method has a timeout override. The requests are only sent through the methods implemented by
+/// `timed_methods`, which enforce these deadlines and count the responses in the budget of the
+/// run.
 #[derive(Clone, Debug)]
-pub(crate) struct SharedRpcClient(Arc<WsClient>, Uri);
+pub(crate) struct SharedRpcClient(Client, Uri, Arc<Timeouts>);
+
+/// The client which sends the requests of a [`SharedRpcClient`].
+#[derive(Clone, Debug)]
+enum Client {
+    Ws(Arc<WsClient>),
+    /// The JSON responses of the requests, by method, regardless of their parameters.
+    #[cfg(test)]
+    Mock(Arc<BTreeMap<String, serde_json::Value>>),
+}
 
-impl Deref for SharedRpcClient {
-    type Target = WsClient;
+/// The request timeout of a client and its per-method overrides.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Timeouts {
+    pub request: Duration,
+    pub methods: BTreeMap<String, Duration>,
+}
+
+impl Timeouts {
+    /// The timeout of the requests of `method`.
+    pub fn of(&self, method: &str) -> Duration {
+        self.methods.get(method).cloned().unwrap_or(self.request)
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// The longest timeout of any method.
+    pub fn max(&self) -> Duration {
+        self.methods
+            .values()
+            .cloned()
+            .fold(self.request, Duration::max)
     }
 }
 
@@ -108,19 +190,157 @@ impl SharedRpcClient {
         &self.1
     }
 
-    /// Create a new shared JSON-RPC web-socket client.
+    /// Create a new shared JSON-RPC web-socket client, with per-method timeout overrides.
     pub(crate) async fn new(
         uri: &str,
         connection_timeout: Duration,
         request_timeout: Duration,
+        method_timeouts: BTreeMap<String, Duration>,
     ) -> Result<Self, RpcError> {
+        let timeouts = Timeouts {
+            request: request_timeout,
+            methods: method_timeouts,
+        };
+
+        // the timeout of the client only bounds the longest deadline, which would otherwise cut
+        // the methods whose override is longer than the request timeout. The deadline of each
+        // request is enforced by `timed`.
         let client = WsClientBuilder::default()
             .connection_timeout(connection_timeout)
             .max_request_body_size(u32::MAX)
-            .request_timeout(request_timeout)
+            .request_timeout(timeouts.max())
             .max_concurrent_requests(u32::MAX as usize)
             .build(uri)
             .await?;
-        Ok(Self(Arc::new(client), uri.to_owned()))
+
+        Ok(Self(
+            Client::Ws(Arc::new(client)),
+            uri.to_owned(),
+            Arc::new(timeouts),
+        ))
     }
+
+    /// Create a client which answers the requests of each method with its canned JSON response,
+    /// and fails the requests of the other methods.
+    #[cfg(test)]
+    pub(crate) fn mock<'a>(
+        responses: impl IntoIterator<Item = (&'a str, serde_json::Value)>,
+    ) -> Self {
+        let responses = responses
+            .into_iter()
+            .map(|(method, response)| (method.to_owned(), response))
+            .collect();
+
+        Self(
+            Client::Mock(Arc::new(responses)),
+            "mock".to_owned(),
+            Arc::new(Timeouts {
+                request: Duration::from_secs(1),
+                methods: Default::default(),
+            }),
+        )
+    }
+
+    /// Returns the timeout of the requests of `method`.
+    pub fn timeout_of(&self, method: &str) -> Duration {
+        self.2.of(method)
+    }
+
+    /// Sends `request` of `method`, failing with a request timeout once its deadline is reached.
+    /// The size of the response is counted by the budget of the run, if it limits the bytes
+    /// downloaded.
+    async fn timed<R: serde::Serialize>(
+        &self,
+        method: &str,
+        request: impl std::future::Future<Output = RpcResult<R>>,
+    ) -> RpcResult<R> {
+        let response = tokio::time::timeout(self.timeout_of(method), request)
+            .await
+            .unwrap_or(Err(RpcError::RequestTimeout))?;
+        if crate::budget::tracks_bytes() {
+            crate::budget::download(serde_json::to_vec(&response).map_or(0, |r| r.len() as u64));
+        }
+        Ok(response)
+    }
+
+    /// Submits an extrinsic and watches its status, failing with a request timeout if the
+    /// subscription is not established before its deadline.
+    pub(crate) async fn watch_extrinsic(
+        &self,
+        bytes: &Bytes,
+    ) -> RpcResult<Subscription<TransactionStatus<Hash, Hash>>> {
+        let client = match &self.0 {
+            Client::Ws(client) => client,
+            #[cfg(test)]
+            Client::Mock(_) => {
+                return Err(RpcError::Custom("subscriptions are not mocked.".to_owned()))
+            }
+        };
+
+        tokio::time::timeout(
+            self.timeout_of("author_submitAndWatchExtrinsic"),
+            RpcApiClient::watch_extrinsic(&**client, bytes),
+        )
+        .await
+        .unwrap_or(Err(RpcError::RequestTimeout))
+    }
+}
+
+/// The canned response of `method`, decoded as the response type of the method.
+#[cfg(test)]
+fn mock_response<R: serde::de::DeserializeOwned>(
+    responses: &BTreeMap<String, serde_json::Value>,
+    method: &str,
+) -> RpcResult<R> {
+    let response = responses
+        .get(method)
+        .ok_or_else(|| RpcError::Custom(format!("no mocked response for {}.", method)))?;
+
+    serde_json::from_value(response.clone())
+        .map_err(|e| RpcError::Custom(format!("invalid mocked response for {}: {}.", method, e)))
+}
+
+/// Implements the methods of [`RpcApiClient`] on [`SharedRpcClient`], bounded by their timeout.
+/// The mocked clients answer with the canned response of the method, ignoring its parameters.
+macro_rules! timed_methods {
+    ($($name:ident($($arg:ident: $ty:ty),*) -> $ret:ty => $method:literal;)*) => {
+        impl SharedRpcClient {
+            $(
+                // the parameters are ignored by the mocked clients.
+                #[cfg_attr(test, allow(unused_variables))]
+                pub(crate) async fn $name(&self, $($arg: $ty),*) -> RpcResult<$ret> {
+                    match &self.0 {
+                        Client::Ws(client) => {
+                            self.timed($method, RpcApiClient::$name(&**client, $($arg),*)).await
+                        }
+                        #[cfg(test)]
+                        Client::Mock(responses) => {
+                            self.timed($method, async { mock_response(responses, $method) }).await
+                        }
+                    }
+                }
+            )*
+        }
+    };
+}
+
+timed_methods! {
+    system_chain() -> String => "system_chain";
+    storage(key: &StorageKey, hash: Option<Hash>) -> Option<Bytes> => "state_getStorage";
+    storage_keys_paged(
+        prefix: Option<StorageKey>,
+        count: u32,
+        start_key: Option<StorageKey>,
+        hash: Option<Hash>
+    ) -> Vec<StorageKey> => "state_getKeysPaged";
+    query_storage_at(keys: Vec<StorageKey>, hash: Option<Hash>) -> Vec<StorageChangeSet<Hash>> => "state_queryStorageAt";
+    query_storage(keys: Vec<StorageKey>, from: Hash, to: Option<Hash>) -> Vec<StorageChangeSet<Hash>> => "state_queryStorage";
+    read_proof(keys: Vec<StorageKey>, hash: Option<Hash>) -> ReadProof => "state_getReadProof";
+    metadata(at: Option<Hash>) -> Bytes => "state_getMetadata";
+    header(hash: Option<Hash>) -> Option<Header> => "chain_getHeader";
+    runtime_version(at: Option<Hash>) -> RuntimeVersion => "state_getRuntimeVersion";
+    payment_query_info(encoded_xt: &Bytes, at: Option<&Hash>) -> RuntimeDispatchInfo<Balance> => "payment_queryInfo";
+    dry_run(extrinsic: &Bytes, at: Option<Hash>) -> Bytes => "system_dryRun";
+    block_hash(number: Option<BlockNumber>) -> Option<Hash> => "chain_getBlockHash";
+    finalized_head() -> Hash => "chain_getFinalizedHead";
 }
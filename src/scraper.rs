@@ -0,0 +1,1154 @@
+//! Scrapers of the state of remote nodes.
+//!
+//! The extract phase fetches the state of a block through a [`Scraper`], so that the engine
+//! backing the extraction can be swapped (e.g. when the upstream `frame-remote-externalities`
+//! crate changes or misbehaves against older nodes) without touching the commands. The default
+//! scraper is backed by the `frame-remote-externalities` builder, which is also used to load the
+//! stored snapshots. The [`RawScraper`] fetches the state with paged RPC queries instead, which
+//! gives control over the page size, the retries and the progress of the extraction.
+//!
+//! Besides the snapshots stored by this crate, snapshots of other versions of the
+//! `frame-remote-externalities` crate (e.g. created by `try-runtime create-snapshot`), legacy
+//! key-value snapshots and delta snapshots (the changes relative to the snapshot of a base block)
+//! are loaded, see [`SnapshotFormat`].
+
+use crate::configs::StateVersion;
+use crate::prelude::*;
+use crate::rpc::SharedRpcClient;
+use crate::TimetravelError;
+
+use codec::{Compact, Decode, DecodeAll, Encode};
+use futures::{StreamExt, TryStreamExt};
+use jsonrpsee::core::RpcResult;
+use remote_externalities::{Builder, Mode, OfflineConfig, OnlineConfig, SnapshotConfig, Transport};
+use serde::de::DeserializeOwned;
+use sp_core::{hashing::twox_128, storage::StorageKey, H256};
+use sp_runtime::traits::BlakeTwo256;
+use sp_trie::cache::{CacheSize, LocalTrieCache, SharedTrieCache};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    marker::PhantomData,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+/// Version of the snapshots stored by the [`RawScraper`], which must match the snapshot version
+/// of the `frame-remote-externalities` crate loading them.
+const SNAPSHOT_VERSION: u16 = 4;
+
+/// Magic number of the zstd frames, which prefixes the compressed snapshots.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression level of the snapshots, the zstd default.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Magic prefix of the delta snapshots, see [`SnapshotFormat::Delta`].
+const DELTA_MAGIC: [u8; 8] = *b"ttdelta1";
+
+/// The changes of the top storage relative to a base state, as `(key, value)` pairs where a
+/// `None` value is a removed key.
+pub(crate) type StorageChanges = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// The state to scrape from a remote node at a block.
+#[derive(Debug, Clone)]
+pub(crate) struct ScrapeRequest {
+    pub uri: String,
+    pub at: H256,
+    /// Pallets whose storage is scraped.
+    pub pallets: Vec<String>,
+    /// Hashed storage prefixes scraped besides the pallets.
+    pub hashed_prefixes: Vec<Vec<u8>>,
+    /// Hashed storage keys scraped besides the pallets.
+    pub hashed_keys: Vec<Vec<u8>>,
+    /// Path where the snapshot of the scraped state is stored, if any.
+    pub snapshot_path: Option<String>,
+    /// Whether the stored snapshot is compressed with zstd.
+    pub compress: bool,
+    pub child_trie: bool,
+    /// Overrides the state version of the runtime at the block.
+    pub state_version: Option<StateVersion>,
+}
+
+/// An engine which scrapes the state of a remote node into an externalities.
+pub(crate) trait Scraper {
+    /// Scrapes the state of `request`. The errors of the requests to the remote node are returned
+    /// as `jsonrpsee` or [`TimetravelError::Rpc`] errors, so that they can be classified as
+    /// transient or permanent by the caller.
+    async fn scrape(&self, request: &ScrapeRequest) -> Result<Ext, anyhow::Error>;
+
+    /// Whether the state is downloaded through the shared RPC client of the run, whose responses
+    /// are counted by the budget of the run as they are received.
+    fn downloads_through_rpc(&self) -> bool {
+        true
+    }
+}
+
+/// The scraper backed by the `frame-remote-externalities` builder.
+pub(crate) struct RemoteExternalities<B>(PhantomData<B>);
+
+impl<B> Default for RemoteExternalities<B> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<B> Scraper for RemoteExternalities<B>
+where
+    B: BlockT<Hash = H256> + DeserializeOwned,
+    B::Header: DeserializeOwned,
+{
+    async fn scrape(&self, request: &ScrapeRequest) -> Result<Ext, anyhow::Error> {
+        let mut builder = Builder::<B>::new().mode(Mode::Online(OnlineConfig {
+            transport: Transport::Uri(request.uri.clone()),
+            at: Some(request.at),
+            pallets: request.pallets.clone(),
+            hashed_prefixes: request.hashed_prefixes.clone(),
+            hashed_keys: request.hashed_keys.clone(),
+            state_snapshot: request.snapshot_path.clone().map(SnapshotConfig::new),
+            child_trie: request.child_trie,
+            ..Default::default()
+        }));
+        if let Some(state_version) = request.state_version {
+            builder = builder.overwrite_state_version(state_version.into());
+        }
+
+        let ext = builder
+            .build()
+            .await
+            .map(|rx| rx.inner_ext)
+            .map_err(builder_error)?;
+
+        // the snapshot is stored by the builder, so it is compressed in place afterwards.
+        if let (true, Some(snapshot_path)) = (request.compress, &request.snapshot_path) {
+            let snapshot = read_snapshot(snapshot_path)?;
+            write_snapshot(snapshot_path, &snapshot, true)?;
+        }
+
+        Ok(ext)
+    }
+
+    fn downloads_through_rpc(&self) -> bool {
+        false
+    }
+}
+
+/// The markers of the errors of the builder which are caused by the connection to the remote node
+/// or its requests, lowercase.
+const TRANSPORT_ERRORS: [&str; 5] = ["rpc", "connect", "transport", "timeout", "timed out"];
+
+/// Classifies an (untyped) error of the builder of the externalities: the failures of the
+/// connection and of the requests to the remote node are [`TimetravelError::Rpc`] errors, and thus
+/// retried, while the decoding failures and the other failures (e.g. an invalid snapshot, a missing
+/// block or a mismatched state version) are permanent.
+pub(crate) fn builder_error(error: impl ToString) -> TimetravelError {
+    let error = error.to_string();
+    let lowercase = error.to_lowercase();
+
+    if lowercase.contains("decod") {
+        TimetravelError::Decode { error }
+    } else if TRANSPORT_ERRORS.iter().any(|m| lowercase.contains(m)) {
+        TimetravelError::Rpc { error }
+    } else {
+        TimetravelError::Snapshot { error }
+    }
+}
+
+/// The scraper which pages through the keys of the requested prefixes with `state_getKeysPaged`
+/// and fetches their values in batches with `state_queryStorageAt`.
+///
+/// Child tries are not scraped.
+pub(crate) struct RawScraper {
+    pub rpc: SharedRpcClient,
+    /// Number of keys (and values) fetched per request.
+    pub page_size: u32,
+    /// Number of times a failed request is retried, with an exponential backoff.
+    pub retries: u32,
+    /// Number of shards in which the key space of each prefix is split, paged concurrently.
+    pub shards: u32,
+    /// Maximum number of concurrent requests, over all the blocks and prefixes scraped
+    /// concurrently.
+    pub max_in_flight: usize,
+    /// Number of prefixes (e.g. pallets) whose keys are paged concurrently.
+    pub concurrency: usize,
+    /// The permits of the requests in flight, shared by all the requests of the scraper.
+    in_flight: Arc<tokio::sync::Semaphore>,
+}
+
+impl RawScraper {
+    /// The raw scraper of `rpc` with the settings of the CLI.
+    pub fn new(rpc: SharedRpcClient, config: &crate::configs::ExtConfig) -> Self {
+        Self {
+            rpc,
+            page_size: config.raw_page_size,
+            retries: config.raw_retries,
+            shards: config.raw_shards,
+            max_in_flight: config.raw_max_in_flight,
+            concurrency: config.concurrency,
+            in_flight: Arc::new(tokio::sync::Semaphore::new(config.raw_max_in_flight.max(1))),
+        }
+    }
+
+    /// Sends the request built by `request`, retrying it while it fails with a transient error
+    /// (see [`crate::commands::is_transient_rpc_error`]). The last error is returned as the
+    /// `jsonrpsee` error, with the method as context. The request waits for a
+    /// permit of the scraper, so that at most `max_in_flight` requests are in flight.
+    async fn with_retries<R, F, Fut>(
+        &self,
+        method: &str,
+        mut request: F,
+    ) -> Result<R, anyhow::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = RpcResult<R>>,
+    {
+        let mut backoff = Duration::from_secs(1);
+        let mut attempt = 0;
+
+        loop {
+            let permit = self.in_flight.acquire().await?;
+            let response = request().await;
+            drop(permit);
+
+            match response {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt < self.retries && crate::commands::is_transient_rpc_error(&e) => {
+                    attempt += 1;
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "{} request failed: {}, retrying in {:?} ({}/{}).",
+                        method,
+                        e,
+                        backoff,
+                        attempt,
+                        self.retries,
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::new(e).context(format!("{} request failed", method)))
+                }
+            }
+        }
+    }
+
+    /// Returns the keys under `prefix` at block `at` after `start` and before `end`, one page at
+    /// a time.
+    async fn shard_keys(
+        &self,
+        prefix: &[u8],
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        at: H256,
+    ) -> Result<Vec<StorageKey>, anyhow::Error> {
+        let mut keys: Vec<StorageKey> = vec![];
+        let mut start_key = start.map(StorageKey);
+
+        loop {
+            let mut page = self
+                .with_retries("state_getKeysPaged", || {
+                    self.rpc.storage_keys_paged(
+                        Some(StorageKey(prefix.to_vec())),
+                        self.page_size.max(1),
+                        start_key.clone(),
+                        Some(at),
+                    )
+                })
+                .await?;
+
+            let in_shard = page
+                .iter()
+                .take_while(|key| end.as_ref().map_or(true, |end| &key.0 < end))
+                .count();
+            let last_page = page.len() < self.page_size.max(1) as usize || in_shard < page.len();
+
+            start_key = page.last().cloned();
+            page.truncate(in_shard);
+            keys.extend(page);
+
+            log::debug!(
+                target: LOG_TARGET,
+                "Fetched {} keys under 0x{} after {:?}.",
+                keys.len(),
+                sp_core::hexdisplay::HexDisplay::from(&prefix),
+                start_key,
+            );
+
+            if last_page {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// Returns all the keys under `prefix` at block `at`, paging through its shards concurrently.
+    /// The keys bounding the shards are returned as well, whether they exist or not.
+    async fn keys(&self, prefix: &[u8], at: H256) -> Result<Vec<StorageKey>, anyhow::Error> {
+        let bounds = shard_bounds(prefix, self.shards);
+        let boundaries = bounds
+            .iter()
+            .filter_map(|(start, _)| start.clone().map(StorageKey))
+            .collect::<Vec<_>>();
+
+        let shards = futures::stream::iter(bounds)
+            .map(|(start, end)| self.shard_keys(prefix, start, end, at))
+            .buffer_unordered(self.max_in_flight.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(shards.into_iter().flatten().chain(boundaries).collect())
+    }
+}
+
+/// Splits the key space under `prefix` in `shards` contiguous shards of the byte following the
+/// prefix, as `(start, end)` bounds. The bounds are exclusive and `None` is unbounded.
+pub(crate) fn shard_bounds(prefix: &[u8], shards: u32) -> Vec<(Option<Vec<u8>>, Option<Vec<u8>>)> {
+    let shards = shards.clamp(1, 256);
+    let bound = |i: u32| match i {
+        0 => None,
+        i if i == shards => None,
+        i => Some([prefix, &[(i * 256 / shards) as u8]].concat()),
+    };
+
+    (0..shards).map(|i| (bound(i), bound(i + 1))).collect()
+}
+
+impl RawScraper {
+    /// Returns the keys of `request`, i.e. the keys under its prefixes and its hashed keys.
+    async fn request_keys(
+        &self,
+        request: &ScrapeRequest,
+    ) -> Result<Vec<StorageKey>, anyhow::Error> {
+        let at = request.at;
+
+        let prefixes = request
+            .pallets
+            .iter()
+            .map(|p| twox_128(p.as_bytes()).to_vec())
+            .chain(request.hashed_prefixes.iter().cloned());
+
+        let mut keys = request.hashed_keys.iter().cloned().collect::<BTreeSet<_>>();
+        let mut prefix_keys = futures::stream::iter(prefixes)
+            .map(|prefix| async move { self.keys(&prefix, at).await.map(|keys| (prefix, keys)) })
+            .buffer_unordered(self.concurrency.max(1));
+        while let Some(scraped) = prefix_keys.next().await {
+            let (prefix, prefix_keys) = scraped?;
+            log::info!(
+                target: LOG_TARGET,
+                "Scraped {} keys under 0x{} at {:?}.",
+                prefix_keys.len(),
+                sp_core::hexdisplay::HexDisplay::from(&prefix),
+                at
+            );
+            keys.extend(prefix_keys.into_iter().map(|k| k.0));
+        }
+
+        Ok(keys.into_iter().map(StorageKey).collect())
+    }
+
+    /// Fetches the key-value pairs of `request`, passing them to `sink` one batch at a time.
+    async fn stream_pairs<F>(
+        &self,
+        request: &ScrapeRequest,
+        mut sink: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<(Vec<u8>, Vec<u8>)>) -> Result<(), anyhow::Error>,
+    {
+        let at = request.at;
+
+        if request.child_trie {
+            log::warn!(
+                target: LOG_TARGET,
+                "The raw engine does not scrape child tries, use --skip-child-trie to silence this warning."
+            );
+        }
+
+        let keys = self.request_keys(request).await?;
+
+        let mut fetched = 0;
+        let batches = keys.chunks(self.page_size.max(1) as usize);
+        let batch_count = batches.len();
+        let mut batches = futures::stream::iter(batches)
+            .map(|batch| async move {
+                self.with_retries("state_queryStorageAt", || {
+                    self.rpc.query_storage_at(batch.to_vec(), Some(at))
+                })
+                .await
+                .map(|change_sets| (batch.len(), change_sets))
+            })
+            .buffer_unordered(self.max_in_flight.max(1))
+            .enumerate();
+
+        while let Some((i, batch)) = batches.next().await {
+            let (batch_len, change_sets) = batch?;
+            fetched += batch_len;
+
+            sink(
+                change_sets
+                    .into_iter()
+                    .flat_map(|set| set.changes)
+                    .filter_map(|(key, value)| value.map(|value| (key.0, value.0)))
+                    .collect(),
+            )?;
+
+            if (i + 1) % 10 == 0 || i + 1 == batch_count {
+                log::info!(
+                    target: LOG_TARGET,
+                    "Fetched the values of {}/{} keys at {:?}.",
+                    fetched,
+                    keys.len(),
+                    at
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Streams the key-value pairs of `request` straight to its snapshot path as a legacy
+    /// key-value snapshot (see [`SnapshotFormat::Legacy`]), without building an externalities,
+    /// and returns the number of pairs stored and their encoded size.
+    ///
+    /// The pairs are spilled to a `.part` file next to the snapshot while they are fetched, since
+    /// their number, which prefixes the snapshot, is only known at the end.
+    pub async fn scrape_to_file(
+        &self,
+        request: &ScrapeRequest,
+    ) -> Result<(u32, u64), anyhow::Error> {
+        use std::io::Write;
+
+        let snapshot_path =
+            request
+                .snapshot_path
+                .clone()
+                .ok_or_else(|| TimetravelError::Snapshot {
+                    error: "Snapshot path required to stream a snapshot".into(),
+                })?;
+        let part_path = format!("{}.part", snapshot_path);
+        let io_error = |e: std::io::Error| TimetravelError::Snapshot {
+            error: format!("{}: {:?}", snapshot_path, e),
+        };
+
+        let mut part =
+            std::io::BufWriter::new(std::fs::File::create(&part_path).map_err(io_error)?);
+        let (mut count, mut bytes) = (0u32, 0u64);
+        self.stream_pairs(request, |pairs| {
+            count += pairs.len() as u32;
+            for pair in pairs {
+                let encoded = pair.encode();
+                bytes += encoded.len() as u64;
+                part.write_all(&encoded).map_err(io_error)?;
+            }
+            Ok(())
+        })
+        .await?;
+        part.flush().map_err(io_error)?;
+        drop(part);
+
+        let file = std::fs::File::create(&snapshot_path).map_err(io_error)?;
+        let mut snapshot: Box<dyn Write> = match request.compress {
+            true => Box::new(
+                zstd::stream::Encoder::new(file, ZSTD_LEVEL)
+                    .map_err(io_error)?
+                    .auto_finish(),
+            ),
+            false => Box::new(std::io::BufWriter::new(file)),
+        };
+        snapshot
+            .write_all(&Compact(count).encode())
+            .map_err(io_error)?;
+        std::io::copy(
+            &mut std::fs::File::open(&part_path).map_err(io_error)?,
+            &mut snapshot,
+        )
+        .map_err(io_error)?;
+        snapshot.flush().map_err(io_error)?;
+        drop(snapshot);
+        std::fs::remove_file(&part_path).map_err(io_error)?;
+
+        log::info!(
+            target: LOG_TARGET,
+            "Streamed {} key-value pairs at {:?} to {}.",
+            count,
+            request.at,
+            snapshot_path
+        );
+
+        Ok((count, bytes))
+    }
+}
+
+impl RawScraper {
+    /// The state version of `request`, i.e. its override or the one of the runtime at the block.
+    async fn state_version(
+        &self,
+        request: &ScrapeRequest,
+    ) -> Result<sp_core::storage::StateVersion, anyhow::Error> {
+        match request.state_version {
+            Some(state_version) => Ok(state_version.into()),
+            None => Ok(self
+                .with_retries("state_getRuntimeVersion", || {
+                    self.rpc.runtime_version(Some(request.at))
+                })
+                .await?
+                .state_version()),
+        }
+    }
+
+    /// Scrapes the state of `request` relative to the state of the `base` block, given its top
+    /// storage pairs. The keys of the block are paged as by [`Scraper::scrape`], but only the
+    /// values changed since the base are fetched, with `state_queryStorage` requests from the base
+    /// to the block. The base must thus be an ancestor of the block.
+    ///
+    /// Returns the externalities of the block and its changes relative to the base.
+    pub async fn scrape_delta(
+        &self,
+        request: &ScrapeRequest,
+        base: &(H256, BTreeMap<Vec<u8>, Vec<u8>>),
+    ) -> Result<(Ext, StorageChanges), anyhow::Error> {
+        let (at, (base_hash, base_pairs)) = (request.at, base);
+
+        // the keys removed since the base are not paged at the block, but are changed to `None`.
+        let keys = self
+            .request_keys(request)
+            .await?
+            .into_iter()
+            .map(|key| key.0)
+            .chain(base_pairs.keys().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(StorageKey)
+            .collect::<Vec<_>>();
+
+        let change_sets = futures::stream::iter(keys.chunks(self.page_size.max(1) as usize))
+            .map(|batch| {
+                self.with_retries("state_queryStorage", || {
+                    self.rpc.query_storage(batch.to_vec(), *base_hash, Some(at))
+                })
+            })
+            .buffered(self.max_in_flight.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        // the change sets of each batch are ordered by block, so the last change of a key is its
+        // value at the block. The values at the base are known already.
+        let mut pairs = base_pairs.clone();
+        for set in change_sets.into_iter().flatten() {
+            if set.block == *base_hash {
+                continue;
+            }
+            for (key, value) in set.changes {
+                match value {
+                    Some(value) => pairs.insert(key.0, value.0),
+                    None => pairs.remove(&key.0),
+                };
+            }
+        }
+        let changes = storage_changes(base_pairs, &pairs);
+
+        log::info!(
+            target: LOG_TARGET,
+            "Fetched {} changed keys (out of {}) at {:?} relative to {:?}.",
+            changes.len(),
+            keys.len(),
+            at,
+            base_hash
+        );
+
+        let mut ext =
+            Ext::new_with_state_version(Default::default(), self.state_version(request).await?);
+        ext.batch_insert(pairs);
+        ext.commit_all()
+            .map_err(|error| TimetravelError::Snapshot { error })?;
+
+        Ok((ext, changes))
+    }
+}
+
+impl Scraper for RawScraper {
+    async fn scrape(&self, request: &ScrapeRequest) -> Result<Ext, anyhow::Error> {
+        let at = request.at;
+
+        let mut pairs = vec![];
+        self.stream_pairs(request, |batch| {
+            pairs.extend(batch);
+            Ok(())
+        })
+        .await?;
+
+        let state_version = self.state_version(request).await?;
+
+        let mut ext = Ext::new_with_state_version(Default::default(), state_version);
+        ext.batch_insert(pairs);
+        ext.commit_all()
+            .map_err(|error| TimetravelError::Snapshot { error })?;
+        let (raw_storage, storage_root) = ext.into_raw_snapshot();
+
+        if let Some(snapshot_path) = &request.snapshot_path {
+            let header = self
+                .with_retries("chain_getHeader", || self.rpc.header(Some(at)))
+                .await?
+                .ok_or_else(|| TimetravelError::Snapshot {
+                    error: format!("Header of block {:?} not found", at),
+                })?;
+
+            let snapshot = (
+                Compact(SNAPSHOT_VERSION),
+                state_version,
+                at,
+                &raw_storage,
+                storage_root,
+                header,
+            )
+                .encode();
+            write_snapshot(snapshot_path, &snapshot, request.compress)?;
+
+            log::info!(target: LOG_TARGET, "Snapshot stored in {}.", snapshot_path);
+        }
+
+        Ok(Ext::from_raw_snapshot(
+            raw_storage,
+            storage_root,
+            state_version,
+        ))
+    }
+}
+
+/// Any of the scrapers, selected with `--engine`.
+pub(crate) enum AnyScraper<B> {
+    RemoteExternalities(RemoteExternalities<B>),
+    Raw(RawScraper),
+}
+
+impl<B> Scraper for AnyScraper<B>
+where
+    B: BlockT<Hash = H256> + DeserializeOwned,
+    B::Header: DeserializeOwned,
+{
+    async fn scrape(&self, request: &ScrapeRequest) -> Result<Ext, anyhow::Error> {
+        match self {
+            AnyScraper::RemoteExternalities(scraper) => scraper.scrape(request).await,
+            AnyScraper::Raw(scraper) => scraper.scrape(request).await,
+        }
+    }
+
+    fn downloads_through_rpc(&self) -> bool {
+        match self {
+            AnyScraper::RemoteExternalities(scraper) => scraper.downloads_through_rpc(),
+            AnyScraper::Raw(scraper) => scraper.downloads_through_rpc(),
+        }
+    }
+}
+
+/// Formats of the stored snapshots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SnapshotFormat {
+    /// A snapshot of the version of `frame-remote-externalities` of this crate.
+    Native,
+    /// A snapshot of another version of `frame-remote-externalities` (e.g. of `try-runtime`),
+    /// encoded as `(version, state_version, block_hash, raw_storage, storage_root, ..)`.
+    Versioned(u16),
+    /// An unversioned snapshot of the top storage key-value pairs, stored by old versions of
+    /// `remote-externalities`.
+    Legacy,
+    /// The changes of the top storage relative to the snapshot of a base block, stored next to
+    /// it, encoded as `(magic, base_block_hash, changes)`.
+    Delta,
+}
+
+/// Returns the changes of the top storage `pairs` relative to the `base` pairs.
+pub(crate) fn storage_changes(
+    base: &BTreeMap<Vec<u8>, Vec<u8>>,
+    pairs: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> StorageChanges {
+    let changed = pairs
+        .iter()
+        .filter(|(key, value)| base.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), Some(value.clone())));
+    let removed = base
+        .keys()
+        .filter(|key| !pairs.contains_key(*key))
+        .map(|key| (key.clone(), None));
+
+    changed.chain(removed).collect()
+}
+
+/// Encodes a delta snapshot of the `changes` relative to the snapshot of block `base`.
+pub(crate) fn encode_delta(base: H256, changes: &StorageChanges) -> Vec<u8> {
+    (DELTA_MAGIC, base, changes).encode()
+}
+
+/// Returns the base block of a delta snapshot given its (decompressed) header, or `None` if it is
+/// not a delta snapshot.
+pub(crate) fn delta_base_of(header: &[u8]) -> Option<H256> {
+    header
+        .strip_prefix(&DELTA_MAGIC)
+        .and_then(|rest| H256::decode(&mut &rest[..]).ok())
+}
+
+/// Returns the base block of the snapshot at `snapshot_path` if it is a delta snapshot, reading
+/// its header only.
+pub(crate) fn delta_base(snapshot_path: &std::path::Path) -> Result<Option<H256>, anyhow::Error> {
+    let (header, _) = read_snapshot_header(&snapshot_path.display().to_string())?;
+    Ok(delta_base_of(&header))
+}
+
+/// The number of (decompressed) bytes of the header of a stored snapshot, enough to detect its
+/// format and to read the base block of a delta snapshot.
+const HEADER_LEN: u64 = 64;
+
+/// Opens the stored snapshot at `snapshot_path` as a stream of its bytes, decompressed on the fly
+/// if the snapshot is compressed. Returns the stream and whether the snapshot is compressed.
+fn open_snapshot(snapshot_path: &str) -> Result<(Box<dyn std::io::Read>, bool), anyhow::Error> {
+    use std::io::BufRead;
+
+    let io_error = |e: std::io::Error| TimetravelError::Snapshot {
+        error: format!("{}: {:?}", snapshot_path, e),
+    };
+
+    let mut file = std::io::BufReader::new(std::fs::File::open(snapshot_path).map_err(io_error)?);
+    let compressed = is_compressed(file.fill_buf().map_err(io_error)?);
+    let reader: Box<dyn std::io::Read> = match compressed {
+        true => Box::new(zstd::stream::read::Decoder::with_buffer(file).map_err(io_error)?),
+        false => Box::new(file),
+    };
+
+    Ok((reader, compressed))
+}
+
+/// Reads the (decompressed) header of the stored snapshot at `snapshot_path`, without reading the
+/// rest of the snapshot. Returns the header and whether the snapshot is compressed.
+pub(crate) fn read_snapshot_header(snapshot_path: &str) -> Result<(Vec<u8>, bool), anyhow::Error> {
+    use std::io::Read;
+
+    let (reader, compressed) = open_snapshot(snapshot_path)?;
+    let mut header = vec![];
+    reader
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)
+        .map_err(|e| TimetravelError::Snapshot {
+            error: format!("{}: {:?}", snapshot_path, e),
+        })?;
+
+    Ok((header, compressed))
+}
+
+/// Returns the path of the snapshot of block `block_hash` stored in the same directory as the
+/// snapshot at `snapshot_path`.
+fn sibling_snapshot_path(snapshot_path: &str, block_hash: H256) -> String {
+    let dir = std::path::Path::new(snapshot_path)
+        .parent()
+        .map_or(".".to_string(), |dir| dir.display().to_string());
+    format!("{}/{}.data", dir, block_hash)
+}
+
+/// Returns whether a stored snapshot is compressed.
+pub(crate) fn is_compressed(snapshot: &[u8]) -> bool {
+    snapshot.starts_with(&ZSTD_MAGIC)
+}
+
+/// Reads a stored snapshot, decompressing it while it is read if compressed.
+pub(crate) fn read_snapshot(snapshot_path: &str) -> Result<Vec<u8>, anyhow::Error> {
+    use std::io::Read;
+
+    let (mut reader, _) = open_snapshot(snapshot_path)?;
+    let mut snapshot = vec![];
+    reader
+        .read_to_end(&mut snapshot)
+        .map_err(|e| TimetravelError::Snapshot {
+            error: format!("{}: {:?}", snapshot_path, e),
+        })?;
+
+    Ok(snapshot)
+}
+
+/// Stores an encoded snapshot, optionally compressed with zstd.
+pub(crate) fn write_snapshot(
+    snapshot_path: &str,
+    snapshot: &[u8],
+    compress: bool,
+) -> Result<(), anyhow::Error> {
+    let compressed;
+    let snapshot = match compress {
+        true => {
+            compressed = zstd::encode_all(snapshot, ZSTD_LEVEL)?;
+            log::info!(
+                target: LOG_TARGET,
+                "Snapshot {} compressed from {} to {} bytes.",
+                snapshot_path,
+                snapshot.len(),
+                compressed.len()
+            );
+            &compressed[..]
+        }
+        false => snapshot,
+    };
+
+    std::fs::write(snapshot_path, snapshot)?;
+    Ok(())
+}
+
+/// Stores a copy of the snapshot at `snapshot_path` at `trimmed_path`, with only the trie nodes of
+/// its storage recorded by `trace`. The trimmed snapshot keeps the storage root of the
+/// original one, so it can replay the operation it was recorded for, but any read outside of the
+/// recorded ones fails.
+pub(crate) fn write_trimmed_snapshot(
+    snapshot_path: &str,
+    trimmed_path: &str,
+    trace: &crate::trace::ReadTrace,
+) -> Result<(), anyhow::Error> {
+    let snapshot = read_snapshot(snapshot_path)?;
+    match detect_snapshot_format(&snapshot) {
+        Some(SnapshotFormat::Native) | Some(SnapshotFormat::Versioned(_)) => (),
+        format => {
+            return Err(TimetravelError::Unsupported {
+                reason: format!(
+                    "trimming snapshot {} of format {:?}.",
+                    snapshot_path, format
+                ),
+            }
+            .into())
+        }
+    }
+
+    let (_, _, _, raw_storage, storage_root) = <(
+        Compact<u16>,
+        sp_core::storage::StateVersion,
+        H256,
+        crate::utils::RawStorage,
+        H256,
+    )>::decode(&mut &snapshot[..])
+    .map_err(|e| TimetravelError::Decode {
+        error: format!("snapshot {}: {:?}", snapshot_path, e),
+    })?;
+
+    let nodes = trace.nodes().into_keys().collect::<BTreeSet<_>>();
+    let entries = raw_storage.len();
+    let trimmed = crate::utils::trim_raw_storage(raw_storage, &nodes);
+
+    log::info!(
+        target: LOG_TARGET,
+        "Snapshot {} trimmed from {} to {} trie nodes, stored in {}.",
+        snapshot_path,
+        entries,
+        trimmed.len(),
+        trimmed_path
+    );
+
+    let trimmed = crate::utils::replace_snapshot_storage(&snapshot, trimmed, storage_root)?;
+    let compress = crate::EXT_CONFIG.get().map_or(false, |c| c.compress);
+    write_snapshot(trimmed_path, &trimmed, compress)
+}
+
+/// Returns the format of an encoded snapshot, if known, given its header (or the whole snapshot),
+/// see [`read_snapshot_header`]. Only the header is decoded: the format is told by the magic of
+/// the delta snapshots, or the version, state version and block hash of the versioned snapshots.
+/// The versions are encoded in a single byte, which tells them apart from the number of pairs of
+/// the legacy snapshots, as long as these hold more than 63 pairs or a non-empty first key.
+pub(crate) fn detect_snapshot_format(header: &[u8]) -> Option<SnapshotFormat> {
+    use sp_core::storage::StateVersion;
+
+    if header.starts_with(&DELTA_MAGIC) {
+        return Some(SnapshotFormat::Delta);
+    }
+
+    let versioned = <(Compact<u16>, StateVersion, H256)>::decode(&mut &header[..]);
+    match versioned {
+        Ok((Compact(SNAPSHOT_VERSION), ..)) => return Some(SnapshotFormat::Native),
+        Ok((Compact(version), ..)) if version < 64 => {
+            return Some(SnapshotFormat::Versioned(version))
+        }
+        _ => (),
+    }
+
+    Compact::<u32>::decode(&mut &header[..])
+        .ok()
+        .map(|_| SnapshotFormat::Legacy)
+}
+
+/// Loads the externalities of a stored snapshot, optionally overriding its state version. The
+/// format of the snapshot is detected from its header, see [`SnapshotFormat`]. The uncompressed
+/// native snapshots are read by the builder only, and the other snapshots are read (and
+/// decompressed) once and decoded directly.
+pub(crate) async fn load_snapshot<B>(
+    snapshot_path: String,
+    state_version: Option<StateVersion>,
+) -> Result<Ext, anyhow::Error>
+where
+    B: BlockT<Hash = H256> + DeserializeOwned,
+    B::Header: DeserializeOwned,
+{
+    // the builder only loads uncompressed snapshots from disk, so the compressed native
+    // snapshots are decoded directly.
+    let (header, compressed) = read_snapshot_header(&snapshot_path)?;
+
+    match detect_snapshot_format(&header) {
+        Some(SnapshotFormat::Native) if !compressed => (),
+        Some(SnapshotFormat::Delta) => {
+            let snapshot = read_snapshot(&snapshot_path)?;
+            let (_, base, changes) = <([u8; 8], H256, StorageChanges)>::decode(&mut &snapshot[..])
+                .map_err(|e| TimetravelError::Decode {
+                    error: format!("delta snapshot {}: {:?}", snapshot_path, e),
+                })?;
+            let base_path = sibling_snapshot_path(&snapshot_path, base);
+            log::info!(
+                target: LOG_TARGET,
+                "Loading delta snapshot {} of {} changes over {}.",
+                snapshot_path,
+                changes.len(),
+                base_path
+            );
+
+            let mut ext = Box::pin(load_snapshot::<B>(base_path, state_version)).await?;
+            ext.execute_with(|| {
+                for (key, value) in changes {
+                    match value {
+                        Some(value) => sp_io::storage::set(&key, &value),
+                        None => sp_io::storage::clear(&key),
+                    }
+                }
+            });
+            ext.commit_all()
+                .map_err(|error| TimetravelError::Snapshot { error })?;
+
+            return Ok(ext);
+        }
+        Some(format) => {
+            log::info!(
+                target: LOG_TARGET,
+                "Loading snapshot {} of format {:?}.",
+                snapshot_path,
+                format
+            );
+            let snapshot = read_snapshot(&snapshot_path)?;
+            return load_foreign_snapshot(&snapshot, format, state_version);
+        }
+        None => {
+            return Err(TimetravelError::Unsupported {
+                reason: format!("unknown format of snapshot {}.", snapshot_path),
+            }
+            .into())
+        }
+    }
+
+    let mut builder = Builder::<B>::new().mode(Mode::Offline(OfflineConfig {
+        state_snapshot: SnapshotConfig::new(snapshot_path),
+    }));
+    if let Some(state_version) = state_version {
+        builder = builder.overwrite_state_version(state_version.into());
+    }
+
+    builder
+        .build()
+        .await
+        .map(|rx| rx.inner_ext)
+        .map_err(|e| builder_error(e).into())
+}
+
+/// The trie cache shared by the externalities of a run, of `--trie-cache-size` bytes.
+static TRIE_CACHE: OnceLock<Option<SharedTrieCache<BlakeTwo256>>> = OnceLock::new();
+
+/// A local cache of the trie cache of the run, if `--trie-cache-size` is set. The nodes and values
+/// are cached by hash and storage root, so the cache is shared by the externalities of all blocks.
+pub(crate) fn local_trie_cache() -> Option<LocalTrieCache<BlakeTwo256>> {
+    TRIE_CACHE
+        .get_or_init(|| {
+            crate::EXT_CONFIG
+                .get()
+                .and_then(|c| c.trie_cache_size)
+                .map(|size| SharedTrieCache::new(CacheSize::new(size)))
+        })
+        .as_ref()
+        .map(|cache| cache.local_cache())
+}
+
+/// Backs the externalities with the trie cache of the run, if any, until its backend is replaced
+/// (e.g. by `commit_all`).
+pub(crate) fn attach_trie_cache(ext: &mut Ext) {
+    if let Some(cache) = local_trie_cache() {
+        let root = *ext.backend.root();
+        let storage = std::mem::take(&mut ext.backend).into_storage();
+        ext.backend = sp_state_machine::TrieBackendBuilder::new(storage, root)
+            .with_cache(cache)
+            .build();
+    }
+}
+
+/// Loads the externalities of a snapshot of a foreign format, i.e. not stored by the version of
+/// `frame-remote-externalities` of this crate.
+fn load_foreign_snapshot(
+    snapshot: &[u8],
+    format: SnapshotFormat,
+    state_version: Option<StateVersion>,
+) -> Result<Ext, anyhow::Error> {
+    let decode_error = |e: codec::Error| TimetravelError::Decode {
+        error: format!("snapshot of format {:?}: {:?}", format, e),
+    };
+
+    match format {
+        SnapshotFormat::Native | SnapshotFormat::Versioned(_) => {
+            let (_, snapshot_state_version, _, raw_storage, storage_root) =
+                <(
+                    Compact<u16>,
+                    sp_core::storage::StateVersion,
+                    H256,
+                    crate::utils::RawStorage,
+                    H256,
+                )>::decode(&mut &snapshot[..])
+                .map_err(decode_error)?;
+
+            Ok(Ext::from_raw_snapshot(
+                raw_storage,
+                storage_root,
+                state_version.map_or(snapshot_state_version, Into::into),
+            ))
+        }
+        SnapshotFormat::Delta => Err(TimetravelError::Unsupported {
+            reason: "delta snapshots are loaded over the snapshot of their base block.".into(),
+        }
+        .into()),
+        SnapshotFormat::Legacy => {
+            let pairs =
+                Vec::<(Vec<u8>, Vec<u8>)>::decode_all(&mut &snapshot[..]).map_err(decode_error)?;
+
+            let state_version = state_version.map_or(Default::default(), Into::into);
+            let mut ext = Ext::new_with_state_version(Default::default(), state_version);
+            ext.batch_insert(pairs);
+            ext.commit_all()
+                .map_err(|error| TimetravelError::Snapshot { error })?;
+
+            Ok(ext)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_snapshot_format_works() {
+        use sp_core::storage::StateVersion;
+
+        let raw_storage: crate::utils::RawStorage = vec![(vec![1], (vec![1, 1], 1))];
+        let versioned = |version: u16| {
+            (
+                Compact(version),
+                StateVersion::V1,
+                H256::repeat_byte(1),
+                raw_storage.clone(),
+                H256::repeat_byte(2),
+                vec![42u8; 10],
+            )
+                .encode()
+        };
+
+        assert_eq!(
+            detect_snapshot_format(&versioned(SNAPSHOT_VERSION)),
+            Some(SnapshotFormat::Native)
+        );
+        assert_eq!(
+            detect_snapshot_format(&versioned(2)),
+            Some(SnapshotFormat::Versioned(2))
+        );
+        assert_eq!(
+            detect_snapshot_format(&vec![(vec![1u8, 2], vec![3u8])].encode()),
+            Some(SnapshotFormat::Legacy)
+        );
+        assert_eq!(
+            detect_snapshot_format(&encode_delta(H256::repeat_byte(1), &vec![])),
+            Some(SnapshotFormat::Delta)
+        );
+        assert_eq!(detect_snapshot_format(&[0xff; 3]), None);
+    }
+
+    #[test]
+    fn storage_changes_works() {
+        let base = BTreeMap::from([(vec![1], vec![1]), (vec![2], vec![2]), (vec![3], vec![3])]);
+        let pairs = BTreeMap::from([(vec![1], vec![1]), (vec![2], vec![20]), (vec![4], vec![4])]);
+
+        assert_eq!(
+            storage_changes(&base, &pairs),
+            vec![
+                (vec![2], Some(vec![20])),
+                (vec![4], Some(vec![4])),
+                (vec![3], None),
+            ]
+        );
+        assert!(storage_changes(&base, &base).is_empty());
+    }
+
+    #[test]
+    fn delta_base_of_works() {
+        let base = H256::repeat_byte(7);
+        assert_eq!(delta_base_of(&encode_delta(base, &vec![])), Some(base));
+        assert_eq!(
+            delta_base_of(&encode_delta(base, &vec![(vec![1], None)])[..40]),
+            Some(base)
+        );
+        assert_eq!(delta_base_of(&vec![(vec![1u8], vec![2u8])].encode()), None);
+        assert_eq!(delta_base_of(&DELTA_MAGIC), None);
+    }
+
+    #[test]
+    fn builder_error_works() {
+        let is_transient =
+            |error: &str| crate::commands::is_transient_error(&builder_error(error).into());
+
+        assert!(is_transient("rpc get_keys failed"));
+        assert!(is_transient("Failed to connect to the remote node"));
+        assert!(matches!(
+            builder_error("failed to decode the state snapshot"),
+            TimetravelError::Decode { .. }
+        ));
+        assert!(!is_transient("failed to decode the state snapshot"));
+        assert!(!is_transient("Invalid snapshot version"));
+        assert!(!is_transient("Block not found"));
+    }
+
+    #[test]
+    fn read_snapshot_works() {
+        let snapshot = vec![(vec![1u8, 2], vec![3u8; 100])].encode();
+        let snapshot_path = std::env::temp_dir().join("substrate_timetravel_read_snapshot.data");
+        let snapshot_path = snapshot_path.to_str().unwrap();
+
+        for compress in [false, true] {
+            write_snapshot(snapshot_path, &snapshot, compress).unwrap();
+            assert_eq!(
+                is_compressed(&std::fs::read(snapshot_path).unwrap()),
+                compress
+            );
+
+            let (header, compressed) = read_snapshot_header(snapshot_path).unwrap();
+            assert_eq!(compressed, compress);
+            assert_eq!(header, snapshot[..HEADER_LEN as usize]);
+            assert_eq!(
+                detect_snapshot_format(&header),
+                Some(SnapshotFormat::Legacy)
+            );
+            assert_eq!(read_snapshot(snapshot_path).unwrap(), snapshot);
+        }
+        std::fs::remove_file(snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn shard_bounds_works() {
+        let prefix = [1u8, 2];
+
+        assert_eq!(shard_bounds(&prefix, 1), vec![(None, None)]);
+        assert_eq!(shard_bounds(&prefix, 0), vec![(None, None)]);
+        assert_eq!(
+            shard_bounds(&prefix, 4),
+            vec![
+                (None, Some(vec![1, 2, 64])),
+                (Some(vec![1, 2, 64]), Some(vec![1, 2, 128])),
+                (Some(vec![1, 2, 128]), Some(vec![1, 2, 192])),
+                (Some(vec![1, 2, 192]), None),
+            ]
+        );
+
+        let bounds = shard_bounds(&prefix, 1_000);
+        assert_eq!(bounds.len(), 256);
+        assert_eq!(bounds[255], (Some(vec![1, 2, 255]), None));
+    }
+}
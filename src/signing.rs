@@ -0,0 +1,276 @@
+//! Offline signing of extrinsics, for air-gapped signers.
+//!
+//! The calls output by the submission-capable operations (e.g. the `.call` files of
+//! `emergency-result`) are turned into an [`UnsignedExtrinsic`], which holds the payload to be
+//! signed offline together with everything needed to assemble the signed extrinsic once the
+//! signature is provided. The data of the transaction extensions is encoded as per their
+//! identifiers in the runtime metadata, so the extrinsics are built without a compiled runtime.
+//!
+//! The extrinsics are of version 4, with a `MultiAddress` address and a `MultiSignature`
+//! signature, as used by the relay chains.
+
+use crate::TimetravelError;
+
+use codec::{Compact, Encode};
+use serde::{Deserialize, Serialize};
+use sp_core::{bytes, crypto::AccountId32, hashing::blake2_256, H256};
+use sp_runtime::{generic::Era, traits::Verify, MultiAddress, MultiSignature};
+
+/// Payloads longer than this are hashed before being signed.
+const MAX_PLAIN_PAYLOAD: usize = 256;
+
+/// Version byte of the signed extrinsics of version 4.
+const SIGNED_EXTRINSIC_V4: u8 = 0b1000_0100;
+
+/// The parameters of the transaction extensions of an extrinsic.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExtensionParams {
+    pub spec_version: u32,
+    pub transaction_version: u32,
+    pub genesis_hash: H256,
+    /// The block the mortality of the extrinsic starts at, or the genesis if immortal.
+    pub checkpoint: H256,
+    pub era: Era,
+    pub nonce: u32,
+    pub tip: u128,
+}
+
+/// Encodes the explicit data (included in the extrinsic) and the implicit data (only signed) of
+/// the transaction extensions with the given `identifiers`, in order.
+pub(crate) fn encode_extensions(
+    identifiers: &[String],
+    params: &ExtensionParams,
+) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+    let (mut explicit, mut implicit) = (vec![], vec![]);
+
+    for identifier in identifiers {
+        match identifier.as_str() {
+            "CheckNonZeroSender"
+            | "CheckWeight"
+            | "WeightReclaim"
+            | "StorageWeightReclaim"
+            | "AuthorizeCall"
+            | "PrevalidateAttests" => (),
+            "CheckSpecVersion" => params.spec_version.encode_to(&mut implicit),
+            "CheckTxVersion" => params.transaction_version.encode_to(&mut implicit),
+            "CheckGenesis" => params.genesis_hash.encode_to(&mut implicit),
+            "CheckMortality" | "CheckEra" => {
+                params.era.encode_to(&mut explicit);
+                params.checkpoint.encode_to(&mut implicit);
+            }
+            "CheckNonce" => Compact(params.nonce).encode_to(&mut explicit),
+            "ChargeTransactionPayment" => Compact(params.tip).encode_to(&mut explicit),
+            "ChargeAssetTxPayment" => {
+                Compact(params.tip).encode_to(&mut explicit);
+                // no asset id, i.e. the fees are paid in the native asset.
+                None::<()>.encode_to(&mut explicit);
+            }
+            "CheckMetadataHash" => {
+                // the metadata hash check is disabled, so no metadata hash is signed.
+                0u8.encode_to(&mut explicit);
+                None::<[u8; 32]>.encode_to(&mut implicit);
+            }
+            other => {
+                return Err(TimetravelError::Unsupported {
+                    reason: format!("transaction extension {}.", other),
+                }
+                .into())
+            }
+        }
+    }
+
+    Ok((explicit, implicit))
+}
+
+/// Returns the payload to sign for a `call` with the given extensions data.
+pub(crate) fn signing_payload(call: &[u8], explicit: &[u8], implicit: &[u8]) -> Vec<u8> {
+    let payload = [call, explicit, implicit].concat();
+    match payload.len() > MAX_PLAIN_PAYLOAD {
+        true => blake2_256(&payload).to_vec(),
+        false => payload,
+    }
+}
+
+/// Signature schemes of a `MultiSignature`.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum SignatureScheme {
+    Ed25519,
+    Sr25519,
+    Ecdsa,
+}
+
+/// Builds a `MultiSignature` from its raw bytes.
+pub(crate) fn multi_signature(
+    scheme: SignatureScheme,
+    signature: &[u8],
+) -> Result<MultiSignature, anyhow::Error> {
+    let invalid = || TimetravelError::Decode {
+        error: format!("{:?} signature of {} bytes.", scheme, signature.len()),
+    };
+
+    Ok(match scheme {
+        SignatureScheme::Ed25519 => MultiSignature::Ed25519(sp_core::ed25519::Signature::from_raw(
+            signature.try_into().map_err(|_| invalid())?,
+        )),
+        SignatureScheme::Sr25519 => MultiSignature::Sr25519(sp_core::sr25519::Signature::from_raw(
+            signature.try_into().map_err(|_| invalid())?,
+        )),
+        SignatureScheme::Ecdsa => MultiSignature::Ecdsa(sp_core::ecdsa::Signature::from_raw(
+            signature.try_into().map_err(|_| invalid())?,
+        )),
+    })
+}
+
+/// An extrinsic ready to be signed offline, stored as JSON between the `signing-payload` and the
+/// `submit-signed` commands. The byte fields are hex encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct UnsignedExtrinsic {
+    /// The block the extrinsic was built at.
+    pub at: H256,
+    pub spec_version: u32,
+    pub signer: AccountId32,
+    pub nonce: u32,
+    pub tip: u128,
+    pub call: String,
+    /// Explicit data of the transaction extensions, included in the extrinsic.
+    pub explicit: String,
+    /// The payload to be signed by the `signer`.
+    pub payload: String,
+}
+
+impl UnsignedExtrinsic {
+    /// Assembles the signed extrinsic, after checking that the `signature` is a valid signature
+    /// of the payload by the signer.
+    pub fn sign(&self, signature: MultiSignature) -> Result<Vec<u8>, anyhow::Error> {
+        let decode = |field: &str, value: &str| {
+            bytes::from_hex(value).map_err(|e| TimetravelError::Decode {
+                error: format!("{} of the unsigned extrinsic: {:?}", field, e),
+            })
+        };
+        let (call, explicit, payload) = (
+            decode("call", &self.call)?,
+            decode("explicit", &self.explicit)?,
+            decode("payload", &self.payload)?,
+        );
+
+        if !signature.verify(&payload[..], &self.signer) {
+            return Err(TimetravelError::OperationFailed {
+                op: "signing extrinsic".to_string(),
+                reason: format!(
+                    "the signature is not a signature of the payload by {}.",
+                    self.signer
+                ),
+            }
+            .into());
+        }
+
+        let body = [
+            &[SIGNED_EXTRINSIC_V4][..],
+            &MultiAddress::<AccountId32, ()>::Id(self.signer.clone()).encode(),
+            &signature.encode(),
+            &explicit,
+            &call,
+        ]
+        .concat();
+
+        Ok([Compact(body.len() as u32).encode(), body].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::Pair;
+
+    fn params() -> ExtensionParams {
+        ExtensionParams {
+            spec_version: 1_000_000,
+            transaction_version: 26,
+            genesis_hash: H256::repeat_byte(1),
+            checkpoint: H256::repeat_byte(2),
+            era: Era::mortal(64, 100),
+            nonce: 7,
+            tip: 0,
+        }
+    }
+
+    #[test]
+    fn encode_extensions_works() {
+        let identifiers = [
+            "CheckNonZeroSender",
+            "CheckSpecVersion",
+            "CheckTxVersion",
+            "CheckGenesis",
+            "CheckMortality",
+            "CheckNonce",
+            "CheckWeight",
+            "ChargeTransactionPayment",
+            "CheckMetadataHash",
+        ]
+        .map(String::from);
+        let params = params();
+
+        let (explicit, implicit) = encode_extensions(&identifiers, &params).unwrap();
+        assert_eq!(
+            explicit,
+            [
+                params.era.encode(),
+                Compact(7u32).encode(),
+                vec![0],
+                vec![0]
+            ]
+            .concat()
+        );
+        assert_eq!(
+            implicit,
+            [
+                params.spec_version.encode(),
+                params.transaction_version.encode(),
+                params.genesis_hash.encode(),
+                params.checkpoint.encode(),
+                vec![0],
+            ]
+            .concat()
+        );
+
+        assert!(encode_extensions(&["Unknown".to_string()], &params).is_err());
+    }
+
+    #[test]
+    fn signing_payload_works() {
+        assert_eq!(signing_payload(&[1], &[2], &[3]), vec![1, 2, 3]);
+        assert_eq!(
+            signing_payload(&[1; 300], &[], &[]),
+            blake2_256(&[1; 300]).to_vec()
+        );
+    }
+
+    #[test]
+    fn sign_works() {
+        let pair = sp_core::sr25519::Pair::from_seed(&[1; 32]);
+        let payload = signing_payload(&[4, 0], &[0], &[1]);
+        let unsigned = UnsignedExtrinsic {
+            at: H256::zero(),
+            spec_version: 1,
+            signer: pair.public().into(),
+            nonce: 0,
+            tip: 0,
+            call: bytes::to_hex(&[4, 0], false),
+            explicit: bytes::to_hex(&[0], false),
+            payload: bytes::to_hex(&payload, false),
+        };
+
+        let signature = pair.sign(&payload);
+        let extrinsic = unsigned.sign(signature.into()).unwrap();
+        // compact length, version, address (1 + 32), signature (1 + 64), explicit and call.
+        assert_eq!(extrinsic.len(), 1 + 1 + 33 + 65 + 1 + 2);
+        assert_eq!(extrinsic[1], SIGNED_EXTRINSIC_V4);
+        assert!(extrinsic.ends_with(&[0, 4, 0]));
+
+        let other = sp_core::sr25519::Pair::from_seed(&[2; 32]).sign(&payload);
+        assert!(unsigned.sign(other.into()).is_err());
+
+        assert!(multi_signature(SignatureScheme::Sr25519, &[0; 63]).is_err());
+        assert!(multi_signature(SignatureScheme::Ecdsa, &[0; 65]).is_ok());
+    }
+}
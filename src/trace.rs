@@ -0,0 +1,301 @@
+//! Tracing of the storage accessed by the operations.
+//!
+//! A [`ReadTrace`] instruments the backend of an externalities with a trie recorder, which records
+//! every trie node read from the stored state, e.g. by the storage reads and iterations of an
+//! operation. The recorded nodes are used to trim snapshots (see `snapshot trim`), and are decoded
+//! with the node codec of `sp-trie` back into the storage keys they lead to, in order to document
+//! the data dependencies of the operations (see `transform --trace-keys`).
+//!
+//! The writes of an operation stay in the overlay of its externalities, and are recorded from the
+//! overlay by [`record_writes`], including the ones of the simulations, which are rolled back.
+//! When the overlay is committed into a new backend with [`commit_all`], the recorder is attached
+//! to the new backend, so that the reads after the commit are traced as well. The keys of the
+//! child tries are not decoded.
+
+use crate::prelude::*;
+use crate::TimetravelError;
+
+use sp_core::{hashing::blake2_256, H256};
+use sp_runtime::traits::BlakeTwo256;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, OnceLock},
+};
+use trie_db::{
+    node::{Node, NodeHandle, Value},
+    NodeCodec as _,
+};
+
+/// The traces attached to the backends of the externalities, by storage root.
+static TRACES: OnceLock<Mutex<BTreeMap<H256, ReadTrace>>> = OnceLock::new();
+
+fn traces() -> std::sync::MutexGuard<'static, BTreeMap<H256, ReadTrace>> {
+    TRACES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// How a traced key was accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::ReadWrite => write!(f, "read_write"),
+        }
+    }
+}
+
+/// The trie nodes read from the backend of an externalities, and the keys written to its overlay.
+#[derive(Clone)]
+pub(crate) struct ReadTrace {
+    recorder: sp_trie::recorder::Recorder<BlakeTwo256>,
+    /// The storage roots of the backends the recorder was attached to.
+    roots: Arc<Mutex<Vec<H256>>>,
+    /// The written keys, and whether their last write removed them.
+    writes: Arc<Mutex<BTreeMap<Vec<u8>, bool>>>,
+}
+
+impl ReadTrace {
+    /// Records the trie nodes read from the backend of `ext` from now on, including after the
+    /// overlay is committed with [`commit_all`]. The backend keeps the trie cache of the run, if
+    /// any.
+    pub fn attach(ext: &mut Ext) -> Self {
+        let trace = Self {
+            recorder: Default::default(),
+            roots: Default::default(),
+            writes: Default::default(),
+        };
+        trace.instrument(ext);
+        trace
+    }
+
+    /// Rebuilds the backend of `ext` with the recorder of the trace.
+    fn instrument(&self, ext: &mut Ext) {
+        let root = *ext.backend.root();
+        let storage = std::mem::take(&mut ext.backend).into_storage();
+        ext.backend = sp_state_machine::TrieBackendBuilder::new(storage, root)
+            .with_optional_cache(crate::scraper::local_trie_cache())
+            .with_recorder(self.recorder.clone())
+            .build();
+
+        lock(&self.roots).push(root);
+        traces().insert(root, self.clone());
+    }
+
+    /// The recorded nodes, by hash.
+    pub fn nodes(&self) -> BTreeMap<H256, Vec<u8>> {
+        self.recorder
+            .to_storage_proof()
+            .into_iter_nodes()
+            .map(|node| (H256(blake2_256(&node)), node))
+            .collect()
+    }
+
+    /// The keys of the top trie whose value is reached by the recorded nodes from any of the
+    /// traced roots, sorted.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        let nodes = self.nodes();
+        let mut keys = BTreeSet::new();
+        for root in lock(&self.roots).iter() {
+            if let Some(root) = nodes.get(root) {
+                collect_keys(root, vec![], &nodes, &mut keys);
+            }
+        }
+        keys.into_iter().collect()
+    }
+
+    /// The written keys, and whether their last write removed them, sorted.
+    pub fn writes(&self) -> BTreeMap<Vec<u8>, bool> {
+        lock(&self.writes).clone()
+    }
+
+    /// The keys read or written, and how they were accessed, sorted.
+    pub fn accesses(&self) -> BTreeMap<Vec<u8>, Access> {
+        let mut accesses = self
+            .keys()
+            .into_iter()
+            .map(|key| (key, Access::Read))
+            .collect::<BTreeMap<_, _>>();
+        for key in self.writes().into_keys() {
+            accesses
+                .entry(key)
+                .and_modify(|access| *access = Access::ReadWrite)
+                .or_insert(Access::Write);
+        }
+        accesses
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records the keys written to the overlay of `ext` in its trace, if it is traced. The changes of
+/// the open storage transactions are recorded too, so the writes of a simulation are recorded by
+/// calling this before rolling it back.
+pub(crate) fn record_writes(ext: &Ext) {
+    let Some(trace) = traces().get(ext.backend.root()).cloned() else {
+        return;
+    };
+    let mut writes = lock(&trace.writes);
+    for (key, value) in ext.overlayed_changes().changes() {
+        writes.insert(key.clone(), value.value().is_none());
+    }
+}
+
+/// Commits the overlay of `ext` into its backend, recording its writes and keeping the trace of
+/// the backend, if any, attached to the new backend.
+pub(crate) fn commit_all(ext: &mut Ext) -> Result<(), anyhow::Error> {
+    record_writes(ext);
+    let trace = traces().get(ext.backend.root()).cloned();
+
+    ext.commit_all()
+        .map_err(|error| TimetravelError::Snapshot { error })?;
+
+    if let Some(trace) = trace {
+        trace.instrument(ext);
+    }
+    Ok(())
+}
+
+/// Collects the keys under the node `data` at the nibble `path` into `keys`, walking the children
+/// which are in `nodes`. The values stored in a separate node are only collected if the value node
+/// is in `nodes`, i.e. if it was read.
+fn collect_keys(
+    data: &[u8],
+    mut path: Vec<u8>,
+    nodes: &BTreeMap<H256, Vec<u8>>,
+    keys: &mut BTreeSet<Vec<u8>>,
+) {
+    let node = match sp_trie::NodeCodec::<BlakeTwo256>::decode(data) {
+        Ok(node) => node,
+        Err(e) => {
+            log::warn!(
+                target: LOG_TARGET,
+                "Undecodable trie node {:?}: {:?}.",
+                H256(blake2_256(data)),
+                e
+            );
+            return;
+        }
+    };
+
+    let (partial, children, value) = match node {
+        Node::Empty => return,
+        Node::Leaf(partial, value) => (Some(partial), Default::default(), Some(value)),
+        Node::Branch(children, value) => (None, children, value),
+        Node::NibbledBranch(partial, children, value) => (Some(partial), children, value),
+        Node::Extension(..) => {
+            log::warn!(target: LOG_TARGET, "Unexpected trie extension node {:?}.", H256(blake2_256(data)));
+            return;
+        }
+    };
+    if let Some(partial) = partial {
+        path.extend((0..partial.len()).map(|i| partial.at(i)));
+    }
+
+    let read = match value {
+        Some(Value::Inline(_)) => true,
+        Some(Value::Node(hash)) => nodes.contains_key(&H256::from_slice(hash)),
+        None => false,
+    };
+    if read && path.len() % 2 == 0 {
+        keys.insert(path.chunks(2).map(|n| n[0] << 4 | n[1]).collect());
+    }
+
+    for (nibble, child) in children.into_iter().enumerate() {
+        let child_path = [&path[..], &[nibble as u8]].concat();
+        match child {
+            Some(NodeHandle::Hash(hash)) => {
+                if let Some(child) = nodes.get(&H256::from_slice(hash)) {
+                    collect_keys(child, child_path, nodes, keys);
+                }
+            }
+            Some(NodeHandle::Inline(child)) => collect_keys(child, child_path, nodes, keys),
+            None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext_with(pairs: &[(&[u8], &[u8])]) -> Ext {
+        let mut ext = Ext::default();
+        ext.execute_with(|| {
+            for (key, value) in pairs {
+                sp_io::storage::set(key, value);
+            }
+        });
+        ext.commit_all().unwrap();
+        ext
+    }
+
+    #[test]
+    fn keys_of_reads_work() {
+        let mut ext = ext_with(&[(b"alpha", &[1; 40]), (b"beta", &[2]), (b"gamma", &[3])]);
+        let trace = ReadTrace::attach(&mut ext);
+
+        ext.execute_with(|| {
+            assert_eq!(sp_io::storage::get(b"alpha").map(|v| v.len()), Some(40));
+            assert_eq!(
+                sp_io::storage::get(b"gamma").map(|v| v.to_vec()),
+                Some(vec![3])
+            );
+        });
+
+        assert_eq!(trace.keys(), vec![b"alpha".to_vec(), b"gamma".to_vec()]);
+        assert!(trace.writes().is_empty());
+    }
+
+    #[test]
+    fn writes_and_reads_after_commit_work() {
+        let mut ext = ext_with(&[(b"alpha", &[1]), (b"beta", &[2]), (b"gamma", &[3])]);
+        let trace = ReadTrace::attach(&mut ext);
+
+        // a simulated write, rolled back.
+        crate::gadgets::simulate(&mut ext, |ext| {
+            ext.execute_with(|| sp_io::storage::set(b"delta", &[4]));
+        });
+        ext.execute_with(|| {
+            sp_io::storage::set(b"alpha", &[5]);
+            sp_io::storage::clear(b"beta");
+        });
+        commit_all(&mut ext).unwrap();
+
+        // the reads after the commit are traced too.
+        ext.execute_with(|| {
+            assert_eq!(
+                sp_io::storage::get(b"gamma").map(|v| v.to_vec()),
+                Some(vec![3])
+            );
+        });
+
+        assert_eq!(
+            trace.writes(),
+            BTreeMap::from([
+                (b"alpha".to_vec(), false),
+                (b"beta".to_vec(), true),
+                (b"delta".to_vec(), false),
+            ])
+        );
+        assert_eq!(
+            trace.accesses().get(&b"gamma".to_vec()),
+            Some(&Access::Read)
+        );
+        assert_eq!(
+            trace.accesses().get(&b"delta".to_vec()),
+            Some(&Access::Write)
+        );
+    }
+}
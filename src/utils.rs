@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, fmt::Debug};
+use std::{collections::BTreeMap, fmt::Debug, future::Future};
 
 #[derive(Copy, Clone, Debug)]
 pub(crate) enum ShareDistribution {
@@ -72,10 +72,498 @@ pub(crate) fn share_distribution<A: Ord + Debug + Clone>(
     }
 }
 
+/// Finds the points in the range `[from, to]` at which the value returned by `value_at` changes,
+/// using binary search.
+///
+/// Assumes that a value never reappears once it changed (e.g. runtime versions), so that sub-ranges
+/// whose bounds have the same value can be skipped. Returns the value at `from` followed by each
+/// change, in ascending order.
+pub(crate) async fn find_value_changes<V, F, Fut>(
+    from: u32,
+    to: u32,
+    mut value_at: F,
+) -> Result<Vec<(u32, V)>, anyhow::Error>
+where
+    V: Clone + PartialEq,
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<V, anyhow::Error>>,
+{
+    let mut values = BTreeMap::new();
+    values.insert(from, value_at(from).await?);
+    if to > from {
+        values.insert(to, value_at(to).await?);
+    }
+
+    let mut changes = vec![(from, values[&from].clone())];
+    let mut ranges = vec![(from, to)];
+
+    while let Some((lo, hi)) = ranges.pop() {
+        if values[&lo] == values[&hi] {
+            continue;
+        }
+        if hi - lo == 1 {
+            changes.push((hi, values[&hi].clone()));
+            continue;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        values.insert(mid, value_at(mid).await?);
+
+        // push the upper range first so that the lower range is processed first.
+        ranges.push((mid, hi));
+        ranges.push((lo, mid));
+    }
+
+    Ok(changes)
+}
+
+/// Finds the first point in the range `[from, to]` at which `holds_at` is true, using binary search.
+///
+/// Assumes that `holds_at` is monotonic in the range, i.e. once it is true, it remains true.
+/// Returns `None` if it does not hold at `to`.
+pub(crate) async fn find_first<F, Fut>(
+    from: u32,
+    to: u32,
+    mut holds_at: F,
+) -> Result<Option<u32>, anyhow::Error>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<bool, anyhow::Error>>,
+{
+    if !holds_at(to).await? {
+        return Ok(None);
+    }
+
+    let (mut lo, mut hi) = (from, to);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if holds_at(mid).await? {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+
+    Ok(Some(hi))
+}
+
+/// Estimates the number of keys under a prefix from a sample of the first `sampled` keys (in
+/// lexicographic order), given the suffix of the last sampled key after the prefix.
+///
+/// If less than `page_size` keys were sampled, all the keys under the prefix have been sampled
+/// and the count is exact. Otherwise, assuming that the suffixes are uniformly distributed (i.e.
+/// hashed keys), the position of the last sampled key in the key space gives the density of keys
+/// under the prefix.
+pub(crate) fn estimate_key_count(sampled: u32, page_size: u32, last_suffix: &[u8]) -> f64 {
+    if sampled < page_size {
+        return sampled as f64;
+    }
+
+    let mut position = [0u8; 8];
+    let len = last_suffix.len().min(8);
+    position[..len].copy_from_slice(&last_suffix[..len]);
+    let fraction = u64::from_be_bytes(position) as f64 / u64::MAX as f64;
+
+    if fraction == 0.0 {
+        sampled as f64
+    } else {
+        (sampled as f64 / fraction).max(sampled as f64)
+    }
+}
+
+/// Fraction of the estimated key count under which a snapshot is considered incomplete, which
+/// accounts for the error of the estimate.
+pub(crate) const INCOMPLETE_TOLERANCE: f64 = 0.5;
+
+/// Returns whether `snapshot_keys` under a prefix appear incomplete, compared to a sample of
+/// `sampled` keys (of a page of `page_size`) of the node under the same prefix. The snapshot must
+/// hold at least the sampled keys, which suffices if the sample holds all the keys of the node.
+/// Otherwise, it must also hold the estimated key count, within [`INCOMPLETE_TOLERANCE`]. A
+/// snapshot with more keys than the node, e.g. after a patch, is not considered incomplete.
+pub(crate) fn appears_incomplete(
+    snapshot_keys: u64,
+    sampled: u32,
+    page_size: u32,
+    estimated: f64,
+) -> bool {
+    if sampled < page_size {
+        snapshot_keys < sampled as u64
+    } else {
+        snapshot_keys < sampled as u64
+            || (snapshot_keys as f64) < estimated * (1.0 - INCOMPLETE_TOLERANCE)
+    }
+}
+
+/// Decodes a SCALE encoded unsigned integer, inferring its width from the number of bytes.
+pub(crate) fn decode_uint(bytes: &[u8]) -> Option<u128> {
+    use codec::Decode;
+
+    let input = &mut &bytes[..];
+    match bytes.len() {
+        1 => u8::decode(input).ok().map(Into::into),
+        2 => u16::decode(input).ok().map(Into::into),
+        4 => u32::decode(input).ok().map(Into::into),
+        8 => u64::decode(input).ok().map(Into::into),
+        16 => u128::decode(input).ok(),
+        _ => None,
+    }
+}
+
+/// Returns the index of the Babe authority which authored a block, as per the Babe pre-runtime
+/// digest of its header.
+///
+/// All the variants of the Babe pre-digest (primary, secondary plain and secondary VRF) are
+/// encoded as the variant index followed by the authority index, so the latter is decoded without
+/// depending on the Babe primitives.
+pub(crate) fn babe_authority_index(digest: &sp_runtime::Digest) -> Option<u32> {
+    use codec::Decode;
+
+    digest
+        .logs
+        .iter()
+        .find_map(|log| match log.as_pre_runtime() {
+            Some((engine_id, data))
+                if engine_id == *b"BABE" && matches!(data.first(), Some(1..=3)) =>
+            {
+                u32::decode(&mut &data[1..]).ok()
+            }
+            _ => None,
+        })
+}
+
+/// A small deterministic pseudo-random number generator (SplitMix64), used to generate
+/// reproducible synthetic data.
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a float uniformly distributed in `(0, 1]`.
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns an integer uniformly distributed in `[0, n)`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+}
+
+/// Samples a stake in `[min, max]` following `distribution`.
+///
+/// The Pareto distribution has shape 1.16 (i.e. 80% of the stake is held by 20% of the stakers)
+/// and scale `min`, the exponential distribution has mean `min + (max - min) / 10`. Both are
+/// capped at `max`.
+pub(crate) fn sample_stake(
+    distribution: &crate::configs::StakeDistribution,
+    rng: &mut SplitMix64,
+    min: u128,
+    max: u128,
+) -> u128 {
+    use crate::configs::StakeDistribution;
+
+    let (min_f, max_f) = (min as f64, max.max(min) as f64);
+    let u = rng.next_f64();
+
+    let stake = match distribution {
+        StakeDistribution::Uniform => min_f + (max_f - min_f) * (1.0 - u),
+        StakeDistribution::Pareto => min_f / u.powf(1.0 / 1.16),
+        StakeDistribution::Exponential => min_f - u.ln() * (max_f - min_f) / 10.0,
+    };
+
+    (stake.min(max_f) as u128).clamp(min, max.max(min))
+}
+
+/// Default expected block time, in milliseconds, used to extrapolate the timestamps of blocks
+/// outside of the sampled ones.
+pub(crate) const DEFAULT_BLOCK_TIME_MS: u64 = 6_000;
+
+/// The timestamps (in milliseconds) of a sample of blocks, from which the timestamps of the
+/// blocks in between are interpolated, so that the blocks of a range can be aligned to calendar
+/// days without fetching every header.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockTimes {
+    samples: BTreeMap<u32, u64>,
+    /// Expected block time, used to extrapolate the timestamps outside of the sampled blocks.
+    block_time_ms: u64,
+}
+
+impl BlockTimes {
+    pub fn new(block_time_ms: u64) -> Self {
+        Self {
+            samples: BTreeMap::new(),
+            block_time_ms,
+        }
+    }
+
+    /// Records the timestamp of a sampled block.
+    pub fn insert(&mut self, block_number: u32, timestamp: u64) {
+        self.samples.insert(block_number, timestamp);
+    }
+
+    /// Returns the timestamp of a block: the sampled one, interpolated linearly between the
+    /// surrounding samples, or extrapolated with the expected block time from the nearest sample.
+    /// Returns `None` if there are no samples.
+    pub fn timestamp_at(&self, block_number: u32) -> Option<u64> {
+        let before = self.samples.range(..=block_number).next_back();
+        let after = self.samples.range(block_number..).next();
+
+        match (before, after) {
+            (Some((&from, &from_ts)), Some((&to, &to_ts))) if from == to => Some(from_ts),
+            (Some((&from, &from_ts)), Some((&to, &to_ts))) => {
+                let elapsed = to_ts.saturating_sub(from_ts) as u128 * (block_number - from) as u128
+                    / (to - from) as u128;
+                Some(from_ts + elapsed as u64)
+            }
+            (Some((&from, &from_ts)), None) => {
+                Some(from_ts.saturating_add((block_number - from) as u64 * self.block_time_ms))
+            }
+            (None, Some((&to, &to_ts))) => {
+                Some(to_ts.saturating_sub((to - block_number) as u64 * self.block_time_ms))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Raw storage of a snapshot, i.e. the trie nodes and their reference counts.
+pub(crate) type RawStorage = Vec<(Vec<u8>, (Vec<u8>, i32))>;
+
+/// Returns the entries of the `raw_storage` of a snapshot whose trie node is one of the `nodes`,
+/// identified by their hash. The keys of the entries end with the hash of their node.
+pub(crate) fn trim_raw_storage(
+    raw_storage: RawStorage,
+    nodes: &std::collections::BTreeSet<sp_core::H256>,
+) -> RawStorage {
+    raw_storage
+        .into_iter()
+        .filter(|(key, _)| {
+            key.len() >= 32 && nodes.contains(&sp_core::H256::from_slice(&key[key.len() - 32..]))
+        })
+        .collect()
+}
+
+/// Replaces the raw storage and storage root of an encoded externalities snapshot.
+///
+/// The snapshot is encoded as `(version, state_version, block_hash, raw_storage, storage_root,
+/// ..)`. The leading and trailing fields are kept as is, so that the resulting snapshot can be
+/// loaded by the same version of the remote externalities which stored it.
+pub(crate) fn replace_snapshot_storage(
+    snapshot: &[u8],
+    raw_storage: RawStorage,
+    storage_root: sp_core::H256,
+) -> Result<Vec<u8>, anyhow::Error> {
+    use codec::{Compact, Decode, Encode};
+    use sp_core::{storage::StateVersion, H256};
+
+    let input = &mut &snapshot[..];
+    <(Compact<u16>, StateVersion, H256)>::decode(input)?;
+    let prefix_len = snapshot.len() - input.len();
+    <(RawStorage, H256)>::decode(input)?;
+
+    Ok([
+        &snapshot[..prefix_len],
+        &(raw_storage, storage_root).encode()[..],
+        input,
+    ]
+    .concat())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn block_times_works() {
+        let mut times = BlockTimes::new(6_000);
+        assert_eq!(times.timestamp_at(10), None);
+
+        times.insert(100, 1_000_000);
+        times.insert(200, 1_700_000);
+
+        assert_eq!(times.timestamp_at(100), Some(1_000_000));
+        assert_eq!(times.timestamp_at(150), Some(1_350_000));
+        assert_eq!(times.timestamp_at(199), Some(1_693_000));
+        // extrapolated with the expected block time.
+        assert_eq!(times.timestamp_at(210), Some(1_760_000));
+        assert_eq!(times.timestamp_at(90), Some(940_000));
+    }
+
+    #[test]
+    fn sample_stake_works() {
+        use crate::configs::StakeDistribution;
+
+        for distribution in [
+            StakeDistribution::Uniform,
+            StakeDistribution::Pareto,
+            StakeDistribution::Exponential,
+        ] {
+            let mut rng = SplitMix64::new(42);
+            let stakes = (0..1_000)
+                .map(|_| sample_stake(&distribution, &mut rng, 100, 10_000))
+                .collect::<Vec<_>>();
+
+            assert!(stakes.iter().all(|s| (100..=10_000).contains(s)));
+
+            // same seed, same stakes.
+            let mut rng = SplitMix64::new(42);
+            assert_eq!(
+                sample_stake(&distribution, &mut rng, 100, 10_000),
+                stakes[0]
+            );
+        }
+
+        // skewed distributions concentrate the stake close to the minimum.
+        let median = |distribution| {
+            let mut rng = SplitMix64::new(0);
+            let mut stakes = (0..1_001)
+                .map(|_| sample_stake(&distribution, &mut rng, 100, 10_000))
+                .collect::<Vec<_>>();
+            stakes.sort();
+            stakes[500]
+        };
+        assert!(median(StakeDistribution::Pareto) < median(StakeDistribution::Uniform));
+        assert!(median(StakeDistribution::Exponential) < median(StakeDistribution::Uniform));
+
+        // degenerated range.
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(
+            sample_stake(&StakeDistribution::Pareto, &mut rng, 100, 10),
+            100
+        );
+    }
+
+    #[test]
+    fn trim_raw_storage_works() {
+        use sp_core::H256;
+
+        let prefixed = [vec![0x12, 0x30], H256::repeat_byte(1).0.to_vec()].concat();
+        let raw_storage: RawStorage = vec![
+            (prefixed.clone(), (vec![1], 1)),
+            (H256::repeat_byte(2).0.to_vec(), (vec![2], 1)),
+            (vec![3], (vec![3], 1)),
+        ];
+        let nodes = [H256::repeat_byte(1), H256::repeat_byte(3)].into();
+
+        assert_eq!(
+            trim_raw_storage(raw_storage, &nodes),
+            vec![(prefixed, (vec![1], 1))]
+        );
+    }
+
+    #[test]
+    fn replace_snapshot_storage_works() {
+        use codec::{Compact, Encode};
+        use sp_core::{storage::StateVersion, H256};
+
+        let header = (Compact(3u16), StateVersion::V1, H256::repeat_byte(1));
+        let old_storage: RawStorage = vec![(vec![1], (vec![1, 1], 1))];
+        let new_storage: RawStorage = vec![(vec![2], (vec![2, 2], 1)), (vec![3], (vec![3], 2))];
+        let trailing = vec![42u8; 10];
+
+        let snapshot = [
+            (header, old_storage, H256::repeat_byte(2)).encode(),
+            trailing.clone(),
+        ]
+        .concat();
+
+        assert_eq!(
+            replace_snapshot_storage(&snapshot, new_storage.clone(), H256::repeat_byte(3)).unwrap(),
+            [
+                (header, new_storage, H256::repeat_byte(3)).encode(),
+                trailing
+            ]
+            .concat()
+        );
+        assert!(replace_snapshot_storage(&[0u8; 3], vec![], H256::zero()).is_err());
+    }
+
+    #[tokio::test]
+    async fn find_first_works() {
+        let holds_at = |n: u32| async move { Ok(n >= 42) };
+
+        assert_eq!(find_first(0, 100, holds_at).await.unwrap(), Some(42));
+        assert_eq!(find_first(42, 100, holds_at).await.unwrap(), Some(42));
+        assert_eq!(find_first(50, 100, holds_at).await.unwrap(), Some(50));
+        assert_eq!(find_first(0, 41, holds_at).await.unwrap(), None);
+    }
+
+    #[test]
+    fn estimate_key_count_works() {
+        // all keys sampled.
+        assert_eq!(estimate_key_count(10, 100, &[0xff; 8]), 10.0);
+        // last sampled key at a quarter of the key space.
+        assert_eq!(
+            estimate_key_count(100, 100, &[0x40, 0, 0, 0, 0, 0, 0, 0]).round(),
+            400.0
+        );
+        // last sampled key at the end of the key space.
+        assert_eq!(estimate_key_count(100, 100, &[0xff; 8]).round(), 100.0);
+        // degenerated suffixes.
+        assert_eq!(estimate_key_count(100, 100, &[]), 100.0);
+    }
+
+    #[test]
+    fn appears_incomplete_works() {
+        // all keys sampled, the snapshot must hold at least as many.
+        assert!(!appears_incomplete(10, 10, 100, 10.0));
+        assert!(!appears_incomplete(11, 10, 100, 10.0));
+        assert!(appears_incomplete(9, 10, 100, 10.0));
+        // estimated counts are trusted within the tolerance.
+        assert!(!appears_incomplete(600, 100, 100, 1_000.0));
+        assert!(appears_incomplete(400, 100, 100, 1_000.0));
+        // less keys than sampled.
+        assert!(appears_incomplete(50, 100, 100, 60.0));
+    }
+
+    #[test]
+    fn decode_uint_works() {
+        use codec::Encode;
+
+        assert_eq!(decode_uint(&7u8.encode()), Some(7));
+        assert_eq!(decode_uint(&1_000u32.encode()), Some(1_000));
+        assert_eq!(decode_uint(&u128::MAX.encode()), Some(u128::MAX));
+        assert_eq!(decode_uint(&[0u8; 3]), None);
+    }
+
+    #[tokio::test]
+    async fn find_value_changes_works() {
+        let version_at = |n: u32| async move {
+            Ok(match n {
+                0..=9 => 1,
+                10..=41 => 2,
+                _ => 3,
+            })
+        };
+
+        assert_eq!(
+            find_value_changes(0, 100, version_at).await.unwrap(),
+            vec![(0, 1), (10, 2), (42, 3)]
+        );
+        assert_eq!(
+            find_value_changes(10, 41, version_at).await.unwrap(),
+            vec![(10, 2)]
+        );
+        assert_eq!(
+            find_value_changes(41, 42, version_at).await.unwrap(),
+            vec![(41, 2), (42, 3)]
+        );
+        assert_eq!(
+            find_value_changes(5, 5, version_at).await.unwrap(),
+            vec![(5, 1)]
+        );
+    }
+
     #[test]
     fn target_votes_works() {
         let v: Vec<(u32, u64, Vec<u32>)> = vec![
@@ -112,4 +600,27 @@ mod tests {
         );
         assert_eq!(pareto_distribution, vec![(4, 6), (2, 6), (1, 6), (3, 80)]);
     }
+
+    #[test]
+    fn babe_authority_index_works() {
+        use codec::Encode;
+        use sp_runtime::{Digest, DigestItem};
+
+        // secondary plain pre-digest: variant, authority index and slot.
+        let pre_digest = (2u8, 7u32, 42u64).encode();
+        let digest = Digest {
+            logs: vec![
+                DigestItem::PreRuntime(*b"aura", 3u32.encode()),
+                DigestItem::PreRuntime(*b"BABE", pre_digest),
+                DigestItem::Seal(*b"BABE", vec![0u8; 64]),
+            ],
+        };
+        assert_eq!(babe_authority_index(&digest), Some(7));
+
+        let digest = Digest {
+            logs: vec![DigestItem::PreRuntime(*b"BABE", (0u8, 7u32).encode())],
+        };
+        assert_eq!(babe_authority_index(&digest), None);
+        assert_eq!(babe_authority_index(&Default::default()), None);
+    }
 }
@@ -0,0 +1,178 @@
+//! Structured warnings of the gadgets and operations.
+//!
+//! Warnings raised while an output entry is computed (e.g. a capped number of desired targets or
+//! skipped voters) are logged and collected in the [`Scope`] of the operation which computes it,
+//! and the output subsystem attaches the warnings collected since the previous entry of the block
+//! to the next entry the operation emits, as the `warnings` (count) and `warnings_summary` fields.
+//! The scope is owned by the `Emitter` of the operation and entered on the thread which runs the
+//! operation, so the warnings of concurrent operations (or runs) never land on each other's
+//! entries. The scope is cleared when the operation moves to the next block (see
+//! `Emitter::begin_block`), so the warnings of a block which emits no entry are not attached to the
+//! entries of the next block.
+//!
+//! The warnings of the run rather than of an entry (e.g. an exhausted budget) are raised with
+//! [`warn_run`], and are reported with the warnings not attached to any entry (including the ones
+//! raised outside of any scope) when the run ends.
+
+use crate::prelude::*;
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    marker::PhantomData,
+    rc::Rc,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// A warning raised by a gadget or operation.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Warning {
+    /// The kind of the warning, e.g. `bad_voter`.
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// The warnings of the run, and the ones which were not attached to any entry.
+static UNATTACHED: Mutex<Vec<Warning>> = Mutex::new(vec![]);
+
+thread_local! {
+    /// The scope entered on the thread, if any.
+    static CURRENT: RefCell<Weak<Mutex<Vec<Warning>>>> = RefCell::new(Weak::new());
+}
+
+/// The warnings raised in the scope of the next output entry of an operation.
+#[derive(Debug, Default)]
+pub(crate) struct Scope(Arc<Mutex<Vec<Warning>>>);
+
+impl Scope {
+    /// Collects the warnings raised on the current thread in the scope, until the returned guard
+    /// is dropped.
+    pub fn enter(&self) -> ScopeGuard {
+        let previous = CURRENT.with(|current| current.replace(Arc::downgrade(&self.0)));
+        ScopeGuard(previous, PhantomData)
+    }
+
+    /// Takes the warnings collected in the scope.
+    pub fn take(&self) -> Vec<Warning> {
+        self.0
+            .lock()
+            .map(|mut warnings| std::mem::take(&mut *warnings))
+            .unwrap_or_default()
+    }
+
+    /// Clears the scope, keeping its warnings as unattached.
+    pub fn clear(&self) {
+        let warnings = self.take();
+        if let Ok(mut unattached) = UNATTACHED.lock() {
+            unattached.extend(warnings);
+        }
+    }
+}
+
+/// Restores the scope entered on the thread before a [`Scope::enter`] when dropped. The guard is
+/// bound to the thread of the scope, so it can not be held across an `await`.
+pub(crate) struct ScopeGuard(Weak<Mutex<Vec<Warning>>>, PhantomData<Rc<()>>);
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.0);
+        CURRENT.with(|current| current.replace(previous));
+    }
+}
+
+/// Logs a warning of `kind` and collects it in the scope entered on the thread, or as unattached
+/// if none is.
+pub(crate) fn warn(kind: &'static str, message: String) {
+    log::warn!(target: LOG_TARGET, "{}", message);
+
+    let warning = Warning { kind, message };
+    let scope = CURRENT.with(|current| current.borrow().upgrade());
+    let warnings = match &scope {
+        Some(scope) => scope.lock(),
+        None => UNATTACHED.lock(),
+    };
+    if let Ok(mut warnings) = warnings {
+        warnings.push(warning);
+    }
+}
+
+/// Logs a warning of `kind` of the whole run, which is not attached to any output entry.
+pub(crate) fn warn_run(kind: &'static str, message: String) {
+    log::warn!(target: LOG_TARGET, "{}", message);
+
+    if let Ok(mut warnings) = UNATTACHED.lock() {
+        warnings.push(Warning { kind, message });
+    }
+}
+
+/// Takes the warnings of the run and the ones not attached to any entry.
+pub(crate) fn take_unattached() -> Vec<Warning> {
+    UNATTACHED
+        .lock()
+        .map(|mut warnings| std::mem::take(&mut *warnings))
+        .unwrap_or_default()
+}
+
+/// Summarizes the warnings as their kinds and counts, e.g. `bad_voter x3; capped_desired_targets`.
+pub(crate) fn summary(warnings: &[Warning]) -> String {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for warning in warnings {
+        *counts.entry(warning.kind).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(kind, count)| match count {
+            1 => kind.to_string(),
+            _ => format!("{} x{}", kind, count),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_works() {
+        let warning = |kind| Warning {
+            kind,
+            message: String::new(),
+        };
+
+        assert_eq!(summary(&[]), "");
+        assert_eq!(
+            summary(&[
+                warning("bad_voter"),
+                warning("capped_desired_targets"),
+                warning("bad_voter"),
+                warning("bad_voter"),
+            ]),
+            "bad_voter x3; capped_desired_targets"
+        );
+    }
+
+    #[test]
+    fn scope_works() {
+        let (scope, other) = (Scope::default(), Scope::default());
+        {
+            let _guard = scope.enter();
+            warn("bad_voter", "in scope".into());
+            {
+                let _guard = other.enter();
+                warn("capped_desired_targets", "in the other scope".into());
+            }
+            warn("bad_voter", "in scope again".into());
+        }
+
+        // the warnings of another thread do not land in the scope.
+        std::thread::spawn(|| warn("no_snapshot", "outside of the scope".into()))
+            .join()
+            .unwrap();
+
+        assert_eq!(summary(&scope.take()), "bad_voter x2");
+        assert_eq!(summary(&other.take()), "capped_desired_targets");
+        assert!(scope.take().is_empty());
+    }
+}